@@ -1,9 +1,31 @@
 #![allow(clippy::print_stdout)]
-use donovim::Editor;
+use donovim::{Args, Editor};
+use std::env;
 
 /**
  * Entry point
  */
 fn main() {
-    Editor::default().run();
+    let args: Vec<String> = env::args().collect();
+    // `--remote` hands the files to an editor started with `--listen`
+    if args.get(1).map(String::as_str) == Some("--remote") {
+        std::process::exit(donovim::open_remote(&args[2..]));
+    }
+    let args = match Args::parse(&args[1..]) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("donovim: {}\nRun donovim --help for the options", err);
+            std::process::exit(2);
+        }
+    };
+    if args.version {
+        println!("{}", donovim::version());
+        return;
+    }
+    if args.help {
+        println!("{}", donovim::USAGE);
+        return;
+    }
+    let code = Editor::new(args).run();
+    std::process::exit(code);
 }
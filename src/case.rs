@@ -0,0 +1,160 @@
+/// A way of writing an identifier made of several words
+#[derive(Clone, Copy)]
+pub enum Case {
+    /// `snake_case`
+    Snake,
+
+    /// `camelCase`
+    Camel,
+
+    /// `PascalCase`
+    Pascal,
+
+    /// `kebab-case`
+    Kebab,
+}
+
+impl Case {
+    /// Find a case by the name `:ConvertCase` takes
+    ///
+    /// # Args
+    ///
+    /// - `name`: e.g. `snake` or `camel`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "snake" => Some(Self::Snake),
+            "camel" => Some(Self::Camel),
+            "pascal" | "mixed" => Some(Self::Pascal),
+            "kebab" | "dash" => Some(Self::Kebab),
+            _ => None,
+        }
+    }
+
+    /// Find a case by the key following `cr`, e.g. `s` for `crs`
+    pub fn from_key(key: char) -> Option<Self> {
+        match key {
+            's' | '_' => Some(Self::Snake),
+            'c' => Some(Self::Camel),
+            'p' | 'm' => Some(Self::Pascal),
+            'k' | '-' => Some(Self::Kebab),
+            _ => None,
+        }
+    }
+
+    /// Rewrite an identifier in this case
+    ///
+    /// # Args
+    ///
+    /// - `identifier`: The identifier, in any case, e.g. `parseHTTPRequest`
+    ///
+    /// # Returns
+    ///
+    /// - The identifier in this case, e.g. `parse_http_request`, keeping any
+    ///   leading underscores
+    pub fn convert(self, identifier: &str) -> String {
+        let name = identifier.trim_start_matches('_');
+        let prefix = &identifier[..identifier.len() - name.len()];
+        let words = words(name);
+        let capitalize = |word: &str| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        };
+        let converted = match self {
+            Self::Snake => words.join("_"),
+            Self::Kebab => words.join("-"),
+            Self::Pascal => words.iter().map(|word| capitalize(word)).collect(),
+            Self::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(index, word)| {
+                    if index == 0 {
+                        word.clone()
+                    } else {
+                        capitalize(word)
+                    }
+                })
+                .collect(),
+        };
+        format!("{}{}", prefix, converted)
+    }
+}
+
+/// Split an identifier into its lowercase words. Words are separated by `_`,
+/// `-`, or a change to uppercase. The last capital of a run of capitals starts
+/// the next word, so `HTTPServer` is `http` and `server`, and digits belong
+/// to the word before them, so `utf8Decoder` is `utf8` and `decoder`
+fn words(identifier: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for part in identifier.split(['_', '-']).filter(|part| !part.is_empty()) {
+        let chars: Vec<char> = part.chars().collect();
+        let mut word = String::new();
+        for (index, &c) in chars.iter().enumerate() {
+            let previous = index.checked_sub(1).map(|index| chars[index]);
+            let next = chars.get(index + 1);
+            let starts_word = c.is_uppercase()
+                && previous.is_some_and(|previous| {
+                    previous.is_lowercase()
+                        || previous.is_numeric()
+                        || (previous.is_uppercase() && next.is_some_and(|next| next.is_lowercase()))
+                });
+            if starts_word {
+                words.push(std::mem::take(&mut word));
+            }
+            word.extend(c.to_lowercase());
+        }
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_words() {
+        assert_eq!(words("parseHTTPRequest"), vec!["parse", "http", "request"]);
+        assert_eq!(words("HTTPServer"), vec!["http", "server"]);
+        assert_eq!(words("utf8Decoder"), vec!["utf8", "decoder"]);
+        assert_eq!(words("kebab--case_x"), vec!["kebab", "case", "x"]);
+    }
+
+    #[test]
+    fn convert_between_cases() {
+        assert_eq!(
+            Case::Snake.convert("parseHTTPRequest"),
+            "parse_http_request"
+        );
+        assert_eq!(
+            Case::Camel.convert("parse_http_request"),
+            "parseHttpRequest"
+        );
+        assert_eq!(
+            Case::Pascal.convert("parse-http-request"),
+            "ParseHttpRequest"
+        );
+        assert_eq!(
+            Case::Kebab.convert("ParseHttpRequest"),
+            "parse-http-request"
+        );
+    }
+
+    #[test]
+    fn convert_keeps_leading_underscores() {
+        assert_eq!(Case::Camel.convert("__private_name"), "__privateName");
+        assert_eq!(Case::Snake.convert("_"), "_");
+    }
+
+    #[test]
+    fn names_and_keys() {
+        assert!(matches!(Case::from_name("Mixed"), Some(Case::Pascal)));
+        assert!(matches!(Case::from_name("dash"), Some(Case::Kebab)));
+        assert!(Case::from_name("title").is_none());
+        assert!(matches!(Case::from_key('_'), Some(Case::Snake)));
+        assert!(matches!(Case::from_key('c'), Some(Case::Camel)));
+        assert!(Case::from_key('x').is_none());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::session;
+use std::path::PathBuf;
+
+/// How the editor is started, shown for `--help` and bad arguments
+pub const USAGE: &str = "Usage: donovim [OPTIONS] [FILE]...
+       donovim [OPTIONS] -
+       donovim -d LEFT RIGHT
+       donovim --remote [--wait] FILE[:LINE]...
+
+Options:
+  +N                 Open the first file at line N, the last line for `+`
+  -R                 Open the files read-only
+  -d                 Compare two files
+  --listen[=PATH]    Accept files from `donovim --remote`
+  --session[=PATH]   Restore a session saved by `:mksession`
+  --version          Print the version and the features of the build
+  --help             Print this help
+  --                 Take the rest of the arguments as files
+
+A directory is opened in the explorer, and `-` reads the text from stdin.";
+
+/// The arguments the editor was started with
+#[derive(Default)]
+pub struct Args {
+    /// The files to open, one buffer each, the first one shown
+    pub files: Vec<String>,
+
+    /// The line to open the first file at, empty for the last line
+    pub line: Option<String>,
+
+    /// Whether the files may not be edited
+    pub read_only: bool,
+
+    /// Whether to read the text from stdin instead of a file
+    pub stdin: bool,
+
+    /// Whether to compare the two files side by side
+    pub diff: bool,
+
+    /// Whether to accept files from `donovim --remote`
+    pub listen: bool,
+
+    /// The socket to listen on, or nothing for the default one
+    pub socket: Option<PathBuf>,
+
+    /// The session file to restore
+    pub session: Option<String>,
+
+    /// Whether to print the version instead of starting
+    pub version: bool,
+
+    /// Whether to print the usage instead of starting
+    pub help: bool,
+}
+
+impl Args {
+    /// Read the arguments of the command line
+    ///
+    /// # Args
+    ///
+    /// - `args`: The arguments, without the name of the program
+    ///
+    /// # Returns
+    ///
+    /// - The arguments, or a description of the one that is not understood
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut options = true;
+        for arg in args {
+            match arg.as_str() {
+                _ if !options => parsed.files.push(arg.clone()),
+                "--" => options = false,
+                "-" => parsed.stdin = true,
+                "-R" => parsed.read_only = true,
+                "-d" => parsed.diff = true,
+                "--listen" => parsed.listen = true,
+                "--session" => parsed.session = Some(session::DEFAULT_FILE.to_string()),
+                "--version" | "-v" => parsed.version = true,
+                "--help" | "-h" => parsed.help = true,
+                _ if arg.starts_with("--listen=") => {
+                    parsed.listen = true;
+                    parsed.socket = Some(PathBuf::from(&arg["--listen=".len()..]));
+                }
+                _ if arg.starts_with("--session=") => {
+                    parsed.session = Some(arg["--session=".len()..].to_string());
+                }
+                _ if arg.starts_with('+') => {
+                    let line = &arg[1..];
+                    if !line.is_empty() && line.parse::<usize>().is_err() {
+                        return Err(format!("Invalid line: {}", arg));
+                    }
+                    parsed.line = Some(line.to_string());
+                }
+                _ if arg.starts_with('-') => {
+                    return Err(format!("Unknown option: {}", arg));
+                }
+                _ => parsed.files.push(arg.clone()),
+            }
+        }
+
+        if parsed.diff && parsed.files.len() != 2 {
+            return Err(String::from("-d takes two files"));
+        }
+        if parsed.stdin && (parsed.diff || !parsed.files.is_empty()) {
+            return Err(String::from("- reads stdin and takes no other files"));
+        }
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Args::parse(&args)
+    }
+
+    #[test]
+    fn files_and_flags() {
+        let args = parse(&["-R", "+12", "a.rs", "b.rs"]).ok().unwrap();
+        assert_eq!(args.files, vec!["a.rs", "b.rs"]);
+        assert_eq!(args.line.as_deref(), Some("12"));
+        assert!(args.read_only && !args.diff && !args.stdin);
+
+        let args = parse(&["+"]).ok().unwrap();
+        assert_eq!(args.line.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn options_with_values() {
+        let args = parse(&["--listen=/tmp/s.sock", "--session=work.vim"])
+            .ok()
+            .unwrap();
+        assert!(args.listen);
+        assert_eq!(args.socket, Some(PathBuf::from("/tmp/s.sock")));
+        assert_eq!(args.session.as_deref(), Some("work.vim"));
+
+        let args = parse(&["--listen", "--session"]).ok().unwrap();
+        assert!(args.listen && args.socket.is_none());
+        assert_eq!(args.session.as_deref(), Some(session::DEFAULT_FILE));
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let args = parse(&["--", "-R", "+3"]).ok().unwrap();
+        assert_eq!(args.files, vec!["-R", "+3"]);
+        assert!(!args.read_only && args.line.is_none());
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(parse(&["-x"]).err().unwrap(), "Unknown option: -x");
+        assert_eq!(parse(&["+a"]).err().unwrap(), "Invalid line: +a");
+        assert_eq!(parse(&["-d", "a"]).err().unwrap(), "-d takes two files");
+        assert_eq!(
+            parse(&["-", "a"]).err().unwrap(),
+            "- reads stdin and takes no other files"
+        );
+        assert!(parse(&["-d", "a", "b"]).ok().unwrap().diff);
+    }
+}
@@ -0,0 +1,255 @@
+/// A command the command line knows
+struct Definition {
+    /// The full name of the command
+    name: &'static str,
+
+    /// The number of letters the name can be shortened to
+    shortest: usize,
+
+    /// Whether the command takes a range of lines
+    range: bool,
+
+    /// Whether the command can be followed by `!`
+    bang: bool,
+}
+
+const fn define(name: &'static str, shortest: usize, range: bool, bang: bool) -> Definition {
+    Definition {
+        name,
+        shortest,
+        range,
+        bang,
+    }
+}
+
+/// Every command of the command line. Commands starting with an uppercase
+/// letter can not be shortened
+const DEFINITIONS: &[Definition] = &[
+    define("bnext", 2, false, false),
+    define("bprevious", 2, false, false),
+    define("buffer", 1, false, false),
+    define("buffers", 7, false, false),
+    define("cclose", 3, false, false),
+    define("cnext", 2, false, false),
+    define("colorscheme", 4, false, false),
+    define("copen", 4, false, false),
+    define("cprevious", 2, false, false),
+    define("cquit", 2, false, false),
+    define("delete", 1, true, false),
+    define("diffoff", 5, false, false),
+    define("edit", 1, false, true),
+    define("grep", 2, false, false),
+    define("ls", 2, false, false),
+    define("messages", 3, false, false),
+    define("mksession", 3, false, true),
+    define("nohlsearch", 3, false, false),
+    define("normal", 4, true, false),
+    define("qall", 2, false, true),
+    define("quit", 1, false, true),
+    define("recover", 3, false, true),
+    define("redir", 4, false, false),
+    define("set", 2, false, false),
+    define("source", 2, false, false),
+    define("substitute", 1, true, false),
+    define("version", 2, false, false),
+    define("vimgrep", 3, false, false),
+    define("wall", 2, false, false),
+    define("wq", 2, false, true),
+    define("write", 1, false, true),
+    define("xall", 2, false, false),
+    define("ConfigReload", 12, false, false),
+    define("ConflictTakeOurs", 16, false, false),
+    define("ConflictTakeTheirs", 18, false, false),
+    define("ConvertCase", 11, false, false),
+    define("EvalSelection", 13, false, false),
+    define("Explore", 7, false, false),
+    define("Gcommit", 7, false, false),
+    define("GitRevertHunk", 13, false, false),
+    define("GitStageHunk", 12, false, false),
+    define("Gstatus", 7, false, false),
+    define("Gwrite", 6, false, false),
+    define("LocalHistory", 12, false, false),
+    define("Notes", 5, false, false),
+    define("Preview", 7, false, false),
+    define("RecoverLast", 11, false, false),
+    define("S", 1, true, false),
+    define("Symbols", 7, false, false),
+    define("Table", 5, false, false),
+    define("Theme", 5, false, false),
+    define("ToBin", 5, false, false),
+    define("ToDec", 5, false, false),
+    define("ToHex", 5, false, false),
+    define("ToOct", 5, false, false),
+    define("Trust", 5, false, false),
+];
+
+/// A command typed on the command line, e.g. `10,20d`
+pub struct Command<'a> {
+    /// The range of lines before the name, e.g. `10,20`, empty if none
+    pub range: &'a str,
+
+    /// The full name of the command, e.g. `delete`, or empty for a range
+    /// alone
+    pub name: &'static str,
+
+    /// Whether the name was followed by `!`
+    pub bang: bool,
+
+    /// The text after the name and any `!`, without leading whitespace
+    pub args: &'a str,
+}
+
+/// Split a command line into its range, name, `!` and arguments, expanding
+/// shortened names
+///
+/// # Args
+///
+/// - `input`: The text typed after `:`
+///
+/// # Returns
+///
+/// - The `Command`, or a description of what is wrong with it
+pub fn parse(input: &str) -> Result<Command<'_>, String> {
+    let (range, rest) = split_range(input.trim_start());
+    let rest = rest.trim_start();
+    // Names are letters, user commands may hold digits too
+    let user = rest.starts_with(|c: char| c.is_ascii_uppercase());
+    let end = rest
+        .find(|c: char| !(c.is_ascii_alphabetic() || (user && c.is_ascii_digit())))
+        .unwrap_or(rest.len());
+    let (typed, rest) = rest.split_at(end);
+    let (bang, args) = match rest.strip_prefix('!') {
+        Some(args) => (true, args),
+        None => (false, rest),
+    };
+
+    if typed.is_empty() {
+        if !rest.trim().is_empty() {
+            return Err(format!("Not an editor command: {}", input.trim()));
+        }
+        return Ok(Command {
+            range,
+            name: "",
+            bang: false,
+            args: "",
+        });
+    }
+
+    let definition = find(typed).ok_or_else(|| unknown(typed))?;
+    if !range.is_empty() && !definition.range {
+        return Err(format!("No range allowed: {}", definition.name));
+    }
+    if bang && !definition.bang {
+        return Err(format!("No ! allowed: {}", definition.name));
+    }
+    Ok(Command {
+        range,
+        name: definition.name,
+        bang,
+        args: args.trim_start(),
+    })
+}
+
+/// Split the range of lines from the front of a command, e.g. `2,$` from
+/// `2,$normal Ax`
+///
+/// # Returns
+///
+/// - The range, empty if there is none, and the rest of the command
+fn split_range(command: &str) -> (&str, &str) {
+    let end = command
+        .find(|c: char| !(c.is_ascii_digit() || ",.$%".contains(c)))
+        .unwrap_or(command.len());
+    command.split_at(end)
+}
+
+/// Find the command a name stands for, in full or shortened
+fn find(typed: &str) -> Option<&'static Definition> {
+    DEFINITIONS
+        .iter()
+        .find(|definition| definition.name.starts_with(typed) && typed.len() >= definition.shortest)
+}
+
+/// Describe an unknown command, suggesting the commands it could be the
+/// start of
+fn unknown(typed: &str) -> String {
+    let candidates: Vec<&str> = DEFINITIONS
+        .iter()
+        .filter(|definition| definition.name.starts_with(typed))
+        .map(|definition| definition.name)
+        .collect();
+    if candidates.is_empty() {
+        format!("Not an editor command: {}", typed)
+    } else {
+        format!(
+            "Not an editor command: {} (did you mean {}?)",
+            typed,
+            candidates.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_shortened_names() {
+        assert_eq!(parse("w").unwrap().name, "write");
+        assert_eq!(parse("bn").unwrap().name, "bnext");
+        assert_eq!(parse("noh").unwrap().name, "nohlsearch");
+        assert_eq!(parse("Explore").unwrap().name, "Explore");
+    }
+
+    #[test]
+    fn splits_range_bang_and_args() {
+        let command = parse(" 10,20d").unwrap();
+        assert_eq!(
+            (command.range, command.name, command.args),
+            ("10,20", "delete", "")
+        );
+
+        let command = parse("e! src/main.rs").unwrap();
+        assert_eq!((command.name, command.bang), ("edit", true));
+        assert_eq!(command.args, "src/main.rs");
+
+        let command = parse("%s/a/b/g").unwrap();
+        assert_eq!((command.range, command.name), ("%", "substitute"));
+        assert_eq!(command.args, "/a/b/g");
+    }
+
+    #[test]
+    fn range_alone() {
+        let command = parse("42").unwrap();
+        assert_eq!(
+            (command.range, command.name, command.bang),
+            ("42", "", false)
+        );
+    }
+
+    #[test]
+    fn user_commands_are_not_shortened() {
+        assert_eq!(parse("ToHex").unwrap().name, "ToHex");
+        assert!(parse("To").is_err());
+        assert!(parse("tohex").is_err());
+    }
+
+    #[test]
+    fn rejects_misplaced_range_and_bang() {
+        assert_eq!(parse("1,2w").err().unwrap(), "No range allowed: write");
+        assert_eq!(parse("set!").err().unwrap(), "No ! allowed: set");
+    }
+
+    #[test]
+    fn suggests_longer_names() {
+        assert_eq!(
+            parse("co").err().unwrap(),
+            "Not an editor command: co (did you mean colorscheme, copen?)"
+        );
+        assert_eq!(parse("zz").err().unwrap(), "Not an editor command: zz");
+        assert_eq!(
+            parse("1 + 1").err().unwrap(),
+            "Not an editor command: 1 + 1"
+        );
+    }
+}
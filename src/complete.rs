@@ -0,0 +1,179 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Find the start of the file path ending at the end of some text, e.g. the
+/// `src/ed` of `open("src/ed`
+///
+/// # Args
+///
+/// - `text`: The text before the cursor
+///
+/// # Returns
+///
+/// - The byte index where the path starts
+pub fn path_start(text: &str) -> usize {
+    text.rfind(|c: char| c.is_whitespace() || "\"'`()[]{}<>,;=".contains(c))
+        .map_or(0, |index| index + 1)
+}
+
+/// Find the end of the file path starting at the start of some text, e.g.
+/// the `rc/editor.rs` of `rc/editor.rs")`
+///
+/// # Args
+///
+/// - `text`: The text from the cursor on
+///
+/// # Returns
+///
+/// - The byte index where the path ends
+pub fn path_end(text: &str) -> usize {
+    text.find(|c: char| c.is_whitespace() || "\"'`()[]{}<>,;=".contains(c))
+        .unwrap_or(text.len())
+}
+
+/// List the files and directories a partial path can be completed to
+///
+/// # Args
+///
+/// - `partial`: The path typed so far, e.g. `src/ed`
+/// - `base`: The directory relative paths start from
+///
+/// # Returns
+///
+/// - The names of the entries completing the last part of the path, sorted,
+///   with a `/` after directories
+pub fn paths(partial: &str, base: &Path) -> Vec<String> {
+    let (directory, prefix) = match partial.rfind('/') {
+        Some(index) => partial.split_at(index + 1),
+        None => ("", partial),
+    };
+    let directory = if let Some(rest) = directory.strip_prefix("~/") {
+        env::var("HOME").map_or_else(
+            |_| PathBuf::from(directory),
+            |home| Path::new(&home).join(rest),
+        )
+    } else {
+        base.join(directory)
+    };
+
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let mut name = entry.file_name().to_str()?.to_string();
+            // Hidden entries are only offered once a `.` is typed
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            if entry.path().is_dir() {
+                name.push('/');
+            }
+            Some(name)
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Find the start of the word ending at the end of some text
+///
+/// # Args
+///
+/// - `text`: The text before the cursor
+///
+/// # Returns
+///
+/// - The byte index where the word starts
+pub fn word_start(text: &str) -> usize {
+    text.char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '\'')
+        .last()
+        .map_or(text.len(), |(index, _)| index)
+}
+
+/// The lines of a dictionary or thesaurus file, read the first time they are
+/// needed
+pub struct WordList {
+    /// The file the lines were read from
+    pub path: String,
+    lines: Vec<String>,
+}
+
+impl WordList {
+    /// Read a word list
+    ///
+    /// # Args
+    ///
+    /// - `path`: The file to read, e.g. `/usr/share/dict/words`
+    ///
+    /// # Returns
+    ///
+    /// - The `WordList` if successful, or a description of the problem
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path, err))?;
+        Ok(Self {
+            path: path.to_string(),
+            lines: contents.lines().map(String::from).collect(),
+        })
+    }
+
+    /// List the words of a dictionary, one word per line, starting with a
+    /// prefix
+    ///
+    /// # Args
+    ///
+    /// - `prefix`: The start of the word typed so far
+    /// - `max`: The number of words to list at most
+    ///
+    /// # Returns
+    ///
+    /// - The matching words in the order of the file
+    pub fn words(&self, prefix: &str, max: usize) -> Vec<String> {
+        self.lines
+            .iter()
+            .map(|line| line.trim())
+            .filter(|word| word.len() > prefix.len() && word.starts_with(prefix))
+            .take(max)
+            .map(String::from)
+            .collect()
+    }
+
+    /// List the synonyms of a word in a thesaurus, where each line holds
+    /// comma-separated words of similar meaning
+    ///
+    /// # Args
+    ///
+    /// - `word`: The word to replace, matched regardless of case
+    /// - `max`: The number of synonyms to list at most
+    ///
+    /// # Returns
+    ///
+    /// - The other words of every line holding the word, without duplicates
+    pub fn synonyms(&self, word: &str, max: usize) -> Vec<String> {
+        let mut synonyms: Vec<String> = Vec::new();
+        for line in &self.lines {
+            let entries: Vec<&str> = line.split(',').map(str::trim).collect();
+            if !entries.iter().any(|entry| entry.eq_ignore_ascii_case(word)) {
+                continue;
+            }
+            for entry in entries {
+                if synonyms.len() == max {
+                    return synonyms;
+                }
+                if !entry.is_empty()
+                    && !entry.eq_ignore_ascii_case(word)
+                    && !synonyms.iter().any(|synonym| synonym == entry)
+                {
+                    synonyms.push(entry.to_string());
+                }
+            }
+        }
+        synonyms
+    }
+}
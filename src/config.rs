@@ -0,0 +1,396 @@
+use crate::gutter;
+use crate::statusline::{self, Segment};
+use crate::version;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use toml::Value;
+
+/// The settings a project's config file may change before the project is
+/// trusted, which only change how text is shown and typed
+const SAFE_SETTINGS: [&str; 10] = [
+    "tab_size",
+    "expand_tab",
+    "shift_width",
+    "text_width",
+    "auto_indent",
+    "auto_pairs",
+    "wrap",
+    "hl_search",
+    "align_columns",
+    "match_words",
+];
+
+/// User settings read from `~/.donovimrc`, written in TOML
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The number of columns between the tab stops `tab` characters reach to
+    pub tab_size: usize,
+
+    /// Whether the `Tab` key inserts spaces rather than a `tab` character
+    pub expand_tab: bool,
+
+    /// The number of columns the `Tab` key indents by when it inserts spaces.
+    /// 0 to use `tab_size`
+    pub shift_width: usize,
+
+    /// Whether to reload the config file automatically when it changes
+    pub watch: bool,
+
+    /// The name of the color theme
+    pub theme: String,
+
+    /// Whether to keep the terminal's default background instead of drawing
+    /// the theme background
+    pub transparent_background: bool,
+
+    /// The columns drawn left of the text, in order
+    pub gutter: Vec<gutter::Component>,
+
+    /// The segments on the left of the status bar, in order
+    pub status_left: Vec<Segment>,
+
+    /// The segments on the right of the status bar, in order
+    pub status_right: Vec<Segment>,
+
+    /// The colors of the status bar segments that have their own, written
+    /// as `#rrggbb`, e.g. `mode = "#fabd2f"`
+    pub status_colors: BTreeMap<String, String>,
+
+    /// Whether to keep the line of the enclosing definition at the top of the
+    /// window once it has scrolled off
+    pub sticky_context: bool,
+
+    /// Whether to show long lines over several screen rows instead of
+    /// scrolling horizontally
+    pub wrap: bool,
+
+    /// Whether to highlight every match of the last search until `:noh`
+    pub hl_search: bool,
+
+    /// Whether letters in `/` searches and `:s` patterns match regardless of
+    /// their case
+    pub ignore_case: bool,
+
+    /// Whether a search holding an upper case letter matches case exactly,
+    /// despite `ignore_case`
+    pub smart_case: bool,
+
+    /// Whether to show the keys pressed last in the top right corner, for
+    /// screencasts and pairing
+    pub show_keys: bool,
+
+    /// Whether to draw for terminal screen readers: only redrawing what
+    /// changed, announcing mode changes on the message bar and marking with
+    /// text what is otherwise only shown by colors
+    pub screen_reader: bool,
+
+    /// Whether to show the columns of CSV and TSV files lined up, without
+    /// changing the file
+    pub align_columns: bool,
+
+    /// Whether new lines start with the indentation of the line they were
+    /// opened from
+    pub auto_indent: bool,
+
+    /// Whether typing `(`, `[`, `{` or a quote in Insert mode types its
+    /// closing character too, which is typed over when typed again and
+    /// deleted along with the opening one by `Backspace`
+    pub auto_pairs: bool,
+
+    /// The width lines are broken at while typing and reflowed to by `gq`.
+    /// 0 to only reflow, to 79 characters
+    pub text_width: usize,
+
+    /// The word list `Ctrl-X Ctrl-K` completes from, one word per line
+    pub dictionary: String,
+
+    /// The thesaurus `Ctrl-X Ctrl-T` offers synonyms from, each line holding
+    /// comma-separated words of similar meaning. Empty for none
+    pub thesaurus: String,
+
+    /// The milliseconds highlighting may take before a frame is drawn. Lines
+    /// above the screen left over are highlighted while waiting for keys. 0
+    /// for no limit
+    pub redraw_time: u64,
+
+    /// The milliseconds the cursor rests on a word before its other
+    /// occurrences on screen are highlighted. 0 to never highlight them
+    pub reference_delay: u64,
+
+    /// The seconds without a key pressed before the changed buffers are
+    /// copied to the recovery area, without touching their files. 0 to never
+    /// copy them
+    pub recovery_delay: u64,
+
+    /// The seconds without a key pressed before the changed buffers are
+    /// written to their files. 0 to only write them when asked
+    pub auto_save_delay: u64,
+
+    /// Whether to write the buffers with unsaved changes before a command runs
+    /// another program on the files or reads them, e.g. `:Gstatus` or `:grep`
+    pub auto_write: bool,
+
+    /// Whether to write the buffers before a command, by command, over
+    /// `auto_write`, e.g. `Symbols = false`
+    pub auto_write_commands: BTreeMap<String, bool>,
+
+    /// Normal mode keys standing for other keys, e.g. `"H" = "0"`
+    pub keys: BTreeMap<String, String>,
+
+    /// More groups of words `%` jumps between, by file type, written as in
+    /// Vim's matchit, e.g. `Shell = ["select:done"]`
+    pub match_words: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tab_size: 4,
+            expand_tab: true,
+            shift_width: 0,
+            watch: false,
+            theme: String::from("gruvbox"),
+            transparent_background: false,
+            gutter: Vec::new(),
+            status_left: vec![
+                Segment::Mode,
+                Segment::FileName,
+                Segment::Modified,
+                Segment::State,
+            ],
+            status_right: vec![
+                Segment::Context,
+                Segment::FileType,
+                Segment::Encoding,
+                Segment::FileFormat,
+                Segment::Position,
+                Segment::Percentage,
+            ],
+            status_colors: BTreeMap::new(),
+            sticky_context: false,
+            wrap: false,
+            hl_search: true,
+            ignore_case: false,
+            smart_case: false,
+            show_keys: false,
+            screen_reader: false,
+            align_columns: true,
+            auto_indent: true,
+            auto_pairs: false,
+            text_width: 0,
+            dictionary: String::from("/usr/share/dict/words"),
+            thesaurus: String::new(),
+            redraw_time: 50,
+            reference_delay: 500,
+            recovery_delay: 4,
+            auto_save_delay: 0,
+            auto_write: false,
+            auto_write_commands: BTreeMap::new(),
+            keys: BTreeMap::new(),
+            match_words: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Retrieve the location of the config file
+    ///
+    /// # Returns
+    ///
+    /// - `$DONOVIMRC` if set, otherwise `~/.donovimrc`
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("DONOVIMRC") {
+            return Some(PathBuf::from(path));
+        }
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".donovimrc"))
+    }
+
+    /// Read the config file, using the defaults when it does not exist
+    ///
+    /// # Returns
+    ///
+    /// - The `Config` if successful, or a description of the problem
+    pub fn load() -> Result<Self, String> {
+        let path = match Self::path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Self::default()),
+        };
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        let mut table: toml::value::Table = toml::from_str(&contents)
+            .map_err(|err| format!("Invalid {}: {}", path.display(), err))?;
+        apply_features(&mut table).map_err(|err| format!("Invalid {}: {}", path.display(), err))?;
+        let config: Self = Value::Table(table)
+            .try_into()
+            .map_err(|err| format!("Invalid {}: {}", path.display(), err))?;
+        config.check()?;
+        Ok(config)
+    }
+
+    /// Apply the settings of a project's config file over these ones. Until
+    /// the project is trusted, only the settings that change how text is
+    /// shown and typed are applied
+    ///
+    /// # Args
+    ///
+    /// - `path`: The config file of the project
+    /// - `contents`: The contents of the config file
+    /// - `trusted`: Whether the user trusts the project with these contents
+    ///
+    /// # Returns
+    ///
+    /// - The names of the settings left out, or a description of the problem
+    pub fn apply_project(
+        &mut self,
+        path: &Path,
+        contents: &str,
+        trusted: bool,
+    ) -> Result<Vec<String>, String> {
+        let mut project: toml::value::Table = toml::from_str(contents)
+            .map_err(|err| format!("Invalid {}: {}", path.display(), err))?;
+        apply_features(&mut project)
+            .map_err(|err| format!("Invalid {}: {}", path.display(), err))?;
+        let mut settings = match Value::try_from(&*self) {
+            Ok(Value::Table(settings)) => settings,
+            _ => return Err("Could not describe settings".to_string()),
+        };
+
+        let mut left_out = Vec::new();
+        for (key, value) in project {
+            if trusted || SAFE_SETTINGS.contains(&key.as_str()) {
+                settings.insert(key, value);
+            } else {
+                left_out.push(key);
+            }
+        }
+        let config: Self = Value::Table(settings)
+            .try_into()
+            .map_err(|err| format!("Invalid {}: {}", path.display(), err))?;
+        config.check()?;
+        *self = config;
+        Ok(left_out)
+    }
+
+    /// Check that the key overrides map single keys to single keys, and that
+    /// the status bar colors are colors
+    ///
+    /// # Returns
+    ///
+    /// - Unit or a description of the first invalid setting
+    fn check(&self) -> Result<(), String> {
+        let single = |key: &String| key.chars().count() == 1;
+        if let Some((key, target)) = self
+            .keys
+            .iter()
+            .find(|(key, target)| !single(key) || !single(target))
+        {
+            return Err(format!(
+                "Invalid key override {:?} = {:?}, keys must be single characters",
+                key, target
+            ));
+        }
+        statusline::parse_colors(&self.status_colors).map(|_| ())
+    }
+
+    /// Retrieve the time the config file was last modified
+    pub fn modified() -> Option<SystemTime> {
+        Self::path()
+            .and_then(|path| fs::metadata(path).ok())
+            .and_then(|metadata| metadata.modified().ok())
+    }
+
+    /// Apply the key overrides to a key pressed in Normal mode
+    ///
+    /// # Args
+    ///
+    /// - `key`: The key pressed
+    ///
+    /// # Returns
+    ///
+    /// - The key it stands for, or the key itself when it is not overridden
+    pub fn normal_key(&self, key: char) -> char {
+        match self.keys.get(&key.to_string()) {
+            Some(target) => target.chars().next().unwrap_or(key),
+            None => key,
+        }
+    }
+
+    /// Describe every setting as it would be written in the config file
+    ///
+    /// # Returns
+    ///
+    /// - One `key = value` line per setting, or a description of the problem
+    pub fn settings(&self) -> Result<String, String> {
+        toml::to_string(self).map_err(|err| format!("Could not describe settings: {}", err))
+    }
+
+    /// Store a top-level setting in the config file, replacing any previous
+    /// value while leaving the rest of the file untouched
+    ///
+    /// # Args
+    ///
+    /// - `key`: The name of the setting
+    /// - `value`: The value of the setting, as a TOML literal
+    ///
+    /// # Returns
+    ///
+    /// - Unit or a description of the problem
+    pub fn persist(key: &str, value: &str) -> Result<(), String> {
+        let path = Self::path().ok_or_else(|| "No config file location".to_string())?;
+        let contents = fs::read_to_string(&path).unwrap_or_default();
+        let setting = format!("{} = {}", key, value);
+        let mut lines: Vec<String> = contents.lines().map(String::from).collect();
+
+        // Top-level settings must come before the first table
+        let tables_start = lines
+            .iter()
+            .position(|line| line.trim_start().starts_with('['))
+            .unwrap_or(lines.len());
+        if let Some(line) = lines[..tables_start]
+            .iter_mut()
+            .find(|line| line.split('=').next().map(str::trim) == Some(key))
+        {
+            *line = setting;
+        } else {
+            lines.insert(0, setting);
+        }
+
+        fs::write(&path, lines.join("\n") + "\n")
+            .map_err(|err| format!("Could not write {}: {}", path.display(), err))
+    }
+}
+
+/// Apply the `[has.<feature>]` tables of a config file over its other
+/// settings, for the features this build has, e.g. `[has.git]` followed by
+/// `gutter = ["git", "numbers"]`. Tables of other features are left out
+///
+/// # Args
+///
+/// - `table`: The settings of the config file
+///
+/// # Returns
+///
+/// - Unit or a description of the problem
+fn apply_features(table: &mut toml::value::Table) -> Result<(), String> {
+    let features = match table.remove("has") {
+        Some(Value::Table(features)) => features,
+        Some(_) => return Err("has must be a table of feature tables".to_string()),
+        None => return Ok(()),
+    };
+    for (feature, settings) in features {
+        let settings = match settings {
+            Value::Table(settings) => settings,
+            _ => return Err(format!("has.{} must be a table of settings", feature)),
+        };
+        if version::has(&feature) {
+            table.extend(settings);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+const OURS_MARKER: &str = "<<<<<<<";
+const BASE_MARKER: &str = "|||||||";
+const SEPARATOR_MARKER: &str = "=======";
+const THEIRS_MARKER: &str = ">>>>>>>";
+
+/// The part of a merge conflict a line belongs to
+#[derive(PartialEq, Clone, Copy)]
+pub enum Section {
+    /// One of the `<<<<<<<`, `|||||||`, `=======` or `>>>>>>>` lines
+    Marker,
+    Ours,
+    Base,
+    Theirs,
+}
+
+/// Line numbers of the markers making up a single merge conflict
+pub struct Conflict {
+    pub start: usize,
+    pub base: Option<usize>,
+    pub separator: usize,
+    pub end: usize,
+}
+
+impl Conflict {
+    /// Checks if a line is part of the conflict, markers included
+    pub fn contains(&self, line: usize) -> bool {
+        line >= self.start && line <= self.end
+    }
+
+    /// Retrieve the section of the conflict a line belongs to
+    ///
+    /// # Args
+    ///
+    /// - `line`: The line number in the document
+    ///
+    /// # Returns
+    ///
+    /// - The section, or `None` if the line is outside of the conflict
+    pub fn section(&self, line: usize) -> Option<Section> {
+        if !self.contains(line) {
+            return None;
+        }
+
+        if line == self.start || line == self.separator || line == self.end {
+            return Some(Section::Marker);
+        }
+
+        match self.base {
+            Some(base) if line == base => Some(Section::Marker),
+            Some(base) if line > base && line < self.separator => Some(Section::Base),
+            _ if line < self.separator => Some(Section::Ours),
+            _ => Some(Section::Theirs),
+        }
+    }
+
+    /// Retrieve the range of lines holding our side of the conflict
+    pub fn ours(&self) -> std::ops::Range<usize> {
+        self.start + 1..self.base.unwrap_or(self.separator)
+    }
+
+    /// Retrieve the range of lines holding their side of the conflict
+    pub fn theirs(&self) -> std::ops::Range<usize> {
+        self.separator + 1..self.end
+    }
+}
+
+/// Find every complete set of git conflict markers in the given lines
+///
+/// # Args
+///
+/// - `lines`: The lines of a document
+///
+/// # Returns
+///
+/// - The conflicts, ordered by position
+pub fn find_conflicts(lines: &[&str]) -> Vec<Conflict> {
+    let mut conflicts: Vec<Conflict> = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut base: Option<usize> = None;
+    let mut separator: Option<usize> = None;
+
+    for (index, line) in lines.iter().enumerate() {
+        if line.starts_with(OURS_MARKER) {
+            start = Some(index);
+            base = None;
+            separator = None;
+        } else if line.starts_with(BASE_MARKER) && start.is_some() && separator.is_none() {
+            base = Some(index);
+        } else if line.starts_with(SEPARATOR_MARKER) && start.is_some() && separator.is_none() {
+            separator = Some(index);
+        } else if line.starts_with(THEIRS_MARKER) {
+            if let (Some(start), Some(separator)) = (start, separator) {
+                conflicts.push(Conflict {
+                    start,
+                    base,
+                    separator,
+                    end: index,
+                });
+            }
+            start = None;
+            base = None;
+            separator = None;
+        }
+    }
+
+    conflicts
+}
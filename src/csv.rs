@@ -0,0 +1,252 @@
+use crate::theme::Color;
+use crate::Theme;
+use std::fmt::Write;
+use std::ops::Range;
+use termion::color;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Split a line of a CSV or TSV file into cells. Separators between double
+/// quotes belong to the cell
+///
+/// # Args
+///
+/// - `line`: The line
+/// - `separator`: The character between cells, e.g. `,`
+///
+/// # Returns
+///
+/// - The characters of each cell, without the separators
+pub fn cells(line: &str, separator: char) -> Vec<Range<usize>> {
+    let mut cells = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut len = 0;
+    for (index, grapheme) in line.graphemes(true).enumerate() {
+        len = index + 1;
+        if grapheme == "\"" {
+            quoted = !quoted;
+        } else if !quoted && grapheme.starts_with(separator) {
+            cells.push(start..index);
+            start = index + 1;
+        }
+    }
+    cells.push(start..len.max(start));
+    cells
+}
+
+/// Find the cell holding a character of a line
+///
+/// # Args
+///
+/// - `line`: The line
+/// - `separator`: The character between cells
+/// - `x`: The index of the character, separators belonging to the cell
+///   before them
+///
+/// # Returns
+///
+/// - The index of the cell
+pub fn cell_at(line: &str, separator: char, x: usize) -> usize {
+    let cells = cells(line, separator);
+    cells
+        .iter()
+        .position(|cell| x <= cell.end)
+        .unwrap_or(cells.len() - 1)
+}
+
+/// The widths of the columns of the lines on screen, so they can be shown
+/// lined up without changing the file
+pub struct Layout {
+    separator: char,
+    widths: Vec<usize>,
+}
+
+impl Layout {
+    /// Measure the columns of some lines
+    ///
+    /// # Args
+    ///
+    /// - `lines`: The lines to line up
+    /// - `separator`: The character between cells
+    pub fn new<'a>(lines: impl Iterator<Item = &'a str>, separator: char) -> Self {
+        let mut widths: Vec<usize> = Vec::new();
+        for line in lines {
+            let graphemes: Vec<&str> = line.graphemes(true).collect();
+            for (column, cell) in cells(line, separator).into_iter().enumerate() {
+                if column == widths.len() {
+                    widths.push(0);
+                }
+                widths[column] = std::cmp::max(widths[column], display_width(&graphemes[cell]));
+            }
+        }
+        Self { separator, widths }
+    }
+
+    /// Find the screen column a character of a line is shown at
+    ///
+    /// # Args
+    ///
+    /// - `line`: The line
+    /// - `x`: The index of the character
+    ///
+    /// # Returns
+    ///
+    /// - The column, counted from the start of the line
+    pub fn column(&self, line: &str, x: usize) -> usize {
+        let graphemes: Vec<&str> = line.graphemes(true).collect();
+        let cells = cells(line, self.separator);
+        let column = cell_at(line, self.separator, x);
+        let cell = &cells[column];
+        let start = self.start(column);
+        if x == cell.end && column + 1 < cells.len() {
+            // The separator is shown after the padding of the cell
+            start + self.width(column)
+        } else {
+            // Past the end of the line, every character takes one column
+            let end = std::cmp::min(x, cell.end);
+            start + display_width(&graphemes[cell.start..end]) + (x - end)
+        }
+    }
+
+    /// Render a line with its cells padded to the widths of their columns,
+    /// coloring the columns alternately
+    ///
+    /// # Args
+    ///
+    /// - `line`: The line
+    /// - `start`: The first screen column to show
+    /// - `width`: The number of screen columns to show
+    /// - `theme`: The colors to use
+    pub fn render(&self, line: &str, start: usize, width: usize, theme: &Theme) -> String {
+        let graphemes: Vec<&str> = line.graphemes(true).collect();
+        let cells = cells(line, self.separator);
+        let mut shown: Vec<(&str, Color)> = Vec::new();
+        for (column, cell) in cells.iter().enumerate() {
+            let color = if column % 2 == 0 {
+                theme.foreground
+            } else {
+                theme.secondary_keywords
+            };
+            for &grapheme in &graphemes[cell.clone()] {
+                shown.push((if grapheme == "\t" { " " } else { grapheme }, color));
+            }
+            if column + 1 < cells.len() {
+                let padding = self
+                    .width(column)
+                    .saturating_sub(display_width(&graphemes[cell.clone()]));
+                shown.extend(std::iter::repeat_n((" ", color), padding));
+                let separator = graphemes[cell.end];
+                let separator = if separator == "\t" { " " } else { separator };
+                shown.push((separator, theme.comment));
+                shown.push((" ", theme.comment));
+            }
+        }
+
+        let mut result = String::new();
+        let mut current = None;
+        let mut at = 0;
+        for (grapheme, color) in shown {
+            let columns = display_width(&[grapheme]);
+            let end = at + columns;
+            // Wide characters cut by the edges are shown as spaces
+            let shown = if at >= start && end <= start + width {
+                grapheme.to_string()
+            } else {
+                " ".repeat(
+                    std::cmp::min(end, start + width).saturating_sub(std::cmp::max(at, start)),
+                )
+            };
+            at = end;
+            if shown.is_empty() {
+                continue;
+            }
+            if current != Some(color) {
+                current = Some(color);
+                write!(result, "{}", color::Fg(color)).ok();
+            }
+            result.push_str(&shown);
+        }
+        write!(result, "{}", color::Fg(color::Reset)).ok();
+        result
+    }
+
+    /// The width of a column, that of its widest cell
+    fn width(&self, column: usize) -> usize {
+        self.widths.get(column).copied().unwrap_or(0)
+    }
+
+    /// The screen column a cell starts at, after the separator and space
+    /// following every cell before it
+    fn start(&self, column: usize) -> usize {
+        (0..column).map(|column| self.width(column) + 2).sum()
+    }
+}
+
+/// Measure the screen columns taken by graphemes, a tab being shown as a
+/// single space
+fn display_width(graphemes: &[&str]) -> usize {
+    graphemes
+        .iter()
+        .map(|&grapheme| {
+            if grapheme == "\t" {
+                1
+            } else {
+                grapheme.width()
+            }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Render a line without its colors
+    fn plain(layout: &Layout, line: &str, start: usize, width: usize) -> String {
+        let rendered = layout.render(line, start, width, &Theme::default());
+        let mut text = String::new();
+        let mut chars = rendered.chars();
+        while let Some(c) = chars.next() {
+            if c == '\u{1b}' {
+                chars.by_ref().find(|c| c.is_ascii_alphabetic());
+            } else {
+                text.push(c);
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn splits_cells_outside_quotes() {
+        assert_eq!(cells("a,\"b,c\",d", ','), vec![0..1, 2..7, 8..9]);
+        assert_eq!(cells("a,", ','), vec![0..1, 2..2]);
+        assert_eq!(cells("", ','), vec![0..0]);
+    }
+
+    #[test]
+    fn finds_the_cell_of_a_character() {
+        assert_eq!(cell_at("ab,cd", ',', 0), 0);
+        assert_eq!(cell_at("ab,cd", ',', 2), 0);
+        assert_eq!(cell_at("ab,cd", ',', 3), 1);
+        assert_eq!(cell_at("ab,cd", ',', 9), 1);
+    }
+
+    #[test]
+    fn lines_up_wide_characters() {
+        let lines = ["名前,x", "ab,y"];
+        let layout = Layout::new(lines.into_iter(), ',');
+        assert_eq!(plain(&layout, "名前,x", 0, 80), "名前, x");
+        assert_eq!(plain(&layout, "ab,y", 0, 80), "ab  , y");
+        assert_eq!(layout.column("名前,x", 1), 2);
+        assert_eq!(layout.column("名前,x", 3), 6);
+        assert_eq!(layout.column("ab,y", 2), 4);
+    }
+
+    #[test]
+    fn blanks_wide_characters_cut_by_the_edges() {
+        let layout = Layout::new(["名前,x"].into_iter(), ',');
+        assert_eq!(plain(&layout, "名前,x", 1, 4), " 前,");
+        assert_eq!(plain(&layout, "名前,x", 0, 3), "名 ");
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{Document, Position};
+
+/// The number of lines `diff` inserts or deletes at most to match two texts.
+/// Texts differing by more are shown as a single hunk
+const MAX_EDITS: usize = 2000;
+
+/// A contiguous block of lines that differ between the left and right side
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hunk {
+    pub left_start: usize,
+    pub left_len: usize,
+    pub right_start: usize,
+    pub right_len: usize,
+}
+
+impl Hunk {
+    /// Retrieve the first line of the hunk on the requested side
+    pub fn start(&self, left: bool) -> usize {
+        if left {
+            self.left_start
+        } else {
+            self.right_start
+        }
+    }
+
+    /// Retrieve the number of lines the hunk covers on the requested side
+    pub fn len(&self, left: bool) -> usize {
+        if left {
+            self.left_len
+        } else {
+            self.right_len
+        }
+    }
+
+    /// Checks if the hunk covers the given line on the requested side. Hunks
+    /// that are empty on that side cover the line they would be inserted at
+    pub fn contains(&self, line: usize, left: bool) -> bool {
+        let start = self.start(left);
+        let len = self.len(left);
+        if len == 0 {
+            return line == start;
+        }
+        line >= start && line < start + len
+    }
+}
+
+/// A single screen line of a side-by-side diff. A `None` side is a filler line
+/// inserted to keep both sides aligned
+pub struct DisplayLine {
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub changed: bool,
+}
+
+/// Holds the state of the second file shown while in diff mode
+pub struct DiffView {
+    /// The document that is not currently focused
+    pub other: Document,
+
+    /// The cursor position in the unfocused document
+    pub other_cursor: Position,
+
+    /// The first aligned line shown on screen, shared by both sides
+    pub top: usize,
+
+    /// Whether the focused document is drawn on the left side
+    pub focus_left: bool,
+
+    /// The hunks between the left and right documents
+    pub hunks: Vec<Hunk>,
+
+    /// The revisions of the focused and the other document the hunks were
+    /// found for, along with which side was focused
+    compared: Option<(usize, usize, bool)>,
+}
+
+impl DiffView {
+    pub fn new(other: Document) -> Self {
+        Self {
+            other,
+            other_cursor: Position::default(),
+            top: 0,
+            focus_left: true,
+            hunks: Vec::new(),
+            compared: None,
+        }
+    }
+
+    /// Recompute the hunks between the focused document and the other one,
+    /// when either changed since they were last found
+    ///
+    /// # Args
+    ///
+    /// - `focused`: The document receiving input
+    pub fn update(&mut self, focused: &Document) {
+        let compared = (focused.revision(), self.other.revision(), self.focus_left);
+        if self.compared == Some(compared) {
+            return;
+        }
+        self.compared = Some(compared);
+        let focused_lines = focused.lines();
+        let other_lines = self.other.lines();
+        self.hunks = if self.focus_left {
+            diff(&focused_lines, &other_lines)
+        } else {
+            diff(&other_lines, &focused_lines)
+        };
+    }
+
+    /// Find the hunk covering a line of the focused document
+    ///
+    /// # Args
+    ///
+    /// - `line`: The line in the focused document
+    ///
+    /// # Returns
+    ///
+    /// - The hunk under the line if there is one
+    pub fn hunk_at(&self, line: usize) -> Option<&Hunk> {
+        self.hunks
+            .iter()
+            .find(|hunk| hunk.contains(line, self.focus_left))
+    }
+}
+
+/// Compute the hunks needed to turn `left` into `right`
+///
+/// # Args
+///
+/// - `left`: The lines of the original text
+/// - `right`: The lines of the new text
+///
+/// # Returns
+///
+/// - The list of hunks, ordered by position
+pub fn diff(left: &[&str], right: &[&str]) -> Vec<Hunk> {
+    let prefix = left
+        .iter()
+        .zip(right.iter())
+        .take_while(|(l, r)| l == r)
+        .count();
+    let suffix = left[prefix..]
+        .iter()
+        .rev()
+        .zip(right[prefix..].iter().rev())
+        .take_while(|(l, r)| l == r)
+        .count();
+    let left_mid = &left[prefix..left.len() - suffix];
+    let right_mid = &right[prefix..right.len() - suffix];
+
+    // Texts too different to compare line by line differ as a whole
+    let matches = common_lines(left_mid, right_mid).unwrap_or_default();
+
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let end = (left_mid.len(), right_mid.len());
+    for (x, y) in matches.into_iter().chain(std::iter::once(end)) {
+        if x > i || y > j {
+            hunks.push(Hunk {
+                left_start: prefix + i,
+                left_len: x - i,
+                right_start: prefix + j,
+                right_len: y - j,
+            });
+        }
+        i = x + 1;
+        j = y + 1;
+    }
+
+    hunks
+}
+
+/// Find the lines both texts have in common, with Myers' algorithm, which
+/// takes time and memory growing with the number of lines that differ
+/// rather than with the size of the texts
+///
+/// # Args
+///
+/// - `left`: The lines of the original text
+/// - `right`: The lines of the new text
+///
+/// # Returns
+///
+/// - The pairs of indices of equal lines, in order, or `None` when more than
+///   `MAX_EDITS` lines would have to be inserted or deleted
+fn common_lines(left: &[&str], right: &[&str]) -> Option<Vec<(usize, usize)>> {
+    let (n, m) = (left.len() as isize, right.len() as isize);
+    let max = std::cmp::min(left.len() + right.len(), MAX_EDITS) as isize;
+    // v[k + max] holds the furthest line of `left` reached on diagonal k,
+    // where k is the line of `left` minus the line of `right`
+    let mut v: Vec<isize> = vec![0; 2 * max as usize + 2];
+    let index = |k: isize| (k + max) as usize;
+    // The part of `v` each round started from, to walk back the path found
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=max {
+        trace.push(v[index(-d)..=index(d)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && left[x as usize] == right[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, left, right));
+            }
+        }
+    }
+    None
+}
+
+/// Walk back the path found by `common_lines`, collecting the equal lines on
+/// its way
+///
+/// # Args
+///
+/// - `trace`: The furthest lines reached on each diagonal at the start of
+///   each round, round d covering the diagonals from -d to d
+/// - `left`: The lines of the original text
+/// - `right`: The lines of the new text
+fn backtrack(trace: &[Vec<isize>], left: &[&str], right: &[&str]) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (left.len() as isize, right.len() as isize);
+    for (d, v) in trace.iter().enumerate().skip(1).rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let previous = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = at(previous);
+        let previous_y = previous_x - previous;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        matches.push((x as usize, y as usize));
+    }
+    matches.reverse();
+    matches
+}
+
+/// Build the aligned list of screen lines for a side-by-side view
+///
+/// # Args
+///
+/// - `hunks`: The hunks between both sides
+/// - `left_len`: The number of lines on the left side
+/// - `right_len`: The number of lines on the right side
+///
+/// # Returns
+///
+/// - One entry per screen line
+pub fn align(hunks: &[Hunk], left_len: usize, right_len: usize) -> Vec<DisplayLine> {
+    let mut lines: Vec<DisplayLine> = Vec::new();
+    let (mut left, mut right) = (0, 0);
+
+    for hunk in hunks {
+        while left < hunk.left_start {
+            lines.push(DisplayLine {
+                left: Some(left),
+                right: Some(right),
+                changed: false,
+            });
+            left += 1;
+            right += 1;
+        }
+        for index in 0..std::cmp::max(hunk.left_len, hunk.right_len) {
+            lines.push(DisplayLine {
+                left: (index < hunk.left_len).then(|| hunk.left_start + index),
+                right: (index < hunk.right_len).then(|| hunk.right_start + index),
+                changed: true,
+            });
+        }
+        left = hunk.left_start + hunk.left_len;
+        right = hunk.right_start + hunk.right_len;
+    }
+
+    while left < left_len || right < right_len {
+        lines.push(DisplayLine {
+            left: (left < left_len).then_some(left),
+            right: (right < right_len).then_some(right),
+            changed: left >= left_len || right >= right_len,
+        });
+        left += 1;
+        right += 1;
+    }
+
+    lines
+}
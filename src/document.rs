@@ -1,17 +1,299 @@
+use crate::conflict::{self, Conflict};
+use crate::diff::{self, Hunk};
+use crate::encoding::Encoding;
+use crate::filetype::{Definition, PairOptions};
+use crate::history::{Change, UndoStack};
+use crate::lines::Lines;
+use crate::recovery;
+use crate::search::Pattern;
 use crate::{FileType, Position, Row};
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::{Error, Write};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The byte order mark some editors put at the start of UTF-8 files
+const BOM: &str = "\u{feff}";
+
+/// Files of this many bytes or more are read in the background
+const BACKGROUND_LOAD_SIZE: u64 = 1 << 20;
+
+/// The number of lines read in the background before they are handed over
+const LOAD_CHUNK_LINES: usize = 10_000;
+
+/// How often the file is checked for changes by other programs
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Lines read by a background thread along with the number of bytes they held
+/// and the encoding they were decoded from
+type Chunk = Result<(Vec<String>, usize, Encoding), Error>;
+
+/// Retrieve the modification time of a file, if it can be read
+fn modified(file_name: &str) -> Option<SystemTime> {
+    fs::metadata(file_name)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// How the lines of a file end
+#[derive(Clone, Copy, Default, PartialEq)]
+enum LineEnding {
+    /// `\n`, as on Unix
+    #[default]
+    Lf,
+
+    /// `\r\n`, as on Windows
+    Crlf,
+}
+
+impl LineEnding {
+    /// Find how the lines of some text end, from its first line. Text without
+    /// any line ending is taken to use `\n`
+    fn detect(text: &[u8]) -> Self {
+        match text.iter().position(|&byte| byte == b'\n') {
+            Some(index) if index > 0 && text[index - 1] == b'\r' => Self::Crlf,
+            _ => Self::Lf,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+        }
+    }
+}
+
+/// The last revision given to a document
+static LAST_REVISION: AtomicUsize = AtomicUsize::new(0);
+
+/// Tells versions of documents apart: every document starts with a revision
+/// of its own and takes a new one with every change, so what was found for
+/// one version is not taken for another
+#[derive(Clone, Copy, PartialEq)]
+struct Revision(usize);
+
+impl Default for Revision {
+    fn default() -> Self {
+        Self(LAST_REVISION.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+}
+
+/// How the text of a file is stored, kept when saving
+#[derive(Clone, Copy)]
+struct Format {
+    encoding: Encoding,
+    bom: bool,
+    line_ending: LineEnding,
+    missing_final_newline: bool,
+}
+
+impl Format {
+    /// Write lines to a file, reporting the lines written
+    ///
+    /// # Args
+    ///
+    /// - `file_name`: The file to write to
+    /// - `lines`: Gives the lines of text, once for each pass over them
+    /// - `len`: The number of lines
+    /// - `progress`: Called with the number of lines written and the total
+    ///
+    /// # Returns
+    ///
+    /// - Unit or any Error encountered writing the file
+    fn write<'a, L, I, F>(
+        &self,
+        file_name: &str,
+        lines: L,
+        len: usize,
+        mut progress: F,
+    ) -> Result<(), Error>
+    where
+        L: Fn() -> I,
+        I: Iterator<Item = &'a str>,
+        F: FnMut(usize, usize),
+    {
+        // The file is left as it is when the text does not fit its encoding
+        if self.encoding == Encoding::Latin1 {
+            for line in lines() {
+                self.encoding.encode(line)?;
+            }
+        }
+
+        let mut file = fs::File::create(file_name)?;
+        if self.bom {
+            file.write_all(&self.encoding.encode(BOM)?)?;
+        }
+        let line_ending = self.encoding.encode(self.line_ending.as_str())?;
+        for (index, line) in lines().enumerate() {
+            file.write_all(&self.encoding.encode(line)?)?;
+            if index + 1 < len || !self.missing_final_newline {
+                file.write_all(&line_ending)?;
+            }
+            progress(index + 1, len);
+        }
+        Ok(())
+    }
+}
+
+/// A copy of the text of a document and of how it is stored, written to the
+/// file in the background while the document itself may change
+pub struct Contents {
+    lines: Vec<String>,
+    format: Format,
+}
+
+impl Contents {
+    /// Write the text to a file, reporting the lines written
+    ///
+    /// # Args
+    ///
+    /// - `file_name`: The file to write to
+    /// - `progress`: Called with the number of lines written and the total
+    ///
+    /// # Returns
+    ///
+    /// - Unit or any Error encountered writing the file
+    pub fn write<F>(&self, file_name: &str, progress: F) -> Result<(), Error>
+    where
+        F: FnMut(usize, usize),
+    {
+        let lines = || self.lines.iter().map(String::as_str);
+        self.format
+            .write(file_name, lines, self.lines.len(), progress)
+    }
+}
+
+/// Receives the lines of a file read in the background
+struct Loader {
+    chunks: Receiver<Chunk>,
+    loaded: usize,
+    total: usize,
+
+    /// Whether the document stays read-only once the whole file is read
+    read_only: bool,
+}
 
 #[derive(Default)]
 pub struct Document {
-    rows: Vec<Row>,
+    rows: Lines,
     pub file_name: Option<String>,
     dirty: bool,
     file_type: FileType,
+    history: UndoStack,
+    bom: bool,
+    read_only: bool,
+    loader: Option<Loader>,
+
+    /// How the text of the file is stored, kept when saving
+    encoding: Encoding,
+
+    /// How the lines of the file end, kept when saving
+    line_ending: LineEnding,
+
+    /// Whether the last line of the file had no line ending, kept when saving
+    missing_final_newline: bool,
+
+    /// The modification time of the file when it was last read or written
+    modified: Option<SystemTime>,
+
+    /// When the file was last checked for changes by other programs, and
+    /// whether it had changed
+    disk_check: Option<(Instant, bool)>,
+
+    /// Rows from this index on are never highlighted, so edits only need to
+    /// reset the rows before it
+    highlighted_until: usize,
+
+    /// The number of rows when `highlighted_until` was last updated, telling
+    /// how far an edit moved the highlighted rows
+    highlighted_len: usize,
+
+    /// The occurrences of the word under the cursor, highlighted once the
+    /// cursor rests on it
+    reference: Option<Pattern>,
+
+    /// The bracket under the cursor and the one it pairs with, highlighted
+    /// while both are on screen
+    match_paren: Vec<Position>,
+
+    /// The number of edits when the document was last copied to the
+    /// recovery area
+    recovered_edits: usize,
+
+    /// The positions set with `m`, by name. They move with the lines they
+    /// are on
+    marks: BTreeMap<char, Position>,
+
+    /// The version of the text, new after every change
+    revision: Revision,
+
+    /// The merge conflicts in the text, along with the revision they were
+    /// found in
+    conflicts: Option<(Revision, Vec<Conflict>)>,
+
+    /// The lines changed since the version staged in git, along with the
+    /// revision they were found in
+    staged_hunks: Option<(Revision, Vec<Hunk>)>,
 }
 
 impl Document {
-    /// Open a file and store the contents in the `rows` vector
+    /// Open a file and store the contents in its rows
+    ///
+    /// # Args
+    ///
+    /// - `filename`: The plain name of the file to open
+    ///
+    /// # Returns
+    ///
+    /// - The `Document` if successful
+    pub fn open(filename: &str) -> Result<Self, std::io::Error> {
+        let bytes = fs::read(filename)?;
+        Ok(Self {
+            file_name: Some(filename.to_string()),
+            file_type: FileType::from(filename),
+            modified: modified(filename),
+            ..Self::from_bytes(&bytes)
+        })
+    }
+
+    /// Build a `Document` that is not backed by a file from the bytes of a
+    /// text, such as the one read from stdin, decoded like a file's. The
+    /// encoding, byte order mark and line endings are kept for saving it
+    ///
+    /// # Args
+    ///
+    /// - `bytes`: The contents of the document
+    ///
+    /// # Returns
+    ///
+    /// - The unnamed `Document`
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let encoding = Encoding::detect(bytes);
+        let contents = encoding.decode(bytes);
+        let (bom, contents) = match contents.strip_prefix(BOM) {
+            Some(contents) => (true, contents),
+            None => (false, &contents[..]),
+        };
+
+        Self {
+            rows: contents.lines().map(Row::from).collect(),
+            bom,
+            encoding,
+            line_ending: LineEnding::detect(contents.as_bytes()),
+            missing_final_newline: !contents.is_empty() && !contents.ends_with('\n'),
+            ..Self::default()
+        }
+    }
+
+    /// Open a file like `open`, reading large files in the background. Such
+    /// documents fill up as `receive_rows` is called, and are read-only until
+    /// the whole file is read
     ///
     /// # Args
     ///
@@ -19,118 +301,766 @@ impl Document {
     ///
     /// # Returns
     ///
-    /// - The `Document` if successful
-    pub fn open(filename: &str) -> Result<Self, std::io::Error> {
-        let contents = fs::read_to_string(filename)?;
-        let file_type = FileType::from(filename);
-        let rows = contents.lines().map(Row::from).collect();
+    /// - The `Document` if the file could be opened
+    pub fn load(filename: &str) -> Result<Self, Error> {
+        let file = fs::File::open(filename)?;
+        let total = file.metadata()?.len();
+        if total < BACKGROUND_LOAD_SIZE {
+            return Self::open(filename);
+        }
+        let (encoding, line_ending, final_newline) = peek_line_endings(&file)?;
+        // UTF-16 can not be split into lines at `\n` bytes
+        if matches!(encoding, Encoding::Utf16Le | Encoding::Utf16Be) {
+            return Self::open(filename);
+        }
+
+        let (sender, chunks) = mpsc::channel();
+        thread::spawn(move || read_chunks(file, &sender));
+        Ok(Self {
+            file_name: Some(filename.to_string()),
+            file_type: FileType::from(filename),
+            read_only: true,
+            loader: Some(Loader {
+                chunks,
+                loaded: 0,
+                total: total as usize,
+                read_only: false,
+            }),
+            line_ending,
+            missing_final_newline: !final_newline,
+            modified: modified(filename),
+            ..Self::default()
+        })
+    }
+
+    /// Add the lines read in the background since the last call
+    ///
+    /// # Returns
+    ///
+    /// - Whether the document changed, or the error that stopped the reading
+    pub fn receive_rows(&mut self) -> Result<bool, Error> {
+        let loader = match self.loader.as_mut() {
+            Some(loader) => loader,
+            None => return Ok(false),
+        };
+
+        let mut received = false;
+        loop {
+            match loader.chunks.try_recv() {
+                Ok(Ok((lines, bytes, encoding))) => {
+                    let first = loader.loaded == 0;
+                    loader.loaded += bytes;
+                    if encoding != self.encoding {
+                        // The rows read so far were valid UTF-8, their bytes
+                        // are decoded again like the rest of the file
+                        let bom = if self.bom { BOM } else { "" };
+                        let rows: Vec<Row> = self
+                            .rows
+                            .iter()
+                            .enumerate()
+                            .map(|(y, row)| {
+                                let text = if y == 0 {
+                                    format!("{}{}", bom, row.as_str())
+                                } else {
+                                    row.as_str().to_string()
+                                };
+                                Row::from(&encoding.decode(text.as_bytes())[..])
+                            })
+                            .collect();
+                        self.rows = rows.into_iter().collect();
+                        self.encoding = encoding;
+                        self.bom = false;
+                    }
+                    let mut rows: Vec<Row> =
+                        lines.iter().map(|line| Row::from(&line[..])).collect();
+                    if let (true, Some(row)) = (first, rows.first_mut()) {
+                        if let Some(rest) = row.as_str().strip_prefix(BOM) {
+                            *row = Row::from(rest);
+                            self.bom = true;
+                        }
+                    }
+                    self.rows.extend(rows);
+                    self.highlighted_len = self.rows.len();
+                    self.revision = Revision::default();
+                    received = true;
+                }
+                Ok(Err(err)) => {
+                    // The document stays read-only, saving it would cut the file short
+                    self.loader = None;
+                    return Err(err);
+                }
+                Err(TryRecvError::Empty) => return Ok(received),
+                Err(TryRecvError::Disconnected) => {
+                    self.read_only = loader.read_only;
+                    self.loader = None;
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    /// Check how much of a file read in the background has been read
+    ///
+    /// # Returns
+    ///
+    /// - The percentage read, if the file is still being read
+    pub fn loading_progress(&self) -> Option<usize> {
+        let loader = self.loader.as_ref()?;
+        Some(loader.loaded.saturating_mul(100) / loader.total.max(1))
+    }
+
+    /// Build an empty `Document` for a file that does not exist yet, written
+    /// on the first save
+    ///
+    /// # Args
+    ///
+    /// - `filename`: The plain name of the file
+    pub fn new_file(filename: &str) -> Self {
+        Self {
+            file_name: Some(filename.to_string()),
+            file_type: FileType::from(filename),
+            ..Self::default()
+        }
+    }
+
+    /// Build a `Document` that is not backed by a file from the given text
+    ///
+    /// # Args
+    ///
+    /// - `text`: The contents of the document
+    ///
+    /// # Returns
+    ///
+    /// - The unnamed `Document`
+    pub fn from_text(text: &str) -> Self {
+        Self {
+            rows: text.lines().map(Row::from).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Retrieve the file type of the current `Document`
+    ///
+    /// # Returns
+    ///
+    /// - The name of the filetype
+    pub fn file_type(&self) -> String {
+        self.file_type.name()
+    }
+
+    /// What `%` jumps between in the document besides brackets
+    pub fn pair_options(&self) -> &PairOptions {
+        self.file_type.pair_options()
+    }
+
+    /// Retrieve the character between the cells of a line, for CSV and TSV
+    /// files
+    pub fn column_separator(&self) -> Option<char> {
+        self.file_type.column_separator()
+    }
+
+    /// Write the current `Document` to disk
+    ///
+    /// # Returns
+    ///
+    /// - Unit or any Error encountered during the save operation
+    pub fn save(&mut self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "Document is read-only",
+            ));
+        }
+        if let Some(file_name) = &self.file_name {
+            self.write(file_name)?;
+            self.file_type = FileType::from(file_name);
+            self.modified = modified(file_name);
+            self.disk_check = None;
+            self.dirty = false;
+        }
+
+        Ok(())
+    }
+
+    /// Write a copy of the `Document` to another file, leaving the document
+    /// attached to its own
+    ///
+    /// # Args
+    ///
+    /// - `file_name`: The file to write to
+    ///
+    /// # Returns
+    ///
+    /// - Unit or any Error encountered writing the file
+    pub fn write_to(&self, file_name: &str) -> Result<(), Error> {
+        if self.loader.is_some() {
+            return Err(Error::other("The file is still being read"));
+        }
+        self.write(file_name)
+    }
+
+    /// Write the rows to a file
+    fn write(&self, file_name: &str) -> Result<(), Error> {
+        let lines = || self.rows.iter().map(Row::as_str);
+        self.format()
+            .write(file_name, lines, self.rows.len(), |_, _| {})
+    }
+
+    /// Retrieve how the text is stored in the file
+    fn format(&self) -> Format {
+        Format {
+            encoding: self.encoding,
+            bom: self.bom,
+            line_ending: self.line_ending,
+            missing_final_newline: self.missing_final_newline,
+        }
+    }
+
+    /// Copy the document for saving it to its file in the background, see
+    /// `finish_save`
+    ///
+    /// # Returns
+    ///
+    /// - The copy to write, or why the document can not be saved
+    pub fn start_save(&self) -> Result<Contents, Error> {
+        if self.read_only {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "Document is read-only",
+            ));
+        }
+        if self.loader.is_some() {
+            return Err(Error::other("The file is still being read"));
+        }
+        Ok(Contents {
+            lines: self.lines().into_iter().map(String::from).collect(),
+            format: self.format(),
+        })
+    }
+
+    /// Take note that a copy made by `start_save` was written to the file
+    ///
+    /// # Args
+    ///
+    /// - `revision`: The revision of the document when it was copied, it is
+    ///   still dirty when edited since
+    pub fn finish_save(&mut self, revision: usize) {
+        if let Some(file_name) = &self.file_name {
+            self.file_type = FileType::from(file_name);
+            self.modified = modified(file_name);
+            self.disk_check = None;
+        }
+        if self.revision() == revision {
+            self.dirty = false;
+        }
+    }
+
+    /// Check whether the document has changes that are neither saved nor
+    /// copied to the recovery area yet
+    pub fn needs_recovery(&self) -> bool {
+        self.dirty && self.file_name.is_some() && self.edits() != self.recovered_edits
+    }
+
+    /// Copy the `Document` to the recovery area, leaving its file untouched.
+    /// A failed copy is only tried again after the next edit
+    ///
+    /// # Returns
+    ///
+    /// - Unit or any Error encountered writing the copy
+    pub fn write_recovery(&mut self) -> Result<(), Error> {
+        self.recovered_edits = self.edits();
+        let path = self
+            .file_name
+            .as_deref()
+            .and_then(recovery::path)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "No state directory"))?;
+        recovery::create_dir()?;
+        // The copy may hold secrets, so only the user can read it whatever
+        // the file itself allows
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        self.write_to(&path.to_string_lossy())
+    }
+
+    /// Check whether the file was changed by another program since it was
+    /// read or last saved, unless it was checked lately. Called while no key
+    /// is pressed, so drawing does not read the file's metadata every frame
+    ///
+    /// # Returns
+    ///
+    /// - Whether the answer of `changed_on_disk` changed
+    pub fn check_disk(&mut self) -> bool {
+        if self
+            .disk_check
+            .is_some_and(|(checked, _)| checked.elapsed() < DISK_CHECK_INTERVAL)
+        {
+            return false;
+        }
+        let changed = match (&self.file_name, self.modified) {
+            (Some(file_name), Some(read)) => modified(file_name).is_some_and(|time| time != read),
+            _ => false,
+        };
+        let before = self.changed_on_disk();
+        self.disk_check = Some((Instant::now(), changed));
+        changed != before
+    }
+
+    /// Tell whether the file was found changed by another program when
+    /// `check_disk` last checked it
+    pub fn changed_on_disk(&self) -> bool {
+        self.disk_check.is_some_and(|(_, changed)| changed)
+    }
+
+    /// Check whether the file starts with a byte order mark, which is kept
+    /// out of the text and written back on save
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    /// Choose whether to write a byte order mark when saving
+    ///
+    /// # Args
+    ///
+    /// - `bom`: Whether the file should start with a byte order mark
+    pub fn set_bom(&mut self, bom: bool) {
+        if self.bom != bom {
+            self.bom = bom;
+            self.dirty = true;
+        }
+    }
+
+    /// Retrieve how the lines of the file end
+    ///
+    /// # Returns
+    ///
+    /// - `unix` for `\n`, `dos` for `\r\n`
+    pub fn file_format(&self) -> &'static str {
+        match self.line_ending {
+            LineEnding::Lf => "unix",
+            LineEnding::Crlf => "dos",
+        }
+    }
+
+    /// Choose how the lines of the file end when saving
+    ///
+    /// # Args
+    ///
+    /// - `format`: `unix` for `\n`, `dos` for `\r\n`
+    ///
+    /// # Returns
+    ///
+    /// - Whether the format is known
+    pub fn set_file_format(&mut self, format: &str) -> bool {
+        let line_ending = match format {
+            "unix" => LineEnding::Lf,
+            "dos" => LineEnding::Crlf,
+            _ => return false,
+        };
+        if self.line_ending != line_ending {
+            self.line_ending = line_ending;
+            self.dirty = true;
+        }
+        true
+    }
+
+    /// Retrieve how the text of the file is stored
+    ///
+    /// # Returns
+    ///
+    /// - The name of the encoding, e.g. `utf-8` or `latin1`
+    pub fn encoding(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    /// Choose how the text of the file is stored when saving
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the encoding, e.g. `utf-8` or `latin1`
+    ///
+    /// # Returns
+    ///
+    /// - Whether the encoding is known
+    pub fn set_encoding(&mut self, name: &str) -> bool {
+        let encoding = match Encoding::from_name(name) {
+            Some(encoding) => encoding,
+            None => return false,
+        };
+        if self.encoding != encoding {
+            self.encoding = encoding;
+            self.dirty = true;
+        }
+        true
+    }
+
+    /// Check whether the last line of the file has no line ending
+    pub fn is_missing_final_newline(&self) -> bool {
+        self.missing_final_newline
+    }
+
+    /// Check whether edits to the document are refused
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Choose whether to refuse edits to the document. A file still read in
+    /// the background stays read-only until it is read in full
+    ///
+    /// # Args
+    ///
+    /// - `read_only`: Whether the document may not be edited
+    pub fn set_read_only(&mut self, read_only: bool) {
+        match self.loader.as_mut() {
+            Some(loader) => loader.read_only = read_only,
+            None => self.read_only = read_only,
+        }
+    }
+
+    /// Insert a single character into a Document at a given position
+    ///
+    /// # Args
+    ///
+    /// - `at`: The (x, y) pair where the character should be placed
+    /// - `c`: The character to insert
+    pub fn insert(&mut self, at: &Position, c: char) {
+        if self.read_only || at.y > self.len() {
+            return;
+        }
+
+        let before = self.capture(at.y);
+        self.dirty = true;
+        if c == '\n' {
+            self.insert_newline(at);
+        } else if let Some(row) = self.rows.get_mut(at.y) {
+            row.insert(at.x, c);
+        } else {
+            let mut row = Row::default();
+            row.insert(0, c);
+            self.rows.push(row);
+        }
+
+        self.record(at, before);
+        self.unhighlight_rows(at.y);
+    }
+
+    /// Adds a line, moving the remainder of a current line down if applicable
+    ///
+    /// # Args
+    ///
+    /// - `at`: The (x, y) pair where the new newline should be placed
+    fn insert_newline(&mut self, at: &Position) {
+        // Past the end, the line being split has to be made first
+        if at.y == self.len() {
+            self.rows.push(Row::default());
+        }
+
+        if let Some(current_row) = self.rows.get_mut(at.y) {
+            let new_row = current_row.split(at.x);
+            self.rows.insert(at.y + 1, new_row);
+        }
+    }
+
+    /// Remove the character under the cursor
+    ///
+    /// # Args
+    ///
+    /// - `at`: The current cursor position
+    pub fn delete(&mut self, at: &Position) {
+        let len: usize = self.len();
+
+        if self.read_only || at.y >= len {
+            return;
+        }
+
+        let before = self.capture(at.y);
+        self.dirty = true;
+
+        let row_len = self.rows.get(at.y).map_or(0, Row::len);
+        if at.x >= row_len && at.y + 1 < len {
+            if let Some(next_row) = self.rows.remove(at.y + 1) {
+                if let Some(row) = self.rows.get_mut(at.y) {
+                    row.append(&next_row);
+                }
+            }
+        } else if let Some(row) = self.rows.get_mut(at.y) {
+            row.delete(at.x);
+        }
+
+        self.record(at, before);
+        self.unhighlight_rows(at.y);
+    }
+
+    /// Copy the lines a single character edit can affect, before the edit
+    ///
+    /// # Args
+    ///
+    /// - `y`: The line being edited
+    ///
+    /// # Returns
+    ///
+    /// - The text of the line and the one after it, and the number of lines
+    ///   in the document
+    fn capture(&self, y: usize) -> (Vec<String>, usize) {
+        let end = std::cmp::min(y.saturating_add(2), self.rows.len());
+        let lines = self.rows.range(y..end).map(|row| row.as_str().to_string());
+        (lines.collect(), self.rows.len())
+    }
+
+    /// Add a single character edit to the undo history
+    ///
+    /// # Args
+    ///
+    /// - `at`: The position of the edit
+    /// - `before`: The lines captured before the edit
+    fn record(&mut self, at: &Position, before: (Vec<String>, usize)) {
+        let (old, len) = before;
+        // Edits add or remove at most one line, within the captured ones
+        let end = (at.y + old.len() + self.rows.len()).saturating_sub(len);
+        let new: Vec<String> = self
+            .rows
+            .range(at.y..end)
+            .map(|row| row.as_str().to_string())
+            .collect();
+        // Edits past the end of a line change nothing
+        if new == old {
+            return;
+        }
+        self.record_change(Change {
+            start: at.y,
+            old,
+            new,
+            cursor: at.clone(),
+        });
+    }
+
+    /// Add an edit to the undo history, moving the marks after it
+    ///
+    /// # Args
+    ///
+    /// - `change`: The edit just made
+    fn record_change(&mut self, change: Change) {
+        move_marks(&mut self.marks, change.start, &change.old, &change.new);
+        self.history.record(change);
+    }
+
+    /// Set a mark, as done by `m`
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the mark, a letter
+    /// - `position`: The position to remember
+    pub fn set_mark(&mut self, name: char, position: Position) {
+        self.marks.insert(name, position);
+    }
+
+    /// Retrieve the position of a mark set with `m`
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the mark
+    pub fn mark(&self, name: char) -> Option<Position> {
+        self.marks.get(&name).cloned()
+    }
 
-        Ok(Self {
-            rows,
-            file_name: Some(filename.to_string()),
-            dirty: false,
-            file_type,
-        })
+    /// Retrieve the position of the latest change to the document
+    pub fn last_change(&self) -> Option<Position> {
+        self.history.last_change().cloned()
     }
 
-    /// Retrieve the file type of the current `Document`
+    /// Retrieve the version of the text, which differs from that of any
+    /// other document or of this one before a change, undo or redo
+    pub fn revision(&self) -> usize {
+        self.revision.0
+    }
+
+    /// Retrieve the number of edits made to the document so far, which
+    /// changes whenever it is edited
+    pub fn edits(&self) -> usize {
+        self.history.edits()
+    }
+
+    /// Close the current undo step, so later edits are undone separately
+    pub fn end_undo_step(&mut self) {
+        self.history.end_step();
+    }
+
+    /// Revert the latest undo step
     ///
     /// # Returns
     ///
-    /// - The name of the filetype
-    pub fn file_type(&self) -> String {
-        self.file_type.name()
+    /// - The position of the first reverted edit, if there was one
+    pub fn undo(&mut self) -> Option<Position> {
+        let changes = self.history.undo()?;
+        for change in changes.iter().rev() {
+            move_marks(&mut self.marks, change.start, &change.new, &change.old);
+            let end = change.start + change.new.len();
+            let old = change.old.iter().map(|line| Row::from(&line[..]));
+            self.rows.splice(change.start..end, old);
+        }
+
+        let start = changes.iter().map(|change| change.start).min()?;
+        let cursor = changes.first()?.cursor.clone();
+        self.dirty = true;
+        self.unhighlight_rows(start);
+        Some(cursor)
     }
 
-    /// Write the current `Document` to disk
+    /// Make the latest undone step again
     ///
     /// # Returns
     ///
-    /// - Unit or any Error encountered during the save operation
-    pub fn save(&mut self) -> Result<(), Error> {
-        if let Some(file_name) = &self.file_name {
-            let mut file = fs::File::create(file_name)?;
-            self.file_type = FileType::from(file_name);
-
-            for row in &self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
-            }
-            self.dirty = false;
+    /// - The position of the first edit made again, if there was one
+    pub fn redo(&mut self) -> Option<Position> {
+        let changes = self.history.redo()?;
+        for change in changes {
+            move_marks(&mut self.marks, change.start, &change.old, &change.new);
+            let end = change.start + change.old.len();
+            let new = change.new.iter().map(|line| Row::from(&line[..]));
+            self.rows.splice(change.start..end, new);
         }
 
-        Ok(())
+        let start = changes.iter().map(|change| change.start).min()?;
+        let cursor = changes.first()?.cursor.clone();
+        self.dirty = true;
+        self.unhighlight_rows(start);
+        Some(cursor)
     }
 
-    /// Insert a single character into a Document at a given position
+    /// Replace a range of lines with new ones
     ///
     /// # Args
     ///
-    /// - `at`: The (x, y) pair where the character should be placed
-    /// - `c`: The character to insert
-    pub fn insert(&mut self, at: &Position, c: char) {
-        if at.y > self.len() {
+    /// - `start`: The first line to replace
+    /// - `len`: The number of lines to replace
+    /// - `lines`: The lines to put in their place
+    pub fn replace_lines(&mut self, start: usize, len: usize, lines: &[String]) {
+        if self.read_only {
             return;
         }
+        let start = std::cmp::min(start, self.rows.len());
+        let end = std::cmp::min(start.saturating_add(len), self.rows.len());
 
         self.dirty = true;
-        if c == '\n' {
-            self.insert_newline(at);
-        } else if at.y == self.rows.len() {
-            let mut row = Row::default();
-            row.insert(0, c);
-            self.rows.push(row);
-        } else {
-            let row = self.rows.get_mut(at.y).unwrap();
-            row.insert(at.x, c);
-        }
+        let old = self
+            .rows
+            .splice(start..end, lines.iter().map(|line| Row::from(&line[..])))
+            .iter()
+            .map(|row| row.as_str().to_string())
+            .collect();
+        self.record_change(Change {
+            start,
+            old,
+            new: lines.to_vec(),
+            cursor: Position { x: 0, y: start },
+        });
+        self.unhighlight_rows(start);
+    }
 
-        self.unhighlight_rows(at.y);
+    /// Retrieve the text between two positions
+    ///
+    /// # Args
+    ///
+    /// - `start`: The position of the first character
+    /// - `end`: The position just past the last character
+    ///
+    /// # Returns
+    ///
+    /// - The text of each line in the range
+    pub fn text(&self, start: &Position, end: &Position) -> Vec<String> {
+        (start.y..=end.y)
+            .filter_map(|y| {
+                let row = self.rows.get(y)?;
+                let from = if y == start.y {
+                    row.byte_index(start.x)
+                } else {
+                    0
+                };
+                let to = if y == end.y {
+                    row.byte_index(end.x)
+                } else {
+                    row.as_str().len()
+                };
+                Some(row.as_str().get(from..to).unwrap_or_default().to_string())
+            })
+            .collect()
     }
 
-    /// Adds a line, moving the remainder of a current line down if applicable
+    /// Delete the text between two positions, joining the lines they are on
     ///
     /// # Args
     ///
-    /// - `at`: The (x, y) pair where the new newline should be placed
-    fn insert_newline(&mut self, at: &Position) {
-        if at.y == self.len() {
-            self.rows.push(Row::default());
+    /// - `start`: The position of the first character
+    /// - `end`: The position just past the last character
+    pub fn delete_range(&mut self, start: &Position, end: &Position) {
+        if start.y > end.y || (start.y == end.y && start.x >= end.x) {
             return;
         }
+        let (first, last) = match (self.rows.get(start.y), self.rows.get(end.y)) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return,
+        };
 
-        let current_row = &mut self.rows[at.y];
-        let new_row = current_row.split(at.x);
-        self.rows.insert(at.y + 1, new_row);
+        let line = format!(
+            "{}{}",
+            &first.as_str()[..first.byte_index(start.x)],
+            &last.as_str()[last.byte_index(end.x)..]
+        );
+        self.replace_lines(start.y, end.y - start.y + 1, &[line]);
     }
 
-    /// Remove the character under the cursor
+    /// Delete whole lines
     ///
     /// # Args
     ///
-    /// - `at`: The current cursor position
-    pub fn delete(&mut self, at: &Position) {
-        let len: usize = self.len();
+    /// - `lines`: The lines to delete
+    pub fn delete_lines(&mut self, lines: Range<usize>) {
+        if lines.start < self.rows.len() {
+            self.replace_lines(lines.start, lines.len(), &[]);
+        }
+    }
 
-        if at.y >= len {
+    /// Insert text at a position, splitting the line when the text holds
+    /// more than one line
+    ///
+    /// # Args
+    ///
+    /// - `at`: The position to insert at
+    /// - `text`: The lines of text to insert
+    pub fn insert_text(&mut self, at: &Position, text: &[String]) {
+        if text.is_empty() || at.y > self.rows.len() {
             return;
         }
 
-        self.dirty = true;
-
-        if at.x == self.rows.get_mut(at.y).unwrap().len() && at.y < len - 1 {
-            let next_row = self.rows.remove(at.y + 1);
-            let row = self.rows.get_mut(at.y).unwrap();
-            row.append(&next_row);
-        } else {
-            let row = self.rows.get_mut(at.y).unwrap();
-            row.delete(at.x);
+        let (before, after) = match self.rows.get(at.y) {
+            Some(row) => row.as_str().split_at(row.byte_index(at.x)),
+            None => ("", ""),
+        };
+        let mut lines = text.to_vec();
+        lines[0].insert_str(0, before);
+        if let Some(last) = lines.last_mut() {
+            last.push_str(after);
         }
 
-        self.unhighlight_rows(at.y);
+        let len = if at.y < self.rows.len() { 1 } else { 0 };
+        self.replace_lines(at.y, len, &lines);
+    }
+
+    /// Retrieve the text of every line in the document
+    ///
+    /// # Returns
+    ///
+    /// - A vector with the contents of each row
+    pub fn lines(&self) -> Vec<&str> {
+        self.rows.iter().map(Row::as_str).collect()
     }
 
-    /// Search document for query
+    /// Search document for query, read as a regular expression or as plain
+    /// text when it is not a valid one
     ///
     /// # Args
     ///
@@ -139,19 +1069,53 @@ impl Document {
     ///
     /// # Returns
     ///
-    /// - The position of the query if found
-    pub fn find(&self, query: &str, after: &Position) -> Option<Position> {
+    /// - The position of the match if found, and its length in graphemes
+    pub fn find(&self, query: &str, after: &Position) -> Option<(Position, usize)> {
+        let pattern = Pattern::new(query, false);
         let mut x = after.x;
         for (y, row) in self.rows.iter().enumerate().skip(after.y) {
-            if let Some(x) = row.find(query, x) {
-                return Some(Position { x, y });
+            if let Some(found) = row.find(&pattern, x) {
+                return Some((Position { x: found.start, y }, found.len()));
             }
             x = 0;
         }
         None
     }
 
-    /// Find all matches for a query
+    /// Search the document backwards for a query, as done by `?`
+    ///
+    /// # Args
+    ///
+    /// - `query`: The text to search for
+    /// - `before`: The location the match has to start before
+    ///
+    /// # Returns
+    ///
+    /// - The position of the match if found, and its length in graphemes
+    pub fn find_before(&self, query: &str, before: &Position) -> Option<(Position, usize)> {
+        let pattern = Pattern::new(query, false);
+        let end = std::cmp::min(before.y.saturating_add(1), self.rows.len());
+        for y in (0..end).rev() {
+            let row = self.row(y)?;
+            let mut x = 0;
+            let mut last = None;
+            while let Some(found) = row.find(&pattern, x) {
+                if y == before.y && found.start >= before.x {
+                    break;
+                }
+                // Step over empty matches so the search moves on
+                x = std::cmp::max(found.end, found.start + 1);
+                last = Some(found);
+            }
+            if let Some(found) = last {
+                return Some((Position { x: found.start, y }, found.len()));
+            }
+        }
+        None
+    }
+
+    /// Find all matches for a query, read as a regular expression or as plain
+    /// text when it is not a valid one
     ///
     /// # Args
     ///
@@ -159,20 +1123,156 @@ impl Document {
     ///
     /// # Returns
     ///
-    /// - A vector of all positions that match the query
-    pub fn find_all(&self, query: &str) -> Vec<Position> {
-        // TODO: Refactor
+    /// - The position and length in graphemes of every match
+    pub fn find_all(&self, query: &str) -> Vec<(Position, usize)> {
+        let pattern = Pattern::new(query, false);
         let mut results = Vec::new();
 
         for (y, row) in self.rows.iter().enumerate() {
-            if let Some(x) = row.find(query, 0) {
-                results.push(Position { x, y });
+            let mut x = 0;
+            while let Some(found) = row.find(&pattern, x) {
+                // Step over empty matches so the search moves on
+                x = std::cmp::max(found.end, found.start + 1);
+                results.push((Position { x: found.start, y }, found.len()));
             }
         }
 
         results
     }
 
+    /// Count the matches for a pattern in a range of lines
+    ///
+    /// # Args
+    ///
+    /// - `pattern`: The pattern to search for
+    /// - `lines`: The lines to search in
+    ///
+    /// # Returns
+    ///
+    /// - The number of matches and the number of lines holding them
+    pub fn count(&self, pattern: &Pattern, lines: Range<usize>) -> (usize, usize) {
+        let end = std::cmp::min(lines.end, self.rows.len());
+        self.rows
+            .range(lines.start..end)
+            .map(|row| row.count(pattern))
+            .filter(|&count| count > 0)
+            .fold((0, 0), |(matches, lines), count| {
+                (matches + count, lines + 1)
+            })
+    }
+
+    /// Overwrite the character at a position, adding it at the end of the
+    /// line past its last character, or as the first line of an empty
+    /// document
+    ///
+    /// # Args
+    ///
+    /// - `at`: The position of the character
+    /// - `c`: The character to put in its place
+    ///
+    /// # Returns
+    ///
+    /// - The whole grapheme that was overwritten, if there was one
+    pub fn replace_char(&mut self, at: &Position, c: char) -> Option<String> {
+        let row = match self.rows.get(at.y) {
+            Some(row) if at.x < row.len() => row,
+            _ => {
+                self.insert(at, c);
+                return None;
+            }
+        };
+        let old = row.as_str()[row.byte_index(at.x)..row.byte_index(at.x + 1)].to_string();
+        self.replace_in_range(at.y, at.x..at.x + 1, &c.to_string());
+        Some(old)
+    }
+
+    /// Replace a range of characters on a line
+    ///
+    /// # Args
+    ///
+    /// - `y`: The line to replace in
+    /// - `range`: The characters to replace
+    /// - `text`: The text to put in their place
+    pub fn replace_in_range(&mut self, y: usize, range: Range<usize>, text: &str) {
+        let row = match self.rows.get(y) {
+            Some(row) if !self.read_only => row,
+            _ => return,
+        };
+
+        let old = row.as_str().to_string();
+        let start = row.byte_index(range.start);
+        let end = row.byte_index(range.end);
+        let new = format!("{}{}{}", &old[..start], text, &old[end..]);
+        if new == old {
+            return;
+        }
+
+        self.rows.splice(y..y + 1, Some(Row::from(&new[..])));
+        self.dirty = true;
+        self.record_change(Change {
+            start: y,
+            old: vec![old],
+            new: vec![new],
+            cursor: Position { x: range.start, y },
+        });
+        self.unhighlight_rows(y);
+    }
+
+    /// Find the definitions in the document, according to its file type
+    ///
+    /// # Returns
+    ///
+    /// - The line of each definition along with the definition, in order
+    pub fn definitions(&self) -> Vec<(usize, Definition<'_>)> {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter_map(|(y, row)| Some((y, self.file_type.definition(row.as_str())?)))
+            .collect()
+    }
+
+    /// Find the definitions enclosing a line, judging by indentation
+    ///
+    /// # Args
+    ///
+    /// - `y`: The line
+    ///
+    /// # Returns
+    ///
+    /// - The line of each enclosing definition along with the definition,
+    ///   outermost first
+    pub fn context(&self, y: usize) -> Vec<(usize, Definition<'_>)> {
+        let mut context = Vec::new();
+        // The smallest indentation between the line and the definitions
+        let mut indent = usize::MAX;
+
+        for index in (0..=y).rev() {
+            let line = if let Some(row) = self.rows.get(index) {
+                row.as_str()
+            } else {
+                continue;
+            };
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            let line_indent = line.len() - trimmed.len();
+            if index == y || line_indent < indent {
+                if let Some(definition) = self.file_type.definition(line) {
+                    context.push((index, definition));
+                }
+            }
+            // The closing brace of a block still belongs to it
+            if index != y || !trimmed.starts_with('}') {
+                indent = std::cmp::min(indent, line_indent);
+            }
+        }
+
+        context.reverse();
+        context
+    }
+
     /// Checks if until is within the bounds of the document
     ///
     /// # Args
@@ -194,30 +1294,233 @@ impl Document {
     ///
     /// # Args
     ///
-    /// - `word`:
+    /// - `word`: The search query whose matches are highlighted, if any
     /// - `until`: The row to highlight to, if `None` will highlight whole document
     pub fn highlight(&mut self, word: &Option<String>, until: Option<usize>) {
-        let mut start_with_comment: bool = false;
         let until = if let Some(until) = until {
             self.unwrap_until(until)
         } else {
             self.rows.len()
         };
+        self.highlight_screen(word, 0..until, None);
+    }
+
+    /// Highlight the rows up to a row that are not highlighted yet, so their
+    /// strings and comments are known, e.g. for matching brackets past the
+    /// screen. The rows already highlighted keep their matches
+    ///
+    /// # Args
+    ///
+    /// - `until`: The row to highlight to
+    pub fn highlight_until(&mut self, until: usize) {
+        let end = self.unwrap_until(until);
+        let options = self.file_type.highlighting_options();
+        let mut start_with_comment = false;
+        for row in self.rows.range_mut(0..end) {
+            // Rows ending inside a comment are never left highlighted
+            start_with_comment =
+                !row.is_highlighted && row.highlight(options, None, None, &[], start_with_comment);
+        }
+    }
+
+    /// Highlight the rows on screen, along with the rows before them which
+    /// tell whether the screen starts inside a comment. Once the deadline
+    /// passes, the rows before the screen are left for a later call and the
+    /// screen is highlighted as if it started outside of a comment
+    ///
+    /// # Args
+    ///
+    /// - `word`: The search query whose matches are highlighted, if any
+    /// - `screen`: The rows on screen
+    /// - `deadline`: When to stop highlighting rows before the screen, if
+    ///   ever
+    ///
+    /// # Returns
+    ///
+    /// - Whether every row was highlighted, rather than some left for later
+    pub fn highlight_screen(
+        &mut self,
+        word: &Option<String>,
+        screen: Range<usize>,
+        deadline: Option<Instant>,
+    ) -> bool {
+        let pattern = word.as_deref().map(|word| Pattern::new(word, false));
+        let end = self.unwrap_until(screen.end);
+        let start = std::cmp::min(screen.start, end);
+        let mut start_with_comment: bool = false;
+        let mut complete = true;
 
-        for row in &mut self.rows[..until] {
+        let match_paren = &self.match_paren;
+        for (y, row) in self.rows.range_mut(0..end).enumerate() {
+            if y < start && !complete {
+                continue;
+            }
+            if y < start && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                complete = false;
+                start_with_comment = false;
+                continue;
+            }
+            // Matches only need to be highlighted where they are seen
             start_with_comment = row.highlight(
                 self.file_type.highlighting_options(),
-                word,
+                if y < start { None } else { pattern.as_ref() },
+                if y < start {
+                    None
+                } else {
+                    self.reference.as_ref()
+                },
+                &match_paren
+                    .iter()
+                    .filter(|position| position.y == y)
+                    .map(|position| position.x)
+                    .collect::<Vec<usize>>(),
                 start_with_comment,
             );
         }
+        if !complete {
+            // The screen is highlighted again once the rows before it are
+            for row in self.rows.range_mut(start..end) {
+                row.is_highlighted = false;
+            }
+        }
+        self.highlighted_until = std::cmp::max(self.highlighted_until, end);
+        self.highlighted_len = self.rows.len();
+        complete
+    }
+
+    /// Highlight the occurrences of a word, or stop highlighting them
+    ///
+    /// # Args
+    ///
+    /// - `word`: The word whose occurrences are highlighted, if any
+    /// - `screen`: The rows on screen, highlighted again to show the change
+    pub fn set_reference(&mut self, word: Option<&str>, screen: Range<usize>) {
+        self.reference =
+            word.map(|word| Pattern::new(&format!(r"\b{}\b", regex::escape(word)), false));
+        let end = std::cmp::min(screen.end, self.rows.len());
+        for row in self.rows.range_mut(std::cmp::min(screen.start, end)..end) {
+            row.is_highlighted = false;
+        }
+    }
+
+    /// Highlight a bracket and the one it pairs with, or stop highlighting
+    /// brackets
+    ///
+    /// # Args
+    ///
+    /// - `brackets`: The positions of the brackets, empty for none
+    ///
+    /// # Returns
+    ///
+    /// - Whether the highlighted brackets changed
+    pub fn set_match_paren(&mut self, brackets: Vec<Position>) -> bool {
+        if brackets == self.match_paren {
+            return false;
+        }
+        let old = std::mem::replace(&mut self.match_paren, brackets);
+        for position in old.iter().chain(self.match_paren.iter()) {
+            if let Some(row) = self.rows.get_mut(position.y) {
+                row.is_highlighted = false;
+            }
+        }
+        true
+    }
+
+    /// Retrieve lines of the document with the strings, characters and
+    /// comments found by highlighting blanked out, so brackets in them are
+    /// not matched. Every other byte keeps its index
+    ///
+    /// # Args
+    ///
+    /// - `range`: The lines to retrieve
+    pub fn code_lines(&self, range: Range<usize>) -> Vec<String> {
+        let end = std::cmp::min(range.end, self.rows.len());
+        let start = std::cmp::min(range.start, end);
+        self.rows.range(start..end).map(Row::code).collect()
+    }
+
+    /// Find the merge conflicts in the text, unless they were found since
+    /// it last changed
+    pub fn find_conflicts(&mut self) {
+        if let Some((revision, _)) = &self.conflicts {
+            if *revision == self.revision {
+                return;
+            }
+        }
+        let conflicts = conflict::find_conflicts(&self.lines());
+        self.conflicts = Some((self.revision, conflicts));
+    }
+
+    /// Retrieve the merge conflicts found by `find_conflicts`
+    ///
+    /// # Returns
+    ///
+    /// - The conflicts, ordered by position
+    pub fn conflicts(&self) -> &[Conflict] {
+        self.conflicts
+            .as_ref()
+            .map_or(&[], |(_, conflicts)| &conflicts[..])
+    }
+
+    /// Find the lines changed since the version staged in git, unless they
+    /// were found since the text last changed
+    ///
+    /// # Args
+    ///
+    /// - `staged`: The contents of the file staged in git, if known
+    pub fn compare_staged(&mut self, staged: Option<&str>) {
+        let staged = match staged {
+            Some(staged) => staged,
+            None => {
+                self.staged_hunks = None;
+                return;
+            }
+        };
+        if let Some((revision, _)) = &self.staged_hunks {
+            if *revision == self.revision {
+                return;
+            }
+        }
+        let hunks = diff::diff(&staged.lines().collect::<Vec<&str>>(), &self.lines());
+        self.staged_hunks = Some((self.revision, hunks));
+    }
+
+    /// Forget the lines found by `compare_staged`, for when the version
+    /// staged in git changed
+    pub fn forget_staged(&mut self) {
+        self.staged_hunks = None;
+    }
+
+    /// Retrieve the lines found by `compare_staged`
+    ///
+    /// # Returns
+    ///
+    /// - The hunks turning the staged version into the text, ordered by
+    ///   position
+    pub fn staged_hunks(&self) -> &[Hunk] {
+        self.staged_hunks
+            .as_ref()
+            .map_or(&[], |(_, hunks)| &hunks[..])
     }
 
+    /// Mark the rows from a line on as needing to be highlighted again, as
+    /// well as the line before, which may open a comment spanning them
+    ///
+    /// # Args
+    ///
+    /// - `start`: The first edited line
     fn unhighlight_rows(&mut self, start: usize) {
+        // Every change of the rows goes through here
+        self.revision = Revision::default();
         let start = start.saturating_sub(1);
-        for row in self.rows.iter_mut().skip(start) {
+        // Rows after the edit moved down by as many rows as it added
+        let added = self.rows.len().saturating_sub(self.highlighted_len);
+        let end = self.highlighted_until.saturating_add(added);
+        for row in self.rows.range_mut(start..end) {
             row.is_highlighted = false;
         }
+        self.highlighted_until = std::cmp::min(self.highlighted_until, start);
+        self.highlighted_len = self.rows.len();
     }
 
     /// Get the `Row` at the given index
@@ -261,3 +1564,245 @@ impl Document {
         self.dirty
     }
 }
+
+/// Move the marks after an edit that replaced some lines with others. Lines
+/// the edit left alike at either end keep their marks, the marks below move
+/// with their lines, and the marks on replaced lines stay on the last new one
+/// or are dropped when no line took their place
+///
+/// # Args
+///
+/// - `marks`: The marks of the document
+/// - `start`: The first line of the edit
+/// - `old`: The lines before the edit
+/// - `new`: The lines after the edit
+fn move_marks(marks: &mut BTreeMap<char, Position>, start: usize, old: &[String], new: &[String]) {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let removed = start + prefix..start + old.len() - suffix;
+    let added = new.len() - prefix - suffix;
+    marks.retain(|_, mark| {
+        if mark.y >= removed.end {
+            mark.y = mark.y - removed.len() + added;
+        } else if mark.y >= removed.start {
+            if added == 0 {
+                return false;
+            }
+            mark.y = std::cmp::min(mark.y, removed.start + added - 1);
+        }
+        true
+    });
+}
+
+/// Find how the lines of a file end without reading all of it: the first
+/// line tells the encoding and the line ending, the last byte whether the
+/// last line has one
+///
+/// # Returns
+///
+/// - The encoding of the first line, the line ending and whether the file
+///   ends with one
+fn peek_line_endings(mut file: &fs::File) -> Result<(Encoding, LineEnding, bool), Error> {
+    let mut first = Vec::new();
+    BufReader::new(file).read_until(b'\n', &mut first)?;
+    let mut last = [0; 1];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok((
+        Encoding::detect(&first),
+        LineEnding::detect(&first),
+        last[0] == b'\n',
+    ))
+}
+
+/// Read the lines of a file, sending them in chunks until the file ends or
+/// the receiving document is gone
+///
+/// # Args
+///
+/// - `file`: The file to read
+/// - `sender`: Where to send the chunks
+fn read_chunks(file: fs::File, sender: &Sender<Chunk>) {
+    let mut reader = BufReader::new(file);
+    let mut lines = Vec::new();
+    let mut bytes = 0;
+    let mut encoding = Encoding::Utf8;
+    loop {
+        let mut line = Vec::new();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => break,
+            Ok(read) => {
+                if encoding == Encoding::Utf8 && std::str::from_utf8(&line).is_err() {
+                    // The rest of the file is read as Latin-1, the document
+                    // decodes the lines sent so far again
+                    let chunk = (
+                        std::mem::take(&mut lines),
+                        std::mem::take(&mut bytes),
+                        encoding,
+                    );
+                    if sender.send(Ok(chunk)).is_err() {
+                        return;
+                    }
+                    encoding = Encoding::Latin1;
+                }
+                bytes += read;
+                if line.ends_with(b"\n") {
+                    line.pop();
+                    if line.ends_with(b"\r") {
+                        line.pop();
+                    }
+                }
+                lines.push(encoding.decode(&line));
+            }
+            Err(err) => {
+                sender.send(Err(err)).ok();
+                return;
+            }
+        }
+
+        if lines.len() == LOAD_CHUNK_LINES {
+            let chunk = (
+                std::mem::take(&mut lines),
+                std::mem::take(&mut bytes),
+                encoding,
+            );
+            if sender.send(Ok(chunk)).is_err() {
+                return;
+            }
+        }
+    }
+    sender.send(Ok((lines, bytes, encoding))).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::{self, WordMotion};
+
+    fn at(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+
+    #[test]
+    fn insert_in_empty_document() {
+        let mut document = Document::default();
+        document.insert(&at(0, 0), 'a');
+        assert_eq!(document.lines(), vec!["a"]);
+    }
+
+    #[test]
+    fn insert_past_last_line() {
+        let mut document = Document::from_text("one");
+        document.insert(&at(0, 1), 'a');
+        assert_eq!(document.lines(), vec!["one", "a"]);
+        document.insert(&at(0, 5), 'b');
+        assert_eq!(document.lines(), vec!["one", "a"]);
+    }
+
+    #[test]
+    fn insert_newline_in_empty_document() {
+        let mut document = Document::default();
+        document.insert(&at(0, 0), '\n');
+        assert_eq!(document.lines(), vec!["", ""]);
+    }
+
+    #[test]
+    fn insert_newline_at_end() {
+        let mut document = Document::from_text("one\ntwo");
+        document.insert(&at(3, 1), '\n');
+        assert_eq!(document.lines(), vec!["one", "two", ""]);
+        document.insert(&at(0, 3), '\n');
+        assert_eq!(document.lines(), vec!["one", "two", "", "", ""]);
+    }
+
+    #[test]
+    fn delete_in_empty_document() {
+        let mut document = Document::default();
+        document.delete(&at(0, 0));
+        assert!(document.is_empty());
+        assert!(document.undo().is_none());
+    }
+
+    #[test]
+    fn delete_at_end_of_last_line() {
+        let mut document = Document::from_text("one\ntwo");
+        document.delete(&at(3, 1));
+        document.delete(&at(0, 2));
+        assert_eq!(document.lines(), vec!["one", "two"]);
+        // Deleting nothing is not recorded
+        assert!(document.undo().is_none());
+    }
+
+    #[test]
+    fn delete_past_end_of_line_joins_next() {
+        let mut document = Document::from_text("one\ntwo");
+        document.delete(&at(5, 0));
+        assert_eq!(document.lines(), vec!["onetwo"]);
+    }
+
+    #[test]
+    fn word_forward_in_empty_document() {
+        let document = Document::default();
+        let to = motion::word(&document, &at(0, 0), WordMotion::Forward, false);
+        assert_eq!((to.x, to.y), (0, 0));
+    }
+
+    #[test]
+    fn word_forward_on_last_word() {
+        let document = Document::from_text("one two");
+        let to = motion::word(&document, &at(4, 0), WordMotion::Forward, false);
+        assert_eq!((to.x, to.y), (7, 0));
+    }
+
+    #[test]
+    fn word_forward_to_next_line() {
+        let document = Document::from_text("one\ntwo");
+        let to = motion::word(&document, &at(0, 0), WordMotion::Forward, false);
+        assert_eq!((to.x, to.y), (0, 1));
+    }
+
+    #[test]
+    fn literals_are_blanked_past_highlighted_rows() {
+        let mut document = Document::from_text("f(\"(\",\n/* ) */\n)");
+        document.file_type = FileType::from("main.rs");
+        document.highlight_until(1);
+        document.highlight_until(3);
+        assert_eq!(document.code_lines(0..3), vec!["f(   ,", "       ", ")"]);
+    }
+
+    #[test]
+    fn from_bytes_keeps_encoding() {
+        let bytes = [0xff, 0xfe, b'a', 0, b'\r', 0, b'\n', 0, 0xe9, 0];
+        let document = Document::from_bytes(&bytes);
+        assert_eq!(document.lines(), vec!["a", "\u{e9}"]);
+        assert!(document.encoding == Encoding::Utf16Le);
+        assert!(document.bom);
+        assert!(document.line_ending == LineEnding::Crlf);
+        assert!(document.missing_final_newline);
+
+        let document = Document::from_bytes(b"caf\xe9\n");
+        assert_eq!(document.lines(), vec!["caf\u{e9}"]);
+        assert!(document.encoding == Encoding::Latin1);
+        assert!(!document.missing_final_newline);
+    }
+
+    #[test]
+    fn replace_char_in_empty_document() {
+        let mut document = Document::default();
+        assert_eq!(document.replace_char(&at(0, 0), 'a'), None);
+        assert_eq!(document.replace_char(&at(1, 0), 'b'), None);
+        assert_eq!(document.lines(), vec!["ab"]);
+        assert_eq!(document.replace_char(&at(0, 0), 'c'), Some("a".to_string()));
+        assert_eq!(document.lines(), vec!["cb"]);
+    }
+}
@@ -1,17 +1,77 @@
+use crate::case::Case;
+use crate::cli::Args;
+use crate::command::{self, Command};
+use crate::complete::{self, WordList};
+use crate::config::Config;
+use crate::conflict::{self, Section};
+use crate::csv::{self, Layout};
+use crate::diff::{self, DiffView, DisplayLine, Hunk};
+use crate::eval;
+use crate::explorer;
+use crate::finder;
+use crate::format;
+use crate::git;
+use crate::gutter::{self, Gutter};
+use crate::inspect;
+use crate::job::Job;
+use crate::jumplist::{Jump, JumpList};
+use crate::local_history;
+use crate::man;
+use crate::motion::{self, WordMotion};
+use crate::notes;
+use crate::pairs::{self, Pairs};
+use crate::project;
+use crate::prompt_history::PromptHistory;
+use crate::quickfix::{self, QuickfixList};
+use crate::recovery;
+use crate::registers::{self, Register, Registers};
+use crate::remote::{Call, Request, Server};
+use crate::search::{self, Pattern};
+use crate::session::{self, Session};
+use crate::showkeys::ShownKeys;
+use crate::statusline;
+use crate::subvert::Subvert;
+use crate::symbols::{self, Symbol};
+use crate::table;
+use crate::terminal::Event;
+use crate::textobject::{self, TextObject};
+use crate::theme;
+use crate::version;
 use crate::Document;
 use crate::Row;
 use crate::Terminal;
+use crate::Theme;
+use std::collections::VecDeque;
 use std::env;
-use std::time::{Duration, Instant};
+use std::io::{ErrorKind, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 use termion::color;
 use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-const STATUS_FG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
-const STATUS_BG_COLOR: color::Rgb = color::Rgb(120, 120, 120);
+const MAX_MESSAGES: usize = 100;
+const MAX_PICKED_SYMBOLS: usize = 10;
+const MAX_LINE_COMPLETIONS: usize = 10;
+const MAX_WORD_COMPLETIONS: usize = 10;
+/// The width `gq` reflows to when no text width is set
+const DEFAULT_TEXT_WIDTH: usize = 79;
+/// The number of lines the preview window shows at most
+const PREVIEW_HEIGHT: usize = 10;
+/// How often the size of the terminal and files read in the background are
+/// checked while waiting for a key
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long messages other than errors stay on the message bar
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The pairs Insert mode closes when `auto_pairs` is set
+const AUTO_PAIRS: [(char, char); 5] =
+    [('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 /// List of Editor Modes
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 enum Mode {
     /// `Normal` mode treats keypresses as commands and does not intepret them
     /// as text to be displayed in the terminal
@@ -20,28 +80,129 @@ enum Mode {
     /// `Insert` mode treats keypresses as-is, meaning they are interpreted as
     /// text and displayed in the terminal
     Insert,
+
+    /// `Replace` mode overwrites the characters under the cursor with the
+    /// keys typed
+    Replace,
+}
+
+/// Buffers that temporarily replace the document until they are closed
+#[derive(PartialEq, Clone, Copy)]
+enum SpecialBuffer {
+    /// Lists changed files, `s` stages and `u` unstages the file under the cursor
+    GitStatus,
+
+    /// Holds a commit message, saving it creates the commit
+    GitCommit,
+
+    /// Lists the saved snapshots of a file, `Enter` diffs the one under the
+    /// cursor against the document and `r` restores it
+    LocalHistory,
+
+    /// Holds the output captured by `:redir`
+    Output,
+
+    /// Holds the notes of the project, saved whenever the buffer is closed
+    Notes,
+
+    /// Shows a man page, which cannot be edited
+    Manual,
+
+    /// Lists a directory, `Enter` opens the entry under the cursor and `-`
+    /// goes up to the parent directory
+    Explorer,
+}
+
+/// A document kept open along with the view of it: where the cursor was, how
+/// far the document was scrolled and its marks, all restored when it is shown
+/// again
+#[derive(Default)]
+struct Buffer {
+    document: Document,
+    cursor_position: Position,
+    offset: Position,
+    marks: Marks,
+}
+
+/// Text shown in a window over the bottom of the document area, closed when
+/// the cursor moves or `q` is pressed
+struct Preview {
+    /// Describes what is shown, e.g. the name of a file
+    title: String,
+
+    /// The lines shown
+    lines: Vec<String>,
+}
+
+/// A list drawn over the bottom of the document area while the user picks
+/// an item of it, such as the files matching the query of the file finder
+struct Popup {
+    /// Describes the list, e.g. how many items it holds
+    title: String,
+
+    /// The items shown, best first
+    items: Vec<String>,
+
+    /// The index of the item selected
+    selected: usize,
+}
+
+/// The positions of a buffer the cursor can go back to with `'` and `` ` ``,
+/// besides the last change the document keeps itself
+#[derive(Default)]
+struct Marks {
+    /// Where the cursor was before the latest jump, the `''` mark
+    jump: Option<Position>,
+
+    /// Where Insert mode was last left, the `'^` mark
+    insert: Option<Position>,
 }
 
 /// Holds cursor positions
-#[derive(Default, Clone)]
+#[derive(Default, Clone, PartialEq)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
 
+/// How important a status message is
+#[derive(PartialEq, Clone, Copy)]
+enum Severity {
+    /// Shown for a few seconds
+    Info,
+
+    /// Shown for a few seconds on the warning color
+    Warning,
+
+    /// Shown on the error color until the next keypress
+    Error,
+}
+
 /// Holds message for the current editor status
 struct StatusMessage {
     text: String,
     time: Instant,
+    severity: Severity,
 }
 
 impl StatusMessage {
     fn from(message: String) -> Self {
+        Self::with_severity(message, Severity::Info)
+    }
+
+    fn with_severity(message: String, severity: Severity) -> Self {
         Self {
             time: Instant::now(),
             text: message,
+            severity,
         }
     }
+
+    /// Check whether the message is still shown: errors until another
+    /// message replaces them, others for `MESSAGE_TIMEOUT`
+    fn is_shown(&self) -> bool {
+        self.severity == Severity::Error || self.time.elapsed() < MESSAGE_TIMEOUT
+    }
 }
 
 /// Maintains editor state
@@ -49,63 +210,620 @@ pub struct Editor {
     /// Whether the editor should quit
     should_quit: bool,
 
+    /// The status the editor exits with, non-zero after `:cq`
+    exit_code: i32,
+
     /// The terminal instance that the editor appears in
     terminal: Terminal,
 
     /// The current position of the cursor
     cursor_position: Position,
 
+    /// The screen column moving up and down keeps the cursor in, along with
+    /// where the cursor was left by the last such move. Moving the cursor in
+    /// any other way makes its column the one to keep
+    goal_column: Option<(Position, usize)>,
+
     /// The current offset
     offset: Position,
 
+    /// Whether lines above the screen were left unhighlighted by the last
+    /// frame, to be highlighted while waiting for keys
+    highlight_pending: bool,
+
+    /// The marks of the current document
+    marks: Marks,
+
+    /// The places jumped from in every buffer, gone through with `Ctrl-O`
+    /// and `Ctrl-I`
+    jumps: JumpList,
+
+    /// The rows of the document area as last drawn, so unchanged rows are not
+    /// drawn again
+    frame: Vec<String>,
+
     /// The current document being worked on
     document: Document,
 
+    /// Every open buffer. The slot of the current buffer is left empty while
+    /// its document lives in `document`
+    buffers: Vec<Buffer>,
+
+    /// The index of the current buffer
+    current_buffer: usize,
+
     /// The current status of the editor
     status_message: StatusMessage,
 
+    /// The messages shown since the editor started, oldest first
+    messages: Vec<String>,
+
+    /// The text typed since Insert mode was entered, which becomes the `.`
+    /// register once it is left
+    inserted: String,
+
+    /// The keys read since the current Normal mode command started, along
+    /// with those typed in the Insert or Replace mode it entered
+    command_keys: Vec<Key>,
+
+    /// The number of edits to the document when the current Normal mode
+    /// command started
+    command_edits: usize,
+
+    /// The keys of the last command that edited the document, repeated by
+    /// `.`
+    last_change: Vec<Key>,
+
+    /// The last `f`, `F`, `t` or `T` motion and its character, repeated by
+    /// `;` and `,`
+    last_find: Option<(char, char)>,
+
+    /// The output captured since `:redir`, if capturing
+    redirect: Option<Vec<String>>,
+
+    /// The preview window, if one is open
+    preview: Option<Preview>,
+
+    /// The list the user picks from, if one is open
+    popup: Option<Popup>,
+
+    /// The matches of the last `:grep` or `:vimgrep`
+    quickfix: QuickfixList,
+
+    /// Whether the quickfix window is shown below the document
+    quickfix_open: bool,
+
+    /// The symbols of the project, collected the first time they are needed
+    symbols: Option<Vec<Symbol>>,
+
+    /// The symbols of the project being collected in the background
+    symbol_index: Option<Job<Vec<Symbol>>>,
+
+    /// The commit being created in the background, reporting git's output
+    commit: Option<Job<String>>,
+
+    /// The man page being rendered in the background, along with its topic
+    man_page: Option<(String, Job<String>)>,
+
+    /// The files being searched by `:grep` in the background, along with the
+    /// pattern
+    grep: Option<(String, Job<Vec<quickfix::Entry>>)>,
+
+    /// The files listed in the background for `Ctrl-P`
+    file_list: Option<Job<Vec<String>>>,
+
+    /// The file being written in the background, along with the revision of
+    /// the document written
+    saving: Option<(String, usize, Job<()>)>,
+
+    /// The number of times the progress of background work was shown, turning
+    /// its spinner
+    progress_frame: usize,
+
+    /// The dictionaries and thesauri read so far, kept for later completions
+    word_lists: Vec<WordList>,
+
+    /// The text yanked into each register
+    registers: Registers,
+
+    /// Whether Markdown tables are realigned while they are edited
+    table_mode: bool,
+
+    /// The characters overwritten in Replace mode, restored by `Backspace`.
+    /// `None` for characters added past the end of a line
+    replaced: Vec<Option<String>>,
+
+    /// The keys left to process while running `:normal`, read in place of
+    /// the terminal
+    replay: Option<VecDeque<Key>>,
+
     /// The current mode of the editor
     mode: Mode,
 
-    /// The number of whitespaces to replace `tab` characters with
-    tab_size: usize,
+    /// The user settings
+    config: Config,
 
-    /// A list of positions matching a query
-    search_results: Vec<Position>,
+    /// The modification time of the config file when it was last loaded
+    config_modified: Option<SystemTime>,
 
-    /// Current highlighted word from a search
+    /// The colors used to draw the editor
+    theme: Theme,
+
+    /// The name and staged contents of the document's file, used for the git
+    /// markers in the gutter
+    git_staged: Option<(String, String)>,
+
+    /// The matches of the last search, with their length in graphemes
+    search_results: Vec<(Position, usize)>,
+
+    /// The query of the last search, which `n` and `N` go on with
+    last_search: Option<String>,
+
+    /// Whether the last search went forward with `/` rather than backward
+    /// with `?`, the way `n` goes on
+    search_forward: bool,
+
+    /// The queries searched for before, kept across sessions
+    search_history: PromptHistory,
+
+    /// The commands run from the `:` prompt before, kept across sessions
+    command_history: PromptHistory,
+
+    /// The search query whose matches are highlighted, if any
     highlighted_word: Option<String>,
+
+    /// When the last key was pressed, telling how long the cursor has rested
+    idle_since: Instant,
+
+    /// The `idle_since` of the last time the buffers were written by
+    /// `auto_save_delay`, so they are written once per pause
+    auto_saved: Option<Instant>,
+
+    /// Whether the message bar showed the status message when last drawn
+    message_shown: bool,
+
+    /// Whether the occurrences of the word under the cursor are highlighted
+    references_shown: bool,
+
+    /// The second file and its hunks when running in diff mode
+    diff: Option<DiffView>,
+
+    /// The special buffer shown in place of the document, along with the
+    /// document and view it replaced
+    special_buffer: Option<(SpecialBuffer, Buffer)>,
+
+    /// The socket other programs send requests on, when started with
+    /// `--listen`
+    server: Option<Server>,
+
+    /// The files other programs wait on after `--remote --wait`, along with
+    /// the requests to answer once their buffers are closed
+    waiting: Vec<(String, Call)>,
+
+    /// The status and message bars as last drawn, so a screen reader only
+    /// hears them again when they change
+    bars: String,
+
+    /// The mode last announced on the message bar for screen readers
+    announced_mode: Mode,
+
+    /// The keys pressed last, shown with `showkeys`
+    shown_keys: ShownKeys,
 }
 
 impl Editor {
-    pub fn default() -> Self {
-        let args: Vec<String> = env::args().collect();
-        let mut initial_status = String::from("HELP: :w = Save | :q = Quit | / = Search");
-
-        let document = if args.len() > 1 {
-            let file_name = &args[1];
-            if let Ok(doc) = Document::open(file_name) {
-                doc
-            } else {
-                initial_status = format!("ERR: Could not open file: {}", file_name);
-                Document::default()
+    /// Start the editor with the arguments of the command line
+    ///
+    /// # Args
+    ///
+    /// - `args`: The arguments, as parsed by `Args::parse`
+    pub fn new(args: Args) -> Self {
+        let mut initial_status =
+            StatusMessage::from(String::from("HELP: :w = Save | :q = Quit | / = Search"));
+        let server = if args.listen {
+            Server::listen(args.socket.clone())
+                .map_err(|err| {
+                    initial_status =
+                        StatusMessage::with_severity(format!("ERR: {}", err), Severity::Error);
+                })
+                .ok()
+        } else {
+            None
+        };
+
+        let mut diff: Option<DiffView> = None;
+
+        let config = Config::load().unwrap_or_else(|err| {
+            initial_status = StatusMessage::with_severity(format!("ERR: {}", err), Severity::Error);
+            Config::default()
+        });
+
+        let first = args.files.first().map(String::as_str);
+        let dir = first.filter(|file| Path::new(file).is_dir());
+        let document = if args.diff {
+            let (left, right) = (&args.files[0], &args.files[1]);
+            match (Document::open(left), Document::open(right)) {
+                (Ok(left), Ok(right)) => {
+                    diff = Some(DiffView::new(right));
+                    left
+                }
+                _ => {
+                    initial_status = StatusMessage::with_severity(
+                        format!("ERR: Could not open files: {} {}", left, right),
+                        Severity::Error,
+                    );
+                    Document::default()
+                }
+            }
+        } else if args.stdin {
+            // Keys are read from the terminal itself once stdin is used up
+            let mut bytes = Vec::new();
+            match std::io::stdin().read_to_end(&mut bytes) {
+                Ok(_) => {
+                    let document = Document::from_bytes(&bytes);
+                    initial_status = StatusMessage::from(format!(
+                        "Read {} from stdin",
+                        plural(document.len(), "line", "lines")
+                    ));
+                    document
+                }
+                Err(err) => {
+                    initial_status = StatusMessage::with_severity(
+                        format!("ERR: Could not read stdin: {}", err),
+                        Severity::Error,
+                    );
+                    Document::default()
+                }
+            }
+        } else if dir.is_some() {
+            // Listed once the editor is set up
+            Document::default()
+        } else if let Some(file_name) = first {
+            match Document::load(file_name) {
+                Ok(doc) => doc,
+                Err(err) if err.kind() == ErrorKind::NotFound => Document::new_file(file_name),
+                Err(_) => {
+                    initial_status = StatusMessage::with_severity(
+                        format!("ERR: Could not open file: {}", file_name),
+                        Severity::Error,
+                    );
+                    Document::default()
+                }
             }
         } else {
             Document::default()
         };
 
-        Self {
+        let mut editor = Self {
             should_quit: false,
+            exit_code: 0,
             terminal: Terminal::default().expect("Failed to initialize terminal"),
             document,
+            buffers: vec![Buffer::default()],
+            current_buffer: 0,
             cursor_position: Position::default(),
+            goal_column: None,
             offset: Position::default(),
-            status_message: StatusMessage::from(initial_status),
+            highlight_pending: false,
+            marks: Marks::default(),
+            jumps: JumpList::default(),
+            frame: Vec::new(),
+            status_message: initial_status,
+            messages: Vec::new(),
+            inserted: String::new(),
+            command_keys: Vec::new(),
+            command_edits: 0,
+            last_change: Vec::new(),
+            last_find: None,
+            redirect: None,
+            preview: None,
+            popup: None,
+            quickfix: QuickfixList::default(),
+            quickfix_open: false,
+            symbols: None,
+            symbol_index: None,
+            commit: None,
+            man_page: None,
+            grep: None,
+            file_list: None,
+            saving: None,
+            progress_frame: 0,
+            word_lists: Vec::new(),
+            registers: Registers::default(),
+            table_mode: false,
+            replaced: Vec::new(),
+            replay: None,
             mode: Mode::Normal,
-            tab_size: 4,
+            theme: Theme::by_name(&config.theme).unwrap_or_default(),
+            config,
+            config_modified: Config::modified(),
             search_results: vec![],
+            last_search: None,
+            search_forward: true,
+            search_history: PromptHistory::load(
+                local_history::state_dir().map(|dir| dir.join("search_history")),
+            ),
+            command_history: PromptHistory::load(
+                local_history::data_dir().map(|dir| dir.join("history")),
+            ),
             highlighted_word: None,
+            idle_since: Instant::now(),
+            auto_saved: None,
+            message_shown: false,
+            references_shown: false,
+            diff,
+            special_buffer: None,
+            git_staged: None,
+            server,
+            waiting: Vec::new(),
+            bars: String::new(),
+            announced_mode: Mode::Normal,
+            shown_keys: ShownKeys::default(),
+        };
+        editor.apply_project_config();
+        editor.claim_document();
+        editor.refresh_git_staged();
+        editor.restore_last_change();
+        if let Some(line) = &args.line {
+            editor.go_to_line(line);
+        }
+        if !args.diff {
+            // Each of the other files gets a buffer, the first one stays shown
+            for file_name in args.files.iter().skip(1) {
+                editor.edit_file(file_name);
+            }
+            editor.switch_buffer(0);
+            if let Some(dir) = dir {
+                editor.explore(dir);
+            }
+        }
+        if args.read_only {
+            editor.document.set_read_only(true);
+            for buffer in &mut editor.buffers {
+                buffer.document.set_read_only(true);
+            }
+        }
+        if let Some(path) = &args.session {
+            editor.source(path);
+        }
+        editor
+    }
+
+    /// Retrieve the number of rows the document is drawn on, above the
+    /// quickfix window when it is open
+    fn document_height(&self) -> usize {
+        (self.terminal.size().height as usize).saturating_sub(self.quickfix_rows())
+    }
+
+    /// Retrieve the number of rows of the quickfix window: its title and as
+    /// many entries as fit in half of the screen. 0 when it is closed, and in
+    /// diff mode where it is not drawn
+    fn quickfix_rows(&self) -> usize {
+        let entries = self.quickfix.entries().len();
+        if !self.quickfix_open || entries == 0 || self.diff.is_some() {
+            return 0;
+        }
+        let height = self.terminal.size().height as usize;
+        std::cmp::min(
+            entries,
+            std::cmp::min(PREVIEW_HEIGHT, (height / 2).saturating_sub(1)),
+        ) + 1
+    }
+
+    /// Move the cursor to a line, as done by `+N`, `:N`, `gg`, `G` and `NG`,
+    /// keeping its column where the line is long enough and showing the line
+    /// in the middle of the screen
+    ///
+    /// # Args
+    ///
+    /// - `line`: The line number starting at 1, or nothing for the last line
+    fn go_to_line(&mut self, line: &str) {
+        let last = self.document.len().saturating_sub(1);
+        let y = match line {
+            "" => last,
+            number => match number.parse::<usize>() {
+                Ok(number) => std::cmp::min(number.saturating_sub(1), last),
+                Err(_) => return,
+            },
+        };
+        self.jump(Position {
+            x: self.cursor_position.x,
+            y,
+        });
+        self.move_cursor(Key::Null);
+        let height = self.document_height();
+        self.offset.y = y.saturating_sub(height / 2);
+        self.scroll();
+    }
+
+    /// Move the cursor to where the document's file was last changed before
+    /// it was saved, when it is opened again
+    fn restore_last_change(&mut self) {
+        if let Some(position) = self
+            .document
+            .file_name
+            .as_deref()
+            .and_then(local_history::last_change)
+        {
+            self.cursor_position = position;
+            self.move_cursor(Key::Null);
+            self.scroll();
+        }
+    }
+
+    /// Move the cursor to the construct paired with the one under it, as done
+    /// by `%`: the matching bracket, closing tag, or next word of a group such
+    /// as `if`, `else` and `fi`
+    fn jump_to_pair(&mut self) {
+        // The pair may be anywhere, not just on screen
+        self.document.highlight_until(self.document.len());
+        let Position { x, y } = self.cursor_position;
+        let options = self.document.pair_options();
+        let extra = self.config.match_words.get(&self.document.file_type());
+        let groups: Vec<String> = options
+            .groups()
+            .iter()
+            .chain(extra.into_iter().flatten())
+            .cloned()
+            .collect();
+        let pairs = Pairs {
+            groups: &groups,
+            blocks: options.blocks(),
+            tags: options.tags(),
+        };
+        let code = self.pair_lines(0..self.document.len());
+        let lines: Vec<&str> = code.iter().map(String::as_str).collect();
+        let at = (y, self.document.row(y).map_or(0, |row| row.byte_index(x)));
+        match pairs::find(&lines, at, &pairs) {
+            Some((y, index)) => {
+                // Blanked out text may take fewer bytes per grapheme
+                let x = self
+                    .document
+                    .row(y)
+                    .map_or(0, |row| Row::from(&row.as_str()[..index]).len());
+                self.jump(Position { x, y });
+                self.scroll();
+            }
+            None => self.echo("No match".to_string()),
+        }
+    }
+
+    /// Retrieve lines of the document for matching pairs in, with the
+    /// strings and comments blanked out so brackets in them are skipped.
+    /// From inside a string or comment, brackets are matched in the text as
+    /// it is. Strings and comments are only known in highlighted rows, so the
+    /// rows up to the end of the range must be highlighted first
+    ///
+    /// # Args
+    ///
+    /// - `range`: The lines to retrieve
+    fn pair_lines(&self, range: Range<usize>) -> Vec<String> {
+        let Position { x, y } = self.cursor_position;
+        if self.document.row(y).is_some_and(|row| row.is_literal(x)) {
+            let lines = self.document.lines();
+            let end = std::cmp::min(range.end, lines.len());
+            let start = std::cmp::min(range.start, end);
+            return lines[start..end]
+                .iter()
+                .map(|line| line.to_string())
+                .collect();
+        }
+        self.document.code_lines(range)
+    }
+
+    /// Highlight the bracket under the cursor along with the one it pairs
+    /// with, when both are on screen
+    ///
+    /// # Returns
+    ///
+    /// - Whether the highlighted brackets changed
+    fn highlight_match_paren(&mut self) -> bool {
+        let Position { x, y } = self.cursor_position;
+        let top = self.offset.y;
+        let bottom = std::cmp::min(top + self.document_height(), self.document.len());
+        let mut brackets = Vec::new();
+        if let (true, Some(row)) = ((top..bottom).contains(&y), self.document.row(y)) {
+            // Only the screen is searched, so the cursor stays quick to move
+            let code = self.pair_lines(top..bottom);
+            let lines: Vec<&str> = code.iter().map(String::as_str).collect();
+            let found = pairs::match_bracket(&lines, (y - top, row.byte_index(x)));
+            if let Some((found, index)) = found {
+                let line = self.document.row(found + top).map_or("", Row::as_str);
+                brackets.push(self.cursor_position.clone());
+                brackets.push(Position {
+                    x: Row::from(&line[..index]).len(),
+                    y: found + top,
+                });
+            }
+        }
+        self.document.set_match_paren(brackets)
+    }
+
+    /// Move the cursor somewhere else, remembering where it was so `''` can
+    /// go back
+    ///
+    /// # Args
+    ///
+    /// - `to`: The new position of the cursor
+    fn jump(&mut self, to: Position) {
+        let from = std::mem::replace(&mut self.cursor_position, to);
+        self.jumps.push(Jump {
+            file_name: self.document.file_name.clone(),
+            position: from.clone(),
+        });
+        self.marks.jump = Some(from);
+    }
+
+    /// Goes back or forth through the jump list, as done by `Ctrl-O` and
+    /// `Ctrl-I`, opening the buffer of the jump when it is another one
+    ///
+    /// # Args
+    ///
+    /// - `back`: Whether to go to an older jump rather than a newer one
+    fn go_through_jumps(&mut self, back: bool) {
+        let target = if back {
+            let from = Jump {
+                file_name: self.document.file_name.clone(),
+                position: self.cursor_position.clone(),
+            };
+            self.jumps.back(from).cloned()
+        } else {
+            self.jumps.forward().cloned()
+        };
+        let target = match target {
+            Some(target) => target,
+            None => return,
+        };
+        if target.file_name != self.document.file_name {
+            match &target.file_name {
+                Some(file_name) => self.edit_file(file_name),
+                None => {
+                    self.echo("The buffer of the jump has no file".to_string());
+                    return;
+                }
+            }
+        }
+        self.cursor_position = target.position;
+        self.move_cursor(Key::Null);
+    }
+
+    /// Move the cursor to a mark: `.` for the last change, `'` or `` ` `` for
+    /// the position before the latest jump, `^` for where Insert mode was
+    /// last left and `a` to `z` for the marks set with `m`
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the mark
+    /// - `exact`: Whether to go to the column of the mark (`` ` ``) rather
+    ///   than the first non-blank character of its line (`'`)
+    fn jump_to_mark(&mut self, name: char, exact: bool) {
+        let mark = match name {
+            '.' => self.document.last_change(),
+            '\'' | '`' => self.marks.jump.clone(),
+            '^' => self.marks.insert.clone(),
+            'a'..='z' => self.document.mark(name),
+            _ => {
+                self.echo_warning(format!("Unknown mark: {}", name));
+                return;
+            }
+        };
+        let mut position = match mark {
+            Some(position) => position,
+            None => {
+                self.echo_warning(format!("Mark not set: {}", name));
+                return;
+            }
+        };
+        if !exact {
+            position.x = self.document.row(position.y).map_or(0, |row| {
+                row.as_str()
+                    .chars()
+                    .take_while(|c| c.is_whitespace())
+                    .count()
+            });
         }
+        self.jump(position);
+        self.move_cursor(Key::Null);
     }
 
     /// Run the `Editor` until an error is encountered or a quit signal is received
@@ -113,10 +831,13 @@ impl Editor {
     /// # Exits
     /// - On `ctrl-q` keypress
     //
+    /// # Returns
+    /// - The status to exit the process with
+    //
     /// # Panics
     /// - On error when refreshing the screen
     /// - On error when processing a keypress
-    pub fn run(&mut self) {
+    pub fn run(&mut self) -> i32 {
         loop {
             if let Err(err) = self.refresh_screen() {
                 error(err);
@@ -124,276 +845,4669 @@ impl Editor {
             if let Err(err) = self.process_keypress() {
                 error(err);
             }
+            if self.config.watch && Config::modified() != self.config_modified {
+                self.reload_config();
+            }
             if self.should_quit {
+                // A file cut short while being written would lose its text
+                let failed = self.saving.take().and_then(|(file_name, _, job)| {
+                    job.wait()
+                        .err()
+                        .map(|err| format!("Could not write {}: {}", file_name, err))
+                });
+                let result = match self.exit_code {
+                    0 => Ok(()),
+                    _ => Err("The editor quit with :cquit".to_string()),
+                };
+                for (_, call) in self.waiting.drain(..) {
+                    call.reply(result.clone());
+                }
+                // Dropping the server removes its socket
+                self.server = None;
+                self.release_documents();
                 // self.cursor_position = Position { x: 1, y: 1 };
                 self.draw_rows();
                 Terminal::clear_screen();
-                break;
+                if let Some(err) = failed {
+                    eprintln!("donovim: {}\r", err);
+                    break 1;
+                }
+                break self.exit_code;
             }
         }
     }
 
     /// Handle given command from a `Normal` mode prompt
     fn process_command(&mut self) {
-        let input = self.prompt(":", |_, _, _| {}).unwrap_or(None);
-
-        if let Some(command) = input {
-            match command.as_ref() {
-                "w" => self.save(),
-                "q" => {
-                    if self.document.is_dirty() {
-                        self.status_message = StatusMessage::from(
-                            "Document has unsaved changes! Add ! to override.".to_string(),
-                        );
-                        return;
-                    }
-                    self.should_quit = true;
+        let mut history = std::mem::take(&mut self.command_history);
+        let input = self
+            .prompt_with_history(":", &mut history, |_, _, _| None)
+            .unwrap_or(None);
+        self.command_history = history;
+        match input {
+            Some(input) => self.run_command(&input),
+            None => self.echo("No command passed".to_string()),
+        }
+    }
+
+    /// Run a command of the command line
+    ///
+    /// # Args
+    ///
+    /// - `input`: The text typed after `:`
+    fn run_command(&mut self, input: &str) {
+        let command = match command::parse(input) {
+            Ok(command) => command,
+            Err(err) => {
+                self.echo_error(err);
+                return;
+            }
+        };
+        let Command {
+            range,
+            name,
+            bang,
+            args,
+        } = command;
+
+        match name {
+            "" => match self.line_range(range) {
+                Some(lines) => self.go_to_line(&lines.end.to_string()),
+                None => self.echo_warning(format!("Invalid range: {}", range)),
+            },
+            "write"
+                if args.trim().is_empty()
+                    && self.special_buffer.is_none()
+                    && self.document.file_name.is_some() =>
+            {
+                self.save_in_background()
+            }
+            "write" => self.write(args, bang),
+            "quit" if self.special_buffer.is_some() => {
+                self.close_special_buffer();
+            }
+            "quit" | "wq" | "cquit" if self.special_buffer.is_none() && self.is_waited_on() => {
+                self.finish_waited(name, args, bang)
+            }
+            "quit" | "qall" => self.quit(bang),
+            "cquit" => {
+                self.exit_code = 1;
+                self.should_quit = true;
+            }
+            "wq" => {
+                let in_special_buffer = self.special_buffer.is_some();
+                self.write(args, bang);
+                if in_special_buffer || self.document.is_dirty() {
+                    return;
                 }
-                "q!" => self.should_quit = true,
-                "wq" => {
-                    self.save();
+                let unsaved = self.unsaved_buffers();
+                if !unsaved.is_empty() {
+                    self.echo_warning(format!("Unsaved changes in {}!", unsaved.join(", ")));
+                    return;
+                }
+                self.should_quit = true;
+            }
+            "wall" => {
+                self.write_all();
+            }
+            "xall" => {
+                if self.write_all() {
                     self.should_quit = true;
                 }
-                _ => {
-                    self.status_message =
-                        StatusMessage::from(format!("Unrecognized Command: {:?}", command))
+            }
+            "edit" if args.is_empty() => self.reload(bang),
+            "edit" => self.edit_file(args.trim_end()),
+            "bnext" => self.switch_buffer((self.current_buffer + 1) % self.buffers.len()),
+            "bprevious" => {
+                let count = self.buffers.len();
+                self.switch_buffer((self.current_buffer + count - 1) % count)
+            }
+            "buffer" => match args.trim_end().parse::<usize>() {
+                Ok(number) if number > 0 => self.switch_buffer(number - 1),
+                _ => self.echo(format!("Invalid buffer: {}", args)),
+            },
+            "ls" | "buffers" => self.list_buffers(),
+            "delete" => self.delete_lines(range, args.trim_end()),
+            "nohlsearch" => self.highlighted_word = None,
+            "normal" => self.normal(range, args),
+            "substitute" => self.substitute(range, args, false),
+            "S" => self.substitute(range, args, true),
+            "set" => self.set(args.trim_end()),
+            "messages" => {
+                for message in self.messages.clone() {
+                    self.output(message);
                 }
             }
-        } else {
-            self.status_message = StatusMessage::from("No command passed".to_string())
+            "version" => self.output(version::describe()),
+            "mksession" => self.make_session(args.trim_end(), bang),
+            "source" => self.source(args.trim_end()),
+            "redir" if args.trim_end() == "END" => self.end_redirect(),
+            "redir" => self.redirect = Some(Vec::new()),
+            "ConflictTakeOurs" => self.resolve_conflict(true),
+            "ConflictTakeTheirs" => self.resolve_conflict(false),
+            "grep" | "Gstatus" | "Gcommit" | "Symbols" if !self.auto_write(name) => {}
+            "Gwrite" => self.git_write(),
+            "Gstatus" => self.git_status(),
+            "Gcommit" => self.git_commit(),
+            "GitStageHunk" => self.git_stage_hunk(),
+            "GitRevertHunk" => self.git_revert_hunk(),
+            "LocalHistory" => self.local_history(),
+            "recover" => self.recover(bang),
+            "RecoverLast" => self.recover_last(),
+            "diffoff" => self.diff_off(),
+            "ConfigReload" => self.reload_config(),
+            "Theme" => self.pick_theme(),
+            "colorscheme" => self.set_theme(args.trim_end()),
+            "Trust" => self.trust_project(),
+            "Symbols" => self.pick_symbol(),
+            "grep" => self.grep(args.trim_end(), false),
+            "vimgrep" => self.grep(args.trim_end(), true),
+            "cnext" => self.step_quickfix(true),
+            "cprevious" => self.step_quickfix(false),
+            "copen" if self.quickfix.entries().is_empty() => {
+                self.echo_warning("No quickfix list".to_string())
+            }
+            "copen" => self.quickfix_open = true,
+            "cclose" => self.quickfix_open = false,
+            "Notes" => self.notes(),
+            "Explore" => self.explore_command(args.trim_end()),
+            "Preview" => self.preview(args.trim_end()),
+            "Table" => {
+                self.table_mode = !self.table_mode;
+                if self.table_mode {
+                    self.align_table();
+                    self.echo("Table mode on".to_string());
+                } else {
+                    self.echo("Table mode off".to_string());
+                }
+            }
+            "EvalSelection" => self.evaluate_expression(false),
+            "ToHex" => self.convert_number(16),
+            "ToDec" => self.convert_number(10),
+            "ToOct" => self.convert_number(8),
+            "ToBin" => self.convert_number(2),
+            "ConvertCase" => match Case::from_name(args.trim_end()) {
+                Some(case) => self.convert_case(case),
+                None => self.echo(format!("Invalid case: {}", args.trim_end())),
+            },
+            _ => self.echo_warning(format!("Unrecognized Command: {:?}", input)),
         }
     }
 
-    /// Handles Keypresses in Normal mode
+    /// Changes a setting for the session, as done by `:set`
     ///
     /// # Args
     ///
-    /// - `c`: The character received from the user
-    fn process_normal_keypress(&mut self, c: char) {
-        match c {
-            'a' => {
-                self.move_cursor(Key::Right);
-                self.mode = Mode::Insert;
+    /// - `setting`: The setting, e.g. `wrap`, `nowrap` or `textwidth=80`
+    fn set(&mut self, setting: &str) {
+        match setting {
+            "all" => match self.config.settings() {
+                Ok(settings) => {
+                    for setting in settings.lines().filter(|line| !line.is_empty()) {
+                        self.output(setting.to_string());
+                    }
+                }
+                Err(err) => self.echo_error(err),
+            },
+            "hlsearch" | "hls" => {
+                self.config.hl_search = true;
+                self.highlighted_word = self
+                    .last_search
+                    .as_deref()
+                    .map(|query| self.with_case(query));
             }
-            'i' => self.mode = Mode::Insert,
-            'j' => self.move_cursor(Key::Down),
-            'k' => self.move_cursor(Key::Up),
-            'h' => self.move_cursor(Key::Left),
-            'l' => self.move_cursor(Key::Right),
-            'w' => self.move_cursor(Key::Char('w')),
-            'o' => {
-                self.move_cursor(Key::Down);
-                self.document.insert(&self.cursor_position, '\n');
-                self.mode = Mode::Insert;
+            "nohlsearch" | "nohls" => {
+                self.config.hl_search = false;
+                self.highlighted_word = None;
             }
-            'n' => {
-                if let Some(new_pos) = self
-                    .search_results
-                    .iter()
-                    .find(|&pos| pos.y > self.cursor_position.y)
-                {
-                    self.cursor_position = new_pos.clone();
-                };
+            "showkeys" => self.config.show_keys = true,
+            "noshowkeys" => self.config.show_keys = false,
+            "screenreader" | "sr" => {
+                self.config.screen_reader = true;
+                self.refresh_git_staged();
+                self.frame.clear();
             }
-            'N' => {
-                if let Some(new_pos) = self
-                    .search_results
-                    .iter()
-                    .rfind(|&pos| pos.y < self.cursor_position.y)
-                {
-                    self.cursor_position = new_pos.clone();
-                };
+            "noscreenreader" | "nosr" => {
+                self.config.screen_reader = false;
+                self.refresh_git_staged();
+                self.frame.clear();
             }
-            ':' => self.process_command(),
-            '/' => self.search(),
-            _ => (),
+            "ignorecase" | "ic" => self.config.ignore_case = true,
+            "noignorecase" | "noic" => self.config.ignore_case = false,
+            "smartcase" | "scs" => self.config.smart_case = true,
+            "nosmartcase" | "noscs" => self.config.smart_case = false,
+            "autowrite" | "aw" => self.config.auto_write = true,
+            "noautowrite" | "noaw" => self.config.auto_write = false,
+            "wrap" => self.config.wrap = true,
+            "nowrap" => self.config.wrap = false,
+            "autopairs" | "ap" => self.config.auto_pairs = true,
+            "noautopairs" | "noap" => self.config.auto_pairs = false,
+            "autoindent" | "ai" => self.config.auto_indent = true,
+            "noautoindent" | "noai" => self.config.auto_indent = false,
+            "aligncolumns" => self.config.align_columns = true,
+            "noaligncolumns" => self.config.align_columns = false,
+            "expandtab" | "et" => self.config.expand_tab = true,
+            "noexpandtab" | "noet" => self.config.expand_tab = false,
+            "bomb" => self.document.set_bom(true),
+            "nobomb" => self.document.set_bom(false),
+            _ if setting.starts_with("fileencoding=") || setting.starts_with("fenc=") => {
+                let encoding = setting.split('=').nth(1).unwrap_or_default().trim();
+                if !self.document.set_encoding(encoding) {
+                    self.echo_warning(format!("Invalid file encoding: {}", encoding));
+                }
+            }
+            _ if setting.starts_with("fileformat=") || setting.starts_with("ff=") => {
+                let format = setting.split('=').nth(1).unwrap_or_default().trim();
+                if !self.document.set_file_format(format) {
+                    self.echo_warning(format!("Invalid file format: {}", format));
+                }
+            }
+            _ if setting.starts_with("redrawtime=") || setting.starts_with("rdt=") => {
+                match setting.split('=').nth(1).map(|time| time.trim().parse()) {
+                    Some(Ok(time)) => self.config.redraw_time = time,
+                    _ => self.echo_warning(format!("Invalid redraw time: {}", setting)),
+                }
+            }
+            _ if setting.starts_with("tabstop=") || setting.starts_with("ts=") => {
+                match setting.split('=').nth(1).map(|size| size.trim().parse()) {
+                    Some(Ok(size)) if size > 0 => self.config.tab_size = size,
+                    _ => self.echo_warning(format!("Invalid tab stop: {}", setting)),
+                }
+            }
+            _ if setting.starts_with("shiftwidth=") || setting.starts_with("sw=") => {
+                match setting.split('=').nth(1).map(|width| width.trim().parse()) {
+                    Some(Ok(width)) => self.config.shift_width = width,
+                    _ => self.echo_warning(format!("Invalid shift width: {}", setting)),
+                }
+            }
+            _ if setting.starts_with("autosave=") => {
+                match setting.split('=').nth(1).map(|delay| delay.trim().parse()) {
+                    Some(Ok(delay)) => self.config.auto_save_delay = delay,
+                    _ => self.echo_warning(format!("Invalid autosave delay: {}", setting)),
+                }
+            }
+            _ if setting.starts_with("textwidth=") || setting.starts_with("tw=") => {
+                match setting.split('=').nth(1).map(|width| width.trim().parse()) {
+                    Some(Ok(width)) => self.config.text_width = width,
+                    _ => self.echo_warning(format!("Invalid text width: {}", setting)),
+                }
+            }
+            _ => self.echo_error(format!("Unknown option: {}", setting)),
         }
     }
 
-    /// Handles Keypresses in Insert mode
+    /// Writes the document, or a copy of it to another file, as done by `:w`
     ///
     /// # Args
     ///
-    /// - `c`: The character to process
-    fn process_insert_keypress(&mut self, c: char) {
-        if c == '\t' {
-            // TODO: Handle this better
-            for _ in 0..self.tab_size {
-                self.document.insert(&self.cursor_position, ' ')
+    /// - `file_name`: The file to write to, empty for the document's own
+    /// - `force`: Whether to overwrite another file that already exists
+    fn write(&mut self, file_name: &str, force: bool) {
+        let file_name = file_name.trim_end();
+        if file_name.is_empty() || self.document.file_name.is_none() {
+            if !file_name.is_empty() {
+                self.document.file_name = Some(file_name.to_string());
             }
-        } else {
-            self.document.insert(&self.cursor_position, c);
+            self.save();
+            return;
         }
-
-        if c == '\n' {
-            self.move_cursor(Key::Down);
-        } else if c == '\t' {
-            self.move_cursor(Key::Char(c))
-        } else {
-            self.move_cursor(Key::Right);
+        if !force && Path::new(file_name).exists() {
+            self.echo_error(format!("File exists (add ! to override): {}", file_name));
+            return;
+        }
+        match self.document.write_to(file_name) {
+            Ok(()) => self.echo(format!("Written to {}", file_name)),
+            Err(err) => self.echo_error(format!("Could not write {}: {}", file_name, err)),
         }
     }
 
-    /// Processes a keypress from the terminal, handling the key depending on the
-    /// current editor mode
+    /// Reads the document's file again, as done by `:e`, keeping the cursor
+    /// on the same line where the file is still long enough
+    ///
+    /// # Args
+    ///
+    /// - `force`: Whether to discard unsaved changes
+    fn reload(&mut self, force: bool) {
+        let file_name = match &self.document.file_name {
+            Some(file_name) if self.special_buffer.is_none() => file_name.clone(),
+            _ => {
+                self.echo_warning("No file name".to_string());
+                return;
+            }
+        };
+        if !force && self.document.is_dirty() {
+            self.echo_warning("Document has unsaved changes! Add ! to override.".to_string());
+            return;
+        }
+        match Document::load(&file_name) {
+            Ok(document) => {
+                self.document = document;
+                self.move_cursor(Key::Null);
+                self.refresh_git_staged();
+                self.echo(format!("Reloaded {}", file_name));
+            }
+            Err(err) => self.echo_error(format!("Could not read {}: {}", file_name, err)),
+        }
+    }
+
+    /// Deletes whole lines into a register, as done by `:d`
+    ///
+    /// # Args
+    ///
+    /// - `range`: The lines to delete, the cursor line if empty
+    /// - `register`: The name of the register, the unnamed one if empty
+    fn delete_lines(&mut self, range: &str, register: &str) {
+        let lines = match self.line_range(range) {
+            Some(lines) if lines.start < self.document.len() => lines,
+            _ => {
+                self.echo_warning(format!("Invalid range: {}", range));
+                return;
+            }
+        };
+        let name = match register.chars().next() {
+            None => registers::UNNAMED,
+            Some(name) if Registers::is_valid(name) && register.len() == 1 => name,
+            Some(_) => {
+                self.echo("Invalid register".to_string());
+                return;
+            }
+        };
+
+        let end = std::cmp::min(lines.end, self.document.len());
+        let text = self.document.lines()[lines.start..end]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        self.registers.set(
+            name,
+            Register {
+                lines: text,
+                linewise: true,
+            },
+        );
+        self.document.delete_lines(lines.start..end);
+        self.cursor_position = Position {
+            x: 0,
+            y: lines.start,
+        };
+        self.move_cursor(Key::Null);
+        if end - lines.start > 2 {
+            self.echo(format!("{} fewer lines", end - lines.start));
+        }
+    }
+
+    /// Let the user find a definition anywhere in the project and open it
+    fn pick_symbol(&mut self) {
+        // The symbols are indexed in the background, ctags may take a while
+        // on large projects
+        if self.symbols.is_none() {
+            if self.symbol_index.is_none() {
+                self.symbol_index = Some(Job::spawn_with_progress("Indexing symbols", |_| {
+                    symbols::load()
+                }));
+            }
+            self.echo("Indexing symbols, :Symbols lists them once done".to_string());
+            return;
+        }
+
+        let query = self
+            .prompt("Symbol: ", |editor, _, query| {
+                let all = editor.symbols.as_deref().unwrap_or_default();
+                Some(format!(
+                    "[{} symbols]",
+                    symbols::filter(all, query, usize::MAX).len()
+                ))
+            })
+            .unwrap_or(None);
+        let query = if let Some(query) = query {
+            query
+        } else {
+            return;
+        };
+
+        let all = self.symbols.as_deref().unwrap_or_default();
+        let found: Vec<Symbol> = symbols::filter(all, &query, MAX_PICKED_SYMBOLS)
+            .into_iter()
+            .cloned()
+            .collect();
+        if found.is_empty() {
+            self.echo(format!("No symbol matching {}", query));
+            return;
+        }
+
+        let items: Vec<String> = found
+            .iter()
+            .map(|symbol| format!("{} ({}:{})", symbol.name, symbol.file_name, symbol.line + 1))
+            .collect();
+        if let Some(index) = self.pick("Symbol: ", &items, 0, |_, _| {}).unwrap_or(None) {
+            self.open_file(&found[index].file_name, found[index].line);
+        }
+    }
+
+    /// Open a file picked by fuzzy matching its path, as done by `Ctrl-P`. The
+    /// files matching what is typed are listed over the document. Listing the
+    /// files of a large project takes a while, so they are listed in the
+    /// background and `finish_jobs` opens the picker with `open_picked_file`
+    fn find_file(&mut self) {
+        if self.file_list.is_none() {
+            self.file_list = Some(Job::spawn_with_progress("Listing files", |_| {
+                Ok(finder::files())
+            }));
+        }
+    }
+
+    /// Let the user pick one of the files listed for `Ctrl-P` and open it
+    ///
+    /// # Args
+    ///
+    /// - `files`: The files to choose from
+    fn open_picked_file(&mut self, files: &[String]) {
+        if files.is_empty() {
+            self.echo("No files found".to_string());
+            return;
+        }
+        let picked = self.pick_file(files).unwrap_or(None);
+        self.popup = None;
+        self.status_message = StatusMessage::from(String::new());
+        if let Some(file_name) = picked {
+            self.edit_file(&file_name);
+        }
+    }
+
+    /// Let the user pick a file: what is typed filters the files, `Ctrl-N`
+    /// and `Ctrl-P` move the selection and `Enter` confirms it
+    ///
+    /// # Args
+    ///
+    /// - `files`: The files to choose from
     ///
     /// # Returns
     ///
-    /// - Unit or any Error encountered processing the key
-    fn process_keypress(&mut self) -> Result<(), std::io::Error> {
-        let pressed_key: Key = Terminal::read_key()?;
-        match pressed_key {
-            Key::Esc => self.mode = Mode::Normal,
-            Key::Char(c) => {
-                if self.mode == Mode::Insert {
-                    self.process_insert_keypress(c);
-                } else {
-                    self.process_normal_keypress(c);
+    /// - The file picked, or `None` if the user cancelled
+    fn pick_file(&mut self, files: &[String]) -> Result<Option<String>, std::io::Error> {
+        let mut query = String::new();
+        let mut selected = 0;
+        loop {
+            let found = finder::filter(files, &query);
+            let shown = std::cmp::min(found.len(), PREVIEW_HEIGHT);
+            selected = std::cmp::min(selected, shown.saturating_sub(1));
+            self.popup = Some(Popup {
+                title: format!(" [Files] {}/{}", found.len(), files.len()),
+                items: found.iter().take(shown).map(ToString::to_string).collect(),
+                selected,
+            });
+            self.status_message = StatusMessage::from(format!("File: {}", query));
+            self.refresh_screen()?;
+
+            match self.read_key()? {
+                Key::Char('\n') => return Ok(found.get(selected).map(ToString::to_string)),
+                Key::Esc => return Ok(None),
+                Key::Ctrl('n') | Key::Down if shown > 0 => selected = (selected + 1) % shown,
+                Key::Ctrl('p') | Key::Up if shown > 0 => selected = (selected + shown - 1) % shown,
+                Key::Backspace => {
+                    query.pop();
+                    selected = 0;
                 }
+                Key::Char(c) if !c.is_control() => {
+                    query.push(c);
+                    selected = 0;
+                }
+                _ => (),
             }
-            Key::Delete => self.document.delete(&self.cursor_position),
-            Key::Backspace => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Backspace);
-                    self.document.delete(&self.cursor_position);
+        }
+    }
+
+    /// Search for a pattern and collect the matches in the quickfix list, as
+    /// done by `:grep` in the files under the working directory and by
+    /// `:vimgrep` in the open buffers, unsaved changes included. The cursor
+    /// goes to the first match and the quickfix window opens. The files are
+    /// searched in the background, `Ctrl-C` stops the search
+    ///
+    /// # Args
+    ///
+    /// - `query`: The pattern, following the case settings of `/`
+    /// - `buffers`: Whether to search the open buffers rather than the files
+    fn grep(&mut self, query: &str, buffers: bool) {
+        if query.is_empty() {
+            self.echo_error("Missing pattern");
+            return;
+        }
+        let pattern = Pattern::new(&self.with_case(query), false);
+        let mut entries = Vec::new();
+        if buffers {
+            let shown = self.special_buffer.is_none().then_some(&self.document);
+            let aside = self
+                .special_buffer
+                .as_ref()
+                .map(|(_, buffer)| &buffer.document);
+            let documents = shown
+                .into_iter()
+                .chain(aside)
+                .chain(self.buffers.iter().map(|buffer| &buffer.document));
+            for document in documents {
+                if entries.len() >= quickfix::MAX_ENTRIES {
+                    break;
+                }
+                if let Some(file_name) = &document.file_name {
+                    quickfix::search(file_name, &document.lines(), &pattern, &mut entries);
                 }
             }
-            Key::Up
-            | Key::Down
-            | Key::Left
-            | Key::Right
-            | Key::PageUp
-            | Key::PageDown
-            | Key::End
-            | Key::Home => self.move_cursor(pressed_key),
-            _ => (),
+        } else {
+            if self.grep.is_some() {
+                self.echo_warning("A search is already running".to_string());
+                return;
+            }
+            // Searching a large project takes a while, the files are searched
+            // in the background and the results shown by `finish_jobs`
+            let job = Job::spawn_with_progress("Searching", move |progress| {
+                let files = finder::files();
+                for (done, file_name) in files.iter().enumerate() {
+                    if progress.is_cancelled() || entries.len() >= quickfix::MAX_ENTRIES {
+                        break;
+                    }
+                    progress.update(done, Some(files.len()));
+                    // Binary files are not searched
+                    if let Ok(contents) = std::fs::read_to_string(file_name) {
+                        let lines: Vec<&str> = contents.lines().collect();
+                        quickfix::search(file_name, &lines, &pattern, &mut entries);
+                    }
+                }
+                Ok(entries)
+            });
+            self.grep = Some((query.to_string(), job));
+            return;
+        }
+        self.show_matches(query, entries);
+    }
+
+    /// Fill the quickfix list with the matches of a search and go to the
+    /// first one
+    ///
+    /// # Args
+    ///
+    /// - `query`: The pattern searched for
+    /// - `entries`: The matches
+    fn show_matches(&mut self, query: &str, entries: Vec<quickfix::Entry>) {
+        if entries.is_empty() {
+            self.echo_warning(format!("Pattern not found: {}", query));
+            return;
+        }
+        self.quickfix = QuickfixList::new(entries);
+        self.quickfix_open = true;
+        self.go_to_quickfix();
+    }
+
+    /// Go to the next or the previous entry of the quickfix list, as done by
+    /// `:cnext` and `:cprevious`
+    ///
+    /// # Args
+    ///
+    /// - `forward`: Whether to go to the next entry
+    fn step_quickfix(&mut self, forward: bool) {
+        if self.quickfix.entries().is_empty() {
+            self.echo_warning("No quickfix list".to_string());
+        } else if self.quickfix.step(forward).is_none() {
+            self.echo_warning("No more items".to_string());
+        } else {
+            self.go_to_quickfix();
+        }
+    }
+
+    /// Move the cursor to the entry of the quickfix list last gone to
+    fn go_to_quickfix(&mut self) {
+        let (file_name, position, text) = match self.quickfix.current() {
+            Some(entry) => (
+                entry.file_name.clone(),
+                entry.position.clone(),
+                entry.text.clone(),
+            ),
+            None => return,
+        };
+        self.jump(self.cursor_position.clone());
+        self.open_file(&file_name, position.y);
+        if self.document.file_name.as_deref() == Some(&file_name[..]) {
+            self.cursor_position = position;
+            self.move_cursor(Key::Null);
+            self.scroll();
+        }
+        self.echo(format!(
+            "({} of {}): {}",
+            self.quickfix.index() + 1,
+            self.quickfix.entries().len(),
+            text.trim()
+        ));
+    }
+
+    /// Switch to the buffer of a file and move the cursor to a line of it
+    ///
+    /// # Args
+    ///
+    /// - `file_name`: The path of the file
+    /// - `line`: The line to move the cursor to
+    fn open_file(&mut self, file_name: &str, line: usize) {
+        self.edit_file(file_name);
+        if self.document.file_name.as_deref() == Some(file_name) {
+            self.cursor_position = Position { x: 0, y: line };
+            self.move_cursor(Key::Null);
+            self.scroll();
+        }
+    }
+
+    /// Switch to the buffer of a file, opening a new buffer if the file is
+    /// not open yet. A file that does not exist is created on save
+    ///
+    /// # Args
+    ///
+    /// - `file_name`: The path of the file
+    fn edit_file(&mut self, file_name: &str) {
+        if Path::new(file_name).is_dir() {
+            self.explore(file_name);
+            return;
         }
+        self.close_special_buffer();
+        if self.document.file_name.as_deref() == Some(file_name) {
+            return;
+        }
+        if let Some(index) = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.document.file_name.as_deref() == Some(file_name))
+        {
+            self.switch_buffer(index);
+            return;
+        }
+
+        // Only a missing file is created on save, other files must not be
+        // replaced by an empty buffer
+        let document = match Document::load(file_name) {
+            Ok(document) => document,
+            Err(err) if err.kind() == ErrorKind::NotFound => Document::new_file(file_name),
+            Err(err) => {
+                self.echo_error(format!("Could not open {}: {}", file_name, err));
+                return;
+            }
+        };
+        // The empty buffer of an editor started without a file is reused
+        if self.document.file_name.is_none() && self.document.is_empty() {
+            self.document = document;
+            self.cursor_position = Position::default();
+            self.offset = Position::default();
+            self.marks = Marks::default();
+            self.diff = None;
+            self.refresh_git_staged();
+            self.restore_last_change();
+            self.claim_document();
+            return;
+        }
+
+        self.buffers.push(Buffer {
+            document,
+            ..Buffer::default()
+        });
+        self.switch_buffer(self.buffers.len() - 1);
+        self.restore_last_change();
+        self.claim_document();
+    }
+
+    /// Marks the document's file as open in this editor. Warns when another
+    /// editor has it open too, or when changes of it were left in the
+    /// recovery area by an editor that did not quit cleanly
+    fn claim_document(&mut self) {
+        let file_name = match &self.document.file_name {
+            Some(file_name) => file_name.clone(),
+            None => return,
+        };
+        if let Some(pid) = recovery::claim(&file_name) {
+            self.echo_warning(format!(
+                "{} is open in another editor (process {})",
+                file_name, pid
+            ));
+        } else if recovery::has_changes(&file_name) {
+            self.echo_warning(format!(
+                "Found unsaved changes of {}, :recover to restore them or :recover! to drop them",
+                file_name
+            ));
+        }
+    }
+
+    /// Marks the files of every buffer as no longer open in this editor
+    fn release_documents(&self) {
+        let aside = self
+            .special_buffer
+            .as_ref()
+            .map(|(_, buffer)| &buffer.document);
+        let documents = std::iter::once(&self.document)
+            .chain(aside)
+            .chain(self.buffers.iter().map(|buffer| &buffer.document));
+        for file_name in documents.filter_map(|document| document.file_name.as_deref()) {
+            recovery::release(file_name);
+        }
+    }
+
+    /// Make another buffer the current one, keeping the cursor and scroll
+    /// position of each
+    ///
+    /// # Args
+    ///
+    /// - `index`: The index of the buffer
+    fn switch_buffer(&mut self, index: usize) {
+        if index >= self.buffers.len() {
+            self.echo(format!("No buffer {}", index + 1));
+            return;
+        }
+        self.close_special_buffer();
+        if index == self.current_buffer {
+            return;
+        }
+
+        self.diff = None;
+        self.buffers[self.current_buffer] = self.take_view();
+        let buffer = std::mem::take(&mut self.buffers[index]);
+        self.restore_view(buffer);
+        self.current_buffer = index;
+        self.refresh_git_staged();
         self.scroll();
-        Ok(())
     }
 
-    /// Save the document. Abort on empty prompt or erorr
-    fn save(&mut self) {
-        if self.document.file_name.is_none() {
-            let new_name: Option<String> = self.prompt("Save as: ", |_, _, _| {}).unwrap_or(None);
+    /// Take the current document out of the editor along with its view,
+    /// leaving an empty document at the top
+    fn take_view(&mut self) -> Buffer {
+        Buffer {
+            document: std::mem::take(&mut self.document),
+            cursor_position: std::mem::take(&mut self.cursor_position),
+            offset: std::mem::take(&mut self.offset),
+            marks: std::mem::take(&mut self.marks),
+        }
+    }
 
-            if new_name.is_none() {
-                self.status_message = StatusMessage::from("Save aborted.".to_string());
+    /// Show a document again exactly as it was seen when `take_view` took it
+    ///
+    /// # Args
+    ///
+    /// - `buffer`: The document and its view
+    fn restore_view(&mut self, buffer: Buffer) {
+        self.document = buffer.document;
+        self.cursor_position = buffer.cursor_position;
+        self.goal_column = None;
+        self.offset = buffer.offset;
+        self.marks = buffer.marks;
+    }
+
+    /// Show the open buffers, marking the current one with `%` and buffers
+    /// with unsaved changes with `+`
+    fn list_buffers(&mut self) {
+        let lines: Vec<String> = (0..self.buffers.len())
+            .map(|index| {
+                let document = if index == self.current_buffer {
+                    &self.document
+                } else {
+                    &self.buffers[index].document
+                };
+                format!(
+                    "{:>3}{}{} {}",
+                    index + 1,
+                    if index == self.current_buffer {
+                        "%"
+                    } else {
+                        " "
+                    },
+                    if document.is_dirty() { "+" } else { " " },
+                    document.file_name.as_deref().unwrap_or("[No Name]")
+                )
+            })
+            .collect();
+        for line in lines {
+            self.output(line);
+        }
+    }
+
+    /// Save the working directory, the buffers that have a file and the view
+    /// of each to a session file, as done by `:mksession`
+    ///
+    /// # Args
+    ///
+    /// - `path`: The session file, empty for `Session.toml`
+    /// - `bang`: Whether to replace an existing file
+    fn make_session(&mut self, path: &str, bang: bool) {
+        let path = if path.is_empty() {
+            session::DEFAULT_FILE
+        } else {
+            path
+        };
+        if !bang && Path::new(path).exists() {
+            self.echo_warning(format!("{} exists! Add ! to overwrite.", path));
+            return;
+        }
+        let dir = match env::current_dir() {
+            Ok(dir) => dir.to_string_lossy().to_string(),
+            Err(err) => {
+                self.echo_error(format!("Could not read working directory: {}", err));
                 return;
             }
+        };
 
-            self.document.file_name = new_name;
+        let mut buffers = Vec::new();
+        let mut current = 0;
+        for index in 0..self.buffers.len() {
+            // The view of the current buffer is set aside while a special
+            // buffer is shown
+            let view = if index == self.current_buffer {
+                self.special_buffer.as_ref().map(|(_, buffer)| buffer)
+            } else {
+                Some(&self.buffers[index])
+            };
+            let (document, cursor, offset) = match view {
+                Some(buffer) => (&buffer.document, &buffer.cursor_position, &buffer.offset),
+                None => (&self.document, &self.cursor_position, &self.offset),
+            };
+            let file_name = match &document.file_name {
+                Some(file_name) => file_name.clone(),
+                None => continue,
+            };
+            if index == self.current_buffer {
+                current = buffers.len();
+            }
+            buffers.push(session::Buffer {
+                file_name,
+                line: cursor.y,
+                column: cursor.x,
+                top: offset.y,
+            });
+        }
+
+        let count = buffers.len();
+        let session = Session {
+            dir,
+            current,
+            quickfix_open: self.quickfix_open,
+            buffers,
+        };
+        match session.save(Path::new(path)) {
+            Ok(()) => self.echo(format!(
+                "Saved session with {} to {}",
+                plural(count, "buffer", "buffers"),
+                path
+            )),
+            Err(err) => self.echo_error(err),
+        }
+    }
+
+    /// Pick up the work saved to a session file, as done by `:source` and
+    /// `--session`: go to its working directory and open its buffers, each
+    /// with the cursor where it was
+    ///
+    /// # Args
+    ///
+    /// - `path`: The session file, empty for `Session.toml`
+    fn source(&mut self, path: &str) {
+        let path = if path.is_empty() {
+            session::DEFAULT_FILE
+        } else {
+            path
+        };
+        let session = match Session::load(Path::new(path)) {
+            Ok(session) => session,
+            Err(err) => {
+                self.echo_error(err);
+                return;
+            }
+        };
+        if let Err(err) = env::set_current_dir(&session.dir) {
+            self.echo_error(format!("Could not go to {}: {}", session.dir, err));
+            return;
+        }
+
+        for buffer in &session.buffers {
+            self.edit_file(&buffer.file_name);
+            if self.document.file_name.as_deref() == Some(&buffer.file_name[..]) {
+                self.cursor_position = Position {
+                    x: buffer.column,
+                    y: buffer.line,
+                };
+                self.offset = Position {
+                    x: 0,
+                    y: buffer.top,
+                };
+                self.move_cursor(Key::Null);
+                self.scroll();
+            }
+        }
+        if let Some(buffer) = session.buffers.get(session.current) {
+            self.edit_file(&buffer.file_name);
+        }
+        self.quickfix_open = session.quickfix_open;
+        self.echo(format!(
+            "Restored session with {}",
+            plural(session.buffers.len(), "buffer", "buffers")
+        ));
+    }
+
+    /// Retrieve the names of the buffers with unsaved changes, `[No Name]`
+    /// for those without a file
+    fn unsaved_buffers(&self) -> Vec<String> {
+        // A special buffer shown is not one of the user's files
+        let shown = self.special_buffer.is_none().then_some(&self.document);
+        let aside = self
+            .special_buffer
+            .as_ref()
+            .map(|(_, buffer)| &buffer.document);
+        shown
+            .into_iter()
+            .chain(aside)
+            .chain(self.buffers.iter().map(|buffer| &buffer.document))
+            .filter(|document| document.is_dirty())
+            .map(|document| {
+                document
+                    .file_name
+                    .clone()
+                    .unwrap_or_else(|| "[No Name]".to_string())
+            })
+            .collect()
+    }
+
+    /// Quit the editor, as done by `:q` and `:qa`, unless a buffer has
+    /// unsaved changes
+    ///
+    /// # Args
+    ///
+    /// - `force`: Whether to drop the unsaved changes
+    fn quit(&mut self, force: bool) {
+        let unsaved = self.unsaved_buffers();
+        if !force && !unsaved.is_empty() {
+            self.echo_warning(format!(
+                "Unsaved changes in {}! Add ! to override.",
+                unsaved.join(", ")
+            ));
+            return;
+        }
+        self.should_quit = true;
+    }
+
+    /// Show a message and remember it for `:messages`
+    ///
+    /// # Args
+    ///
+    /// - `message`: The text of the message
+    fn echo(&mut self, message: String) {
+        self.echo_with_severity(message, Severity::Info);
+    }
+
+    /// Show a warning and remember it for `:messages`
+    ///
+    /// # Args
+    ///
+    /// - `message`: The text of the warning
+    fn echo_warning(&mut self, message: String) {
+        self.echo_with_severity(message, Severity::Warning);
+    }
+
+    /// Show an error until the next keypress and remember it for `:messages`
+    ///
+    /// # Args
+    ///
+    /// - `err`: The error
+    fn echo_error<E: std::fmt::Display>(&mut self, err: E) {
+        self.echo_with_severity(format!("ERR: {}", err), Severity::Error);
+    }
+
+    fn echo_with_severity(&mut self, message: String, severity: Severity) {
+        self.messages.push(message.clone());
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+        self.output(message);
+        self.status_message.severity = severity;
+    }
+
+    /// Write a line of command output, capturing it when redirected and
+    /// showing it in the message bar
+    ///
+    /// # Args
+    ///
+    /// - `text`: The line of output
+    fn output(&mut self, text: String) {
+        if let Some(captured) = self.redirect.as_mut() {
+            captured.push(text.clone());
+        }
+        self.status_message = StatusMessage::from(text);
+    }
+
+    /// Open the preview window, as done by `:Preview`. `:Preview` shows the
+    /// file named under the cursor, from its line for names such as
+    /// `src/main.rs:12`, `:Preview "a` shows the contents of a register and
+    /// `:Preview` followed by a command shows what the command outputs
+    ///
+    /// # Args
+    ///
+    /// - `args`: What to show
+    fn preview(&mut self, args: &str) {
+        let (title, lines) = if args.is_empty() {
+            match self.preview_file() {
+                Some(preview) => preview,
+                None => return,
+            }
+        } else if let Some(name) = args.strip_prefix('"') {
+            let name = name.chars().next().unwrap_or(registers::UNNAMED);
+            match self.registers.get(name) {
+                Some(register) => (format!("Register \"{}", name), register.lines.clone()),
+                None => {
+                    self.echo_warning(format!("Register is empty: {}", name));
+                    return;
+                }
+            }
+        } else {
+            // The output is also kept by a `:redir` in progress
+            let outer = self.redirect.replace(Vec::new());
+            self.run_command(args);
+            let captured = std::mem::replace(&mut self.redirect, outer).unwrap_or_default();
+            if let Some(outer) = self.redirect.as_mut() {
+                outer.extend(captured.iter().cloned());
+            }
+            (format!(":{}", args), captured)
+        };
+
+        if lines.is_empty() {
+            self.echo(format!("Nothing to preview for {}", title));
+            return;
         }
+        self.preview = Some(Preview { title, lines });
+    }
+
+    /// Read the file named under the cursor for the preview window
+    ///
+    /// # Returns
+    ///
+    /// - The title and lines of the preview, or `None` if there is no file to
+    ///   show
+    fn preview_file(&mut self) -> Option<(String, Vec<String>)> {
+        let Position { x, y } = self.cursor_position;
+        let line = self.document.row(y)?.as_str();
+        let at = self.document.row(y)?.byte_index(x);
+        let start = complete::path_start(&line[..at]);
+        let end = at + complete::path_end(&line[at..]);
+        let name = &line[start..end];
+        let line_number = name
+            .rsplit_once(':')
+            .and_then(|(file_name, number)| Some((file_name, number.parse::<usize>().ok()?)));
+        let (file_name, first) = match line_number {
+            Some((file_name, number)) => (file_name, number.saturating_sub(1)),
+            None => (name.trim_end_matches(':'), 0),
+        };
+        if file_name.is_empty() {
+            self.echo_warning("No file name under the cursor".to_string());
+            return None;
+        }
+
+        let file_name = file_name.to_string();
+        match std::fs::read_to_string(&file_name) {
+            Ok(contents) => Some((
+                file_name,
+                contents.lines().skip(first).map(str::to_string).collect(),
+            )),
+            Err(err) => {
+                self.echo_error(format!("Could not read {}: {}", file_name, err));
+                None
+            }
+        }
+    }
+
+    /// Stop capturing command output and show what was captured in a special
+    /// buffer
+    fn end_redirect(&mut self) {
+        if let Some(captured) = self.redirect.take() {
+            let document = Document::from_text(&captured.join("\n"));
+            self.open_special_buffer(SpecialBuffer::Output, document);
+            self.status_message =
+                StatusMessage::from(format!("Captured {} lines, :q to close", captured.len()));
+        } else {
+            self.echo("Not redirecting output".to_string());
+        }
+    }
+
+    /// Read the config file again and apply it, keeping the current settings
+    /// if it is invalid
+    fn reload_config(&mut self) {
+        self.config_modified = Config::modified();
+        match Config::load() {
+            Ok(config) => {
+                self.config = config;
+                self.echo("Config reloaded.".to_string());
+                self.apply_project_config();
+                self.refresh_git_staged();
+            }
+            Err(err) => self.echo_error(err),
+        }
+    }
+
+    /// Applies the settings of the current project's config file over the
+    /// user's. Until the project is trusted with `:Trust`, only the settings
+    /// that change how text is shown and typed are applied
+    fn apply_project_config(&mut self) {
+        if let Some(path) = project::config_path() {
+            // The file is read once, so what is trusted is what is applied
+            let applied = std::fs::read_to_string(&path)
+                .map_err(|err| format!("Could not read {}: {}", path.display(), err))
+                .and_then(|contents| {
+                    let trusted =
+                        project::root().is_ok_and(|root| project::is_trusted(&root, &contents));
+                    self.config.apply_project(&path, &contents, trusted)
+                });
+            match applied {
+                Ok(left_out) if left_out.is_empty() => (),
+                Ok(left_out) => self.echo_warning(format!(
+                    "{} is not trusted, :Trust to apply {}",
+                    path.display(),
+                    left_out.join(", ")
+                )),
+                Err(err) => self.echo_error(err),
+            }
+        }
+        if let Some(theme) = Theme::by_name(&self.config.theme) {
+            self.theme = theme;
+        }
+    }
+
+    /// Trusts the current project to change every setting from its config
+    /// file as it is now, as done by `:Trust`, and applies them
+    fn trust_project(&mut self) {
+        let contents = project::config_path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        match project::root().and_then(|root| project::trust(&root, &contents)) {
+            Ok(()) => self.reload_config(),
+            Err(err) => self.echo_error(err),
+        }
+    }
+
+    /// Let the user browse the themes, previewing each one as it is selected,
+    /// and store the confirmed choice in the config file
+    fn pick_theme(&mut self) {
+        let themes = Theme::all();
+        let names: Vec<String> = themes.iter().map(|theme| theme.name.to_string()).collect();
+        let original = self.theme.clone();
+        let current = names
+            .iter()
+            .position(|name| name == original.name)
+            .unwrap_or(0);
+
+        let picked = self
+            .pick("Theme: ", &names, current, |editor, index| {
+                editor.theme = themes[index].clone();
+            })
+            .unwrap_or(None);
+
+        if let Some(index) = picked {
+            self.theme = themes[index].clone();
+            self.config.theme = names[index].clone();
+            match Config::persist("theme", &format!("{:?}", names[index])) {
+                Ok(()) => self.echo(format!("Theme set to {}", names[index])),
+                Err(err) => self.echo_error(err),
+            }
+            self.config_modified = Config::modified();
+        } else {
+            self.theme = original;
+        }
+    }
+
+    /// Switches to another theme for the rest of the session, as done by
+    /// `:colorscheme`, or shows the current one when no name is given
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the theme
+    fn set_theme(&mut self, name: &str) {
+        if name.is_empty() {
+            self.echo(self.theme.name.to_string());
+            return;
+        }
+        match Theme::by_name(name) {
+            Some(theme) => {
+                self.theme = theme;
+                self.config.theme = name.to_string();
+                // Every row is drawn again in the new colors
+                self.frame.clear();
+            }
+            None => {
+                let names: Vec<&str> = Theme::all().iter().map(|theme| theme.name).collect();
+                self.echo_error(format!(
+                    "Unknown theme: {}, expected one of {}",
+                    name,
+                    names.join(", ")
+                ));
+            }
+        }
+    }
+
+    /// Handles Keypresses in Normal mode
+    ///
+    /// # Args
+    ///
+    /// - `c`: The character received from the user
+    fn process_normal_keypress(&mut self, c: char) {
+        let handled = match self.special_buffer_kind() {
+            Some(SpecialBuffer::GitStatus) => self.process_git_status_keypress(c),
+            Some(SpecialBuffer::LocalHistory) => self.process_local_history_keypress(c),
+            Some(SpecialBuffer::Explorer) => self.process_explorer_keypress(c),
+            _ => false,
+        };
+        if handled {
+            return;
+        }
+
+        match c {
+            'a' => {
+                self.move_cursor(Key::Right);
+                self.mode = Mode::Insert;
+            }
+            'i' => self.mode = Mode::Insert,
+            'R' => {
+                self.replaced.clear();
+                self.mode = Mode::Replace;
+            }
+            'r' => {
+                if let Some(c) = self.read_char() {
+                    self.replace_char(c);
+                }
+            }
+            'j' => self.move_cursor(Key::Down),
+            'k' => self.move_cursor(Key::Up),
+            'h' => self.move_cursor(Key::Left),
+            'l' => self.move_cursor(Key::Right),
+            'w' | 'W' => self.move_word(WordMotion::Forward, c == 'W'),
+            'e' | 'E' => self.move_word(WordMotion::ForwardEnd, c == 'E'),
+            'b' | 'B' => self.move_word(WordMotion::Backward, c == 'B'),
+            'o' => {
+                let y = self.cursor_position.y;
+                let x = self.document.row(y).map_or(0, Row::len);
+                self.document.insert(&Position { x, y }, '\n');
+                self.cursor_position = Position { x: 0, y: y + 1 };
+                self.indent_like(y);
+                self.mode = Mode::Insert;
+            }
+            'O' => {
+                let y = self.cursor_position.y;
+                self.document.replace_lines(y, 0, &[String::new()]);
+                self.cursor_position = Position { x: 0, y };
+                self.indent_like(y + 1);
+                self.mode = Mode::Insert;
+            }
+            'n' | 'N' => self.next_match((c == 'n') == self.search_forward),
+            ']' | '[' => match self.read_char() {
+                Some('c') if self.diff.is_none() && self.document.column_separator().is_some() => {
+                    self.jump_to_cell(c == ']');
+                }
+                Some('c') => self.jump_to_hunk(c == ']'),
+                Some('x') => self.jump_to_conflict(c == ']'),
+                Some('h') => self.jump_to_git_hunk(c == ']'),
+                Some('m') => self.jump_to_definition(c == ']', true),
+                Some(next) if next == c => self.jump_to_definition(c == ']', false),
+                _ => (),
+            },
+            'd' => match self.read_char() {
+                Some('o') => self.diff_transfer(false),
+                Some('p') => self.diff_transfer(true),
+                motion => self.delete(registers::UNNAMED, motion),
+            },
+            'x' => self.delete(registers::UNNAMED, Some('l')),
+            'f' | 'F' | 't' | 'T' | ';' | ',' => {
+                if let Some((_, x)) = self.find_char(Some(c)) {
+                    self.cursor_position.x = x;
+                }
+            }
+            'c' => match self.read_char() {
+                Some('r') => {
+                    if let Some(case) = self.read_char().and_then(Case::from_key) {
+                        self.convert_case(case);
+                    }
+                }
+                motion => self.change(motion),
+            },
+            'C' => self.change(Some('$')),
+            'S' => self.change(Some('c')),
+            'g' => match self.read_char() {
+                Some('=') => self.evaluate_expression(true),
+                Some('j') => self.move_screen_row(true),
+                Some('k') => self.move_screen_row(false),
+                Some(next @ ('e' | 'E')) => self.move_word(WordMotion::BackwardEnd, next == 'E'),
+                Some('a') => self.inspect_character(),
+                Some('g') => self.go_to_line("1"),
+                Some('q') => {
+                    let motion = self.read_char();
+                    self.reflow(motion);
+                }
+                _ => (),
+            },
+            'G' => self.go_to_line(""),
+            '%' => self.jump_to_pair(),
+            '.' => self.repeat_change(1),
+            // `Ctrl-I` is read as `Tab`
+            '\t' => self.go_through_jumps(false),
+            '1'..='9' => {
+                // Counts are only taken by `G` and `.` so far
+                let mut count = c.to_string();
+                loop {
+                    match self.read_char() {
+                        Some(digit) if digit.is_ascii_digit() => count.push(digit),
+                        Some('G') => break self.go_to_line(&count),
+                        Some('.') => break self.repeat_change(count.parse().unwrap_or(1)),
+                        _ => break,
+                    }
+                }
+            }
+            'K' => self.man_page(),
+            'u' => self.undo(true),
+            'y' => self.yank(registers::UNNAMED),
+            'p' => self.paste(registers::UNNAMED, false),
+            'P' => self.paste(registers::UNNAMED, true),
+            '"' => match (self.read_char(), self.read_char()) {
+                (Some(name), Some(command)) if Registers::is_readable(name) => match command {
+                    'p' => self.paste(name, false),
+                    'P' => self.paste(name, true),
+                    _ if !Registers::is_valid(name) => {
+                        self.echo(format!("Register {} is read-only", name));
+                    }
+                    'y' => self.yank(name),
+                    'd' => {
+                        let motion = self.read_char();
+                        self.delete(name, motion);
+                    }
+                    'x' => self.delete(name, Some('l')),
+                    _ => (),
+                },
+                _ => self.echo("Invalid register".to_string()),
+            },
+            ':' => self.process_command(),
+            '/' | '?' => self.search(c == '/'),
+            '\'' | '`' => {
+                if let Some(name) = self.read_char() {
+                    self.jump_to_mark(name, c == '`');
+                }
+            }
+            'm' => match self.read_char() {
+                Some(name @ 'a'..='z') => {
+                    let position = self.cursor_position.clone();
+                    self.document.set_mark(name, position);
+                }
+                Some(name) => self.echo_warning(format!("Invalid mark: {}", name)),
+                None => (),
+            },
+            _ => (),
+        }
+    }
+
+    /// Reads the next key of a multi-key Normal mode command
+    ///
+    /// # Returns
+    ///
+    /// - The character pressed, or `None` for any non-character key
+    fn read_char(&mut self) -> Option<char> {
+        match self.read_key() {
+            Ok(Key::Char(c)) => Some(c),
+            _ => None,
+        }
+    }
+
+    /// Reads the next key, taken from the keys of `:normal` while it runs.
+    /// Running out of them acts as `Esc`, so unfinished commands are
+    /// cancelled rather than waiting for the terminal. The screen is redrawn
+    /// whenever the terminal is resized or more of the file is read while
+    /// waiting
+    ///
+    /// # Returns
+    ///
+    /// - The key pressed or any Error encountered reading it
+    fn read_key(&mut self) -> Result<Key, std::io::Error> {
+        if let Some(keys) = &mut self.replay {
+            let key = keys.pop_front().unwrap_or(Key::Esc);
+            self.command_keys.push(key);
+            return Ok(key);
+        }
+        loop {
+            let loaded = match self.document.receive_rows() {
+                Ok(loaded) => loaded,
+                Err(err) => {
+                    self.echo_error(format!("Could not read file: {}", err));
+                    true
+                }
+            };
+            // Leftover highlighting is done in slices between checks for keys
+            let timeout = if self.highlight_pending {
+                Duration::ZERO
+            } else {
+                POLL_INTERVAL
+            };
+            let resized = match self.terminal.next_event(timeout)? {
+                Event::Key(key) => {
+                    self.idle_since = Instant::now();
+                    self.command_keys.push(key);
+                    if self.config.show_keys {
+                        self.shown_keys.push(key);
+                    }
+                    return Ok(key);
+                }
+                Event::Resize => {
+                    self.fit_screen();
+                    true
+                }
+                Event::Tick => false,
+            };
+            self.write_recovery();
+            let saved = self.auto_save();
+            let served = self.serve_remote();
+            let finished = self.finish_jobs();
+            let progressed = self.show_progress();
+            let referenced = self.highlight_references();
+            let changed = self.document.check_disk();
+            // A message that timed out is taken off the message bar
+            let expired = self.message_shown && !self.status_message.is_shown();
+            if resized
+                || loaded
+                || self.highlight_pending
+                || referenced
+                || changed
+                || served
+                || finished
+                || progressed
+                || saved
+                || expired
+            {
+                self.refresh_screen()?;
+            }
+        }
+    }
+
+    /// Copies the buffers with unsaved changes to the recovery area once no
+    /// key has been pressed for `recovery_delay`, so `:RecoverLast` can bring
+    /// the changes back after a crash or `:q!`
+    fn write_recovery(&mut self) {
+        let delay = Duration::from_secs(self.config.recovery_delay);
+        if delay.is_zero() || self.idle_since.elapsed() < delay {
+            return;
+        }
+        let aside = self
+            .special_buffer
+            .as_mut()
+            .map(|(_, buffer)| &mut buffer.document);
+        let documents = std::iter::once(&mut self.document)
+            .chain(aside)
+            .chain(self.buffers.iter_mut().map(|buffer| &mut buffer.document));
+        let mut failed = None;
+        for document in documents.filter(|document| document.needs_recovery()) {
+            if let Err(err) = document.write_recovery() {
+                failed = Some(err);
+            }
+        }
+        if let Some(err) = failed {
+            self.echo_error(format!("Could not write recovery copy: {}", err));
+        }
+    }
+
+    /// Writes the buffers with unsaved changes before a command runs another
+    /// program on the files, so it sees what is on screen. Whether to is
+    /// taken from `auto_write_commands`, falling back to `auto_write`
+    ///
+    /// # Args
+    ///
+    /// - `command`: The full name of the command
+    ///
+    /// # Returns
+    ///
+    /// - Whether the command may run, false when a buffer could not be written
+    fn auto_write(&mut self, command: &str) -> bool {
+        let enabled = self
+            .config
+            .auto_write_commands
+            .get(command)
+            .copied()
+            .unwrap_or(self.config.auto_write);
+        !enabled || self.write_dirty_buffers()
+    }
+
+    /// Writes the buffers with unsaved changes to their files once no key has
+    /// been pressed for `auto_save_delay`. A buffer that could not be written
+    /// is only tried again after the next key
+    ///
+    /// # Returns
+    ///
+    /// - Whether the buffers were just written
+    fn auto_save(&mut self) -> bool {
+        let delay = Duration::from_secs(self.config.auto_save_delay);
+        if delay.is_zero()
+            || self.idle_since.elapsed() < delay
+            || self.auto_saved == Some(self.idle_since)
+        {
+            return false;
+        }
+        self.auto_saved = Some(self.idle_since);
+        self.write_dirty_buffers();
+        true
+    }
+
+    /// Writes every buffer with unsaved changes, as done by `:wa` and `:xa`
+    ///
+    /// # Returns
+    ///
+    /// - Whether no buffer is left with unsaved changes
+    fn write_all(&mut self) -> bool {
+        if !self.write_dirty_buffers() {
+            return false;
+        }
+        let unsaved = self.unsaved_buffers();
+        if !unsaved.is_empty() {
+            self.echo_warning(format!(
+                "No file name to write {} to",
+                plural(unsaved.len(), "buffer", "buffers")
+            ));
+            return false;
+        }
+        true
+    }
+
+    /// Writes the buffers with unsaved changes that have a file
+    ///
+    /// # Returns
+    ///
+    /// - Whether every buffer could be written
+    fn write_dirty_buffers(&mut self) -> bool {
+        // A special buffer shown is not one of the user's files
+        let shown = self.special_buffer.is_none().then_some(&mut self.document);
+        let aside = self
+            .special_buffer
+            .as_mut()
+            .map(|(_, buffer)| &mut buffer.document);
+        let documents = shown
+            .into_iter()
+            .chain(aside)
+            .chain(self.buffers.iter_mut().map(|buffer| &mut buffer.document));
+        let mut written = Vec::new();
+        let mut failed = None;
+        for document in documents.filter(|document| document.is_dirty()) {
+            let file_name = match document.file_name.clone() {
+                Some(file_name) => file_name,
+                None => continue,
+            };
+            match document.save() {
+                Ok(()) => written.push(file_name),
+                Err(err) => failed = Some(format!("Could not write {}: {}", file_name, err)),
+            }
+        }
+        for file_name in &written {
+            self.after_save(file_name);
+        }
+        if !written.is_empty() {
+            self.refresh_git_staged();
+        }
+        match failed {
+            Some(err) => {
+                self.echo_error(err);
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Check whether another program waits on the current buffer to be closed
+    fn is_waited_on(&self) -> bool {
+        self.document
+            .file_name
+            .as_ref()
+            .is_some_and(|file_name| self.waiting.iter().any(|(waited, _)| waited == file_name))
+    }
+
+    /// Closes the buffer another program waits on, as done by `:q`, `:wq` and
+    /// `:cq` in it, and lets the program go on. The editor keeps running for
+    /// the next file sent to it
+    ///
+    /// # Args
+    ///
+    /// - `name`: The command closing the buffer
+    /// - `args`: The arguments of the command
+    /// - `bang`: Whether the command was given a `!`
+    fn finish_waited(&mut self, name: &str, args: &str, bang: bool) {
+        if name == "wq" {
+            self.write(args, bang);
+            if self.document.is_dirty() {
+                return;
+            }
+        } else if name == "quit" && !bang && self.document.is_dirty() {
+            self.echo_warning("Document has unsaved changes! Add ! to override.".to_string());
+            return;
+        }
+        let result = match name {
+            "cquit" => Err("The buffer was closed with :cquit".to_string()),
+            _ => Ok(()),
+        };
+        let file_name = self.document.file_name.clone();
+        let (done, waiting) = std::mem::take(&mut self.waiting)
+            .into_iter()
+            .partition(|(waited, _)| Some(waited) == file_name.as_ref());
+        self.waiting = waiting;
+        for (_, call) in done {
+            call.reply(result.clone());
+        }
+        self.close_buffer();
+    }
+
+    /// Drop the current buffer and show the one before it, or an empty one
+    /// when it was the last
+    fn close_buffer(&mut self) {
+        if let Some(file_name) = &self.document.file_name {
+            recovery::release(file_name);
+        }
+        self.diff = None;
+        if self.buffers.len() == 1 {
+            self.restore_view(Buffer::default());
+        } else {
+            self.buffers.remove(self.current_buffer);
+            self.current_buffer = self.current_buffer.saturating_sub(1);
+            let buffer = std::mem::take(&mut self.buffers[self.current_buffer]);
+            self.restore_view(buffer);
+        }
+        self.refresh_git_staged();
+        self.scroll();
+    }
+
+    /// Carries out the requests other programs sent through the server. They
+    /// wait until no command is half typed, so they do not mix with its keys
+    ///
+    /// # Returns
+    ///
+    /// - Whether any request was carried out
+    fn serve_remote(&mut self) -> bool {
+        if self.mode != Mode::Normal || !self.command_keys.is_empty() {
+            return false;
+        }
+        let mut served = false;
+        while let Some(call) = self.server.as_ref().and_then(Server::try_recv) {
+            self.status_message = StatusMessage::from(String::new());
+            let mut waited = None;
+            match &call.request {
+                Request::Open { file, line, wait } => {
+                    // Files below the working directory keep their short name
+                    let file_name = env::current_dir()
+                        .ok()
+                        .and_then(|dir| Path::new(file).strip_prefix(dir).ok())
+                        .map_or_else(|| file.clone(), |path| path.display().to_string());
+                    self.edit_file(&file_name);
+                    if let Some(line) = line {
+                        self.go_to_line(&line.to_string());
+                    }
+                    if *wait {
+                        waited = Some(file_name);
+                    }
+                }
+                Request::Command { args } => self.run_command(args),
+            }
+            served = true;
+            let result = match self.status_message.severity {
+                Severity::Error => {
+                    let text = &self.status_message.text;
+                    Err(text.strip_prefix("ERR: ").unwrap_or(text).to_string())
+                }
+                _ => Ok(()),
+            };
+            match waited {
+                Some(file_name) if result.is_ok() => {
+                    self.echo(format!(
+                        "Another program waits on {}, :wq or :q when done, :cq to fail",
+                        file_name
+                    ));
+                    self.waiting.push((file_name, call));
+                }
+                _ => call.reply(result),
+            }
+        }
+        served
+    }
+
+    /// Puts the changes last copied to the recovery area back into their
+    /// file's buffer, as done by `:RecoverLast`. They can be undone like any
+    /// other change
+    fn recover_last(&mut self) {
+        match recovery::latest() {
+            Some((file_name, copy)) => self.recover_copy(&file_name, &copy),
+            None => self.echo("No changes to recover".to_string()),
+        }
+    }
+
+    /// Puts the changes of the document's file copied to the recovery area
+    /// back into the document, as done by `:recover`, or drops them, as done
+    /// by `:recover!`
+    ///
+    /// # Args
+    ///
+    /// - `bang`: Whether to drop the changes
+    fn recover(&mut self, bang: bool) {
+        let file_name = match &self.document.file_name {
+            Some(file_name) if recovery::has_changes(file_name) => file_name.clone(),
+            _ => {
+                self.echo("No changes to recover".to_string());
+                return;
+            }
+        };
+        if bang {
+            recovery::remove(&file_name);
+            self.echo(format!("Dropped the unsaved changes of {}", file_name));
+        } else if let Some(copy) = recovery::path(&file_name) {
+            self.recover_copy(&file_name, &copy);
+        }
+    }
+
+    /// Puts the changes copied to the recovery area back into their file's
+    /// buffer
+    ///
+    /// # Args
+    ///
+    /// - `file_name`: The path of the file
+    /// - `copy`: The path of the copy
+    fn recover_copy(&mut self, file_name: &str, copy: &Path) {
+        let recovered = match Document::open(&copy.to_string_lossy()) {
+            Ok(recovered) => recovered,
+            Err(err) => {
+                self.echo_error(format!("Could not read {}: {}", copy.display(), err));
+                return;
+            }
+        };
+        self.edit_file(file_name);
+        if self.document.is_read_only() {
+            self.echo_warning(format!("Could not recover {}, it is read-only", file_name));
+            return;
+        }
+        let lines: Vec<String> = recovered
+            .lines()
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        self.document.replace_lines(0, self.document.len(), &lines);
+        self.document.end_undo_step();
+        self.move_cursor(Key::Null);
+        self.echo(format!("Recovered {}, :w to keep the changes", file_name));
+    }
+
+    /// Highlights the other occurrences of the word under the cursor on
+    /// screen once the cursor has rested on it for `reference_delay`
+    ///
+    /// # Returns
+    ///
+    /// - Whether the occurrences were just highlighted
+    fn highlight_references(&mut self) -> bool {
+        let delay = Duration::from_millis(self.config.reference_delay);
+        // References are only told apart by their color
+        if self.references_shown
+            || delay.is_zero()
+            || self.config.screen_reader
+            || self.mode != Mode::Normal
+            || self.idle_since.elapsed() < delay
+        {
+            return false;
+        }
+        let Position { x, y } = self.cursor_position;
+        let word = self
+            .document
+            .row(y)
+            .and_then(|row| Some(row.as_str()[row.word_at(x)?].to_string()));
+        // Numbers are not identifiers
+        let word = match word {
+            Some(word) if !word.starts_with(|c: char| c.is_numeric()) => word,
+            _ => return false,
+        };
+        let height = self.document_height();
+        let screen = self.offset.y..self.offset.y.saturating_add(height);
+        self.document.set_reference(Some(&word), screen);
+        self.references_shown = true;
+        true
+    }
+
+    /// Stops highlighting the occurrences of the word under the cursor
+    fn clear_references(&mut self) {
+        if !self.references_shown {
+            return;
+        }
+        let height = self.document_height();
+        let screen = self.offset.y..self.offset.y.saturating_add(height);
+        self.document.set_reference(None, screen);
+        self.references_shown = false;
+    }
+
+    /// Adapts to a new size of the terminal, keeping the cursor in view and
+    /// clearing the screen so it is drawn again in full
+    ///
+    /// # Returns
+    ///
+    /// - Whether the size changed, or any Error encountered reading it
+    fn resize(&mut self) -> Result<bool, std::io::Error> {
+        if !self.terminal.refresh_size()? {
+            return Ok(false);
+        }
+        self.fit_screen();
+        Ok(true)
+    }
+
+    /// Keeps the cursor in view after the terminal changed size, clearing
+    /// the screen so it is drawn again in full
+    fn fit_screen(&mut self) {
+        Terminal::clear_screen();
+        self.frame.clear();
+        self.scroll();
+    }
+
+    /// Runs Normal mode keys on every line of a range, from a command such
+    /// as `normal Ax` or `%norm I// `
+    ///
+    /// # Args
+    ///
+    /// - `range`: The lines to run the keys on, the cursor line if empty
+    /// - `keys`: The keys to run
+    fn normal(&mut self, range: &str, keys: &str) {
+        let mut lines = match self.line_range(range) {
+            Some(lines) => lines,
+            None => {
+                self.echo_warning(format!("Invalid range: {}", range));
+                return;
+            }
+        };
+        let keys: VecDeque<Key> = keys.chars().map(Key::Char).collect();
+
+        let mut y = lines.start;
+        while y < lines.end && y < self.document.len() {
+            let len = self.document.len();
+            self.mode = Mode::Normal;
+            self.cursor_position = Position { x: 0, y };
+            self.replay = Some(keys.clone());
+            while self.replay.as_ref().is_some_and(|keys| !keys.is_empty()) {
+                if let Err(err) = self.process_keypress() {
+                    self.echo_error(err);
+                    break;
+                }
+            }
+            self.replay = None;
+            self.mode = Mode::Normal;
+            self.document.end_undo_step();
+
+            // Lines added or removed by the keys move the rest of the range
+            let added = self.document.len() as isize - len as isize;
+            lines.end = lines.end.saturating_add_signed(added);
+            y = (y + 1).saturating_add_signed(added);
+        }
+        self.move_cursor(Key::Null);
+    }
+
+    /// Converts a range such as `%`, `.`, `$`, `3` or `2,$` to line indices.
+    /// Line numbers start at 1, and no range means the cursor line
+    ///
+    /// # Args
+    ///
+    /// - `range`: The text of the range
+    ///
+    /// # Returns
+    ///
+    /// - The lines of the range, or `None` if it is not a valid range
+    fn line_range(&self, range: &str) -> Option<Range<usize>> {
+        let line = |address: &str| match address {
+            "" | "." => Some(self.cursor_position.y),
+            "$" => Some(self.document.len().saturating_sub(1)),
+            number => number.parse::<usize>().ok()?.checked_sub(1),
+        };
+
+        if range == "%" {
+            return Some(0..self.document.len());
+        }
+        let (first, last) = match range.split_once(',') {
+            Some((first, last)) => (line(first)?, line(last)?),
+            None => (line(range)?, line(range)?),
+        };
+        Some(std::cmp::min(first, last)..std::cmp::max(first, last) + 1)
+    }
+
+    /// Evaluates the arithmetic expression under the cursor
+    ///
+    /// # Args
+    ///
+    /// - `replace`: Whether to replace the expression with its value rather
+    ///   than showing the value in the message bar
+    fn evaluate_expression(&mut self, replace: bool) {
+        let Position { x, y } = self.cursor_position;
+        let found = self.document.row(y).and_then(|row| {
+            let text = row.as_str().to_string();
+            let range = eval::expression_at(&text, row.byte_index(x))?;
+            Some((text, range))
+        });
+        let (text, range) = if let Some(found) = found {
+            found
+        } else {
+            self.echo("No expression under cursor".to_string());
+            return;
+        };
+
+        match eval::evaluate(&text[range.clone()]) {
+            Ok(value) if replace => {
+                let line = format!(
+                    "{}{}{}",
+                    &text[..range.start],
+                    eval::format(value),
+                    &text[range.end..]
+                );
+                self.document.replace_lines(y, 1, &[line]);
+                self.cursor_position.x = Row::from(&text[..range.start]).len();
+                self.move_cursor(Key::Null);
+            }
+            Ok(value) => self.echo(format!("= {}", eval::format(value))),
+            Err(err) => self.echo_error(err),
+        }
+    }
+
+    /// Shows the codepoints, UTF-8 bytes, Unicode names and width of the
+    /// character under the cursor
+    fn inspect_character(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        let grapheme = self.document.row(y).map_or_else(String::new, |row| {
+            row.as_str()[row.byte_index(x)..row.byte_index(x + 1)].to_string()
+        });
+        if grapheme.is_empty() {
+            self.echo("NUL".to_string());
+        } else {
+            self.echo(inspect::describe(&grapheme));
+        }
+    }
+
+    /// Rewrites the integer under the cursor in another base
+    ///
+    /// # Args
+    ///
+    /// - `radix`: One of 2, 8, 10 or 16
+    fn convert_number(&mut self, radix: u32) {
+        let Position { x, y } = self.cursor_position;
+        let found = self
+            .document
+            .row(y)
+            .and_then(|row| Some((row.as_str().to_string(), row.word_at(x)?)));
+        let (text, word) = if let Some(found) = found {
+            found
+        } else {
+            self.echo("No number under cursor".to_string());
+            return;
+        };
+
+        // A minus sign right before the number belongs to it
+        let start = if text[..word.start].ends_with('-') {
+            word.start - 1
+        } else {
+            word.start
+        };
+        let value = if let Some(value) = eval::parse_integer(&text[start..word.end]) {
+            value
+        } else {
+            self.echo("No number under cursor".to_string());
+            return;
+        };
+
+        let line = format!(
+            "{}{}{}",
+            &text[..start],
+            eval::format_integer(value, radix),
+            &text[word.end..]
+        );
+        self.document.replace_lines(y, 1, &[line]);
+        self.cursor_position.x = Row::from(&text[..start]).len();
+    }
+
+    /// Rewrites the identifier under the cursor in another case, as done by
+    /// `crs`, `crc`, `crp` and `crk`. Words joined by `-` are part of the
+    /// identifier so kebab-case can be converted back
+    ///
+    /// # Args
+    ///
+    /// - `case`: The case to write the identifier in
+    fn convert_case(&mut self, case: Case) {
+        let Position { x, y } = self.cursor_position;
+        let found = self
+            .document
+            .row(y)
+            .and_then(|row| Some((row.as_str().to_string(), row.word_at(x)?)));
+        let (text, word) = if let Some(found) = found {
+            found
+        } else {
+            self.echo("No identifier under cursor".to_string());
+            return;
+        };
+
+        let is_letter = |c: Option<char>| c.is_some_and(char::is_alphabetic);
+        let mut start = word.start;
+        while text[..start].ends_with('-') && is_letter(text[..start - 1].chars().last()) {
+            start = text[..start - 1]
+                .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .map_or(0, |index| index + 1);
+        }
+        let mut end = word.end;
+        while text[end..].starts_with('-') && is_letter(text[end + 1..].chars().next()) {
+            end = text[end + 1..]
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .map_or(text.len(), |index| end + 1 + index);
+        }
+
+        let line = format!(
+            "{}{}{}",
+            &text[..start],
+            case.convert(&text[start..end]),
+            &text[end..]
+        );
+        self.document.replace_lines(y, 1, &[line]);
+        self.cursor_position.x = Row::from(&text[..start]).len();
+    }
+
+    /// Moves the cursor to the start of the next or previous diff hunk
+    ///
+    /// # Args
+    ///
+    /// - `forward`: Whether to search below the cursor rather than above it
+    fn jump_to_hunk(&mut self, forward: bool) {
+        let diff = if let Some(diff) = &self.diff {
+            diff
+        } else {
+            self.echo("Not in diff mode".to_string());
+            return;
+        };
+
+        let starts: Vec<usize> = diff
+            .hunks
+            .iter()
+            .map(|hunk| hunk.start(diff.focus_left))
+            .collect();
+        self.jump_to_line(&starts, forward, "No more hunks");
+    }
+
+    /// Moves the cursor to the start of the next or previous cell of a CSV or
+    /// TSV file, continuing on the next or previous line
+    ///
+    /// # Args
+    ///
+    /// - `forward`: Whether to move right rather than left
+    fn jump_to_cell(&mut self, forward: bool) {
+        let separator = match self.document.column_separator() {
+            Some(separator) => separator,
+            None => return,
+        };
+        let Position { x, y } = self.cursor_position;
+        let line = |y: usize| self.document.row(y).map_or("", Row::as_str);
+        let cells = csv::cells(line(y), separator);
+        let cell = csv::cell_at(line(y), separator, x);
+        self.cursor_position = if forward && cell + 1 < cells.len() {
+            Position {
+                x: cells[cell + 1].start,
+                y,
+            }
+        } else if forward && y + 1 < self.document.len() {
+            Position { x: 0, y: y + 1 }
+        } else if !forward && x > cells[cell].start {
+            Position {
+                x: cells[cell].start,
+                y,
+            }
+        } else if !forward && cell > 0 {
+            Position {
+                x: cells[cell - 1].start,
+                y,
+            }
+        } else if !forward && y > 0 {
+            let cells = csv::cells(line(y - 1), separator);
+            Position {
+                x: cells.last().map_or(0, |cell| cell.start),
+                y: y - 1,
+            }
+        } else {
+            return;
+        };
+    }
+
+    /// Moves the cursor to the next or previous definition
+    ///
+    /// # Args
+    ///
+    /// - `forward`: Whether to search below the cursor rather than above it
+    /// - `functions`: Whether to stop at every function (`]m`) rather than at
+    ///   top-level definitions only (`]]`)
+    fn jump_to_definition(&mut self, forward: bool, functions: bool) {
+        let lines: Vec<usize> = self
+            .document
+            .definitions()
+            .into_iter()
+            .filter(|(_, definition)| {
+                if functions {
+                    definition.is_function
+                } else {
+                    definition.is_top_level
+                }
+            })
+            .map(|(y, _)| y)
+            .collect();
+        self.jump_to_line(&lines, forward, "No more definitions");
+    }
+
+    /// Moves the cursor to the closest of the given lines below or above it
+    ///
+    /// # Args
+    ///
+    /// - `lines`: The candidate lines, in ascending order
+    /// - `forward`: Whether to search below the cursor rather than above it
+    /// - `not_found`: The message shown when there is no candidate
+    fn jump_to_line(&mut self, lines: &[usize], forward: bool, not_found: &str) {
+        let y = self.cursor_position.y;
+        let target = if forward {
+            lines.iter().find(|&&line| line > y)
+        } else {
+            lines.iter().rfind(|&&line| line < y)
+        };
+
+        if let Some(&y) = target {
+            self.jump(Position { x: 0, y });
+        } else {
+            self.echo(not_found.to_string());
+        }
+    }
+
+    /// Copies the diff hunk under the cursor from one side to the other
+    ///
+    /// # Args
+    ///
+    /// - `put`: Whether to put the focused side into the other document (`dp`)
+    ///   rather than obtain the other side into the focused document (`do`)
+    fn diff_transfer(&mut self, put: bool) {
+        let diff = if let Some(diff) = &mut self.diff {
+            diff
+        } else {
+            self.echo("Not in diff mode".to_string());
+            return;
+        };
+
+        let hunk = if let Some(hunk) = diff.hunk_at(self.cursor_position.y) {
+            hunk.clone()
+        } else {
+            self.echo("No hunk under cursor".to_string());
+            return;
+        };
+
+        let focus = diff.focus_left;
+        let (source, target) = if put {
+            (&self.document, &mut diff.other)
+        } else {
+            (&diff.other, &mut self.document)
+        };
+        let source_side = if put { focus } else { !focus };
+        let start = hunk.start(source_side);
+        let lines: Vec<String> = source.lines()[start..start + hunk.len(source_side)]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        target.replace_lines(hunk.start(!source_side), hunk.len(!source_side), &lines);
+
+        diff.update(&self.document);
+        self.move_cursor(Key::Null);
+    }
+
+    /// Moves the cursor to the start of the next or previous merge conflict
+    ///
+    /// # Args
+    ///
+    /// - `forward`: Whether to search below the cursor rather than above it
+    fn jump_to_conflict(&mut self, forward: bool) {
+        let starts: Vec<usize> = conflict::find_conflicts(&self.document.lines())
+            .iter()
+            .map(|conflict| conflict.start)
+            .collect();
+        self.jump_to_line(&starts, forward, "No more conflicts");
+    }
+
+    /// Replaces the merge conflict under the cursor with one of its sides
+    ///
+    /// # Args
+    ///
+    /// - `ours`: Whether to keep our side rather than theirs
+    fn resolve_conflict(&mut self, ours: bool) {
+        let y = self.cursor_position.y;
+        let conflicts = conflict::find_conflicts(&self.document.lines());
+        let conflict = if let Some(conflict) = conflicts.iter().find(|c| c.contains(y)) {
+            conflict
+        } else {
+            self.echo("No conflict under cursor".to_string());
+            return;
+        };
+
+        let range = if ours {
+            conflict.ours()
+        } else {
+            conflict.theirs()
+        };
+        let lines: Vec<String> = self.document.lines()[range]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        self.document
+            .replace_lines(conflict.start, conflict.end + 1 - conflict.start, &lines);
+        self.cursor_position = Position {
+            x: 0,
+            y: conflict.start,
+        };
+        self.move_cursor(Key::Null);
+    }
+
+    /// Leave diff mode, keeping the document that was opened first
+    fn diff_off(&mut self) {
+        if let Some(diff) = &self.diff {
+            if !diff.focus_left {
+                self.switch_diff_focus();
+            }
+        }
+        if self.diff.take().is_none() {
+            self.echo("Not in diff mode".to_string());
+        }
+    }
+
+    /// Moves input focus between the two documents of diff mode
+    fn switch_diff_focus(&mut self) {
+        if let Some(diff) = &mut self.diff {
+            std::mem::swap(&mut self.document, &mut diff.other);
+            std::mem::swap(&mut self.cursor_position, &mut diff.other_cursor);
+            diff.focus_left = !diff.focus_left;
+        }
+        self.refresh_git_staged();
+    }
+
+    /// Handles Keypresses in Insert mode
+    ///
+    /// # Args
+    ///
+    /// - `c`: The character to process
+    fn process_insert_keypress(&mut self, c: char) {
+        self.inserted.push(c);
+        if c == '\t' {
+            let tab = self.tab_text();
+            let len = tab.len();
+            self.document.insert_text(&self.cursor_position, &[tab]);
+            self.cursor_position.x += len;
+            return;
+        }
+        if self.config.auto_pairs && self.type_pair(c) {
+            return;
+        }
+        self.document.insert(&self.cursor_position, c);
+
+        if c == '\n' {
+            let y = self.cursor_position.y;
+            self.cursor_position = Position {
+                x: 0,
+                y: y.saturating_add(1),
+            };
+            self.indent_like(y);
+        } else {
+            self.move_cursor(Key::Right);
+            if c == '|' && self.table_mode {
+                self.align_table();
+            } else if !c.is_whitespace() {
+                self.break_line();
+            }
+        }
+    }
+
+    /// Types a character of a pair: an opening one along with its closing
+    /// one, or a closing one over the same character after the cursor
+    ///
+    /// # Args
+    ///
+    /// - `c`: The character typed
+    ///
+    /// # Returns
+    ///
+    /// - Whether the character was typed, rather than left to be inserted
+    fn type_pair(&mut self, c: char) -> bool {
+        let (before, after) = self.chars_around_cursor();
+        let is_close = |c: char| AUTO_PAIRS.iter().any(|&(_, close)| close == c);
+        if after == Some(c) && is_close(c) {
+            self.move_cursor(Key::Right);
+            return true;
+        }
+        let close = match AUTO_PAIRS.iter().find(|&&(open, _)| open == c) {
+            Some(&(_, close)) => close,
+            None => return false,
+        };
+        // A quote after a word is an apostrophe, as in `don't`
+        if c == close && before.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            return false;
+        }
+        // Text right after the cursor is likely what the pair is opened for
+        if after.is_some_and(|c| !c.is_whitespace() && !is_close(c)) {
+            return false;
+        }
+        self.document
+            .insert_text(&self.cursor_position, &[format!("{}{}", c, close)]);
+        self.move_cursor(Key::Right);
+        true
+    }
+
+    /// Check whether the cursor is between the two characters of a pair with
+    /// nothing in it, as left by `type_pair`
+    fn is_in_empty_pair(&self) -> bool {
+        match self.chars_around_cursor() {
+            (Some(before), Some(after)) => AUTO_PAIRS.contains(&(before, after)),
+            _ => false,
+        }
+    }
+
+    /// Retrieve the characters before the cursor and under it, if any
+    fn chars_around_cursor(&self) -> (Option<char>, Option<char>) {
+        let Position { x, y } = self.cursor_position;
+        match self.document.row(y) {
+            Some(row) => {
+                let (before, after) = row.as_str().split_at(row.byte_index(x));
+                (before.chars().next_back(), after.chars().next())
+            }
+            None => (None, None),
+        }
+    }
+
+    /// Find the text the `Tab` key types at the cursor: spaces up to the next
+    /// multiple of `shift_width` when `expand_tab` is set, otherwise a `tab`
+    /// character
+    fn tab_text(&self) -> String {
+        if !self.config.expand_tab {
+            return "\t".to_string();
+        }
+        let shift_width = match self.config.shift_width {
+            0 => self.config.tab_size.max(1),
+            width => width,
+        };
+        let column = self.cursor_column(None);
+        " ".repeat(shift_width - column % shift_width)
+    }
+
+    /// Indents the cursor line like another line, when `autoindent` is set,
+    /// moving the cursor past the indentation
+    ///
+    /// # Args
+    ///
+    /// - `y`: The line whose indentation is copied
+    fn indent_like(&mut self, y: usize) {
+        if !self.config.auto_indent {
+            return;
+        }
+        let indent: String = self.document.row(y).map_or_else(String::new, |row| {
+            row.as_str()
+                .chars()
+                .take_while(|c| c.is_whitespace())
+                .collect()
+        });
+        if indent.is_empty() {
+            return;
+        }
+        let len = indent.chars().count();
+        self.document.insert_text(&self.cursor_position, &[indent]);
+        self.cursor_position.x += len;
+    }
+
+    /// Types the text typed during the last visit to Insert mode again, as
+    /// done by `Ctrl-A`
+    fn insert_again(&mut self) {
+        let text = match self.registers.get(registers::LAST_INSERTED) {
+            Some(register) => register.lines.join("\n"),
+            None => {
+                self.echo("Nothing inserted yet".to_string());
+                return;
+            }
+        };
+        for c in text.chars() {
+            self.process_insert_keypress(c);
+        }
+    }
+
+    /// Lines up the cells of the Markdown table under the cursor, keeping the
+    /// cursor in the same cell
+    fn align_table(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        let lines = self.document.lines();
+        let rows = match table::bounds(&lines, y) {
+            Some(rows) => rows,
+            None => return,
+        };
+        let (cell, offset) = table::cell_at(lines[y], x);
+        let aligned = table::align(&lines[rows.clone()]);
+        if aligned
+            .iter()
+            .zip(&lines[rows.clone()])
+            .all(|(new, old)| new == old)
+        {
+            return;
+        }
+
+        let x = table::position(&aligned[y - rows.start], cell, offset);
+        self.document
+            .replace_lines(rows.start, rows.len(), &aligned);
+        self.cursor_position = Position { x, y };
+    }
+
+    /// Handles Keypresses in Replace mode, where `Enter` still starts a new
+    /// line
+    ///
+    /// # Args
+    ///
+    /// - `c`: The character to process
+    fn process_replace_keypress(&mut self, c: char) {
+        if c == '\n' {
+            self.replaced.clear();
+            self.process_insert_keypress(c);
+            return;
+        }
+        let text = if c == '\t' {
+            self.tab_text()
+        } else {
+            c.to_string()
+        };
+        for c in text.chars() {
+            let replaced = self.document.replace_char(&self.cursor_position, c);
+            self.replaced.push(replaced);
+            self.cursor_position.x += 1;
+        }
+        self.break_line();
+    }
+
+    /// Moves back over the last character typed in Replace mode, putting back
+    /// the character it overwrote
+    fn restore_replaced(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        if x == 0 {
+            return;
+        }
+        let at = Position { x: x - 1, y };
+        match self.replaced.pop() {
+            Some(Some(old)) => self.document.replace_in_range(y, at.x..x, &old),
+            Some(None) => self.document.delete(&at),
+            None => (),
+        }
+        self.cursor_position = at;
+    }
+
+    /// Replaces the character under the cursor, as done by `r`. `Enter` splits
+    /// the line in its place
+    ///
+    /// # Args
+    ///
+    /// - `c`: The character to put in its place
+    fn replace_char(&mut self, c: char) {
+        let Position { x, y } = self.cursor_position;
+        if x >= self.document.row(y).map_or(0, Row::len) {
+            return;
+        }
+        if c == '\n' {
+            self.document.delete(&self.cursor_position);
+            self.document.insert(&self.cursor_position, '\n');
+            self.cursor_position = Position { x: 0, y: y + 1 };
+        } else {
+            self.document.replace_char(&self.cursor_position, c);
+        }
+    }
+
+    /// Breaks the cursor line once it is wider than the text width, moving
+    /// the last words to a new line with the same comment marker and
+    /// indentation
+    fn break_line(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        let width = self.config.text_width;
+        let broken = match self.document.row(y) {
+            Some(row) if width > 0 => format::break_line(row.as_str(), width),
+            _ => None,
+        };
+        let (before, after, replaced) = match broken {
+            Some(broken) => broken,
+            None => return,
+        };
+        // Only words typed past the limit are moved, not the cursor's own
+        if x < replaced {
+            return;
+        }
+
+        // The characters after the cursor stay after it
+        let following = self.document.row(y).map_or(0, Row::len) - x;
+        let x = Row::from(&after[..]).len() - following;
+        self.document.replace_lines(y, 1, &[before, after]);
+        self.cursor_position = Position { x, y: y + 1 };
+    }
+
+    /// Completes the file path before the cursor, relative to the directory of
+    /// the document or to the working directory for unnamed documents. Several
+    /// candidates are offered in the message bar
+    ///
+    /// # Returns
+    ///
+    /// - Unit or any Error encountered while the user chooses a candidate
+    fn complete_path(&mut self) -> Result<(), std::io::Error> {
+        let Position { x, y } = self.cursor_position;
+        let before = match self.document.row(y) {
+            Some(row) => row.as_str()[..row.byte_index(x)].to_string(),
+            None => return Ok(()),
+        };
+        let partial = &before[complete::path_start(&before)..];
+        let prefix = &partial[partial.rfind('/').map_or(0, |index| index + 1)..];
+
+        let base = self
+            .document
+            .file_name
+            .as_deref()
+            .and_then(|file_name| Path::new(file_name).parent())
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        let candidates = complete::paths(partial, &base);
+        if candidates.is_empty() {
+            self.echo(format!("No files match {}", partial));
+            return Ok(());
+        }
+        let chosen = match self.choose_completion(&candidates)? {
+            Some(chosen) => chosen,
+            None => return Ok(()),
+        };
+
+        let completion = candidates[chosen][prefix.len()..].to_string();
+        let len = Row::from(&completion[..]).len();
+        self.document
+            .insert_text(&self.cursor_position, &[completion]);
+        self.cursor_position.x += len;
+        Ok(())
+    }
+
+    /// Completes the cursor line with a whole line of any open buffer starting
+    /// with the text typed so far, ignoring indentation
+    ///
+    /// # Returns
+    ///
+    /// - Unit or any Error encountered while the user chooses a candidate
+    fn complete_line(&mut self) -> Result<(), std::io::Error> {
+        let Position { x, y } = self.cursor_position;
+        let before = match self.document.row(y) {
+            Some(row) => row.as_str()[..row.byte_index(x)].trim_start().to_string(),
+            None => return Ok(()),
+        };
+
+        let documents = std::iter::once(&self.document)
+            .chain(self.buffers.iter().map(|buffer| &buffer.document));
+        let mut candidates: Vec<String> = Vec::new();
+        'documents: for (index, document) in documents.enumerate() {
+            for (line, text) in document.lines().into_iter().enumerate() {
+                if candidates.len() == MAX_LINE_COMPLETIONS {
+                    break 'documents;
+                }
+                let is_cursor_line = index == 0 && line == y;
+                let trimmed = text.trim_start();
+                if !is_cursor_line
+                    && trimmed.len() > before.len()
+                    && trimmed.starts_with(&before)
+                    && !candidates.iter().any(|candidate| candidate == text)
+                {
+                    candidates.push(text.to_string());
+                }
+            }
+        }
+        if candidates.is_empty() {
+            self.echo(format!("No lines match {}", before));
+            return Ok(());
+        }
+
+        if let Some(chosen) = self.choose_completion(&candidates)? {
+            let line = candidates.swap_remove(chosen);
+            let len = Row::from(&line[..]).len();
+            self.document.replace_lines(y, 1, &[line]);
+            self.cursor_position.x = len;
+        }
+        Ok(())
+    }
+
+    /// Completes the word before the cursor from the dictionary, or replaces
+    /// it with a synonym from the thesaurus
+    ///
+    /// # Args
+    ///
+    /// - `thesaurus`: Whether to offer synonyms rather than completions
+    ///
+    /// # Returns
+    ///
+    /// - Unit or any Error encountered while the user chooses a candidate
+    fn complete_word(&mut self, thesaurus: bool) -> Result<(), std::io::Error> {
+        let Position { x, y } = self.cursor_position;
+        let before = match self.document.row(y) {
+            Some(row) => row.as_str()[..row.byte_index(x)].to_string(),
+            None => return Ok(()),
+        };
+        let word = &before[complete::word_start(&before)..];
+        if word.is_empty() {
+            self.echo("No word before the cursor".to_string());
+            return Ok(());
+        }
+
+        let path = if thesaurus {
+            self.config.thesaurus.clone()
+        } else {
+            self.config.dictionary.clone()
+        };
+        if path.is_empty() {
+            self.echo_warning("No thesaurus set".to_string());
+            return Ok(());
+        }
+        let list = match self.word_list(&path) {
+            Some(list) => list,
+            None => return Ok(()),
+        };
+        let candidates = if thesaurus {
+            list.synonyms(word, MAX_WORD_COMPLETIONS)
+        } else {
+            list.words(word, MAX_WORD_COMPLETIONS)
+        };
+        if candidates.is_empty() {
+            self.echo(format!("No words match {}", word));
+            return Ok(());
+        }
+
+        if let Some(chosen) = self.choose_completion(&candidates)? {
+            let start = x - Row::from(word).len();
+            let len = Row::from(&candidates[chosen][..]).len();
+            self.document
+                .replace_in_range(y, start..x, &candidates[chosen]);
+            self.cursor_position.x = start + len;
+        }
+        Ok(())
+    }
+
+    /// Retrieves a dictionary or thesaurus, reading it the first time
+    ///
+    /// # Args
+    ///
+    /// - `path`: The file of the word list
+    ///
+    /// # Returns
+    ///
+    /// - The word list, or `None` after reporting why it could not be read
+    fn word_list(&mut self, path: &str) -> Option<&WordList> {
+        let index = match self.word_lists.iter().position(|list| list.path == path) {
+            Some(index) => index,
+            None => match WordList::load(path) {
+                Ok(list) => {
+                    self.word_lists.push(list);
+                    self.word_lists.len() - 1
+                }
+                Err(err) => {
+                    self.echo_error(err);
+                    return None;
+                }
+            },
+        };
+        self.word_lists.get(index)
+    }
+
+    /// Lets the user choose a completion in the message bar, unless there is
+    /// only one
+    ///
+    /// # Args
+    ///
+    /// - `candidates`: The completions to choose from
+    ///
+    /// # Returns
+    ///
+    /// - The index of the chosen completion, or `None` if the user cancelled
+    fn choose_completion(
+        &mut self,
+        candidates: &[String],
+    ) -> Result<Option<usize>, std::io::Error> {
+        if candidates.len() == 1 {
+            return Ok(Some(0));
+        }
+        self.pick("", candidates, 0, |_, _| ())
+    }
+
+    /// Processes a keypress from the terminal, handling the key depending on the
+    /// current editor mode
+    ///
+    /// # Returns
+    ///
+    /// - Unit or any Error encountered processing the key
+    fn process_keypress(&mut self) -> Result<(), std::io::Error> {
+        if self.mode == Mode::Normal {
+            self.command_keys.clear();
+            self.command_edits = self.document.edits();
+        }
+        let pressed_key: Key = self.read_key()?;
+        self.clear_references();
+        // Errors stay until the user has had a chance to read them
+        if self.status_message.severity == Severity::Error {
+            self.status_message = StatusMessage::from(String::new());
+        }
+        if self.preview.is_some() && self.mode == Mode::Normal && pressed_key == Key::Char('q') {
+            self.preview = None;
+            return Ok(());
+        }
+        let cursor = self.cursor_position.clone();
+        match pressed_key {
+            Key::Esc => {
+                if self.table_mode && self.mode != Mode::Normal {
+                    self.align_table();
+                }
+                if self.mode != Mode::Normal {
+                    self.marks.insert = Some(self.cursor_position.clone());
+                }
+                let inserted = std::mem::take(&mut self.inserted);
+                if self.mode == Mode::Insert && !inserted.is_empty() {
+                    self.registers.set_last_inserted(&inserted);
+                }
+                self.mode = Mode::Normal;
+                // Normal mode has no line past the end of the document
+                self.move_cursor(Key::Null);
+            }
+            Key::Char(c) => {
+                if self.mode == Mode::Insert {
+                    self.process_insert_keypress(c);
+                } else if self.mode == Mode::Replace {
+                    self.process_replace_keypress(c);
+                } else {
+                    let c = self.config.normal_key(c);
+                    self.process_normal_keypress(c);
+                }
+            }
+            Key::Ctrl('x') if self.mode == Mode::Insert => match self.read_key()? {
+                Key::Ctrl('f') => self.complete_path()?,
+                Key::Ctrl('l') => self.complete_line()?,
+                Key::Ctrl('k') => self.complete_word(false)?,
+                Key::Ctrl('t') => self.complete_word(true)?,
+                _ => (),
+            },
+            Key::Ctrl('a') if self.mode == Mode::Insert => self.insert_again(),
+            Key::Ctrl('w') => self.switch_diff_focus(),
+            Key::Ctrl('c') if self.mode == Mode::Normal => self.cancel_jobs(),
+            Key::Ctrl('o') if self.mode == Mode::Normal => self.go_through_jumps(true),
+            Key::Ctrl('p') if self.mode == Mode::Normal => self.find_file(),
+            Key::Ctrl('r') => self.undo(false),
+            Key::Delete => self.document.delete(&self.cursor_position),
+            Key::Backspace if self.mode == Mode::Replace => self.restore_replaced(),
+            Key::Backspace if (self.cursor_position.x > 0 || self.cursor_position.y > 0) => {
+                if self.mode == Mode::Insert {
+                    self.inserted.pop();
+                    // The closing character goes along with the opening one
+                    if self.config.auto_pairs && self.is_in_empty_pair() {
+                        self.document.delete(&self.cursor_position);
+                    }
+                }
+                self.move_cursor(Key::Backspace);
+                self.document.delete(&self.cursor_position);
+            }
+            Key::Up
+            | Key::Down
+            | Key::Left
+            | Key::Right
+            | Key::PageUp
+            | Key::PageDown
+            | Key::End
+            | Key::Home => self.move_cursor(pressed_key),
+            _ => (),
+        }
+        if self.mode != Mode::Normal && self.document.is_read_only() {
+            self.mode = Mode::Normal;
+            self.echo_warning("Buffer is read-only".to_string());
+        }
+        // Everything typed in Insert mode is undone as a single step, and
+        // repeated as a single change
+        if self.mode == Mode::Normal {
+            self.document.end_undo_step();
+            self.record_change();
+        }
+        if self.cursor_position != cursor {
+            self.preview = None;
+        }
+        self.scroll();
+        Ok(())
+    }
+
+    /// Keeps the keys of the Normal mode command just finished as the change
+    /// `.` repeats, if it edited the document. Commands typed after `:` are
+    /// not repeated
+    fn record_change(&mut self) {
+        let is_command = match self.command_keys.first() {
+            Some(Key::Char(c)) => self.config.normal_key(*c) == ':',
+            _ => false,
+        };
+        if self.document.edits() != self.command_edits && !is_command {
+            self.last_change = std::mem::take(&mut self.command_keys);
+        }
+    }
+
+    /// Repeats the last change at the cursor, as done by `.`
+    ///
+    /// # Args
+    ///
+    /// - `count`: The number of times to repeat it
+    fn repeat_change(&mut self, count: usize) {
+        if self.last_change.is_empty() {
+            self.echo("No change to repeat".to_string());
+            return;
+        }
+        let keys: VecDeque<Key> = self.last_change.iter().copied().collect();
+        // `.` may itself be run by `:normal`
+        let replay = self.replay.take();
+        for _ in 0..count {
+            self.mode = Mode::Normal;
+            self.replay = Some(keys.clone());
+            while self.replay.as_ref().is_some_and(|keys| !keys.is_empty()) {
+                if let Err(err) = self.process_keypress() {
+                    self.echo_error(err);
+                    break;
+                }
+            }
+            self.mode = Mode::Normal;
+        }
+        self.replay = replay;
+        // The repeated change stays the last one, rather than `.` itself
+        self.last_change = keys.into();
+        self.command_edits = self.document.edits();
+    }
+
+    /// Copies text into a register, reading the motion that selects it: `y`
+    /// for the cursor line, or any motion of `motion_range`
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the register
+    fn yank(&mut self, name: char) {
+        let motion = self.read_char();
+        let y = self.cursor_position.y;
+        let row = if let Some(row) = self.document.row(y) {
+            row
+        } else {
+            return;
+        };
+
+        if motion == Some('y') {
+            let register = Register {
+                lines: vec![row.as_str().to_string()],
+                linewise: true,
+            };
+            self.registers.set(name, register);
+            return;
+        }
+        let object = match self.motion_object(motion) {
+            Some(object) => object,
+            None => return,
+        };
+
+        let lines = if object.linewise {
+            self.document.lines()[object.start.y..object.end.y]
+                .iter()
+                .map(|line| line.to_string())
+                .collect()
+        } else {
+            self.document.text(&object.start, &object.end)
+        };
+        self.registers.set(
+            name,
+            Register {
+                lines,
+                linewise: object.linewise,
+            },
+        );
+    }
+
+    /// Deletes text into a register: `d` for the cursor line, or any motion
+    /// of `motion_range`
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the register
+    /// - `motion`: The motion selecting the text
+    fn delete(&mut self, name: char, motion: Option<char>) {
+        let y = self.cursor_position.y;
+        let row = if let Some(row) = self.document.row(y) {
+            row
+        } else {
+            return;
+        };
+
+        if motion == Some('d') {
+            let register = Register {
+                lines: vec![row.as_str().to_string()],
+                linewise: true,
+            };
+            self.registers.set(name, register);
+            self.document.delete_lines(y..y + 1);
+            self.cursor_position.x = 0;
+            self.move_cursor(Key::Null);
+            return;
+        }
+        match self.motion_object(motion) {
+            Some(object) if object.start != object.end => self.delete_object(name, object),
+            _ => (),
+        }
+    }
+
+    /// Deletes text into a register, leaving the cursor where it started
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the register
+    /// - `object`: The text to delete
+    fn delete_object(&mut self, name: char, object: TextObject) {
+        let TextObject {
+            start,
+            end,
+            linewise,
+        } = object;
+        if linewise {
+            let lines = self.document.lines()[start.y..end.y]
+                .iter()
+                .map(|line| line.to_string())
+                .collect();
+            self.registers.set(name, Register { lines, linewise });
+            self.document.delete_lines(start.y..end.y);
+        } else {
+            let lines = self.document.text(&start, &end);
+            self.registers.set(name, Register { lines, linewise });
+            self.document.delete_range(&start, &end);
+        }
+        self.cursor_position = start;
+        self.move_cursor(Key::Null);
+    }
+
+    /// Deletes text and starts Insert mode in its place: `c` followed by a
+    /// motion or text object, `cc` for the cursor line after its indentation
+    /// when `autoindent` is set, and `$` up to the end of the line. Like in
+    /// Vim, `cw` on a word only changes up to the end of the word, and whole
+    /// lines are replaced by an empty line
+    ///
+    /// # Args
+    ///
+    /// - `motion`: The key of the motion
+    fn change(&mut self, motion: Option<char>) {
+        let Position { x, y } = self.cursor_position;
+        let line = self.document.row(y).map_or("", Row::as_str);
+        let on_word = line[self.document.row(y).map_or(0, |row| row.byte_index(x))..]
+            .starts_with(|c: char| !c.is_whitespace());
+        let object = match motion {
+            Some('c') => {
+                let indent = if self.config.auto_indent {
+                    Row::from(&line[..line.len() - line.trim_start().len()]).len()
+                } else {
+                    0
+                };
+                Some(TextObject {
+                    start: Position { x: indent, y },
+                    end: Position {
+                        x: Row::from(line).len(),
+                        y,
+                    },
+                    linewise: false,
+                })
+            }
+            Some(word @ ('w' | 'W')) if on_word => {
+                let lines = self.document.lines();
+                textobject::find(&lines, &self.cursor_position, true, word).map(|object| {
+                    TextObject {
+                        start: self.cursor_position.clone(),
+                        ..object
+                    }
+                })
+            }
+            motion => self.motion_object(motion),
+        };
+        let object = match object {
+            Some(object) => object,
+            None => return,
+        };
+
+        let (start, linewise) = (object.start.clone(), object.linewise);
+        if object.start != object.end {
+            self.delete_object(registers::UNNAMED, object);
+        }
+        if linewise {
+            self.document.replace_lines(start.y, 0, &[String::new()]);
+        }
+        self.cursor_position = start;
+        self.mode = Mode::Insert;
+    }
+
+    /// Finds the text an operator such as `d` works on: a text object such
+    /// as `iw` or `a(` after `i` or `a`, or the characters covered by any
+    /// other motion of `motion_range`
+    ///
+    /// # Args
+    ///
+    /// - `motion`: The key of the motion
+    ///
+    /// # Returns
+    ///
+    /// - The text, or `None` for an unknown motion or text object
+    fn motion_object(&mut self, motion: Option<char>) -> Option<TextObject> {
+        if let Some(around @ ('i' | 'a')) = motion {
+            let kind = self.read_char()?;
+            let lines = self.document.lines();
+            return textobject::find(&lines, &self.cursor_position, around == 'i', kind);
+        }
+        let y = self.cursor_position.y;
+        let range = self.motion_range(motion)?;
+        Some(TextObject {
+            start: Position { x: range.start, y },
+            end: Position { x: range.end, y },
+            linewise: false,
+        })
+    }
+
+    /// Finds where a character motion moves the cursor: `f`, `F`, `t` or `T`
+    /// followed by the character to find, `;` to repeat the last of them and
+    /// `,` to repeat it in the other direction
+    ///
+    /// # Args
+    ///
+    /// - `motion`: The key of the motion
+    ///
+    /// # Returns
+    ///
+    /// - The motion made, `;` and `,` standing for the one they repeat, and
+    ///   the index the cursor moves to, or `None` if nothing was found
+    fn find_char(&mut self, motion: Option<char>) -> Option<(char, usize)> {
+        let (kind, target, repeat) = match motion? {
+            kind @ ('f' | 'F' | 't' | 'T') => {
+                let target = self.read_char()?;
+                self.last_find = Some((kind, target));
+                (kind, target, false)
+            }
+            ';' => {
+                let (kind, target) = self.last_find?;
+                (kind, target, true)
+            }
+            ',' => {
+                let (kind, target) = self.last_find?;
+                let reversed = if kind.is_ascii_lowercase() {
+                    kind.to_ascii_uppercase()
+                } else {
+                    kind.to_ascii_lowercase()
+                };
+                (reversed, target, true)
+            }
+            _ => return None,
+        };
+        let Position { x, y } = self.cursor_position;
+        let line = self.document.row(y)?.as_str();
+        motion::find_char(line, x, kind, target, repeat).map(|x| (kind, x))
+    }
+
+    /// Moves the cursor by words
+    ///
+    /// # Args
+    ///
+    /// - `motion`: The word motion to make
+    /// - `big`: Whether to move by WORDs, which are separated by whitespace only
+    fn move_word(&mut self, motion: WordMotion, big: bool) {
+        self.cursor_position = motion::word(&self.document, &self.cursor_position, motion, big);
+        self.move_cursor(Key::Null);
+    }
+
+    /// Reflows the lines covered by a motion to the text width: `q` for the
+    /// cursor line, `j` and `k` for the line below or above too, `}` to the
+    /// end of the paragraph, `G` to the end of the document and `ap` or `ip`
+    /// for the whole paragraph
+    ///
+    /// # Args
+    ///
+    /// - `motion`: The key of the motion
+    fn reflow(&mut self, motion: Option<char>) {
+        let y = self.cursor_position.y;
+        let len = self.document.len();
+        let lines = match motion {
+            Some('q') => y..y + 1,
+            Some('j') => y..y + 2,
+            Some('k') => y.saturating_sub(1)..y + 1,
+            Some('G') => y..len,
+            Some('}') => y..format::paragraph(&self.document.lines(), y).end,
+            Some('a' | 'i') if self.read_char() == Some('p') => {
+                format::paragraph(&self.document.lines(), y)
+            }
+            _ => return,
+        };
+        let lines = lines.start..std::cmp::min(lines.end, len);
+        if lines.is_empty() {
+            return;
+        }
+
+        let width = match self.config.text_width {
+            0 => DEFAULT_TEXT_WIDTH,
+            width => width,
+        };
+        let reflowed = format::reflow(&self.document.lines()[lines.clone()], width);
+        let last = lines.start + reflowed.len().saturating_sub(1);
+        self.document
+            .replace_lines(lines.start, lines.len(), &reflowed);
+        self.cursor_position = Position { x: 0, y: last };
+        self.move_cursor(Key::Null);
+    }
+
+    /// Finds the characters of the cursor line covered by a motion: `l` for
+    /// the character under the cursor, `w` up to the next word, `e` to the end
+    /// of the word, `b` back to the start of the word, `$` up to the end of
+    /// the line and `0` from the start of the line. The uppercase `W`, `E` and
+    /// `B` move by WORDs. `f`, `t`, `F`, `T`, `;` and `,` cover the
+    /// characters up to where they move the cursor, including the one found
+    /// by `f` and `t`
+    ///
+    /// # Args
+    ///
+    /// - `motion`: The key of the motion
+    ///
+    /// # Returns
+    ///
+    /// - The range of characters, or `None` for an unknown motion
+    fn motion_range(&mut self, motion: Option<char>) -> Option<Range<usize>> {
+        let Position { x, y } = self.cursor_position;
+        if matches!(motion, Some('f' | 'F' | 't' | 'T' | ';' | ',')) {
+            return match self.find_char(motion)? {
+                ('f' | 't', found) => Some(x..found + 1),
+                (_, found) => Some(found..x),
+            };
+        }
+        let row = self.document.row(y)?;
+        let key = motion?;
+        // Word motions stop at the edges of the cursor line
+        let word = |word_motion| {
+            let target = motion::word(
+                &self.document,
+                &self.cursor_position,
+                word_motion,
+                key.is_ascii_uppercase(),
+            );
+            (target.y == y).then_some(target.x)
+        };
+
+        match key {
+            'l' => Some(x..std::cmp::min(x.saturating_add(1), row.len())),
+            'w' | 'W' => Some(x..word(WordMotion::Forward).unwrap_or(row.len())),
+            'e' | 'E' => {
+                let end = word(WordMotion::ForwardEnd).map_or(row.len(), |end| end + 1);
+                Some(x..std::cmp::min(end, row.len()))
+            }
+            'b' | 'B' => Some(word(WordMotion::Backward).unwrap_or(0)..x),
+            '$' => Some(x..row.len()),
+            '0' => Some(0..x),
+            _ => None,
+        }
+    }
+
+    /// Puts the text of a register into the document. Whole lines go below
+    /// or above the cursor line, other text after or before the cursor
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the register
+    /// - `before`: Whether to put the text before the cursor (`P`)
+    fn paste(&mut self, name: char, before: bool) {
+        let register = if let Some(register) = self.registers.get(name) {
+            register.clone()
+        } else {
+            self.echo(format!("Nothing in register {}", name));
+            return;
+        };
+
+        let Position { x, y } = self.cursor_position;
+        if register.linewise {
+            let y = if before {
+                y
+            } else {
+                std::cmp::min(y.saturating_add(1), self.document.len())
+            };
+            self.document.replace_lines(y, 0, &register.lines);
+            self.cursor_position = Position { x: 0, y };
+        } else {
+            let len = self.document.row(y).map_or(0, Row::len);
+            let x = if before || len == 0 {
+                x
+            } else {
+                std::cmp::min(x.saturating_add(1), len)
+            };
+            self.document
+                .insert_text(&Position { x, y }, &register.lines);
+            self.cursor_position = Position { x, y };
+        }
+    }
+
+    /// Reverts the latest change to the document, or makes the latest
+    /// reverted change again
+    ///
+    /// # Args
+    ///
+    /// - `undo`: Whether to revert rather than make the change again
+    fn undo(&mut self, undo: bool) {
+        let position = if undo {
+            self.document.undo()
+        } else {
+            self.document.redo()
+        };
+
+        if let Some(Position { x, y }) = position {
+            let y = std::cmp::min(y, self.document.len());
+            self.cursor_position = Position { x, y };
+            self.move_cursor(Key::Null);
+        } else if undo {
+            self.echo("Already at oldest change".to_string());
+        } else {
+            self.echo("Already at newest change".to_string());
+        }
+    }
+
+    /// Save the document. Abort on empty prompt or erorr
+    fn save(&mut self) {
+        // The file written in the background would otherwise be written
+        // over with the older text once done
+        if let Some((file_name, revision, job)) = self.saving.take() {
+            self.finish_save(&file_name, revision, job.wait());
+        }
+        if self.document.file_name.is_none() {
+            let new_name: Option<String> = self.prompt("Save as: ", |_, _, _| None).unwrap_or(None);
+
+            if new_name.is_none() {
+                self.echo("Save aborted.".to_string());
+                return;
+            }
+
+            self.document.file_name = new_name;
+        }
+
+        if self.document.save().is_ok() {
+            self.echo("File saved successfully.".to_string());
+            if self.special_buffer_kind() == Some(SpecialBuffer::GitCommit) {
+                self.finish_git_commit();
+            } else if let Some(file_name) = self.document.file_name.clone() {
+                self.after_save(&file_name);
+                self.refresh_git_staged();
+            }
+        } else {
+            self.echo_error("Could not write to disk");
+        }
+    }
+
+    /// Save the document in the background, as done by `:w`, so the editor
+    /// keeps taking keys while a large file is written. The result is
+    /// reported by `finish_jobs`
+    fn save_in_background(&mut self) {
+        if self.saving.is_some() {
+            self.echo_warning("A file is already being written".to_string());
+            return;
+        }
+        let file_name = match &self.document.file_name {
+            Some(file_name) => file_name.clone(),
+            None => return,
+        };
+        let contents = match self.document.start_save() {
+            Ok(contents) => contents,
+            Err(err) => {
+                self.echo_error(format!("Could not write {}: {}", file_name, err));
+                return;
+            }
+        };
+        let revision = self.document.revision();
+        let path = file_name.clone();
+        let job = Job::spawn_with_progress("Writing", move |progress| {
+            contents
+                .write(&path, |done, total| progress.update(done, Some(total)))
+                .map_err(|err| err.to_string())
+        });
+        self.saving = Some((file_name, revision, job));
+    }
+
+    /// Mark the document written in the background as saved, unless it was
+    /// changed in the meantime
+    ///
+    /// # Args
+    ///
+    /// - `file_name`: The file written
+    /// - `revision`: The revision of the document written
+    /// - `result`: The result of writing the file
+    fn finish_save(&mut self, file_name: &str, revision: usize, result: Result<(), String>) {
+        if let Err(err) = result {
+            self.echo_error(format!("Could not write {}: {}", file_name, err));
+            return;
+        }
+        if let Some(document) = self.document_named(file_name) {
+            document.finish_save(revision);
+        }
+        self.after_save(file_name);
+        self.refresh_git_staged();
+        self.echo(format!("\"{}\" written", file_name));
+    }
+
+    /// Keep track of a file just saved: snapshot it in the local history,
+    /// drop its recovery copy and remember where it was last changed
+    ///
+    /// # Args
+    ///
+    /// - `file_name`: The file saved
+    fn after_save(&mut self, file_name: &str) {
+        // A failed snapshot must not get in the way of saving
+        local_history::snapshot(file_name).ok();
+        recovery::remove(file_name);
+        let last_change = self
+            .document_named(file_name)
+            .and_then(|document| document.last_change());
+        if let Some(position) = last_change {
+            local_history::save_last_change(file_name, &position).ok();
+        }
+    }
+
+    /// Retrieve the open document of a file, whether it is shown, set aside
+    /// for a special buffer or in the background
+    ///
+    /// # Args
+    ///
+    /// - `file_name`: The file of the document
+    fn document_named(&mut self, file_name: &str) -> Option<&mut Document> {
+        let aside = self
+            .special_buffer
+            .as_mut()
+            .map(|(_, buffer)| &mut buffer.document);
+        std::iter::once(&mut self.document)
+            .chain(aside)
+            .chain(self.buffers.iter_mut().map(|buffer| &mut buffer.document))
+            .find(|document| document.file_name.as_deref() == Some(file_name))
+    }
+
+    /// Stop the background work, as done by `Ctrl-C`. A file being written is
+    /// left to finish so it is not cut short
+    fn cancel_jobs(&mut self) {
+        let mut cancelled = false;
+        if let Some(job) = self.symbol_index.take() {
+            job.cancel();
+            cancelled = true;
+        }
+        if let Some((_, job)) = self.grep.take() {
+            job.cancel();
+            cancelled = true;
+        }
+        if let Some(job) = self.file_list.take() {
+            job.cancel();
+            cancelled = true;
+        }
+        if cancelled {
+            self.echo("Cancelled".to_string());
+        } else if self.saving.is_some() {
+            self.echo_warning("Writing the file cannot be cancelled".to_string());
+        }
+    }
+
+    /// Show the progress of background work in the message bar
+    ///
+    /// # Returns
+    ///
+    /// - Whether any progress was shown
+    fn show_progress(&mut self) -> bool {
+        let saving = self.saving.as_ref().map(|(_, _, job)| job);
+        let text = saving
+            .and_then(Job::progress)
+            .or_else(|| self.grep.as_ref().and_then(|(_, job)| job.progress()))
+            .or_else(|| self.file_list.as_ref().and_then(Job::progress))
+            .or_else(|| self.symbol_index.as_ref().and_then(Job::progress))
+            .and_then(|progress| progress.describe(self.progress_frame));
+        match text {
+            Some(text) => {
+                self.progress_frame += 1;
+                self.echo(text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Retrieve the kind of special buffer currently shown, if any
+    fn special_buffer_kind(&self) -> Option<SpecialBuffer> {
+        self.special_buffer.as_ref().map(|(kind, _)| *kind)
+    }
+
+    /// Replace the document with a special buffer, keeping the document aside so
+    /// it can be restored when the special buffer is closed
+    ///
+    /// # Args
+    ///
+    /// - `kind`: The kind of special buffer
+    /// - `document`: The contents of the special buffer
+    fn open_special_buffer(&mut self, kind: SpecialBuffer, document: Document) {
+        self.close_special_buffer();
+        let previous = self.take_view();
+        self.document = document;
+        self.special_buffer = Some((kind, previous));
+        self.scroll();
+    }
+
+    /// Close the current special buffer, restoring the document it replaced
+    ///
+    /// # Returns
+    ///
+    /// - Whether a special buffer was open
+    fn close_special_buffer(&mut self) -> bool {
+        if self.special_buffer_kind() == Some(SpecialBuffer::Notes) && self.document.is_dirty() {
+            if let Err(err) = self.document.save() {
+                self.echo_error(format!("Could not save notes: {}", err));
+            }
+        }
+
+        if let Some((_, previous)) = self.special_buffer.take() {
+            self.restore_view(previous);
+            self.refresh_git_staged();
+            self.scroll();
+            return true;
+        }
+        false
+    }
+
+    /// Open the man page of the word under the cursor in a read-only special
+    /// buffer, for the file types documented by man pages
+    fn man_page(&mut self) {
+        let file_type = self.document.file_type();
+        if !matches!(file_type.as_str(), "C" | "Shell")
+            && self.special_buffer_kind() != Some(SpecialBuffer::Manual)
+        {
+            self.echo_warning(format!("No man pages for {}", file_type));
+            return;
+        }
+
+        let Position { x, y } = self.cursor_position;
+        let topic = match self
+            .document
+            .row(y)
+            .and_then(|row| Some(row.as_str()[row.word_at(x)?].to_string()))
+        {
+            Some(topic) => topic,
+            None => {
+                self.echo("No word under the cursor".to_string());
+                return;
+            }
+        };
+
+        // man and groff may take a while, the page opens once rendered
+        let width = self.text_width(&self.document, self.terminal.size().width as usize);
+        let job = {
+            let topic = topic.clone();
+            Job::spawn(move || man::page(&topic, width))
+        };
+        self.echo(format!("Rendering man {}...", topic));
+        self.man_page = Some((topic, job));
+    }
+
+    /// Open a man page rendered in the background
+    ///
+    /// # Args
+    ///
+    /// - `topic`: The name of the page
+    /// - `result`: The text of the page, or the error reported by man
+    fn open_man_page(&mut self, topic: &str, result: Result<String, String>) {
+        match result {
+            Ok(page) => {
+                let mut document = Document::from_text(&page);
+                document.set_read_only(true);
+                self.open_special_buffer(SpecialBuffer::Manual, document);
+                self.status_message = StatusMessage::from(format!("man {}, :q to close", topic));
+            }
+            Err(err) => self.echo_error(err),
+        }
+    }
+
+    /// Browse a directory, as done by `:Explore`: the one given, or the one of
+    /// the document's file
+    ///
+    /// # Args
+    ///
+    /// - `dir`: The directory, empty for the one of the document's file
+    fn explore_command(&mut self, dir: &str) {
+        if !dir.is_empty() {
+            self.explore(dir);
+            return;
+        }
+        let file_name = self.document.file_name.clone().unwrap_or_default();
+        if self.special_buffer_kind() == Some(SpecialBuffer::Explorer) {
+            self.explore(&file_name);
+            return;
+        }
+        let dir = match Path::new(&file_name).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().to_string(),
+            _ => ".".to_string(),
+        };
+        self.explore(&dir);
+    }
+
+    /// List a directory in a read-only special buffer named after it, or in
+    /// the one already shown
+    ///
+    /// # Args
+    ///
+    /// - `dir`: The directory
+    fn explore(&mut self, dir: &str) {
+        let lines = match explorer::list(Path::new(dir)) {
+            Ok(lines) => lines,
+            Err(err) => {
+                self.echo_error(err);
+                return;
+            }
+        };
+        let mut document = Document::from_text(&lines.join("\n"));
+        document.file_name = Some(dir.to_string());
+        document.set_read_only(true);
+        if self.special_buffer_kind() == Some(SpecialBuffer::Explorer) {
+            self.document = document;
+        } else {
+            self.open_special_buffer(SpecialBuffer::Explorer, document);
+        }
+        // On `../`, the first entry below is usually the one wanted
+        self.cursor_position = Position { x: 0, y: 1 };
+        self.offset = Position::default();
+        self.move_cursor(Key::Null);
+        self.status_message = StatusMessage::from("Enter opens, - goes up, q closes".to_string());
+    }
+
+    /// Handles the keys of the explorer: `Enter` opens the file or directory
+    /// under the cursor, `-` lists the parent directory and `q` closes the
+    /// explorer
+    ///
+    /// # Args
+    ///
+    /// - `c`: The character received from the user
+    ///
+    /// # Returns
+    ///
+    /// - Whether the key was handled
+    fn process_explorer_keypress(&mut self, c: char) -> bool {
+        let dir = PathBuf::from(self.document.file_name.clone().unwrap_or_default());
+        let path = match c {
+            '\n' => {
+                let lines = self.document.lines();
+                match explorer::entry(&dir, &lines, self.cursor_position.y) {
+                    Some(path) => path,
+                    None => return true,
+                }
+            }
+            '-' => explorer::parent(&dir),
+            'q' => {
+                self.close_special_buffer();
+                return true;
+            }
+            _ => return false,
+        };
+        let path = path.to_string_lossy().to_string();
+        if Path::new(&path).is_dir() {
+            self.explore(&path);
+        } else {
+            self.close_special_buffer();
+            self.edit_file(&path);
+        }
+        true
+    }
+
+    /// Open the notes of the current project in a special buffer
+    fn notes(&mut self) {
+        if self.special_buffer_kind() == Some(SpecialBuffer::Notes) {
+            return;
+        }
+
+        let path = match notes::path() {
+            Ok(path) => path.to_string_lossy().to_string(),
+            Err(err) => {
+                self.echo_error(err);
+                return;
+            }
+        };
+        let mut document = Document::open(&path).unwrap_or_default();
+        document.file_name = Some(path);
+        self.open_special_buffer(SpecialBuffer::Notes, document);
+    }
+
+    /// List the local history snapshots of the current file in a special buffer
+    fn local_history(&mut self) {
+        let file_name = if let Some(file_name) = &self.document.file_name {
+            file_name.clone()
+        } else {
+            self.echo("Document has no file name".to_string());
+            return;
+        };
+
+        let snapshots = local_history::list(&file_name);
+        if snapshots.is_empty() {
+            self.echo(format!("No history for {}", file_name));
+            return;
+        }
+
+        let listing: Vec<String> = snapshots
+            .iter()
+            .map(|snapshot| local_history::describe(snapshot))
+            .collect();
+        self.open_special_buffer(
+            SpecialBuffer::LocalHistory,
+            Document::from_text(&listing.join("\n")),
+        );
+        self.status_message =
+            StatusMessage::from("Enter = Diff | r = Restore | q = Close".to_string());
+    }
+
+    /// Handles Keypresses in the local history buffer
+    ///
+    /// # Args
+    ///
+    /// - `c`: The character received from the user
+    ///
+    /// # Returns
+    ///
+    /// - Whether the key was handled
+    fn process_local_history_keypress(&mut self, c: char) -> bool {
+        if !matches!(c, '\n' | 'r' | 'q') {
+            return false;
+        }
+
+        let snapshot = self
+            .special_buffer
+            .as_ref()
+            .and_then(|(_, buffer)| buffer.document.file_name.as_ref())
+            .map(|file_name| local_history::list(file_name))
+            .and_then(|snapshots| snapshots.into_iter().nth(self.cursor_position.y));
+        self.close_special_buffer();
+        if c == 'q' {
+            return true;
+        }
+
+        let contents = match snapshot.map(std::fs::read_to_string) {
+            Some(Ok(contents)) => contents,
+            _ => {
+                self.echo("Could not read snapshot".to_string());
+                return true;
+            }
+        };
+
+        if c == 'r' {
+            let lines: Vec<String> = contents.lines().map(ToString::to_string).collect();
+            self.document.replace_lines(0, self.document.len(), &lines);
+            self.move_cursor(Key::Null);
+            self.echo("Restored snapshot".to_string());
+        } else {
+            self.diff_off();
+            self.diff = Some(DiffView::new(Document::from_text(&contents)));
+            self.echo("Comparing with snapshot, :diffoff to close".to_string());
+        }
+        true
+    }
+
+    /// Read the staged contents of the document's file again, if the gutter
+    /// shows git markers
+    fn refresh_git_staged(&mut self) {
+        self.git_staged = None;
+        self.document.forget_staged();
+        if let Some(diff) = self.diff.as_mut() {
+            diff.other.forget_staged();
+        }
+        if !self.gutter_components().contains(&gutter::Component::Git) {
+            return;
+        }
+
+        if let Some(file_name) = &self.document.file_name {
+            if let Ok(staged) =
+                git::full_name(file_name).and_then(|name| git::index_contents(&name))
+            {
+                self.git_staged = Some((file_name.clone(), staged));
+            }
+        }
+    }
+
+    /// Lay out the gutter for a document
+    ///
+    /// # Args
+    ///
+    /// - `document`: The document shown next to the gutter
+    fn gutter<'a>(&self, document: &'a Document) -> Gutter<'a> {
+        Gutter::new(self.gutter_components(), document)
+    }
+
+    /// Find the conflicts and the lines changed since the version staged in
+    /// git in the documents on screen, for the documents that changed since
+    /// they were last found, so drawing does not search them every frame
+    fn scan_documents(&mut self) {
+        let git = self.gutter_components().contains(&gutter::Component::Git);
+        let git_staged = self.git_staged.as_ref().filter(|_| git);
+        let documents = std::iter::once(&mut self.document)
+            .chain(self.diff.as_mut().map(|diff| &mut diff.other));
+        for document in documents {
+            document.find_conflicts();
+            let staged = match (git_staged, &document.file_name) {
+                (Some((name, staged)), Some(file_name)) if name == file_name => Some(&staged[..]),
+                _ => None,
+            };
+            document.compare_staged(staged);
+        }
+    }
+
+    /// Retrieve the components of the gutter set in the config. Screen
+    /// readers cannot see the colors of conflicts and changed lines, so their
+    /// signs are added for them
+    fn gutter_components(&self) -> Vec<gutter::Component> {
+        let mut components = self.config.gutter.clone();
+        if self.config.screen_reader {
+            for component in [gutter::Component::Signs, gutter::Component::Git] {
+                if !components.contains(&component) {
+                    components.push(component);
+                }
+            }
+        }
+        components
+    }
+
+    /// Retrieve the number of columns left for text next to the gutter
+    ///
+    /// # Args
+    ///
+    /// - `document`: The document shown next to the gutter
+    /// - `width`: The width of the window
+    fn text_width(&self, document: &Document, width: usize) -> usize {
+        let gutter_width = gutter::width(
+            &self.gutter_components(),
+            gutter::number_width(document.len()),
+        );
+        width.saturating_sub(gutter_width)
+    }
+
+    /// Check whether long lines are shown over several screen rows. Lined up
+    /// columns are scrolled horizontally instead
+    fn wraps(&self) -> bool {
+        self.config.wrap && self.column_layout().is_none()
+    }
+
+    /// Measure the columns of the lines on screen, when the document is a CSV
+    /// or TSV file shown with its columns lined up
+    ///
+    /// # Returns
+    ///
+    /// - The widths of the columns, or `None` when the lines are shown as
+    ///   they are
+    fn column_layout(&self) -> Option<Layout> {
+        let separator = self.document.column_separator()?;
+        if !self.config.align_columns || self.diff.is_some() {
+            return None;
+        }
+        let height = self.document_height();
+        let lines = (self.offset.y..self.offset.y.saturating_add(height))
+            .map_while(|y| self.document.row(y))
+            .map(Row::as_str);
+        Some(Layout::new(lines, separator))
+    }
+
+    /// Find the screen column of the cursor, counted from the start of its
+    /// line, which differs from its character after tabs and wide characters
+    /// or when columns are lined up
+    fn cursor_column(&self, layout: Option<&Layout>) -> usize {
+        let Position { x, y } = self.cursor_position;
+        match (layout, self.document.row(y)) {
+            (Some(layout), Some(row)) => layout.column(row.as_str(), x),
+            (None, Some(row)) => row.width_until(x, self.config.tab_size),
+            _ => x,
+        }
+    }
+
+    /// Save the current file and add it to the git index
+    fn git_write(&mut self) {
+        self.save();
+        if self.document.is_dirty() {
+            return;
+        }
+
+        if let Some(file_name) = &self.document.file_name {
+            match git::stage(file_name) {
+                Ok(_) => self.echo(format!("Staged {}", file_name)),
+                Err(err) => self.echo_error(err),
+            }
+            self.refresh_git_staged();
+        }
+    }
+
+    /// Show the status of the repository in a special buffer, refreshing it if it
+    /// is already open
+    fn git_status(&mut self) {
+        let status = match git::status() {
+            Ok(status) => status,
+            Err(err) => {
+                self.echo_error(err);
+                return;
+            }
+        };
+
+        if self.special_buffer_kind() == Some(SpecialBuffer::GitStatus) {
+            self.document = Document::from_text(&status);
+            self.move_cursor(Key::Null);
+        } else {
+            self.open_special_buffer(SpecialBuffer::GitStatus, Document::from_text(&status));
+        }
+        self.status_message =
+            StatusMessage::from("s = Stage | u = Unstage | q = Close".to_string());
+    }
+
+    /// Handles Keypresses in the git status buffer
+    ///
+    /// # Args
+    ///
+    /// - `c`: The character received from the user
+    ///
+    /// # Returns
+    ///
+    /// - Whether the key was handled
+    fn process_git_status_keypress(&mut self, c: char) -> bool {
+        // Status paths are relative to the top of the repository
+        let path = self
+            .document
+            .row(self.cursor_position.y)
+            .and_then(|row| git::status_path(row.as_str()))
+            .map(|path| format!(":(top){}", path));
+
+        let result = match (c, path) {
+            ('q', _) => {
+                self.close_special_buffer();
+                return true;
+            }
+            ('s', Some(path)) => git::stage(&path),
+            ('u', Some(path)) => git::unstage(&path),
+            _ => return false,
+        };
+
+        self.git_status();
+        if let Err(err) = result {
+            self.echo_error(err);
+        }
+        true
+    }
+
+    /// Compare the document with the version of its file staged in the index
+    ///
+    /// # Returns
+    ///
+    /// - The path of the file in the repository, the staged contents and the
+    ///   hunks turning the staged contents into the document
+    fn git_hunks(&self) -> Result<(String, String, Vec<Hunk>), String> {
+        let file_name = self
+            .document
+            .file_name
+            .as_ref()
+            .ok_or_else(|| "Document has no file name".to_string())?;
+        let full_name = git::full_name(file_name)?;
+        let staged = git::index_contents(&full_name)?;
+        let staged_lines: Vec<&str> = staged.lines().collect();
+        let hunks = diff::diff(&staged_lines, &self.document.lines());
+        Ok((full_name, staged, hunks))
+    }
+
+    /// Find the unstaged hunk under the cursor, reporting any failure in the
+    /// message bar
+    ///
+    /// # Returns
+    ///
+    /// - The path of the file in the repository, the staged contents and the
+    ///   hunk if there is one
+    fn git_hunk_under_cursor(&mut self) -> Option<(String, String, Hunk)> {
+        match self.git_hunks() {
+            Ok((full_name, staged, hunks)) => {
+                let y = self.cursor_position.y;
+                if let Some(hunk) = hunks.into_iter().find(|hunk| hunk.contains(y, false)) {
+                    return Some((full_name, staged, hunk));
+                }
+                self.echo("No hunk under cursor".to_string());
+            }
+            Err(err) => self.echo_error(err),
+        }
+        None
+    }
+
+    /// Moves the cursor to the next or previous hunk not yet staged in git
+    ///
+    /// # Args
+    ///
+    /// - `forward`: Whether to search below the cursor rather than above it
+    fn jump_to_git_hunk(&mut self, forward: bool) {
+        match self.git_hunks() {
+            Ok((_, _, hunks)) => {
+                let starts: Vec<usize> = hunks.iter().map(|hunk| hunk.right_start).collect();
+                self.jump_to_line(&starts, forward, "No more hunks");
+            }
+            Err(err) => self.echo_error(err),
+        }
+    }
+
+    /// Add the hunk under the cursor to the git index
+    fn git_stage_hunk(&mut self) {
+        if let Some((full_name, staged, hunk)) = self.git_hunk_under_cursor() {
+            let staged_lines: Vec<&str> = staged.lines().collect();
+            let patch = git::hunk_patch(&full_name, &hunk, &staged_lines, &self.document.lines());
+            match git::apply_cached(&patch) {
+                Ok(_) => self.echo("Staged hunk".to_string()),
+                Err(err) => self.echo_error(err),
+            }
+            self.refresh_git_staged();
+        }
+    }
+
+    /// Replace the hunk under the cursor with the version staged in the index
+    fn git_revert_hunk(&mut self) {
+        if let Some((_, staged, hunk)) = self.git_hunk_under_cursor() {
+            let lines: Vec<String> = staged
+                .lines()
+                .skip(hunk.left_start)
+                .take(hunk.left_len)
+                .map(ToString::to_string)
+                .collect();
+            self.document
+                .replace_lines(hunk.right_start, hunk.right_len, &lines);
+            self.move_cursor(Key::Null);
+            self.echo("Reverted hunk".to_string());
+        }
+    }
+
+    /// Open a special buffer for the commit message, the commit is created once
+    /// the buffer is saved
+    fn git_commit(&mut self) {
+        match git::commit_message_path() {
+            Ok(path) => {
+                let mut document = Document::from_text("");
+                document.file_name = Some(path);
+                self.open_special_buffer(SpecialBuffer::GitCommit, document);
+                self.status_message = StatusMessage::from(
+                    "Write the commit message, :w to commit, :q to abort".to_string(),
+                );
+            }
+            Err(err) => self.echo_error(err),
+        }
+    }
+
+    /// Create the commit from the saved commit message buffer in the
+    /// background and close the buffer, the result is reported by
+    /// `finish_jobs` once git is done
+    fn finish_git_commit(&mut self) {
+        let path = match &self.document.file_name {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        if self.commit.is_some() {
+            self.echo_warning("A commit is already being created".to_string());
+            return;
+        }
+        self.close_special_buffer();
+        self.commit = Some(Job::spawn(move || git::commit(&path)));
+        self.echo("Committing...".to_string());
+    }
+
+    /// Report the background work that finished since the last check
+    ///
+    /// # Returns
+    ///
+    /// - Whether any work finished
+    fn finish_jobs(&mut self) -> bool {
+        let mut finished = false;
+        if let Some(result) = self.commit.as_ref().and_then(Job::try_finish) {
+            self.commit = None;
+            match result {
+                Ok(output) => self.echo(output.lines().next().unwrap_or_default().to_string()),
+                Err(err) => self.echo_error(err),
+            }
+            finished = true;
+        }
+        if let Some(result) = self.symbol_index.as_ref().and_then(Job::try_finish) {
+            self.symbol_index = None;
+            match result {
+                Ok(found) => {
+                    self.echo(format!(
+                        "Indexed {}, :Symbols to search them",
+                        plural(found.len(), "symbol", "symbols")
+                    ));
+                    self.symbols = Some(found);
+                }
+                Err(err) => self.echo_error(err),
+            }
+            finished = true;
+        }
+        if let Some(result) = self
+            .saving
+            .as_ref()
+            .and_then(|(_, _, job)| job.try_finish())
+        {
+            if let Some((file_name, revision, _)) = self.saving.take() {
+                self.finish_save(&file_name, revision, result);
+            }
+            finished = true;
+        }
+        // Man pages, the file picker and the matches of :grep take over the
+        // screen, so they wait for commands being typed to finish
+        if self.mode == Mode::Normal && self.command_keys.is_empty() {
+            if let Some(result) = self.man_page.as_ref().and_then(|(_, job)| job.try_finish()) {
+                if let Some((topic, _)) = self.man_page.take() {
+                    self.open_man_page(&topic, result);
+                }
+                finished = true;
+            }
+            if let Some(result) = self.file_list.as_ref().and_then(Job::try_finish) {
+                self.file_list = None;
+                match result {
+                    Ok(files) => self.open_picked_file(&files),
+                    Err(err) => self.echo_error(err),
+                }
+                // The keys typed in the picker are not part of a command
+                self.command_keys.clear();
+                finished = true;
+            }
+            if let Some(result) = self.grep.as_ref().and_then(|(_, job)| job.try_finish()) {
+                if let Some((query, _)) = self.grep.take() {
+                    match result {
+                        Ok(entries) => self.show_matches(&query, entries),
+                        Err(err) => self.echo_error(err),
+                    }
+                }
+                finished = true;
+            }
+        }
+        finished
+    }
+
+    /// Prompt the user for an input
+    ///
+    /// # Args
+    ///
+    /// - `prompt`: The prompt to the user
+    /// - `callback`: A function to be called on a keypress, returning a hint
+    ///   shown after the input
+    ///
+    /// # Returns
+    ///
+    /// - A
+    fn prompt<C>(&mut self, prompt: &str, callback: C) -> Result<Option<String>, std::io::Error>
+    where
+        C: Fn(&mut Self, Key, &String) -> Option<String>,
+    {
+        self.prompt_with_history(prompt, &mut PromptHistory::default(), callback)
+    }
+
+    /// Prompt the user for an input, `Up` and `Down` going through the
+    /// entries of a history that start with what was typed. The input is
+    /// added to the history once confirmed
+    ///
+    /// # Args
+    ///
+    /// - `prompt`: The prompt to the user
+    /// - `history`: The inputs given before
+    /// - `callback`: A function to be called on a keypress, returning a hint
+    ///   shown after the input
+    ///
+    /// # Returns
+    ///
+    /// - The input, or `None` if it was empty or cancelled
+    fn prompt_with_history<C>(
+        &mut self,
+        prompt: &str,
+        history: &mut PromptHistory,
+        callback: C,
+    ) -> Result<Option<String>, std::io::Error>
+    where
+        C: Fn(&mut Self, Key, &String) -> Option<String>,
+    {
+        let mut result: String = String::new();
+        let mut hint: Option<String> = None;
+        // The entry shown, along with what was typed before going to it
+        let mut shown: Option<(usize, String)> = None;
+
+        loop {
+            self.status_message = StatusMessage::from(match &hint {
+                Some(hint) => format!("{}{}  {}", prompt, result, hint),
+                None => format!("{}{}", prompt, result),
+            });
+            self.refresh_screen()?;
+
+            let key: Key = self.read_key()?;
+            match key {
+                Key::Backspace if !result.is_empty() => {
+                    result.pop();
+                    shown = None;
+                }
+
+                Key::Char('\n') => break,
+
+                Key::Char(c) if !c.is_control() => {
+                    result.push(c);
+                    shown = None;
+                }
+
+                Key::Up => {
+                    let (before, typed) = shown.take().unwrap_or((usize::MAX, result.clone()));
+                    if let Some((index, entry)) = history.older(before, &typed) {
+                        result = entry.to_string();
+                        shown = Some((index, typed));
+                    } else if before != usize::MAX {
+                        shown = Some((before, typed));
+                    }
+                }
+
+                Key::Down => {
+                    if let Some((after, typed)) = shown.take() {
+                        if let Some((index, entry)) = history.newer(after, &typed) {
+                            result = entry.to_string();
+                            shown = Some((index, typed));
+                        } else {
+                            result = typed;
+                        }
+                    }
+                }
+
+                Key::Esc => {
+                    result.truncate(0);
+                    break;
+                }
+                _ => (),
+            }
+            hint = callback(self, key, &result);
+        }
+
+        self.status_message = StatusMessage::from(String::new());
+
+        if result.is_empty() {
+            return Ok(None);
+        }
+
+        if let Err(err) = history.add(&result) {
+            self.echo_error(format!("Could not save history: {}", err));
+        }
+        Ok(Some(result))
+    }
+
+    /// Let the user choose an item from a list shown in the message bar
+    ///
+    /// # Args
+    ///
+    /// - `prompt`: The prompt to the user
+    /// - `items`: The items to choose from
+    /// - `selected`: The index of the item selected initially
+    /// - `callback`: A function called with the new index whenever the
+    ///   selection moves
+    ///
+    /// # Returns
+    ///
+    /// - The index of the confirmed item, or `None` if the user cancelled
+    fn pick<C>(
+        &mut self,
+        prompt: &str,
+        items: &[String],
+        selected: usize,
+        callback: C,
+    ) -> Result<Option<usize>, std::io::Error>
+    where
+        C: Fn(&mut Self, usize),
+    {
+        if items.is_empty() {
+            return Ok(None);
+        }
+        let mut selected = std::cmp::min(selected, items.len() - 1);
+
+        let result = loop {
+            let list: Vec<String> = items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    if index == selected {
+                        format!("[{}]", item)
+                    } else {
+                        item.clone()
+                    }
+                })
+                .collect();
+            self.status_message = StatusMessage::from(format!("{}{}", prompt, list.join(" ")));
+            self.refresh_screen()?;
+
+            match self.read_key()? {
+                Key::Char('\n') => break Some(selected),
+                Key::Esc => break None,
+                Key::Char('j' | 'l' | '\t') | Key::Ctrl('n') | Key::Down | Key::Right => {
+                    selected = (selected + 1) % items.len();
+                }
+                Key::Char('k' | 'h') | Key::Ctrl('p') | Key::Up | Key::Left => {
+                    selected = (selected + items.len() - 1) % items.len();
+                }
+                _ => continue,
+            }
+            callback(self, selected);
+        };
+
+        self.status_message = StatusMessage::from(String::new());
+        Ok(result)
+    }
+
+    /**
+     * Changes the offset to keep up with the cursor position
+     */
+    fn scroll(&mut self) {
+        let y = self.cursor_position.y;
+        let width: usize = self.text_width(&self.document, self.terminal.size().width as usize);
+        let height: usize = self.document_height();
+        if self.wraps() {
+            // Whole lines are scrolled, until the screen row of the cursor fits
+            let x = self.cursor_column(None);
+            self.offset.x = 0;
+            if y < self.offset.y {
+                self.offset.y = y;
+            }
+            while self.offset.y < y
+                && self.screen_rows(self.offset.y..y, width) + x / width.max(1) >= height
+            {
+                self.offset.y += 1;
+            }
+            return;
+        }
+        if y < self.offset.y {
+            self.offset.y = y;
+        } else if y >= self.offset.y.saturating_add(height) {
+            self.offset.y = y.saturating_sub(height).saturating_add(1);
+        }
+        // The columns are measured once the lines on screen are known
+        let x = self.cursor_column(self.column_layout().as_ref());
+        let offset: &mut Position = &mut self.offset;
+        if x < offset.x {
+            offset.x = x;
+        } else if x >= offset.x.saturating_add(width) {
+            offset.x = x.saturating_sub(width).saturating_add(1);
+        }
+    }
+
+    /// Count the screen rows taken by lines of the document when long lines
+    /// are wrapped
+    ///
+    /// # Args
+    ///
+    /// - `lines`: The lines of the document
+    /// - `width`: The width of the text area
+    fn screen_rows(&self, lines: Range<usize>, width: usize) -> usize {
+        lines
+            .map(|y| {
+                let len = self
+                    .document
+                    .row(y)
+                    .map_or(0, |row| row.width(self.config.tab_size));
+                std::cmp::max(1, len.div_ceil(width.max(1)))
+            })
+            .sum()
+    }
+
+    /// Find the part of the document shown on each screen row, starting at
+    /// the offset
+    ///
+    /// # Args
+    ///
+    /// - `height`: The number of screen rows
+    ///
+    /// # Returns
+    ///
+    /// - The line and the first shown screen column of it for each row
+    ///   showing the document
+    fn screen_lines(&self, height: usize) -> Vec<(usize, usize)> {
+        let lines = self.offset.y..self.document.len();
+        if !self.wraps() {
+            return lines.take(height).map(|y| (y, self.offset.x)).collect();
+        }
+
+        let width = self
+            .text_width(&self.document, self.terminal.size().width as usize)
+            .max(1);
+        lines
+            .flat_map(|y| {
+                let len = self
+                    .document
+                    .row(y)
+                    .map_or(0, |row| row.width(self.config.tab_size));
+                (0..std::cmp::max(1, len.div_ceil(width))).map(move |part| (y, part * width))
+            })
+            .take(height)
+            .collect()
+    }
+
+    /// Moves the cursor a screen row down or up, staying on the same line
+    /// while it is wrapped over several rows (`gj` and `gk`)
+    ///
+    /// # Args
+    ///
+    /// - `down`: Whether to move down rather than up
+    fn move_screen_row(&mut self, down: bool) {
+        if !self.wraps() {
+            self.move_cursor(if down { Key::Down } else { Key::Up });
+            return;
+        }
+
+        let width = self
+            .text_width(&self.document, self.terminal.size().width as usize)
+            .max(1);
+        // Moves are made in screen columns, which tabs make differ from
+        // characters
+        let tab_size = self.config.tab_size;
+        let y = self.cursor_position.y;
+        let x = self.cursor_column(None);
+        let len = self.document.row(y).map_or(0, |row| row.width(tab_size));
+        let (x, y) = if down && x + width < len {
+            (x + width, y)
+        } else if down {
+            (x % width, y.saturating_add(1))
+        } else if x >= width {
+            (x - width, y)
+        } else if y > 0 {
+            let len = self
+                .document
+                .row(y - 1)
+                .map_or(0, |row| row.width(tab_size));
+            (len.saturating_sub(1) / width * width + x, y - 1)
+        } else {
+            return;
+        };
+        let x = self
+            .document
+            .row(y)
+            .map_or(0, |row| row.index_at(x, tab_size));
+        self.cursor_position = Position { x, y };
+        self.move_cursor(Key::Null);
+    }
+
+    /// Query the document incrementally, forward from the cursor as done by
+    /// `/` or backward as done by `?`. Up and Down in the prompt go through
+    /// the queries searched for before
+    ///
+    /// # Args
+    ///
+    /// - `forward`: Whether to search forward rather than backward
+    fn search(&mut self, forward: bool) {
+        let old_position: Position = self.cursor_position.clone();
+        let old_highlight = self.highlighted_word.clone();
+        let prefix = if forward { "/" } else { "?" };
+        let find = |document: &Document, pattern: &str, from: &Position| {
+            if forward {
+                document.find(pattern, from)
+            } else {
+                document.find_before(pattern, from)
+            }
+        };
+        let mut history = std::mem::take(&mut self.search_history);
+        let query = self
+            .prompt_with_history(prefix, &mut history, |editor, _, query| {
+                let pattern = editor.with_case(query);
+                // Going backward, every match found is before the cursor
+                let from = if forward {
+                    editor.cursor_position.clone()
+                } else {
+                    old_position.clone()
+                };
+                if let Some((position, _)) = find(&editor.document, &pattern, &from) {
+                    editor.cursor_position = position;
+                    editor.scroll();
+                }
+                let matches = editor.document.find_all(&pattern).len();
+                editor.highlighted_word = Some(pattern);
+
+                if query.is_empty() {
+                    None
+                } else {
+                    Some(format!("[{} matches]", matches))
+                }
+            })
+            .unwrap_or(None);
+        self.search_history = history;
+        if let Some(query) = query {
+            let pattern = self.with_case(&query);
+            if let Some((position, _)) = find(&self.document, &pattern, &old_position) {
+                self.cursor_position = old_position;
+                self.jump(position);
+                self.search_results = self.document.find_all(&pattern);
+                let matches = self.search_results.len();
+                let mut lines: Vec<usize> =
+                    self.search_results.iter().map(|(pos, _)| pos.y).collect();
+                lines.dedup();
+                let lines = lines.len();
+                self.echo(format!(
+                    "{}{} {} on {}",
+                    prefix,
+                    query,
+                    plural(matches, "match", "matches"),
+                    plural(lines, "line", "lines")
+                ));
+                self.highlighted_word = self.config.hl_search.then_some(pattern);
+                self.last_search = Some(query);
+                self.search_forward = forward;
+                return;
+            }
+            self.cursor_position = old_position;
+            self.scroll();
+            self.echo_warning(format!("Pattern not found: {}", query));
+        } else {
+            self.cursor_position = old_position;
+            self.scroll();
+        }
+        self.highlighted_word = old_highlight;
+    }
+
+    /// Applies the `ignore_case` and `smart_case` settings to a query, by
+    /// starting it with `\c` when they make letters match regardless of their
+    /// case. Overrides in the query itself come later and win
+    ///
+    /// # Args
+    ///
+    /// - `query`: The text typed by the user
+    fn with_case(&self, query: &str) -> String {
+        let smart = self.config.smart_case && search::has_upper_case(query);
+        if self.config.ignore_case && !smart {
+            format!("\\c{}", query)
+        } else {
+            query.to_string()
+        }
+    }
+
+    /// Moves to the next match of the last search, as done by `n`, or to the
+    /// previous one, as done by `N`. Past the last match, the search goes on
+    /// from the other end of the document
+    ///
+    /// # Args
+    ///
+    /// - `forward`: Whether to move to the next match rather than the
+    ///   previous one
+    fn next_match(&mut self, forward: bool) {
+        let query = match self.last_search.clone() {
+            Some(query) => query,
+            None => {
+                self.echo_warning("No previous search".to_string());
+                return;
+            }
+        };
+        // The settings for case may have changed since the search
+        let pattern = self.with_case(&query);
+        let Position { x, y } = self.cursor_position;
+        let (found, wrapped) = if forward {
+            let after = Position { x: x + 1, y };
+            match self.document.find(&pattern, &after) {
+                Some(found) => (Some(found), false),
+                None => (self.document.find(&pattern, &Position::default()), true),
+            }
+        } else {
+            let end = Position {
+                x: 0,
+                y: self.document.len(),
+            };
+            match self.document.find_before(&pattern, &self.cursor_position) {
+                Some(found) => (Some(found), false),
+                None => (self.document.find_before(&pattern, &end), true),
+            }
+        };
+        let position = match found {
+            Some((position, _)) => position,
+            None => {
+                self.echo_warning(format!("Pattern not found: {}", query));
+                return;
+            }
+        };
+        self.jump(position);
+        if self.config.hl_search {
+            self.highlighted_word = Some(pattern);
+        }
+        if !wrapped {
+            let prefix = if self.search_forward { "/" } else { "?" };
+            self.echo(format!("{}{}", prefix, query));
+        } else if forward {
+            self.echo_warning("Search hit BOTTOM, continuing at TOP".to_string());
+        } else {
+            self.echo_warning("Search hit TOP, continuing at BOTTOM".to_string());
+        }
+    }
+
+    /// Replace or count the matches of a pattern, from a command such as
+    /// `s/old/new/` or `%s/old/new/gc`. The flags are `g` to replace every
+    /// match on a line, `c` to confirm each replacement, `i` to ignore case,
+    /// `I` to match case despite `ignore_case` and `n` to only count the
+    /// matches.
+    ///
+    /// `S/old/new/` replaces `old` in any case by `new` in the same case, so
+    /// `Old` becomes `New` and `OLD` becomes `NEW`. Its `p` flag replaces
+    /// plurals too, `olds` becoming `news`
+    ///
+    /// # Args
+    ///
+    /// - `range`: The lines to replace in, the cursor line if empty
+    /// - `args`: The pattern, replacement and flags, e.g. `/old/new/g`
+    /// - `keep_case`: Whether the pattern is a word to replace in any case,
+    ///   as done by `S`, rather than a regular expression
+    fn substitute(&mut self, range: &str, args: &str, keep_case: bool) {
+        let lines = match self.line_range(range) {
+            Some(lines) => lines,
+            None => {
+                self.echo_warning(format!("Invalid range: {}", range));
+                return;
+            }
+        };
+
+        let mut parts = args.strip_prefix('/').unwrap_or(args).splitn(3, '/');
+        let query = parts.next().unwrap_or_default();
+        let replacement = parts.next().unwrap_or_default();
+        let flags = parts.next().unwrap_or_default();
+        if query.is_empty() {
+            self.echo("No pattern given".to_string());
+            return;
+        }
+
+        let subvert = Subvert::new(query, replacement, flags.contains('p'));
+        let pattern = if keep_case {
+            subvert.pattern()
+        } else if flags.contains('I') {
+            Pattern::new(query, false)
+        } else {
+            Pattern::new(&self.with_case(query), flags.contains('i'))
+        };
+        let (count, lines) = if flags.contains('n') {
+            let (matches, lines) = self.document.count(&pattern, lines);
+            (plural(matches, "match", "matches"), lines)
+        } else {
+            let replace = |found: &str| {
+                if keep_case {
+                    subvert.replacement(found)
+                } else {
+                    replacement.to_string()
+                }
+            };
+            let (count, lines) = self.replace_matches(
+                &pattern,
+                replace,
+                lines,
+                flags.contains('g'),
+                flags.contains('c'),
+            );
+            (plural(count, "substitution", "substitutions"), lines)
+        };
 
-        if self.document.save().is_ok() {
-            self.status_message = StatusMessage::from("File saved successfully.".to_string());
+        if lines == 0 {
+            self.echo(format!("Pattern not found: {}", query));
         } else {
-            self.status_message = StatusMessage::from("Error writing to disk.".to_string());
+            self.echo(format!("{} on {}", count, plural(lines, "line", "lines")));
         }
     }
 
-    /// Prompt the user for an input
+    /// Replace the matches for a pattern in a range of lines
     ///
     /// # Args
     ///
-    /// - `prompt`: The prompt to the user
-    /// - `callback`: A function to be called on a keypress
+    /// - `pattern`: The pattern to replace
+    /// - `replacement`: Finds the text to put in place of a match
+    /// - `lines`: The lines to replace in
+    /// - `global`: Whether to replace every match on a line, not just the first
+    /// - `confirm`: Whether to ask before each replacement
     ///
     /// # Returns
     ///
-    /// - A
-    fn prompt<C>(&mut self, prompt: &str, callback: C) -> Result<Option<String>, std::io::Error>
-    where
-        C: Fn(&mut Self, Key, &String),
-    {
-        let mut result: String = String::new();
-
-        loop {
-            self.status_message = StatusMessage::from(format!("{}{}", prompt, result));
-            self.refresh_screen()?;
-
-            let key: Key = Terminal::read_key()?;
-            match key {
-                Key::Backspace => {
-                    if !result.is_empty() {
-                        result.truncate(result.len() - 1);
-                    }
-                }
+    /// - The number of substitutions and the number of lines changed
+    fn replace_matches(
+        &mut self,
+        pattern: &Pattern,
+        replacement: impl Fn(&str) -> String,
+        lines: Range<usize>,
+        global: bool,
+        mut confirm: bool,
+    ) -> (usize, usize) {
+        let (mut substitutions, mut changed) = (0, 0);
+        let mut done = false;
 
-                Key::Char('\n') => break,
-
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        result.push(c);
+        for y in lines {
+            if done {
+                break;
+            }
+            let mut x = 0;
+            let mut line_changed = false;
+            while let Some(found) = self.document.row(y).and_then(|row| row.find(pattern, x)) {
+                let replacement = self.document.row(y).map_or_else(String::new, |row| {
+                    let text = row.as_str();
+                    replacement(&text[row.byte_index(found.start)..row.byte_index(found.end)])
+                });
+                if confirm {
+                    self.cursor_position = Position { x: found.start, y };
+                    self.scroll();
+                    match self.confirm_replacement(&replacement) {
+                        Some('y') => (),
+                        Some('a') => confirm = false,
+                        Some('l') => done = true,
+                        Some('n') => {
+                            x = std::cmp::max(found.end, found.start + 1);
+                            continue;
+                        }
+                        _ => {
+                            done = true;
+                            break;
+                        }
                     }
                 }
 
-                Key::Esc => {
-                    result.truncate(0);
+                self.document
+                    .replace_in_range(y, found.clone(), &replacement);
+                substitutions += 1;
+                line_changed = true;
+                // Step over empty matches so the replacement moves on
+                x = found.start + Row::from(&replacement[..]).len() + usize::from(found.is_empty());
+                if done || !global {
                     break;
                 }
-                _ => (),
             }
-            callback(self, key, &result);
-        }
-
-        self.status_message = StatusMessage::from(String::new());
-
-        if result.is_empty() {
-            return Ok(None);
+            if line_changed {
+                changed += 1;
+            }
         }
 
-        Ok(Some(result))
+        // Lines may have become shorter than the cursor position
+        self.move_cursor(Key::Null);
+        (substitutions, changed)
     }
 
-    /**
-     * Changes the offset to keep up with the cursor position
-     */
-    fn scroll(&mut self) {
-        let Position { x, y } = self.cursor_position;
-        let width: usize = self.terminal.size().width as usize;
-        let height: usize = self.terminal.size().height as usize;
-        let mut offset: &mut Position = &mut self.offset;
-
-        if y < offset.y {
-            offset.y = y;
-        } else if y >= offset.y.saturating_add(height) {
-            offset.y = y.saturating_sub(height).saturating_add(1);
-        }
-
-        if x < offset.x {
-            offset.x = x;
-        } else if x >= offset.x.saturating_add(width) {
-            offset.x = x.saturating_sub(width).saturating_add(1);
+    /// Ask whether to replace the match under the cursor
+    ///
+    /// # Returns
+    ///
+    /// - `y` to replace it, `n` to skip it, `a` to replace it and every
+    ///   match after it, `l` to replace it and stop, or `None` to stop
+    fn confirm_replacement(&mut self, replacement: &str) -> Option<char> {
+        self.status_message =
+            StatusMessage::from(format!("replace with {} (y/n/a/q/l)?", replacement));
+        if let Err(err) = self.refresh_screen() {
+            self.echo_error(err);
+            return None;
         }
-    }
-
-    /**
-     * Query the document incrementally
-     */
-    fn search(&mut self) {
-        let old_position: Position = self.cursor_position.clone();
-        if let Some(query) = self
-            .prompt("/", |editor, _, query| {
-                if let Some(position) = editor.document.find(query, &editor.cursor_position) {
-                    editor.cursor_position = position;
-                    editor.scroll();
-                }
-                editor.highlighted_word = Some(query.to_string());
-            })
-            .unwrap_or(None)
-        {
-            if let Some(position) = self.document.find(&query[..], &old_position) {
-                self.cursor_position = position;
-                self.search_results = self.document.find_all(&query);
-            } else {
-                self.status_message = StatusMessage::from(format!("Pattern not found: {}", query));
-            }
-        } else {
-            self.cursor_position = old_position;
-            self.scroll();
+        match self.read_char() {
+            Some(c @ ('y' | 'n' | 'a' | 'l')) => Some(c),
+            _ => None,
         }
-        self.highlighted_word = None;
     }
 
     /// Moves the cursor based on the given key
@@ -402,7 +5516,7 @@ impl Editor {
     ///
     /// - `key`: The key entered by the user
     fn move_cursor(&mut self, key: Key) {
-        let terminal_height = self.terminal.size().height as usize;
+        let terminal_height = self.document_height();
         let Position { mut x, mut y } = self.cursor_position;
 
         let height = self.document.len();
@@ -413,39 +5527,11 @@ impl Editor {
         };
 
         match key {
-            Key::Char('w') => {
-                let row = self.document.row(y).unwrap();
-                let new_idx = row.peek_white(x);
-
-                if new_idx > 0 {
-                    x = new_idx;
-                } else if let Some(row) = self.document.row(y + 1) {
-                    let new_idx: usize = row.peek_alphanumeric(0);
-                    x = new_idx;
-                    y += 1;
-                }
-            }
-
-            // TODO: Fix
-            Key::Char('\t') => {
-                if x.saturating_add(self.tab_size) < width {
-                    x = x.saturating_add(self.tab_size)
-                }
-            }
-
             Key::Up => y = y.saturating_sub(1),
-            Key::Down => {
-                if y < height {
-                    y = y.saturating_add(1)
-                }
-            }
+            Key::Down => y = y.saturating_add(1),
 
             Key::Left => x = x.saturating_sub(1),
-            Key::Right => {
-                if x < width {
-                    x = x.saturating_add(1)
-                }
-            }
+            Key::Right if x < width => x = x.saturating_add(1),
 
             Key::Backspace => {
                 if x > 0 {
@@ -460,167 +5546,754 @@ impl Editor {
                 }
             }
 
-            Key::PageUp => {
-                y = if y > terminal_height {
-                    y - terminal_height
-                } else {
-                    0
-                }
-            }
-            Key::PageDown => {
-                y = if y.saturating_add(terminal_height) < height {
-                    y + terminal_height as usize
-                } else {
-                    height
-                }
-            }
+            Key::PageUp => y = y.saturating_sub(terminal_height),
+            Key::PageDown => y = y.saturating_add(terminal_height),
 
             Key::Home => x = 0,
             Key::End => x = width,
             _ => (),
         }
 
+        y = clamp_line(y, height, self.mode);
         width = if let Some(row) = self.document.row(y) {
             row.len()
         } else {
             0
         };
 
+        let tab_size = self.config.tab_size;
+        let goal = match key {
+            Key::Up | Key::Down | Key::PageUp | Key::PageDown => {
+                let goal = match &self.goal_column {
+                    Some((at, goal)) if *at == self.cursor_position => *goal,
+                    _ => self
+                        .document
+                        .row(self.cursor_position.y)
+                        .map_or(0, |row| row.width_until(self.cursor_position.x, tab_size)),
+                };
+                if let Some(row) = self.document.row(y) {
+                    x = row.index_at(goal, tab_size);
+                }
+                Some(goal)
+            }
+            // After `End`, the cursor stays at the end of the lines it goes to
+            Key::End => Some(usize::MAX),
+            _ => None,
+        };
+
         if x > width {
             x = width;
         }
-        self.cursor_position = Position { x, y }
+        self.cursor_position = Position { x, y };
+        self.goal_column = goal.map(|goal| (self.cursor_position.clone(), goal));
     }
 
     /**
      * Clears the screen by writing an escape sequence to the terminal
      */
     fn refresh_screen(&mut self) -> Result<(), std::io::Error> {
-        Terminal::cursor_hide();
+        // Screen readers follow the cursor, so it is kept in sight for them
+        if !self.config.screen_reader {
+            Terminal::cursor_hide();
+        }
+        self.resize()?;
+        self.announce_mode();
+        self.scan_documents();
         Terminal::cursor_position(&Position::default());
         if self.should_quit {
             Terminal::clear_screen();
             println!("Goodbye.\r");
+        } else if let Some(mut diff) = self.diff.take() {
+            // Diff mode draws every row, so the next frame starts afresh
+            self.frame.clear();
+            let cursor = self.draw_diff(&mut diff);
+            self.diff = Some(diff);
+            self.highlight_pending = false;
+            self.draw_bars();
+            Terminal::cursor_position(&cursor);
         } else {
-            self.document.highlight(
-                &self.highlighted_word,
-                Some(
-                    self.offset
-                        .y
-                        .saturating_add(self.terminal.size().height as usize),
-                ),
-            );
+            let height = self.document_height();
+            let screen = self.offset.y..self.offset.y.saturating_add(height);
+            let deadline = (self.config.redraw_time > 0)
+                .then(|| Instant::now() + Duration::from_millis(self.config.redraw_time));
+            let mut complete =
+                self.document
+                    .highlight_screen(&self.highlighted_word, screen.clone(), deadline);
+            // Brackets are told apart from strings and comments once the
+            // screen is highlighted
+            if self.highlight_match_paren() {
+                complete = self
+                    .document
+                    .highlight_screen(&self.highlighted_word, screen, deadline);
+            }
+            self.highlight_pending = !complete;
+            let redrawn = self.frame.is_empty();
             self.draw_rows();
-            self.draw_status_bar();
-            self.draw_message_bar();
-            Terminal::cursor_position(&Position {
-                x: self.cursor_position.x.saturating_sub(self.offset.x),
-                y: self.cursor_position.y.saturating_sub(self.offset.y),
+            if redrawn {
+                self.bars.clear();
+            }
+            self.draw_bars();
+            let width = self.terminal.size().width as usize;
+            let text_width = self.text_width(&self.document, width);
+            let gutter_width = width - text_width;
+            let y = self.cursor_position.y;
+            Terminal::cursor_position(&if self.wraps() {
+                let x = self.cursor_column(None);
+                let text_width = text_width.max(1);
+                Position {
+                    x: x % text_width + gutter_width,
+                    y: self.screen_rows(self.offset.y..y, text_width) + x / text_width,
+                }
+            } else {
+                let x = self.cursor_column(self.column_layout().as_ref());
+                Position {
+                    x: x.saturating_sub(self.offset.x) + gutter_width,
+                    y: y.saturating_sub(self.offset.y),
+                }
             });
         }
         Terminal::cursor_show();
         Terminal::flush()
     }
 
+    /// Draws the status bar and the message bar below it. For screen
+    /// readers, bars that did not change are left alone, so they are not
+    /// read out again
+    fn draw_bars(&mut self) {
+        let bars = format!("{}{}", self.status_bar(), self.message_bar());
+        if self.config.screen_reader && bars == self.bars {
+            return;
+        }
+        print!("{}", bars);
+        self.bars = bars;
+        self.message_shown = self.status_message.is_shown();
+    }
+
+    /// Announces the mode on the message bar when it changes, which screen
+    /// readers cannot tell from the color of the status bar
+    fn announce_mode(&mut self) {
+        if self.mode == self.announced_mode {
+            return;
+        }
+        self.announced_mode = self.mode;
+        if self.config.screen_reader {
+            self.status_message = StatusMessage::from(format!(
+                "-- {} --",
+                format!("{:?}", self.mode).to_uppercase()
+            ));
+        }
+    }
+
     /**
-     * Draw bar for status data
+     * Build bar for status data
      */
-    fn draw_status_bar(&self) {
-        let mut status: String;
+    fn status_bar(&self) -> String {
         let width: usize = self.terminal.size().width as usize;
-        let modified_indicator = if self.document.is_dirty() {
-            " (modified) "
+
+        let bar = if let Some(diff) = &self.diff {
+            let pane_width = width.saturating_sub(1) / 2;
+            let focused = self.status_text(&self.document, &self.cursor_position, pane_width, true);
+            let other = self.status_text(&diff.other, &diff.other_cursor, pane_width, false);
+            let (left, right) = if diff.focus_left {
+                (focused, other)
+            } else {
+                (other, focused)
+            };
+
+            format!(
+                "{}{}{} {}{}",
+                self.status_bar_colors(diff.focus_left),
+                left,
+                self.status_bar_colors(false),
+                self.status_bar_colors(!diff.focus_left),
+                right
+            )
         } else {
-            ""
+            format!(
+                "{}{}",
+                self.status_bar_colors(true),
+                self.status_text(&self.document, &self.cursor_position, width, true)
+            )
         };
-        let mut file_name: String = "[No Name]".to_string();
-        if let Some(name) = &self.document.file_name {
-            file_name = name.clone();
-            file_name.truncate(20);
-        }
+        format!(
+            "{}\r\n{}{}",
+            bar,
+            color::Bg(color::Reset),
+            color::Fg(color::Reset)
+        )
+    }
 
-        status = format!(
-            "{} - {} lines{}- {:?}",
-            file_name,
-            self.document.len(),
-            modified_indicator,
-            self.mode,
-        );
+    /// Retrieve the colors of a status bar, dimming the ones of unfocused
+    /// windows
+    ///
+    /// # Args
+    ///
+    /// - `focused`: Whether the status bar belongs to the window receiving input
+    fn status_bar_colors(&self, focused: bool) -> String {
+        if focused {
+            format!(
+                "{}{}",
+                color::Bg(self.status_bar_bg()),
+                color::Fg(self.theme.status_fg)
+            )
+        } else {
+            format!(
+                "{}{}",
+                color::Bg(self.theme.inactive_status_bg),
+                color::Fg(self.theme.inactive_status_fg)
+            )
+        }
+    }
 
-        let line_indicator: String = format!(
-            "{} | {}/{}",
-            self.document.file_type(),
-            self.cursor_position.y.saturating_add(1),
-            self.document.len()
-        );
-        let len: usize = status.len() + line_indicator.len();
-        if width > len {
-            status.push_str(&" ".repeat(width - len));
+    /// Find the background of the focused status bar: tinted while the
+    /// document has merge conflicts, otherwise the color of the mode
+    fn status_bar_bg(&self) -> theme::Color {
+        if !self.document.conflicts().is_empty() {
+            return self.theme.conflict_status_bg;
         }
-        status = format!("{}{}", status, line_indicator);
+        match self.mode {
+            Mode::Normal => self.theme.status_bg,
+            Mode::Insert => self.theme.insert_status_bg,
+            Mode::Replace => self.theme.replace_status_bg,
+        }
+    }
 
-        status.truncate(width);
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{}\r", status);
-        Terminal::reset_bg_color();
-        Terminal::reset_fg_color();
+    /// Build the status bar text for a document from the segments set in the
+    /// config
+    ///
+    /// # Args
+    ///
+    /// - `document`: The document the status bar describes
+    /// - `cursor`: The cursor position in the document
+    /// - `width`: The width of the status bar
+    /// - `focused`: Whether the status bar belongs to the window receiving input
+    ///
+    /// # Returns
+    ///
+    /// - The text, padded or truncated to `width`
+    fn status_text(
+        &self,
+        document: &Document,
+        cursor: &Position,
+        width: usize,
+        focused: bool,
+    ) -> String {
+        let info = statusline::Info {
+            document,
+            cursor,
+            mode: format!("{:?}", self.mode),
+            screen_reader: self.config.screen_reader,
+        };
+        let pieces = statusline::layout(
+            &self.config.status_left,
+            &self.config.status_right,
+            &statusline::parse_colors(&self.config.status_colors).unwrap_or_default(),
+            &info,
+            width,
+        );
+        // Segments keep their own colors only in the focused window
+        let text_color = color::Fg(self.theme.status_fg);
+        pieces
+            .into_iter()
+            .map(|(text, segment_color)| match segment_color {
+                Some(segment_color) if focused => {
+                    format!("{}{}{}", color::Fg(segment_color), text, text_color)
+                }
+                _ => text,
+            })
+            .collect()
     }
 
     /**
-     * Draw bar for messages
+     * Build bar for messages
      */
-    fn draw_message_bar(&self) {
-        Terminal::clear_current_line();
+    fn message_bar(&self) -> String {
+        let mut bar = termion::clear::CurrentLine.to_string();
         let message: &StatusMessage = &self.status_message;
-        if Instant::now() - message.time < Duration::new(5, 0) {
+        if message.is_shown() {
             let mut text: String = message.text.clone();
-            text.truncate(self.terminal.size().width as usize);
-            print!("{}", text);
+            // Errors start with `ERR:`, warnings are only told apart by color
+            if self.config.screen_reader && message.severity == Severity::Warning {
+                text.insert_str(0, "WARN: ");
+            }
+            statusline::truncate(&mut text, self.terminal.size().width as usize);
+            match message.severity {
+                Severity::Info => bar.push_str(&text),
+                Severity::Warning | Severity::Error => {
+                    let background = if message.severity == Severity::Error {
+                        self.theme.error_bg
+                    } else {
+                        self.theme.warning_bg
+                    };
+                    bar.push_str(&format!(
+                        "{}{}{}{}{}",
+                        color::Bg(background),
+                        color::Fg(self.theme.status_fg),
+                        text,
+                        color::Fg(color::Reset),
+                        color::Bg(color::Reset)
+                    ));
+                }
+            }
+        }
+        bar
+    }
+    /// Draws the preview window over the last rows of the document area
+    ///
+    /// # Args
+    ///
+    /// - `preview`: The contents of the preview window
+    /// - `frame`: The rows of the document area
+    fn draw_preview(&self, preview: &Preview, frame: &mut [String]) {
+        let title = format!(" [Preview] {} - q to close", preview.title);
+        self.draw_window(&title, &preview.lines, None, frame);
+    }
+
+    /// Draws a window over the last rows of the document area: a title bar,
+    /// then as many lines as fit in half of the area
+    ///
+    /// # Args
+    ///
+    /// - `title`: The text of the title bar
+    /// - `lines`: The lines of the window
+    /// - `selected`: The index of the line drawn in the colors of the status
+    ///   bar, if any
+    /// - `frame`: The rows of the document area
+    fn draw_window(
+        &self,
+        title: &str,
+        lines: &[String],
+        selected: Option<usize>,
+        frame: &mut [String],
+    ) {
+        let width = self.terminal.size().width as usize;
+        let shown = std::cmp::min(
+            lines.len(),
+            std::cmp::min(PREVIEW_HEIGHT, (frame.len() / 2).saturating_sub(1)),
+        );
+        let first = frame.len().saturating_sub(shown + 1);
+        let mut title = title.to_string();
+        statusline::truncate(&mut title, width);
+        frame[first] = format!(
+            "{}{}{}{}{}{}",
+            color::Bg(self.theme.inactive_background),
+            color::Fg(self.theme.status_fg),
+            termion::clear::CurrentLine,
+            title,
+            termion::clear::UntilNewline,
+            color::Fg(color::Reset),
+        );
+        for (index, text) in lines.iter().take(shown).enumerate() {
+            let row = Row::from(&text[..]);
+            frame[first + 1 + index] = if selected == Some(index) {
+                format!(
+                    "{}{}{}{}{}",
+                    color::Bg(self.theme.status_bg),
+                    color::Fg(self.theme.status_fg),
+                    termion::clear::CurrentLine,
+                    row.render(0, width, &self.theme, self.config.tab_size),
+                    color::Fg(color::Reset),
+                )
+            } else {
+                format!(
+                    "{}{}{}",
+                    self.editor_bg(),
+                    termion::clear::CurrentLine,
+                    row.render(0, width, &self.theme, self.config.tab_size)
+                )
+            };
         }
     }
+
+    /// Draws the quickfix window below the document, scrolled so the entry
+    /// last gone to is in sight
+    ///
+    /// # Args
+    ///
+    /// - `frame`: The rows of the screen above the status bar
+    fn draw_quickfix(&self, frame: &mut [String]) {
+        let entries = self.quickfix.entries();
+        let index = self.quickfix.index();
+        let shown = self.quickfix_rows().saturating_sub(1);
+        let first = index
+            .saturating_sub(shown / 2)
+            .min(entries.len().saturating_sub(shown));
+        let lines: Vec<String> = entries[first..]
+            .iter()
+            .take(shown)
+            .map(quickfix::Entry::describe)
+            .collect();
+        let title = format!(
+            " [Quickfix] {}/{} - :cclose to close",
+            index + 1,
+            entries.len()
+        );
+        self.draw_window(&title, &lines, Some(index - first), frame);
+    }
+
+    /// Draws a piece of text over the end of a row of the document area,
+    /// leaving the rest of the row as it is
+    ///
+    /// # Args
+    ///
+    /// - `text`: The text, cut to the width of the screen from its start so
+    ///   its end stays in sight
+    /// - `row`: The row of the document area
+    /// - `frame`: The rows of the document area
+    fn draw_overlay(&self, text: &str, row: usize, frame: &mut [String]) {
+        let width = self.terminal.size().width as usize;
+        // The end of the text is kept, as much of it as fits on screen
+        let mut shown_width = 0;
+        let start = text
+            .grapheme_indices(true)
+            .rev()
+            .take_while(|(_, grapheme)| {
+                shown_width += grapheme.width();
+                shown_width <= width
+            })
+            .last()
+            .map_or(text.len(), |(index, _)| index);
+        let shown = &text[start..];
+        let line = match frame.get_mut(row) {
+            Some(line) => line,
+            None => return,
+        };
+        // The row is drawn first, then the cursor goes back over its end
+        let x = width.saturating_sub(shown.width());
+        line.push_str(&format!(
+            "{}{}{}{}{}{}",
+            termion::cursor::Goto(x as u16 + 1, row as u16 + 1),
+            color::Bg(self.theme.inactive_status_bg),
+            color::Fg(self.theme.inactive_status_fg),
+            shown,
+            color::Fg(color::Reset),
+            color::Bg(color::Reset),
+        ));
+    }
+
     /**
-     * Displays the welcome message in the center of the screen
+     * Returns the welcome message shown in the center of the screen
      */
-    fn draw_welcome_message(&self) {
-        let mut welcome_msg: String = format!("Hecto editor -- version {}\r", VERSION);
+    fn welcome_message(&self) -> String {
+        let mut welcome_msg: String = format!("Hecto editor -- version {}", VERSION);
         let width: usize = std::cmp::min(self.terminal.size().width as usize, welcome_msg.len());
         let len: usize = welcome_msg.len();
         let padding: usize = width.saturating_sub(len) / 2;
         let spaces: String = " ".repeat(padding.saturating_sub(1));
         welcome_msg = format!("~{}{}", spaces, welcome_msg);
-        welcome_msg.truncate(width);
-        println!("{}\r", welcome_msg);
+        statusline::truncate(&mut welcome_msg, self.terminal.size().width as usize);
+        welcome_msg
     }
 
     /**
-     * Display the range of lines of the file according to the offset x
+     * Render the part of a line of the file starting at the given character
      */
-    pub fn draw_row(&self, row: &Row) {
-        let width: usize = self.terminal.size().width as usize;
-        let start: usize = self.offset.x;
-        let end: usize = self.offset.x + width;
-        let row: String = row.render(start, end);
-        println!("{}\r", row)
+    fn row_text(&self, row: &Row, start: usize) -> String {
+        let width: usize = self.text_width(&self.document, self.terminal.size().width as usize);
+        let end: usize = start + width;
+        row.render(start, end, &self.theme, self.config.tab_size)
+    }
+
+    /// Draws both documents of diff mode side by side with their lines aligned,
+    /// scrolling both sides together
+    ///
+    /// # Args
+    ///
+    /// - `diff`: The diff mode state
+    ///
+    /// # Returns
+    ///
+    /// - The position of the cursor on screen
+    fn draw_diff(&mut self, diff: &mut DiffView) -> Position {
+        let height = self.terminal.size().height as usize;
+        let pane_width = (self.terminal.size().width as usize).saturating_sub(1) / 2;
+
+        self.document.highlight(&self.highlighted_word, None);
+        diff.other.highlight(&None, None);
+        diff.update(&self.document);
+
+        let x = self.cursor_column(None);
+        let text_width = self.text_width(&self.document, pane_width);
+        if x >= self.offset.x.saturating_add(text_width) {
+            self.offset.x = x.saturating_sub(text_width).saturating_add(1);
+        }
+
+        let (left, right) = if diff.focus_left {
+            (&self.document, &diff.other)
+        } else {
+            (&diff.other, &self.document)
+        };
+        let (left_gutter, right_gutter) = (self.gutter(left), self.gutter(right));
+        let lines = diff::align(&diff.hunks, left.len(), right.len());
+        let y = self.cursor_position.y;
+        let cursor_line = lines
+            .iter()
+            .position(|line| {
+                if diff.focus_left {
+                    line.left == Some(y)
+                } else {
+                    line.right == Some(y)
+                }
+            })
+            .unwrap_or(lines.len());
+
+        if cursor_line < diff.top {
+            diff.top = cursor_line;
+        } else if cursor_line >= diff.top.saturating_add(height) {
+            diff.top = cursor_line.saturating_sub(height).saturating_add(1);
+        }
+
+        self.set_editor_bg_color();
+        for terminal_row in 0..height {
+            Terminal::clear_current_line();
+            if let Some(line) = lines.get(diff.top + terminal_row) {
+                self.draw_diff_pane(left, &left_gutter, line, true, diff.focus_left, pane_width);
+                self.set_editor_bg_color();
+                print!("│");
+                self.draw_diff_pane(
+                    right,
+                    &right_gutter,
+                    line,
+                    false,
+                    !diff.focus_left,
+                    pane_width,
+                );
+                self.set_editor_bg_color();
+                println!("\r");
+            } else {
+                println!("~\r");
+            }
+        }
+        Terminal::reset_bg_color();
+
+        let x = x.saturating_sub(self.offset.x) + pane_width - text_width;
+        Position {
+            x: if diff.focus_left {
+                x
+            } else {
+                x + pane_width + 1
+            },
+            y: cursor_line - diff.top,
+        }
+    }
+
+    /// Draws one side of a diff mode line, padded to the width of the pane
+    ///
+    /// # Args
+    ///
+    /// - `document`: The document shown in the pane
+    /// - `gutter`: The gutter of the pane
+    /// - `line`: The aligned line to draw
+    /// - `left`: Whether the pane is the left one
+    /// - `focused`: Whether the pane receives input, other panes are dimmed
+    /// - `width`: The width of the pane
+    fn draw_diff_pane(
+        &self,
+        document: &Document,
+        gutter: &Gutter,
+        line: &DisplayLine,
+        left: bool,
+        focused: bool,
+        width: usize,
+    ) {
+        let (index, other) = if left {
+            (line.left, line.right)
+        } else {
+            (line.right, line.left)
+        };
+        let row = index.and_then(|index| document.row(index));
+
+        let (background, sign) = match (row, other) {
+            (None, _) => (Some(self.theme.diff_filler_bg), ' '),
+            (Some(_), None) => (Some(self.theme.diff_add_bg), '+'),
+            _ if line.changed => (Some(self.theme.diff_change_bg), '~'),
+            _ => (None, ' '),
+        };
+        self.draw_gutter(gutter, row.and(index), sign);
+        let width = width.saturating_sub(gutter.width());
+        match background {
+            Some(background) => Terminal::set_bg_color(background),
+            None if focused || self.config.transparent_background => self.set_editor_bg_color(),
+            None => Terminal::set_bg_color(self.theme.inactive_background),
+        }
+
+        if let Some(row) = row {
+            let start = self.offset.x;
+            let len = std::cmp::min(row.width(self.config.tab_size).saturating_sub(start), width);
+            print!(
+                "{}{}",
+                row.render(start, start + width, &self.theme, self.config.tab_size),
+                " ".repeat(width - len)
+            );
+        } else {
+            print!("{}", "-".repeat(width));
+        }
+    }
+
+    /// Draws the gutter of a single line. Screen readers cannot see the
+    /// color of added and changed lines, so for them its last column holds a
+    /// sign instead
+    ///
+    /// # Args
+    ///
+    /// - `gutter`: The gutter of the window
+    /// - `line`: The line of the document, `None` for filler lines
+    /// - `sign`: `+` for added lines, `~` for changed ones, else a space
+    fn draw_gutter(&self, gutter: &Gutter, line: Option<usize>, sign: char) {
+        let mut text = gutter.render(line);
+        if self.config.screen_reader && text.ends_with(' ') {
+            text.pop();
+            text.push(sign);
+        }
+        print!(
+            "{}{}{}",
+            color::Fg(self.theme.gutter_fg),
+            text,
+            color::Fg(color::Reset)
+        );
+    }
+
+    /// Renders the gutter of a single line, colored with the theme
+    ///
+    /// # Args
+    ///
+    /// - `gutter`: The gutter of the window
+    /// - `line`: The line of the document, `None` for filler lines
+    fn gutter_text(&self, gutter: &Gutter, line: Option<usize>) -> String {
+        format!(
+            "{}{}{}",
+            color::Fg(self.theme.gutter_fg),
+            gutter.render(line),
+            color::Fg(color::Reset)
+        )
+    }
+
+    /// Sets the background used behind the document, leaving the terminal's
+    /// own background in place when the theme background is disabled
+    fn set_editor_bg_color(&self) {
+        print!("{}", self.editor_bg());
+    }
+
+    /// The escape sequence setting the background used behind the document
+    fn editor_bg(&self) -> String {
+        if self.config.transparent_background {
+            color::Bg(color::Reset).to_string()
+        } else {
+            color::Bg(self.theme.background).to_string()
+        }
+    }
+
+    /// Find the definition to keep at the top of the window, when the
+    /// definition enclosing the first visible line has scrolled off
+    ///
+    /// # Returns
+    ///
+    /// - The line of the definition, if one should be shown
+    fn sticky_context(&self) -> Option<usize> {
+        let top = self.offset.y;
+        // Never hide the cursor line behind the header
+        if !self.config.sticky_context || self.cursor_position.y == top {
+            return None;
+        }
+
+        let (line, _) = self.document.context(top).pop()?;
+        if line < top {
+            Some(line)
+        } else {
+            None
+        }
     }
 
     /**
-     * Display the range of terminal rows according to offset y
+     * Display the range of terminal rows according to offset y, drawing only
+     * the rows that changed since the last time
      */
-    fn draw_rows(&self) {
-        Terminal::set_bg_color(color::Rgb(29, 32, 33));
-        let height: u16 = self.terminal.size().height;
+    fn draw_rows(&mut self) {
+        let height = self.document_height();
+        let conflicts = self.document.conflicts();
+        let gutter = self.gutter(&self.document);
+        let sticky = self.sticky_context();
+        let screen_lines = self.screen_lines(height);
+        let background = self.editor_bg();
+        let layout = self.column_layout();
+        let text_width = self.text_width(&self.document, self.terminal.size().width as usize);
+
+        let mut frame = Vec::with_capacity(height);
         for terminal_row in 0..height {
-            Terminal::clear_current_line();
-            if let Some(row) = self.document.row(terminal_row as usize + self.offset.y) {
-                self.draw_row(row);
+            let mut line = format!("{}{}", background, termion::clear::CurrentLine);
+            let screen_line = screen_lines.get(terminal_row);
+            if let (0, Some(index)) = (terminal_row, sticky) {
+                line.push_str(&self.gutter_text(&gutter, Some(index)));
+                line.push_str(&format!(
+                    "{}{}",
+                    color::Bg(self.theme.inactive_background),
+                    termion::clear::UntilNewline
+                ));
+                if let Some(row) = self.document.row(index) {
+                    line.push_str(&self.row_text(row, self.offset.x));
+                }
+            } else if let Some((row, &(index, start))) =
+                screen_line.and_then(|line| Some((self.document.row(line.0)?, line)))
+            {
+                // Only the first screen row of a wrapped line is numbered
+                let first = !self.wraps() || start == 0;
+                line.push_str(&self.gutter_text(&gutter, first.then_some(index)));
+                let section = conflicts
+                    .iter()
+                    .find_map(|conflict| conflict.section(index));
+                if let Some(section) = section {
+                    let section_bg = match section {
+                        Section::Marker => self.theme.conflict_marker_bg,
+                        Section::Ours => self.theme.conflict_ours_bg,
+                        Section::Base => self.theme.conflict_base_bg,
+                        Section::Theirs => self.theme.conflict_theirs_bg,
+                    };
+                    line.push_str(&format!(
+                        "{}{}",
+                        color::Bg(section_bg),
+                        termion::clear::UntilNewline
+                    ));
+                }
+                match &layout {
+                    Some(layout) => {
+                        line.push_str(&layout.render(row.as_str(), start, text_width, &self.theme))
+                    }
+                    None => line.push_str(&self.row_text(row, start)),
+                }
             } else if self.document.is_empty() && terminal_row == height / 3 {
-                self.draw_welcome_message();
+                line.push_str(&self.welcome_message());
             } else {
-                println!("~\r");
+                line.push('~');
+            }
+            frame.push(line);
+        }
+        if let Some(preview) = &self.preview {
+            self.draw_preview(preview, &mut frame);
+        }
+        if self.quickfix_rows() > 0 {
+            frame.resize(self.terminal.size().height as usize, String::new());
+            self.draw_quickfix(&mut frame);
+        }
+        if let Some(popup) = &self.popup {
+            self.draw_window(&popup.title, &popup.items, Some(popup.selected), &mut frame);
+        }
+        if self.config.show_keys && !self.shown_keys.text().is_empty() {
+            let keys = format!(" {} ", self.shown_keys.text());
+            self.draw_overlay(&keys, 0, &mut frame);
+        }
+
+        for (terminal_row, line) in frame.iter().enumerate() {
+            if self.frame.get(terminal_row) != Some(line) {
+                Terminal::cursor_position(&Position {
+                    x: 0,
+                    y: terminal_row,
+                });
+                print!("{}", line);
             }
         }
+        self.frame = frame;
         Terminal::reset_bg_color();
+        Terminal::cursor_position(&Position { x: 0, y: height });
     }
 }
 
@@ -631,3 +6304,60 @@ fn error(e: std::io::Error) -> ! {
     Terminal::clear_screen();
     panic!("{}", e);
 }
+
+/// Describe a number of things, e.g. `1 line` or `3 lines`
+///
+/// # Args
+///
+/// - `count`: The number of things
+/// - `singular`: The name of one thing
+/// - `plural`: The name of several things
+fn plural(count: usize, singular: &str, plural: &str) -> String {
+    format!("{} {}", count, if count == 1 { singular } else { plural })
+}
+
+/// Keep a line the cursor moves to within the document. Normal and Replace
+/// mode stop at the last line, while Insert mode may also go to the line
+/// after it, which typing there opens
+///
+/// # Args
+///
+/// - `y`: The line the cursor moves to
+/// - `len`: The number of lines in the document
+/// - `mode`: The mode of the editor
+///
+/// # Returns
+///
+/// - The line the cursor may rest on
+fn clamp_line(y: usize, len: usize, mode: Mode) -> usize {
+    let last = match mode {
+        Mode::Insert => len,
+        Mode::Normal | Mode::Replace => len.saturating_sub(1),
+    };
+    std::cmp::min(y, last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normal_mode_stops_at_last_line() {
+        assert_eq!(clamp_line(5, 3, Mode::Normal), 2);
+        assert_eq!(clamp_line(3, 3, Mode::Replace), 2);
+        assert_eq!(clamp_line(1, 3, Mode::Normal), 1);
+    }
+
+    #[test]
+    fn insert_mode_may_open_line_at_end() {
+        assert_eq!(clamp_line(3, 3, Mode::Insert), 3);
+        assert_eq!(clamp_line(5, 3, Mode::Insert), 3);
+        assert_eq!(clamp_line(1, 3, Mode::Insert), 1);
+    }
+
+    #[test]
+    fn empty_document_has_one_line() {
+        assert_eq!(clamp_line(1, 0, Mode::Normal), 0);
+        assert_eq!(clamp_line(1, 0, Mode::Insert), 0);
+    }
+}
@@ -0,0 +1,179 @@
+use std::io::{Error, ErrorKind};
+
+/// How the text of a file is stored as bytes
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+
+    /// UTF-16, least significant byte first, recognized by its byte order
+    /// mark
+    Utf16Le,
+
+    /// UTF-16, most significant byte first, recognized by its byte order mark
+    Utf16Be,
+
+    /// One byte per character, used for files that are not valid UTF-8 so
+    /// every byte is kept as it is
+    Latin1,
+}
+
+impl Encoding {
+    /// Find the encoding of a file from its contents: UTF-16 when it starts
+    /// with a UTF-16 byte order mark, UTF-8 when it is valid UTF-8 and
+    /// Latin-1 otherwise
+    ///
+    /// # Args
+    ///
+    /// - `bytes`: The contents of the file
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&[0xff, 0xfe]) {
+            Self::Utf16Le
+        } else if bytes.starts_with(&[0xfe, 0xff]) {
+            Self::Utf16Be
+        } else if std::str::from_utf8(bytes).is_ok() {
+            Self::Utf8
+        } else {
+            Self::Latin1
+        }
+    }
+
+    /// Find an encoding by the name `:set fileencoding` takes
+    ///
+    /// # Args
+    ///
+    /// - `name`: e.g. `utf-8` or `latin1`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Self::Utf8),
+            "utf-16le" | "utf16le" => Some(Self::Utf16Le),
+            "utf-16be" | "utf16be" | "utf-16" | "utf16" => Some(Self::Utf16Be),
+            "latin1" | "iso-8859-1" => Some(Self::Latin1),
+            _ => None,
+        }
+    }
+
+    /// Retrieve the name of the encoding, as shown in the status bar
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Utf8 => "utf-8",
+            Self::Utf16Le => "utf-16le",
+            Self::Utf16Be => "utf-16be",
+            Self::Latin1 => "latin1",
+        }
+    }
+
+    /// Turn bytes of this encoding into text. A byte order mark is kept as
+    /// the first character
+    ///
+    /// # Args
+    ///
+    /// - `bytes`: The bytes to decode
+    ///
+    /// # Returns
+    ///
+    /// - The text, with invalid sequences replaced by `U+FFFD`
+    pub fn decode(self, bytes: &[u8]) -> String {
+        let units = |to_unit: fn([u8; 2]) -> u16| {
+            let units = bytes
+                .chunks_exact(2)
+                .map(|pair| to_unit([pair[0], pair[1]]));
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        };
+        match self {
+            Self::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Self::Utf16Le => units(u16::from_le_bytes),
+            Self::Utf16Be => units(u16::from_be_bytes),
+            Self::Latin1 => bytes.iter().map(|&byte| char::from(byte)).collect(),
+        }
+    }
+
+    /// Turn text into bytes of this encoding
+    ///
+    /// # Args
+    ///
+    /// - `text`: The text to encode
+    ///
+    /// # Returns
+    ///
+    /// - The bytes, or an Error if a character has no encoding in Latin-1
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Utf8 => Ok(text.as_bytes().to_vec()),
+            Self::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Self::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Self::Latin1 => text
+                .chars()
+                .map(|c| {
+                    u8::try_from(c).map_err(|_| {
+                        Error::new(
+                            ErrorKind::InvalidData,
+                            format!("{:?} can not be written as latin1", c),
+                        )
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_by_contents() {
+        assert_eq!(Encoding::detect(&[0xff, 0xfe, b'a', 0]), Encoding::Utf16Le);
+        assert_eq!(Encoding::detect(&[0xfe, 0xff, 0, b'a']), Encoding::Utf16Be);
+        assert_eq!(Encoding::detect("café".as_bytes()), Encoding::Utf8);
+        assert_eq!(
+            Encoding::detect(&[b'c', b'a', b'f', 0xe9]),
+            Encoding::Latin1
+        );
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for encoding in [
+            Encoding::Utf8,
+            Encoding::Utf16Le,
+            Encoding::Utf16Be,
+            Encoding::Latin1,
+        ] {
+            assert_eq!(Encoding::from_name(encoding.name()), Some(encoding));
+        }
+        assert_eq!(Encoding::from_name("UTF16"), Some(Encoding::Utf16Be));
+        assert_eq!(Encoding::from_name("ascii"), None);
+    }
+
+    #[test]
+    fn utf16_keeps_byte_order_mark() {
+        let bytes = [0xff, 0xfe, b'h', 0, b'i', 0];
+        let text = Encoding::Utf16Le.decode(&bytes);
+        assert_eq!(text, "\u{feff}hi");
+        assert_eq!(Encoding::Utf16Le.encode(&text).unwrap(), bytes);
+        let text = Encoding::Utf16Be.decode(&[0xfe, 0xff, 0, b'h']);
+        assert_eq!(text, "\u{feff}h");
+    }
+
+    #[test]
+    fn latin1_keeps_every_byte() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let text = Encoding::Latin1.decode(&bytes);
+        assert_eq!(Encoding::Latin1.encode(&text).unwrap(), bytes);
+    }
+
+    #[test]
+    fn latin1_rejects_wide_characters() {
+        let error = Encoding::Latin1.encode("€").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn invalid_utf16_is_replaced() {
+        let text = Encoding::Utf16Le.decode(&[0x00, 0xd8, b'a', 0]);
+        assert_eq!(text, "\u{fffd}a");
+    }
+}
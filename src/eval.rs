@@ -0,0 +1,289 @@
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::Chars;
+
+/// Evaluate an arithmetic expression such as `(1 + 2) * 3 / 4`
+///
+/// Supports `+`, `-`, `*`, `/`, `%`, `^`, parentheses and unary minus on
+/// decimal numbers
+///
+/// # Args
+///
+/// - `expression`: The text of the expression
+///
+/// # Returns
+///
+/// - The value if successful, or a description of the problem
+pub fn evaluate(expression: &str) -> Result<f64, String> {
+    let mut parser = Parser {
+        chars: expression.chars().peekable(),
+    };
+    let value = parser.sum()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        Some(c) => Err(format!("Unexpected {:?}", c)),
+        None => Ok(value),
+    }
+}
+
+/// Find the arithmetic expression around a position of a line, such as
+/// `3 * (4 + 5)` in `total = 3 * (4 + 5) units`
+///
+/// # Args
+///
+/// - `line`: The text of the line
+/// - `at`: The byte index of the position
+///
+/// # Returns
+///
+/// - The byte range of the longest expression holding the position, if any
+pub fn expression_at(line: &str, at: usize) -> Option<Range<usize>> {
+    let is_part = |c: char| c.is_ascii_digit() || c.is_whitespace() || "+-*/%^().".contains(c);
+    if !line.get(at..)?.chars().next().is_some_and(is_part) {
+        return None;
+    }
+    let start = line[..at]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_part(*c))
+        .last()
+        .map_or(at, |(index, _)| index);
+    let end = line[at..]
+        .char_indices()
+        .find(|(_, c)| !is_part(*c))
+        .map_or(line.len(), |(index, _)| at + index);
+
+    // Digits and signs next to the run belong to a word, such as `x2 - 1`
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == ')';
+    let word_before = line[..start].chars().next_back().is_some_and(is_word);
+    let word_after = line[end..].chars().next().is_some_and(is_word);
+    // The expression is cut at whole characters only, and never starts or
+    // ends with whitespace
+    let starts = (start..=at).filter(|&index| {
+        line.is_char_boundary(index)
+            && line[index..]
+                .chars()
+                .next()
+                .is_some_and(|c| !c.is_whitespace())
+            && (index > start || !word_before)
+    });
+    let mut found: Option<Range<usize>> = None;
+    for first in starts {
+        // A leading minus after a word subtracts rather than negates
+        let before = line[..first].trim_end();
+        if line[first..].starts_with('-') && before.chars().next_back().is_some_and(is_word) {
+            continue;
+        }
+        let ends = (at + 1..=end).rev().filter(|&index| {
+            line.is_char_boundary(index)
+                && line[..index]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| !c.is_whitespace())
+                && (index < end || !word_after)
+        });
+        for last in ends {
+            if found
+                .as_ref()
+                .is_some_and(|found| found.len() >= last - first)
+            {
+                break;
+            }
+            let text = &line[first..last];
+            if text.contains(|c: char| c.is_ascii_digit()) && evaluate(text).is_ok() {
+                found = Some(first..last);
+                break;
+            }
+        }
+    }
+    found
+}
+
+/// Format a value, leaving out the fraction of whole numbers
+pub fn format(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+/// Parse an integer written in decimal, or in hexadecimal, binary or octal
+/// with a `0x`, `0b` or `0o` prefix
+///
+/// # Args
+///
+/// - `literal`: The text of the integer, e.g. `-0xff` or `1_000`
+///
+/// # Returns
+///
+/// - The value if the literal is a valid integer
+pub fn parse_integer(literal: &str) -> Option<i128> {
+    let (negative, literal) = match literal.strip_prefix('-') {
+        Some(literal) => (true, literal),
+        None => (false, literal),
+    };
+    let literal = literal.to_ascii_lowercase().replace('_', "");
+    let (radix, digits) = if let Some(digits) = literal.strip_prefix("0x") {
+        (16, digits)
+    } else if let Some(digits) = literal.strip_prefix("0b") {
+        (2, digits)
+    } else if let Some(digits) = literal.strip_prefix("0o") {
+        (8, digits)
+    } else {
+        (10, &literal[..])
+    };
+
+    // `from_str_radix` would also accept a sign after the prefix
+    if digits.starts_with(['+', '-']) {
+        return None;
+    }
+    let value = i128::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// Format an integer in a base, using the prefixes read by `parse_integer`
+///
+/// # Args
+///
+/// - `value`: The integer
+/// - `radix`: One of 2, 8, 10 or 16
+pub fn format_integer(value: i128, radix: u32) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+    let magnitude = value.unsigned_abs();
+    match radix {
+        16 => format!("{}0x{:x}", sign, magnitude),
+        8 => format!("{}0o{:o}", sign, magnitude),
+        2 => format!("{}0b{:b}", sign, magnitude),
+        _ => value.to_string(),
+    }
+}
+
+/// A recursive descent parser evaluating as it goes
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    /// Take the next operator if it is one of the given ones
+    fn operator(&mut self, operators: &[char]) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.next_if(|c| operators.contains(c))
+    }
+
+    fn sum(&mut self) -> Result<f64, String> {
+        let mut value = self.product()?;
+        while let Some(operator) = self.operator(&['+', '-']) {
+            let rhs = self.product()?;
+            value = if operator == '+' {
+                value + rhs
+            } else {
+                value - rhs
+            };
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<f64, String> {
+        let mut value = self.power()?;
+        while let Some(operator) = self.operator(&['*', '/', '%']) {
+            let rhs = self.power()?;
+            if operator != '*' && rhs == 0.0 {
+                return Err("Division by zero".to_string());
+            }
+            value = match operator {
+                '*' => value * rhs,
+                '/' => value / rhs,
+                _ => value % rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.unary()?;
+        if self.operator(&['^']).is_some() {
+            // Exponents are right associative
+            return Ok(base.powf(self.power()?));
+        }
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.operator(&['-']).is_some() {
+            return Ok(-self.unary()?);
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<f64, String> {
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'(').is_some() {
+            let value = self.sum()?;
+            return match self.operator(&[')']) {
+                Some(_) => Ok(value),
+                None => Err("Missing )".to_string()),
+            };
+        }
+
+        let mut number = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+            number.push(c);
+        }
+        match self.chars.peek() {
+            _ if !number.is_empty() => number
+                .parse()
+                .map_err(|_| format!("Invalid number {}", number)),
+            Some(c) => Err(format!("Unexpected {:?}", c)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expression(line: &str, at: usize) -> Option<&str> {
+        expression_at(line, at).map(|range| &line[range])
+    }
+
+    #[test]
+    fn expression_in_text() {
+        let line = "total = 3 * (4 + 5) units";
+        assert_eq!(expression(line, 13), Some("3 * (4 + 5)"));
+        assert_eq!(expression(line, 9), Some("3 * (4 + 5)"));
+        assert_eq!(expression(line, 2), None);
+    }
+
+    #[test]
+    fn expression_leaves_words_alone() {
+        assert_eq!(expression("x2 - 1", 5), Some("1"));
+        assert_eq!(expression("x2 - 1", 1), None);
+        assert_eq!(expression("a - 3*4", 4), Some("3*4"));
+    }
+
+    #[test]
+    fn expression_keeps_negation() {
+        assert_eq!(expression("= -2 * 3", 3), Some("-2 * 3"));
+    }
+
+    #[test]
+    fn expression_with_wide_whitespace() {
+        assert_eq!(expression("x = 1\u{a0}+ 2", 7), Some("1\u{a0}+ 2"));
+        assert_eq!(expression("x = 1\u{a0}+ 2", 5), Some("1\u{a0}+ 2"));
+        assert_eq!(
+            expression("x = 1\u{3000}+\u{3000}2", 9),
+            Some("1\u{3000}+\u{3000}2")
+        );
+    }
+
+    #[test]
+    fn expression_skips_unbalanced_parentheses() {
+        assert_eq!(expression("f(1 + 2", 2), Some("1 + 2"));
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// List a directory as the explorer shows it: the directory itself, `../`,
+/// then its subdirectories followed by its files, each group sorted by name.
+/// Subdirectories end with `/`
+///
+/// # Args
+///
+/// - `dir`: The directory
+///
+/// # Returns
+///
+/// - The lines of the listing, or a description of the problem
+pub fn list(dir: &Path) -> Result<Vec<String>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|err| format!("Could not read {}: {}", dir.display(), err))?;
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Symbolic links to directories are browsed like directories
+        if entry.path().is_dir() {
+            dirs.push(format!("{}/", name));
+        } else {
+            files.push(name);
+        }
+    }
+    dirs.sort();
+    files.sort();
+
+    let mut header = dir.display().to_string();
+    if !header.ends_with('/') {
+        header.push('/');
+    }
+    let mut lines = vec![header, "../".to_string()];
+    lines.extend(dirs);
+    lines.extend(files);
+    Ok(lines)
+}
+
+/// Find what a line of a listing leads to
+///
+/// # Args
+///
+/// - `dir`: The directory listed
+/// - `lines`: The lines of the listing, as returned by `list`
+/// - `y`: The index of the line
+///
+/// # Returns
+///
+/// - The path of the entry, or nothing for the line naming the directory
+pub fn entry(dir: &Path, lines: &[&str], y: usize) -> Option<PathBuf> {
+    match *lines.get(y)? {
+        _ if y == 0 => None,
+        "../" => Some(parent(dir)),
+        name => Some(join(dir, name.trim_end_matches('/'))),
+    }
+}
+
+/// Retrieve the directory above another, keeping relative paths relative,
+/// e.g. `.` for `src` and `../..` for `..`
+pub fn parent(dir: &Path) -> PathBuf {
+    match dir.components().next_back() {
+        None | Some(Component::CurDir) | Some(Component::ParentDir) => dir.join(".."),
+        _ => match dir.parent() {
+            Some(parent) if parent.as_os_str().is_empty() => PathBuf::from("."),
+            Some(parent) => parent.to_path_buf(),
+            // The root is its own parent
+            None => dir.to_path_buf(),
+        },
+    }
+}
+
+/// Retrieve the path of an entry of a directory, without a leading `./` for
+/// the working directory
+fn join(dir: &Path, name: &str) -> PathBuf {
+    if dir == Path::new(".") {
+        PathBuf::from(name)
+    } else {
+        dir.join(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_directories_first() {
+        let dir = std::env::temp_dir().join(format!("donovim-explorer-{}", std::process::id()));
+        fs::create_dir_all(dir.join("b")).unwrap();
+        fs::create_dir_all(dir.join("c")).unwrap();
+        fs::write(dir.join("a"), "").unwrap();
+        let listed = list(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let header = format!("{}/", dir.display());
+        assert_eq!(listed.unwrap(), vec![&header[..], "../", "b/", "c/", "a"]);
+    }
+
+    #[test]
+    fn list_missing_directory() {
+        assert!(list(Path::new("/nonexistent/donovim")).is_err());
+    }
+
+    #[test]
+    fn entry_of_line() {
+        let lines = ["src/", "../", "bin/", "main.rs"];
+        let dir = Path::new("src");
+        assert_eq!(entry(dir, &lines, 0), None);
+        assert_eq!(entry(dir, &lines, 1), Some(PathBuf::from(".")));
+        assert_eq!(entry(dir, &lines, 2), Some(PathBuf::from("src/bin")));
+        assert_eq!(entry(dir, &lines, 3), Some(PathBuf::from("src/main.rs")));
+        assert_eq!(entry(dir, &lines, 4), None);
+        assert_eq!(
+            entry(Path::new("."), &lines, 3),
+            Some(PathBuf::from("main.rs"))
+        );
+    }
+
+    #[test]
+    fn parent_keeps_relative_paths() {
+        assert_eq!(parent(Path::new("src")), PathBuf::from("."));
+        assert_eq!(parent(Path::new("..")), PathBuf::from("../.."));
+        assert_eq!(parent(Path::new("/a/b")), PathBuf::from("/a"));
+        assert_eq!(parent(Path::new("/")), PathBuf::from("/"));
+    }
+}
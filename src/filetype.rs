@@ -1,22 +1,206 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+/// The type of a document's file. Only its name is detected when the file is
+/// opened
 pub struct FileType {
-    name: String,
+    name: &'static str,
+
+    /// The syntax of the type, shared by every document of the same type
+    syntax: OnceLock<&'static Syntax>,
+}
+
+/// Describes how a file type is written: its keywords, definitions, cells and
+/// what `%` jumps between
+#[derive(Default)]
+struct Syntax {
     hl_opts: HighlightingOptions,
+    definitions: DefinitionOptions,
+
+    /// The character between the cells of a line, for tables of values
+    separator: Option<char>,
+
+    /// What `%` jumps between besides brackets
+    pairs: PairOptions,
+}
+
+/// Describes the constructs `%` jumps between in a file type
+#[derive(Default)]
+pub struct PairOptions {
+    /// Groups of words such as `if:elif:else:fi`, as in Vim's matchit
+    groups: Vec<String>,
+
+    /// Words starting a block between braces, such as `if`
+    blocks: Vec<String>,
+
+    /// Whether HTML or XML tags are matched with their closing tags
+    tags: bool,
+}
+
+/// Describes how definitions are written in a file type
+#[derive(Default)]
+pub struct DefinitionOptions {
+    /// Words that may come before the keyword of a definition
+    modifiers: Vec<String>,
+
+    /// Keywords that start a function definition
+    functions: Vec<String>,
+
+    /// Keywords that start any other definition
+    items: Vec<String>,
 }
 
+/// A definition found on a line
+pub struct Definition<'a> {
+    /// The name of what is defined
+    pub name: &'a str,
+
+    /// Whether a function is defined
+    pub is_function: bool,
+
+    /// Whether the definition starts at the beginning of the line
+    pub is_top_level: bool,
+}
+
+
+/// The names of the files git writes messages to before opening an editor
+const GIT_MESSAGE_FILES: [&str; 4] = ["COMMIT_EDITMSG", "MERGE_MSG", "TAG_EDITMSG", "EDIT_DESCRIPTION"];
 
 impl FileType {
     pub fn name(&self) -> String {
-        self.name.clone()
+        self.name.to_string()
     }
 
     pub fn highlighting_options(&self) -> &HighlightingOptions {
-        &self.hl_opts
+        &self.syntax().hl_opts
+    }
+
+    /// The character between the cells of a line, for CSV and TSV files
+    pub fn column_separator(&self) -> Option<char> {
+        self.syntax().separator
+    }
+
+    pub fn pair_options(&self) -> &PairOptions {
+        &self.syntax().pairs
     }
 
+    /// Find the definition starting on a line, e.g. `pub fn main() {`
+    ///
+    /// # Args
+    ///
+    /// - `line`: The text of the line
+    ///
+    /// # Returns
+    ///
+    /// - The definition if the line starts one
+    pub fn definition<'a>(&self, line: &'a str) -> Option<Definition<'a>> {
+        let opts = &self.syntax().definitions;
+        let trimmed = line.trim_start();
+        let mut words = trimmed.split_whitespace().skip_while(|word| {
+            // Visibility modifiers may be restricted, as in `pub(crate)`
+            let word = word.split('(').next().unwrap_or_default();
+            // ABI strings follow `extern`, as in `extern "C" fn`
+            word.starts_with('"') || opts.modifiers.iter().any(|modifier| modifier == word)
+        });
+
+        // Keywords may be followed by generics, as in `impl<T>`
+        let keyword = words.next()?.split('<').next().unwrap_or_default();
+        let is_function = opts.functions.iter().any(|function| function == keyword);
+        if !is_function && !opts.items.iter().any(|item| item == keyword) {
+            return None;
+        }
+
+        let name = words.next()?;
+        let end = name
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(name.len());
+        Some(Definition {
+            name: &name[..end],
+            is_function,
+            is_top_level: trimmed.len() == line.len(),
+        })
+    }
+
+    /// Detect the type of a file from its name. The syntax of the type is
+    /// only built once it is needed
     pub fn from(file_name: &str) -> Self {
-        if file_name.ends_with(".rs") {
+        Self {
+            name: detect(file_name),
+            syntax: OnceLock::new(),
+        }
+    }
+
+    /// Retrieve the syntax of the type, building it on first use
+    fn syntax(&self) -> &'static Syntax {
+        self.syntax.get_or_init(|| Syntax::load(self.name))
+    }
+}
+
+/// Detect the type of a file from its name
+///
+/// # Args
+///
+/// - `file_name`: The name of the file
+///
+/// # Returns
+///
+/// - The name of the file type
+fn detect(file_name: &str) -> &'static str {
+    if file_name.ends_with(".rs") {
+        return "Rust";
+    }
+    if file_name.ends_with(".c") || file_name.ends_with(".h") {
+        return "C";
+    }
+    if file_name.ends_with(".sh") || file_name.ends_with(".bash") {
+        return "Shell";
+    }
+    if file_name.ends_with(".csv") {
+        return "CSV";
+    }
+    if file_name.ends_with(".tsv") {
+        return "TSV";
+    }
+    if file_name.ends_with(".xml") {
+        return "XML";
+    }
+    if file_name.ends_with(".html") || file_name.ends_with(".htm") {
+        return "HTML";
+    }
+    // Messages git asks an editor for, such as `.git/COMMIT_EDITMSG`
+    let base_name = file_name.rsplit('/').next().unwrap_or_default();
+    if GIT_MESSAGE_FILES.contains(&base_name) {
+        return "Git commit";
+    }
+    "No filetype"
+}
+
+impl Syntax {
+    /// Retrieve the syntax of a file type, built the first time a document of
+    /// the type needs it and shared by every later one
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the file type
+    fn load(name: &'static str) -> &'static Self {
+        static LOADED: OnceLock<Mutex<HashMap<&str, &Syntax>>> = OnceLock::new();
+        let mut loaded = LOADED
+            .get_or_init(Mutex::default)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        loaded
+            .entry(name)
+            .or_insert_with(|| Box::leak(Box::new(Self::build(name))))
+    }
+
+    /// Build the syntax of a file type
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the file type
+    fn build(name: &str) -> Self {
+        if name == "Rust" {
             return Self {
-                name: String::from("Rust"),
                 hl_opts: HighlightingOptions { 
                     numbers: true,
                     strings: true,
@@ -93,6 +277,157 @@ impl FileType {
                         "f64".to_string(), 
                     ],
                 },
+                definitions: DefinitionOptions {
+                    modifiers: vec![
+                        "pub".to_string(),
+                        "async".to_string(),
+                        "const".to_string(),
+                        "unsafe".to_string(),
+                        "extern".to_string(),
+                        "default".to_string(),
+                    ],
+                    functions: vec!["fn".to_string()],
+                    items: vec![
+                        "struct".to_string(),
+                        "enum".to_string(),
+                        "union".to_string(),
+                        "trait".to_string(),
+                        "impl".to_string(),
+                        "mod".to_string(),
+                        "macro_rules!".to_string(),
+                    ],
+                },
+                separator: None,
+                pairs: PairOptions {
+                    blocks: vec![
+                        "if".to_string(),
+                        "else".to_string(),
+                        "match".to_string(),
+                        "#[cfg".to_string(),
+                    ],
+                    ..PairOptions::default()
+                },
+            };
+        }
+        if name == "C" {
+            return Self {
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: true,
+                    characters: true,
+                    comments: true,
+                    multiline_comments: true,
+                    primary_keywords: vec![
+                        "auto".to_string(),
+                        "break".to_string(),
+                        "case".to_string(),
+                        "const".to_string(),
+                        "continue".to_string(),
+                        "default".to_string(),
+                        "do".to_string(),
+                        "else".to_string(),
+                        "enum".to_string(),
+                        "extern".to_string(),
+                        "for".to_string(),
+                        "goto".to_string(),
+                        "if".to_string(),
+                        "inline".to_string(),
+                        "register".to_string(),
+                        "restrict".to_string(),
+                        "return".to_string(),
+                        "sizeof".to_string(),
+                        "static".to_string(),
+                        "struct".to_string(),
+                        "switch".to_string(),
+                        "typedef".to_string(),
+                        "union".to_string(),
+                        "volatile".to_string(),
+                        "while".to_string(),
+                    ],
+                    secondary_keywords: vec![
+                        "bool".to_string(),
+                        "char".to_string(),
+                        "double".to_string(),
+                        "float".to_string(),
+                        "int".to_string(),
+                        "long".to_string(),
+                        "short".to_string(),
+                        "signed".to_string(),
+                        "unsigned".to_string(),
+                        "void".to_string(),
+                        "size_t".to_string(),
+                    ],
+                },
+                definitions: DefinitionOptions::default(),
+                separator: None,
+                pairs: PairOptions {
+                    groups: vec!["#if|#ifdef|#ifndef:#elif:#else:#endif".to_string()],
+                    blocks: vec![
+                        "if".to_string(),
+                        "else".to_string(),
+                        "switch".to_string(),
+                    ],
+                    ..PairOptions::default()
+                },
+            };
+        }
+        if name == "Shell" {
+            return Self {
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: true,
+                    primary_keywords: vec![
+                        "if".to_string(),
+                        "then".to_string(),
+                        "else".to_string(),
+                        "elif".to_string(),
+                        "fi".to_string(),
+                        "case".to_string(),
+                        "esac".to_string(),
+                        "for".to_string(),
+                        "select".to_string(),
+                        "while".to_string(),
+                        "until".to_string(),
+                        "do".to_string(),
+                        "done".to_string(),
+                        "in".to_string(),
+                        "function".to_string(),
+                        "return".to_string(),
+                        "local".to_string(),
+                        "export".to_string(),
+                        "readonly".to_string(),
+                    ],
+                    ..HighlightingOptions::default()
+                },
+                definitions: DefinitionOptions::default(),
+                separator: None,
+                pairs: PairOptions {
+                    groups: vec![
+                        "if:elif:else:fi".to_string(),
+                        "case:esac".to_string(),
+                        "do:done".to_string(),
+                    ],
+                    ..PairOptions::default()
+                },
+            };
+        }
+        if name == "CSV" || name == "TSV" {
+            return Self {
+                separator: Some(if name == "CSV" { ',' } else { '\t' }),
+                ..Self::default()
+            };
+        }
+        if name == "HTML" || name == "XML" {
+            return Self {
+                hl_opts: HighlightingOptions {
+                    strings: true,
+                    ..HighlightingOptions::default()
+                },
+                pairs: PairOptions {
+                    tags: true,
+                    ..PairOptions::default()
+                },
+                ..Self::default()
             };
         }
         Self::default()
@@ -102,8 +437,8 @@ impl FileType {
 impl Default for FileType {
     fn default() -> Self {
         Self {
-            name: String::from("No filetype"),
-            hl_opts: HighlightingOptions::default(),
+            name: "No filetype",
+            syntax: OnceLock::new(),
         }
     }
 }
@@ -119,6 +454,20 @@ pub struct HighlightingOptions {
     secondary_keywords: Vec<String>,
 }
 
+impl PairOptions {
+    pub fn groups(&self) -> &Vec<String> {
+        &self.groups
+    }
+
+    pub fn blocks(&self) -> &Vec<String> {
+        &self.blocks
+    }
+
+    pub fn tags(&self) -> bool {
+        self.tags
+    }
+}
+
 impl HighlightingOptions {
     pub fn numbers(&self) -> bool {
         self.numbers
@@ -0,0 +1,80 @@
+use crate::git;
+use crate::symbols;
+use std::fs;
+use std::path::Path;
+
+/// The number of files listed at most, so huge directories stay usable
+const MAX_FILES: usize = 50_000;
+
+/// List the files under the working directory for the file finder. In a git
+/// repository, the files ignored by `.gitignore` are left out. Elsewhere,
+/// hidden files and directories are
+///
+/// # Returns
+///
+/// - The paths of the files, relative to the working directory
+pub fn files() -> Vec<String> {
+    if let Ok(listed) = git::list_files() {
+        // Files deleted but still in the index are listed by git too
+        return listed
+            .into_iter()
+            .filter(|path| Path::new(path).is_file())
+            .take(MAX_FILES)
+            .collect();
+    }
+    let mut files = Vec::new();
+    walk(Path::new("."), &mut files);
+    files
+}
+
+/// Collect the files under a directory, skipping hidden entries
+///
+/// # Args
+///
+/// - `dir`: The directory
+/// - `files`: The files found so far
+fn walk(dir: &Path, files: &mut Vec<String>) {
+    let mut entries: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(Result::ok).collect(),
+        Err(_) => return,
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        if files.len() >= MAX_FILES {
+            return;
+        }
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => walk(&path, files),
+            Ok(_) => {
+                let path = path.strip_prefix(".").unwrap_or(&path);
+                files.push(path.to_string_lossy().to_string());
+            }
+            Err(_) => (),
+        }
+    }
+}
+
+/// Filter files by a query, best matches first
+///
+/// # Args
+///
+/// - `files`: The files to filter
+/// - `query`: The characters typed by the user
+///
+/// # Returns
+///
+/// - The files matching, all of them in their order for an empty query
+pub fn filter<'a>(files: &'a [String], query: &str) -> Vec<&'a str> {
+    let mut matches: Vec<(usize, &str)> = files
+        .iter()
+        .filter_map(|file| Some((symbols::fuzzy_score(query, file)?, file.as_str())))
+        .collect();
+    if !query.is_empty() {
+        matches.sort_by_key(|(score, _)| *score);
+    }
+    matches.into_iter().map(|(_, file)| file).collect()
+}
@@ -0,0 +1,265 @@
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Comment markers kept at the start of every reflowed line, longest first
+const COMMENT_MARKERS: [&str; 7] = ["///", "//!", "//", "--", "#", ";", ">"];
+
+/// The parts of a line of prose
+struct Line<'a> {
+    /// The indentation and comment marker, e.g. `    //`
+    leader: &'a str,
+
+    /// The whitespace between the comment marker and the text
+    gap: &'a str,
+
+    /// The list bullet starting the text, e.g. `- ` or `1. `
+    bullet: &'a str,
+
+    /// The rest of the line
+    text: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn parse(line: &'a str) -> Self {
+        let indent = line.len() - line.trim_start().len();
+        let marker = COMMENT_MARKERS
+            .iter()
+            .find(|marker| line[indent..].starts_with(*marker))
+            .map_or(0, |marker| marker.len());
+        let (leader, rest) = line.split_at(indent + marker);
+        let (gap, rest) = if marker > 0 {
+            rest.split_at(rest.len() - rest.trim_start().len())
+        } else {
+            ("", rest)
+        };
+        let (bullet, text) = rest.split_at(bullet_len(rest));
+        Self {
+            leader,
+            gap,
+            bullet,
+            text,
+        }
+    }
+
+    fn is_blank(&self) -> bool {
+        self.bullet.is_empty() && self.text.trim().is_empty()
+    }
+
+    /// The prefix of the lines continuing this one, lined up with the text
+    /// after any bullet
+    fn continuation(&self) -> String {
+        format!(
+            "{}{}{}",
+            self.leader,
+            self.gap,
+            " ".repeat(self.bullet.graphemes(true).count())
+        )
+    }
+}
+
+/// Measure the list bullet at the start of some text: `-`, `*` or `+`, or a
+/// number followed by `.` or `)`, then a space
+fn bullet_len(text: &str) -> usize {
+    let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = &text[digits..];
+    let marker = if digits > 0 {
+        rest.starts_with(['.', ')'])
+    } else {
+        rest.starts_with(['-', '*', '+'])
+    };
+    if marker && rest[1..].starts_with(' ') {
+        digits + 2
+    } else {
+        0
+    }
+}
+
+/// Reformat lines so none is wider than a limit where possible. Paragraphs,
+/// separated by blank lines, list items or a change of comment marker, are
+/// reflowed separately, keeping their comment markers and bullets
+///
+/// # Args
+///
+/// - `lines`: The lines to reformat
+/// - `width`: The number of characters a line should not exceed
+///
+/// # Returns
+///
+/// - The reformatted lines
+pub fn reflow(lines: &[&str], width: usize) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut index = 0;
+    while let Some(line) = lines.get(index) {
+        index += 1;
+        let first = Line::parse(line);
+        if first.is_blank() {
+            result.push(line.trim_end().to_string());
+            continue;
+        }
+
+        let continuation = first.continuation();
+        let mut words: Vec<&str> = first.text.split_whitespace().collect();
+        while let Some(next) = lines.get(index).map(|line| Line::parse(line)) {
+            let continues = if first.bullet.is_empty() {
+                next.leader == first.leader
+            } else {
+                format!("{}{}", next.leader, next.gap) == continuation
+            };
+            if !continues || !next.bullet.is_empty() || next.is_blank() {
+                break;
+            }
+            words.extend(next.text.split_whitespace());
+            index += 1;
+        }
+
+        let mut current = format!("{}{}{}", first.leader, first.gap, first.bullet);
+        let mut has_words = false;
+        for word in words {
+            let len = current.graphemes(true).count() + word.graphemes(true).count();
+            if has_words && len + 1 > width {
+                result.push(current);
+                current = continuation.clone();
+                has_words = false;
+            }
+            if has_words {
+                current.push(' ');
+            }
+            current.push_str(word);
+            has_words = true;
+        }
+        result.push(current);
+    }
+    result
+}
+
+/// Find the paragraph holding a line: the lines around it up to the nearest
+/// blank lines, ignoring comment markers
+///
+/// # Args
+///
+/// - `lines`: The lines of the document
+/// - `y`: The line
+///
+/// # Returns
+///
+/// - The lines of the paragraph, empty on a blank line
+pub fn paragraph(lines: &[&str], y: usize) -> Range<usize> {
+    let is_text = |line: &&str| !Line::parse(line).is_blank();
+    if !lines.get(y).is_some_and(is_text) {
+        return y..y;
+    }
+    let start = lines[..y]
+        .iter()
+        .rposition(|line| !is_text(line))
+        .map_or(0, |index| index + 1);
+    let end = lines[y..]
+        .iter()
+        .position(|line| !is_text(line))
+        .map_or(lines.len(), |index| y + index);
+    start..end
+}
+
+/// Break a line that grew past a width limit at the last whitespace before
+/// the limit, as done while typing
+///
+/// # Args
+///
+/// - `line`: The line to break
+/// - `width`: The number of characters the line should not exceed
+///
+/// # Returns
+///
+/// - The line before the break, the line after it and the number of
+///   characters of the original line replaced by the prefix of the second
+///   one, or `None` when the line fits or has nowhere to break
+pub fn break_line(line: &str, width: usize) -> Option<(String, String, usize)> {
+    let parsed = Line::parse(line);
+    let text_start = line.len() - parsed.text.len();
+    let graphemes: Vec<(usize, &str)> = line.grapheme_indices(true).collect();
+    if graphemes.len() <= width {
+        return None;
+    }
+
+    // The last whitespace at or before the limit, after some text
+    let space = (0..=width)
+        .rev()
+        .filter_map(|column| graphemes.get(column))
+        .find(|(index, grapheme)| *index > text_start && grapheme.chars().all(char::is_whitespace))?
+        .0;
+    let before = line[..space].trim_end();
+    let after = line[space..].trim_start();
+    if before.len() <= text_start || after.is_empty() {
+        return None;
+    }
+
+    let replaced = line[..line.len() - after.len()].graphemes(true).count();
+    Some((
+        before.to_string(),
+        format!("{}{}", parsed.continuation(), after),
+        replaced,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reflow_joins_and_wraps() {
+        assert_eq!(
+            reflow(&["one two three four"], 10),
+            vec!["one two", "three four"]
+        );
+        assert_eq!(
+            reflow(&["one", "two", "", "three"], 80),
+            vec!["one two", "", "three"]
+        );
+    }
+
+    #[test]
+    fn reflow_keeps_comment_markers() {
+        assert_eq!(
+            reflow(&["// one two three"], 10),
+            vec!["// one two", "// three"]
+        );
+        assert_eq!(reflow(&["// a", "# b"], 80), vec!["// a", "# b"]);
+    }
+
+    #[test]
+    fn reflow_lines_up_with_bullets() {
+        assert_eq!(
+            reflow(&["- alpha beta gamma"], 12),
+            vec!["- alpha beta", "  gamma"]
+        );
+        assert_eq!(reflow(&["1. a", "2. b"], 80), vec!["1. a", "2. b"]);
+        assert_eq!(reflow(&["-a", "b"], 80), vec!["-a b"]);
+    }
+
+    #[test]
+    fn paragraph_stops_at_blank_lines() {
+        let lines = ["a", "b", "", "c"];
+        assert_eq!(paragraph(&lines, 1), 0..2);
+        assert_eq!(paragraph(&lines, 2), 2..2);
+        assert_eq!(paragraph(&lines, 3), 3..4);
+        assert_eq!(paragraph(&["// a", "//", "// b"], 0), 0..1);
+    }
+
+    #[test]
+    fn break_line_at_last_space() {
+        assert_eq!(
+            break_line("one two three", 10),
+            Some(("one two".to_string(), "three".to_string(), 8))
+        );
+        assert_eq!(
+            break_line("// aaa bbb", 8),
+            Some(("// aaa".to_string(), "// bbb".to_string(), 7))
+        );
+    }
+
+    #[test]
+    fn break_line_needs_a_space_after_text() {
+        assert_eq!(break_line("one two", 10), None);
+        assert_eq!(break_line("abcdefghijk", 5), None);
+        assert_eq!(break_line("    abcdefghijk", 5), None);
+    }
+}
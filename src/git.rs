@@ -0,0 +1,191 @@
+use crate::diff::Hunk;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Run git with the given arguments
+///
+/// # Args
+///
+/// - `args`: The arguments passed to git
+///
+/// # Returns
+///
+/// - The standard output on success, or the error reported by git
+pub fn run(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|err| format!("Could not run git: {}", err))?;
+
+    result(&output)
+}
+
+/// Run git with the given arguments, feeding it the input on stdin
+///
+/// # Args
+///
+/// - `args`: The arguments passed to git
+/// - `input`: The text written to the standard input of git
+///
+/// # Returns
+///
+/// - The standard output on success, or the error reported by git
+pub fn run_with_input(args: &[&str], input: &str) -> Result<String, String> {
+    let mut child = Command::new("git")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("Could not run git: {}", err))?;
+
+    if let Some(stdin) = child.stdin.as_mut() {
+        stdin
+            .write_all(input.as_bytes())
+            .map_err(|err| format!("Could not write to git: {}", err))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("Could not run git: {}", err))?;
+
+    result(&output)
+}
+
+fn result(output: &Output) -> Result<String, String> {
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// Add a file to the index
+pub fn stage(path: &str) -> Result<String, String> {
+    run(&["add", "--", path])
+}
+
+/// Remove a file from the index, keeping the working tree changes
+pub fn unstage(path: &str) -> Result<String, String> {
+    run(&["reset", "-q", "HEAD", "--", path])
+}
+
+/// Retrieve the short status of the repository, one file per line
+pub fn status() -> Result<String, String> {
+    run(&["status", "--porcelain"])
+}
+
+/// Extract the path from a line of `git status --porcelain` output
+///
+/// # Args
+///
+/// - `line`: A line such as ` M src/editor.rs` or `R  old -> new`
+///
+/// # Returns
+///
+/// - The path of the file if the line holds one
+pub fn status_path(line: &str) -> Option<&str> {
+    let path = line.get(3..)?;
+    if path.is_empty() {
+        return None;
+    }
+    Some(path.rsplit(" -> ").next().unwrap_or(path))
+}
+
+/// Retrieve the path of the file git uses for commit messages
+pub fn commit_message_path() -> Result<String, String> {
+    run(&["rev-parse", "--git-path", "COMMIT_EDITMSG"]).map(|path| path.trim().to_string())
+}
+
+/// Commit the staged changes using the message stored in a file
+pub fn commit(message_path: &str) -> Result<String, String> {
+    run(&["commit", "--cleanup=strip", "-F", message_path])
+}
+
+/// Retrieve the root directory of the repository
+pub fn top_level() -> Result<String, String> {
+    run(&["rev-parse", "--show-toplevel"]).map(|path| path.trim().to_string())
+}
+
+/// Retrieve the path of a tracked file relative to the root of the repository
+pub fn full_name(path: &str) -> Result<String, String> {
+    run(&["ls-files", "--full-name", "--error-unmatch", "--", path])
+        .map(|name| name.trim().to_string())
+}
+
+/// List the files under the working directory that git does not ignore,
+/// tracked or not. The names are separated by NUL, so git leaves names with
+/// newlines or unusual characters unquoted
+pub fn list_files() -> Result<Vec<String>, String> {
+    let listed = run(&[
+        "ls-files",
+        "-z",
+        "--cached",
+        "--others",
+        "--exclude-standard",
+    ])?;
+    Ok(listed
+        .split('\0')
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Retrieve the contents of a file as currently staged in the index
+///
+/// # Args
+///
+/// - `full_name`: The path of the file relative to the root of the repository
+pub fn index_contents(full_name: &str) -> Result<String, String> {
+    run(&["show", &format!(":{}", full_name)])
+}
+
+/// Build a patch without context lines that applies a single hunk
+///
+/// # Args
+///
+/// - `full_name`: The path of the file relative to the root of the repository
+/// - `hunk`: The hunk, with the index on the left and the new text on the right
+/// - `old`: The lines of the file in the index
+/// - `new`: The lines of the new version of the file
+///
+/// # Returns
+///
+/// - The patch in unified diff format
+pub fn hunk_patch(full_name: &str, hunk: &Hunk, old: &[&str], new: &[&str]) -> String {
+    // Without context, an empty side refers to the line preceding the change
+    let old_start = if hunk.left_len == 0 {
+        hunk.left_start
+    } else {
+        hunk.left_start + 1
+    };
+    let new_start = if hunk.right_len == 0 {
+        hunk.right_start
+    } else {
+        hunk.right_start + 1
+    };
+
+    let mut patch = format!(
+        "--- a/{name}\n+++ b/{name}\n@@ -{},{} +{},{} @@\n",
+        old_start,
+        hunk.left_len,
+        new_start,
+        hunk.right_len,
+        name = full_name,
+    );
+    for line in &old[hunk.left_start..hunk.left_start + hunk.left_len] {
+        patch.push_str(&format!("-{}\n", line));
+    }
+    for line in &new[hunk.right_start..hunk.right_start + hunk.right_len] {
+        patch.push_str(&format!("+{}\n", line));
+    }
+    patch
+}
+
+/// Apply a patch to the index without touching the working tree
+pub fn apply_cached(patch: &str) -> Result<String, String> {
+    let top = top_level()?;
+    run_with_input(
+        &["-C", &top, "apply", "--cached", "--unidiff-zero", "-"],
+        patch,
+    )
+}
@@ -0,0 +1,128 @@
+use crate::conflict::Conflict;
+use crate::diff::Hunk;
+use crate::Document;
+use serde::{Deserialize, Serialize};
+
+/// A column drawn to the left of the text
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Component {
+    /// The line number
+    Numbers,
+
+    /// A `!` on lines that are part of a merge conflict
+    Signs,
+
+    /// `+`, `~` or `-` for lines added, changed or removed since the version
+    /// staged in git
+    Git,
+}
+
+/// The gutter of a single window, laid out for the document it shows
+pub struct Gutter<'a> {
+    components: Vec<Component>,
+    number_width: usize,
+    conflicts: &'a [Conflict],
+    hunks: &'a [Hunk],
+}
+
+impl<'a> Gutter<'a> {
+    /// Lay out the gutter for a document, from the conflicts and changed
+    /// lines found in it beforehand
+    ///
+    /// # Args
+    ///
+    /// - `components`: The components to draw, in order
+    /// - `document`: The document shown next to the gutter
+    pub fn new(components: Vec<Component>, document: &'a Document) -> Self {
+        Self {
+            components,
+            number_width: number_width(document.len()),
+            conflicts: document.conflicts(),
+            hunks: document.staged_hunks(),
+        }
+    }
+
+    /// Retrieve the number of columns taken by the gutter
+    pub fn width(&self) -> usize {
+        width(&self.components, self.number_width)
+    }
+
+    /// Build the gutter for a single line
+    ///
+    /// # Args
+    ///
+    /// - `line`: The line of the document, `None` for filler lines
+    ///
+    /// # Returns
+    ///
+    /// - The text of the gutter, exactly `width` columns long
+    pub fn render(&self, line: Option<usize>) -> String {
+        let line = if let Some(line) = line {
+            line
+        } else {
+            return " ".repeat(self.width());
+        };
+
+        let mut result = String::new();
+        for component in &self.components {
+            match component {
+                Component::Numbers => result.push_str(&format!(
+                    "{:>width$} ",
+                    line.saturating_add(1),
+                    width = self.number_width
+                )),
+                Component::Signs => {
+                    let in_conflict = self.conflicts.iter().any(|c| c.contains(line));
+                    result.push_str(if in_conflict { "! " } else { "  " });
+                }
+                Component::Git => {
+                    result.push(self.git_sign(line));
+                    result.push(' ');
+                }
+            }
+        }
+        result
+    }
+
+    fn git_sign(&self, line: usize) -> char {
+        for hunk in self.hunks {
+            if hunk.right_len == 0 {
+                // Removed lines are marked on the line that followed them
+                if line == hunk.right_start {
+                    return '-';
+                }
+            } else if hunk.contains(line, false) {
+                return if hunk.left_len == 0 { '+' } else { '~' };
+            }
+        }
+        ' '
+    }
+}
+
+/// Retrieve the number of columns needed for the line numbers of a document
+///
+/// # Args
+///
+/// - `document_len`: The number of lines in the document
+pub fn number_width(document_len: usize) -> usize {
+    std::cmp::max(document_len, 1).to_string().len()
+}
+
+/// Compute the number of columns taken by a gutter without laying it out
+///
+/// # Args
+///
+/// - `components`: The components of the gutter
+/// - `number_width`: The number of columns needed for line numbers
+pub fn width(components: &[Component], number_width: usize) -> usize {
+    components
+        .iter()
+        .map(|component| match component {
+            Component::Numbers => number_width,
+            Component::Signs | Component::Git => 1,
+        })
+        // Every component is followed by a space
+        .map(|columns| columns + 1)
+        .sum()
+}
@@ -0,0 +1,95 @@
+use crate::Position;
+
+/// The number of undo steps kept, older ones are discarded
+const MAX_STEPS: usize = 1000;
+
+/// A reversible edit, described by the lines it replaced
+pub struct Change {
+    /// The first line affected by the edit
+    pub start: usize,
+
+    /// The lines before the edit
+    pub old: Vec<String>,
+
+    /// The lines after the edit
+    pub new: Vec<String>,
+
+    /// The position of the edit, where the cursor goes when it is undone
+    pub cursor: Position,
+}
+
+/// Records the edits made to a document so they can be undone and redone.
+/// Edits are grouped into steps, so everything typed in a single visit to
+/// Insert mode is undone at once
+#[derive(Default)]
+pub struct UndoStack {
+    undo: Vec<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+    pending: Vec<Change>,
+
+    /// The number of edits recorded so far, telling whether a command edited
+    /// the document
+    edits: usize,
+
+    /// The position of the latest edit, undo or redo
+    last_change: Option<Position>,
+}
+
+impl UndoStack {
+    /// Add an edit to the current step, forgetting anything that was undone
+    pub fn record(&mut self, change: Change) {
+        self.last_change = Some(change.cursor.clone());
+        self.edits += 1;
+        self.pending.push(change);
+        self.redo.clear();
+    }
+
+    /// Close the current step, later edits start a new one
+    pub fn end_step(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        self.undo.push(std::mem::take(&mut self.pending));
+        if self.undo.len() > MAX_STEPS {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Take the latest step to undo
+    ///
+    /// # Returns
+    ///
+    /// - The edits of the step in the order they were made, if there is one
+    pub fn undo(&mut self) -> Option<&[Change]> {
+        self.end_step();
+        let step = self.undo.pop()?;
+        self.last_change = step.first().map(|change| change.cursor.clone());
+        self.redo.push(step);
+        self.redo.last().map(Vec::as_slice)
+    }
+
+    /// Take the latest undone step to make again
+    ///
+    /// # Returns
+    ///
+    /// - The edits of the step in the order they were made, if there is one
+    pub fn redo(&mut self) -> Option<&[Change]> {
+        self.end_step();
+        let step = self.redo.pop()?;
+        self.last_change = step.first().map(|change| change.cursor.clone());
+        self.undo.push(step);
+        self.undo.last().map(Vec::as_slice)
+    }
+
+    /// Retrieve the position of the latest edit, undo or redo, the `'.` mark
+    pub fn last_change(&self) -> Option<&Position> {
+        self.last_change.as_ref()
+    }
+
+    /// Retrieve the number of edits recorded so far. Undo and redo are not
+    /// counted
+    pub fn edits(&self) -> usize {
+        self.edits
+    }
+}
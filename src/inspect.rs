@@ -0,0 +1,58 @@
+use unicode_width::UnicodeWidthStr;
+
+/// Describe a grapheme for `ga`: each of its codepoints with its Unicode
+/// name, its UTF-8 bytes and the number of columns it takes up
+///
+/// # Args
+///
+/// - `grapheme`: The grapheme to describe
+///
+/// # Returns
+///
+/// - The description, e.g. `<é> U+00E9 LATIN SMALL LETTER E WITH ACUTE, UTF-8 c3 a9, width 1`
+pub fn describe(grapheme: &str) -> String {
+    let codepoints: Vec<String> = grapheme
+        .chars()
+        .map(|c| {
+            let name = unicode_names2::name(c)
+                .map_or_else(|| control_name(c).to_string(), |name| name.to_string());
+            format!("U+{:04X} {}", c as u32, name)
+        })
+        .collect();
+    let bytes: Vec<String> = grapheme
+        .bytes()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!(
+        "<{}> {}, UTF-8 {}, width {}",
+        printable(grapheme),
+        codepoints.join(" + "),
+        bytes.join(" "),
+        grapheme.width()
+    )
+}
+
+/// Show invisible characters in a readable way, e.g. `^I` for a tab
+fn printable(grapheme: &str) -> String {
+    grapheme
+        .chars()
+        .map(|c| match c as u32 {
+            code @ 0..=0x1f => format!("^{}", (code as u8 + b'@') as char),
+            0x7f => "^?".to_string(),
+            _ => c.to_string(),
+        })
+        .collect()
+}
+
+/// Name the characters Unicode leaves unnamed, such as control characters
+fn control_name(c: char) -> &'static str {
+    match c {
+        '\t' => "CHARACTER TABULATION",
+        '\n' => "LINE FEED",
+        '\r' => "CARRIAGE RETURN",
+        '\u{1b}' => "ESCAPE",
+        '\u{7f}' => "DELETE",
+        _ if c.is_control() => "CONTROL",
+        _ => "UNNAMED",
+    }
+}
@@ -0,0 +1,90 @@
+use crate::progress::Progress;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// Work done on a thread of its own so the editor keeps taking keys while it
+/// runs, such as a program that may take a while. The editor checks for the
+/// result between keys
+pub struct Job<T> {
+    result: Receiver<Result<T, String>>,
+
+    /// How far the work got, for work that reports it
+    progress: Option<Progress>,
+}
+
+impl<T: Send + 'static> Job<T> {
+    /// Start the work in the background
+    ///
+    /// # Args
+    ///
+    /// - `work`: The work to do, returning its result or a description of
+    ///   the problem
+    pub fn spawn<F>(work: F) -> Self
+    where
+        F: FnOnce() -> Result<T, String> + Send + 'static,
+    {
+        let (sender, result) = mpsc::channel();
+        thread::spawn(move || sender.send(work()).ok());
+        Self {
+            result,
+            progress: None,
+        }
+    }
+
+    /// Start work in the background that reports its progress, shown in the
+    /// message bar while it runs
+    ///
+    /// # Args
+    ///
+    /// - `label`: What the work does, e.g. `Writing`
+    /// - `work`: The work to do, given the progress to update
+    pub fn spawn_with_progress<F>(label: &str, work: F) -> Self
+    where
+        F: FnOnce(&Progress) -> Result<T, String> + Send + 'static,
+    {
+        let progress = Progress::new(label);
+        let reported = progress.clone();
+        let mut job = Self::spawn(move || work(&reported));
+        job.progress = Some(progress);
+        job
+    }
+
+    /// Retrieve the progress of the work, if it reports it
+    pub fn progress(&self) -> Option<&Progress> {
+        self.progress.as_ref()
+    }
+
+    /// Give up on the work, telling it to stop early if it reports its
+    /// progress. Its result is ignored
+    pub fn cancel(self) {
+        if let Some(progress) = &self.progress {
+            progress.cancel();
+        }
+    }
+
+    /// Take the result of the work, without blocking
+    ///
+    /// # Returns
+    ///
+    /// - The result once the work is done, `None` while it still runs
+    pub fn try_finish(&self) -> Option<Result<T, String>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                Some(Err("The job stopped unexpectedly".to_string()))
+            }
+        }
+    }
+
+    /// Wait for the work to finish, for work that must not be cut short
+    ///
+    /// # Returns
+    ///
+    /// - The result of the work
+    pub fn wait(self) -> Result<T, String> {
+        self.result
+            .recv()
+            .unwrap_or_else(|_| Err("The job stopped unexpectedly".to_string()))
+    }
+}
@@ -0,0 +1,78 @@
+use crate::Position;
+
+/// The number of jumps remembered, older ones are forgotten
+const MAX_JUMPS: usize = 100;
+
+/// A place the cursor jumped from
+#[derive(Clone)]
+pub struct Jump {
+    /// The file of the buffer, `None` for a buffer without a file
+    pub file_name: Option<String>,
+
+    /// The position of the cursor
+    pub position: Position,
+}
+
+/// The places the cursor jumped from with large movements such as searches
+/// and `G`, which `Ctrl-O` and `Ctrl-I` go back and forth through across
+/// buffers
+#[derive(Default)]
+pub struct JumpList {
+    /// The jumps, oldest first
+    jumps: Vec<Jump>,
+
+    /// The jump last gone to with `Ctrl-O` or `Ctrl-I`, or the length of the
+    /// list when none was
+    index: usize,
+}
+
+impl JumpList {
+    /// Remember a place the cursor jumped from. An older jump to the same
+    /// line is dropped, so each line appears once
+    ///
+    /// # Args
+    ///
+    /// - `jump`: The place the cursor left
+    pub fn push(&mut self, jump: Jump) {
+        self.jumps.retain(|other| {
+            other.file_name != jump.file_name || other.position.y != jump.position.y
+        });
+        self.jumps.push(jump);
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.index = self.jumps.len();
+    }
+
+    /// Go back to the previous jump, as done by `Ctrl-O`. Leaving the end of
+    /// the list remembers the cursor, so `Ctrl-I` can come back to it
+    ///
+    /// # Args
+    ///
+    /// - `from`: Where the cursor is
+    ///
+    /// # Returns
+    ///
+    /// - The place to go to, if there is an older one
+    pub fn back(&mut self, from: Jump) -> Option<&Jump> {
+        if self.index >= self.jumps.len() {
+            self.push(from);
+            self.index = self.jumps.len() - 1;
+        }
+        self.index = self.index.checked_sub(1)?;
+        self.jumps.get(self.index)
+    }
+
+    /// Go forward to the next jump, as done by `Ctrl-I`
+    ///
+    /// # Returns
+    ///
+    /// - The place to go to, if there is a newer one
+    pub fn forward(&mut self) -> Option<&Jump> {
+        if self.index + 1 >= self.jumps.len() {
+            return None;
+        }
+        self.index += 1;
+        self.jumps.get(self.index)
+    }
+}
@@ -1,12 +1,58 @@
+pub use cli::{Args, USAGE};
 pub use document::Document;
 pub use editor::{Editor, Position};
 pub use row::Row;
 pub use terminal::{Size, Terminal};
+pub use theme::Theme;
 pub use filetype::FileType;
+pub use remote::open_remote;
+pub use version::describe as version;
 
+mod case;
+mod cli;
+mod command;
+mod complete;
+mod config;
+mod conflict;
+mod csv;
+mod diff;
 mod document;
 mod editor;
+mod encoding;
+mod eval;
+mod explorer;
 mod row;
 mod terminal;
+mod theme;
 mod highlighting;
+mod history;
+mod inspect;
+mod job;
+mod jumplist;
+mod lines;
 mod filetype;
+mod finder;
+mod format;
+mod git;
+mod gutter;
+mod local_history;
+mod man;
+mod motion;
+mod notes;
+mod pairs;
+mod progress;
+mod project;
+mod prompt_history;
+mod quickfix;
+mod recovery;
+mod remote;
+mod registers;
+mod search;
+mod session;
+mod showkeys;
+mod statusline;
+mod subvert;
+mod symbols;
+mod table;
+mod textobject;
+mod version;
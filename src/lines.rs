@@ -0,0 +1,301 @@
+use crate::Row;
+use std::cmp;
+use std::iter::FromIterator;
+use std::ops::Range;
+
+/// The number of rows a chunk holds after it is split
+const CHUNK_ROWS: usize = 1024;
+
+/// The rows of a document, stored as a sequence of chunks so inserting or
+/// removing a line only moves the rows of one chunk rather than every row
+/// after it
+#[derive(Default)]
+pub struct Lines {
+    chunks: Vec<Vec<Row>>,
+
+    /// The index of the first row of each chunk
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl FromIterator<Row> for Lines {
+    fn from_iter<I: IntoIterator<Item = Row>>(rows: I) -> Self {
+        let mut lines = Self {
+            chunks: into_chunks(rows),
+            ..Self::default()
+        };
+        lines.reindex();
+        lines
+    }
+}
+
+impl Extend<Row> for Lines {
+    fn extend<I: IntoIterator<Item = Row>>(&mut self, rows: I) {
+        self.splice(self.len..self.len, rows);
+    }
+}
+
+impl Lines {
+    /// Retrieve the number of rows
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether there are no rows
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Retrieve the row at an index
+    ///
+    /// # Args
+    ///
+    /// - `index`: The index of the row
+    ///
+    /// # Returns
+    ///
+    /// - The row if one exists at `index`
+    pub fn get(&self, index: usize) -> Option<&Row> {
+        if index >= self.len {
+            return None;
+        }
+        let (chunk, offset) = self.locate(index);
+        self.chunks[chunk].get(offset)
+    }
+
+    /// Retrieve the row at an index for changing it
+    ///
+    /// # Args
+    ///
+    /// - `index`: The index of the row
+    ///
+    /// # Returns
+    ///
+    /// - The row if one exists at `index`
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Row> {
+        if index >= self.len {
+            return None;
+        }
+        let (chunk, offset) = self.locate(index);
+        self.chunks[chunk].get_mut(offset)
+    }
+
+    /// Add a row at the end
+    pub fn push(&mut self, row: Row) {
+        self.splice(self.len..self.len, Some(row));
+    }
+
+    /// Add a row before the row at an index, or at the end past the last row
+    pub fn insert(&mut self, index: usize, row: Row) {
+        let index = cmp::min(index, self.len);
+        self.splice(index..index, Some(row));
+    }
+
+    /// Take out the row at an index, if there is one
+    pub fn remove(&mut self, index: usize) -> Option<Row> {
+        if index >= self.len {
+            return None;
+        }
+        self.splice(index..index + 1, None).pop()
+    }
+
+    /// Replace a range of rows with other rows
+    ///
+    /// # Args
+    ///
+    /// - `range`: The rows to replace, clamped to the existing rows
+    /// - `rows`: The rows to put in their place
+    ///
+    /// # Returns
+    ///
+    /// - The replaced rows
+    pub fn splice<I: IntoIterator<Item = Row>>(
+        &mut self,
+        range: Range<usize>,
+        rows: I,
+    ) -> Vec<Row> {
+        let end = cmp::min(range.end, self.len);
+        let start = cmp::min(range.start, end);
+        if self.chunks.is_empty() {
+            self.chunks.push(Vec::new());
+        }
+        let (first, offset) = self.locate(start);
+
+        let mut removed = Vec::with_capacity(end - start);
+        let (mut chunk, mut from) = (first, offset);
+        while removed.len() < end - start {
+            let rows = &mut self.chunks[chunk];
+            let to = cmp::min(rows.len(), from + end - start - removed.len());
+            removed.extend(rows.drain(from..to));
+            chunk += 1;
+            from = 0;
+        }
+
+        let added: Vec<Row> = rows.into_iter().collect();
+        if !added.is_empty() {
+            self.chunks[first].splice(offset..offset, added);
+            if self.chunks[first].len() > 2 * CHUNK_ROWS {
+                let rows = std::mem::take(&mut self.chunks[first]);
+                self.chunks.splice(first..=first, into_chunks(rows));
+            }
+        }
+
+        self.chunks.retain(|rows| !rows.is_empty());
+        self.reindex();
+        removed
+    }
+
+    /// Iterate over every row
+    pub fn iter(&self) -> impl Iterator<Item = &Row> {
+        self.chunks.iter().flatten()
+    }
+
+    /// Iterate over a range of rows, clamped to the existing rows
+    pub fn range(&self, range: Range<usize>) -> impl Iterator<Item = &Row> {
+        let end = cmp::min(range.end, self.len);
+        let start = cmp::min(range.start, end);
+        let (chunk, offset) = self.locate(start);
+        self.chunks
+            .iter()
+            .skip(chunk)
+            .flatten()
+            .skip(offset)
+            .take(end - start)
+    }
+
+    /// Iterate over a range of rows for changing them, clamped to the
+    /// existing rows
+    pub fn range_mut(&mut self, range: Range<usize>) -> impl Iterator<Item = &mut Row> {
+        let end = cmp::min(range.end, self.len);
+        let start = cmp::min(range.start, end);
+        let (chunk, offset) = self.locate(start);
+        self.chunks
+            .iter_mut()
+            .skip(chunk)
+            .flatten()
+            .skip(offset)
+            .take(end - start)
+    }
+
+    /// Find the chunk holding the row at an index, and the index of the row
+    /// within it. Indexes past the last row are located at the end of the
+    /// last chunk
+    fn locate(&self, index: usize) -> (usize, usize) {
+        if index >= self.len {
+            let last = self.chunks.len().saturating_sub(1);
+            return (last, self.chunks.get(last).map_or(0, Vec::len));
+        }
+        let chunk = self.starts.partition_point(|&start| start <= index) - 1;
+        (chunk, index - self.starts[chunk])
+    }
+
+    /// Recount the rows after the chunks changed
+    fn reindex(&mut self) {
+        self.starts.clear();
+        self.len = 0;
+        for rows in &self.chunks {
+            self.starts.push(self.len);
+            self.len += rows.len();
+        }
+    }
+}
+
+/// Split rows into chunks of `CHUNK_ROWS`
+fn into_chunks<I: IntoIterator<Item = Row>>(rows: I) -> Vec<Vec<Row>> {
+    let mut rows = rows.into_iter();
+    let mut chunks = Vec::new();
+    loop {
+        let chunk: Vec<Row> = rows.by_ref().take(CHUNK_ROWS).collect();
+        if chunk.is_empty() {
+            return chunks;
+        }
+        chunks.push(chunk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(range: Range<usize>) -> Lines {
+        range.map(|n| Row::from(&n.to_string()[..])).collect()
+    }
+
+    fn texts<'a>(rows: impl Iterator<Item = &'a Row>) -> Vec<String> {
+        rows.map(|row| row.as_str().to_string()).collect()
+    }
+
+    #[test]
+    fn get_across_chunks() {
+        let lines = numbered(0..3 * CHUNK_ROWS + 5);
+        assert_eq!(lines.len(), 3 * CHUNK_ROWS + 5);
+        for index in [
+            0,
+            CHUNK_ROWS - 1,
+            CHUNK_ROWS,
+            2 * CHUNK_ROWS + 1,
+            3 * CHUNK_ROWS + 4,
+        ] {
+            assert_eq!(
+                lines.get(index).map(Row::as_str),
+                Some(&index.to_string()[..])
+            );
+        }
+        assert!(lines.get(3 * CHUNK_ROWS + 5).is_none());
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut lines = numbered(0..3);
+        lines.insert(1, Row::from("a"));
+        lines.insert(10, Row::from("b"));
+        assert_eq!(texts(lines.iter()), vec!["0", "a", "1", "2", "b"]);
+        assert_eq!(
+            lines.remove(0).map(|row| row.as_str().to_string()),
+            Some("0".to_string())
+        );
+        assert!(lines.remove(4).is_none());
+        assert_eq!(texts(lines.iter()), vec!["a", "1", "2", "b"]);
+    }
+
+    #[test]
+    fn splice_across_chunks() {
+        let mut lines = numbered(0..2 * CHUNK_ROWS);
+        let removed = lines.splice(CHUNK_ROWS - 1..CHUNK_ROWS + 1, vec![Row::from("x")]);
+        assert_eq!(
+            texts(removed.iter()),
+            vec![(CHUNK_ROWS - 1).to_string(), CHUNK_ROWS.to_string()]
+        );
+        assert_eq!(lines.len(), 2 * CHUNK_ROWS - 1);
+        assert_eq!(lines.get(CHUNK_ROWS - 1).map(Row::as_str), Some("x"));
+        assert_eq!(
+            lines.get(CHUNK_ROWS).map(Row::as_str),
+            Some(&(CHUNK_ROWS + 1).to_string()[..])
+        );
+    }
+
+    #[test]
+    fn large_insert_splits_chunk() {
+        let mut lines = numbered(0..2);
+        lines.splice(1..1, (0..3 * CHUNK_ROWS).map(|_| Row::from("x")));
+        assert_eq!(lines.len(), 3 * CHUNK_ROWS + 2);
+        assert!(lines.chunks.iter().all(|rows| rows.len() <= 2 * CHUNK_ROWS));
+        assert_eq!(lines.get(3 * CHUNK_ROWS + 1).map(Row::as_str), Some("1"));
+    }
+
+    #[test]
+    fn range_is_clamped() {
+        let lines = numbered(0..5);
+        assert_eq!(texts(lines.range(3..10)), vec!["3", "4"]);
+        assert_eq!(texts(lines.range(7..10)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn removing_every_row_leaves_empty_lines() {
+        let mut lines = numbered(0..3);
+        lines.splice(0..3, None);
+        assert!(lines.is_empty());
+        lines.push(Row::from("a"));
+        assert_eq!(texts(lines.iter()), vec!["a"]);
+    }
+}
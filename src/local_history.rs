@@ -0,0 +1,199 @@
+use crate::Position;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The number of snapshots kept for each file, older ones are discarded
+const MAX_SNAPSHOTS: usize = 20;
+
+/// The number of files whose last change is remembered, the files saved
+/// longest ago are forgotten
+const MAX_LAST_CHANGES: usize = 100;
+
+/// Retrieve the directory holding the editor's persistent state
+///
+/// # Returns
+///
+/// - `$XDG_STATE_HOME/donovim`, falling back to `~/.local/state/donovim`
+pub fn state_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir).join("donovim"));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state/donovim"))
+}
+
+/// Retrieve the directory holding the editor's data kept for the user
+///
+/// # Returns
+///
+/// - `$XDG_DATA_HOME/donovim`, falling back to `~/.local/share/donovim`
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir).join("donovim"));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share/donovim"))
+}
+
+/// Retrieve the directory holding the snapshots of a file. The absolute path
+/// of the file is flattened into a single directory name
+fn snapshot_dir(file_name: &str) -> Option<PathBuf> {
+    let path = fs::canonicalize(file_name).ok()?;
+    let name = path.to_string_lossy().replace('/', "%");
+    state_dir().map(|dir| dir.join("history").join(name))
+}
+
+/// Store a copy of a file as it currently is on disk, dropping the oldest
+/// snapshots once there are too many
+///
+/// # Args
+///
+/// - `file_name`: The path of the file
+pub fn snapshot(file_name: &str) -> Result<(), io::Error> {
+    let dir = snapshot_dir(file_name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+    fs::create_dir_all(&dir)?;
+
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    fs::copy(file_name, dir.join(millis.to_string()))?;
+
+    for old in list(file_name).iter().skip(MAX_SNAPSHOTS) {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// List the snapshots of a file
+///
+/// # Args
+///
+/// - `file_name`: The path of the file
+///
+/// # Returns
+///
+/// - The paths of the snapshots, newest first
+pub fn list(file_name: &str) -> Vec<PathBuf> {
+    let mut snapshots: Vec<(u128, PathBuf)> = snapshot_dir(file_name)
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|entry| Some((timestamp(&entry.path())?, entry.path())))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    snapshots.sort_by_key(|(time, _)| std::cmp::Reverse(*time));
+    snapshots.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Retrieve the time a snapshot was taken, in milliseconds since the epoch
+fn timestamp(snapshot: &Path) -> Option<u128> {
+    snapshot.file_name()?.to_str()?.parse().ok()
+}
+
+/// Remember where a file was last changed, so it can be opened there again
+///
+/// # Args
+///
+/// - `file_name`: The path of the file
+/// - `position`: The position of the last change
+pub fn save_last_change(file_name: &str, position: &Position) -> Result<(), io::Error> {
+    let dir =
+        state_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+    let path = fs::canonicalize(file_name)?.to_string_lossy().to_string();
+    fs::create_dir_all(&dir)?;
+
+    // The most recently saved file comes first
+    let mut lines = vec![format!("{}\t{}\t{}", position.y, position.x, path)];
+    lines.extend(
+        last_changes()
+            .into_iter()
+            .filter(|(_, other)| *other != path)
+            .take(MAX_LAST_CHANGES - 1)
+            .map(|(position, other)| format!("{}\t{}\t{}", position.y, position.x, other)),
+    );
+    fs::write(dir.join("last_changes"), lines.join("\n") + "\n")
+}
+
+/// Find where a file was changed before it was last saved
+///
+/// # Args
+///
+/// - `file_name`: The path of the file
+///
+/// # Returns
+///
+/// - The position of the last change, if one was remembered
+pub fn last_change(file_name: &str) -> Option<Position> {
+    let path = fs::canonicalize(file_name)
+        .ok()?
+        .to_string_lossy()
+        .to_string();
+    last_changes()
+        .into_iter()
+        .find(|(_, other)| *other == path)
+        .map(|(position, _)| position)
+}
+
+/// Read the remembered last changes, each line holding the line and column
+/// of the change and the absolute path of the file, separated by tabs
+fn last_changes() -> Vec<(Position, String)> {
+    let contents = state_dir()
+        .and_then(|dir| fs::read_to_string(dir.join("last_changes")).ok())
+        .unwrap_or_default();
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let y = fields.next()?.parse().ok()?;
+            let x = fields.next()?.parse().ok()?;
+            Some((Position { x, y }, fields.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// Describe when a snapshot was taken
+///
+/// # Args
+///
+/// - `snapshot`: The path of the snapshot
+///
+/// # Returns
+///
+/// - The UTC date and time, e.g. `2021-03-14 15:09:26`
+pub fn describe(snapshot: &Path) -> String {
+    let secs = timestamp(snapshot).unwrap_or_default() / 1000;
+    let days = secs / 86_400;
+    let time = secs % 86_400;
+
+    // Convert days since the epoch to a civil date
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u128::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
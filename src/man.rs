@@ -0,0 +1,46 @@
+use std::process::Command;
+
+/// Render the man page of a topic as plain text
+///
+/// # Args
+///
+/// - `topic`: The name of the page, e.g. `printf`
+/// - `width`: The number of columns to format the page for
+///
+/// # Returns
+///
+/// - The text of the page if successful, or the error reported by man
+pub fn page(topic: &str, width: usize) -> Result<String, String> {
+    let output = Command::new("man")
+        .args(["-P", "cat", topic])
+        .env("MANWIDTH", width.to_string())
+        // Ask groff for overstrikes rather than escape sequences
+        .env("GROFF_NO_SGR", "1")
+        .output()
+        .map_err(|err| format!("Could not run man: {}", err))?;
+
+    if output.status.success() {
+        Ok(strip_formatting(&String::from_utf8_lossy(&output.stdout)))
+    } else {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        if message.is_empty() {
+            Err(format!("No manual entry for {}", topic))
+        } else {
+            Err(message)
+        }
+    }
+}
+
+/// Remove the overstrikes man uses for bold and underlined text, such as
+/// `c\x08c` or `_\x08c`, keeping the character that is shown
+fn strip_formatting(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\x08' {
+            result.pop();
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
@@ -0,0 +1,293 @@
+use crate::{Document, Position};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The word motions of Normal mode
+#[derive(Clone, Copy)]
+pub enum WordMotion {
+    /// `w`: to the start of the next word
+    Forward,
+
+    /// `e`: to the end of the current or next word
+    ForwardEnd,
+
+    /// `b`: to the start of the current or previous word
+    Backward,
+
+    /// `ge`: to the end of the previous word
+    BackwardEnd,
+}
+
+/// What a character is made of, words are runs of the same class
+#[derive(Clone, Copy, PartialEq)]
+pub enum Class {
+    /// Whitespace, and the end of every line
+    Space,
+
+    /// An empty line, which counts as a word of its own
+    Empty,
+
+    /// Letters, digits and underscores
+    Word,
+
+    /// Any other character
+    Punctuation,
+}
+
+/// Find where a character motion moves the cursor on its line: `f` to the
+/// next occurrence of a character, `t` to just before it, and `F` and `T`
+/// likewise to the left
+///
+/// # Args
+///
+/// - `line`: The cursor line
+/// - `x`: The index of the cursor
+/// - `kind`: The motion, one of `f`, `F`, `t` and `T`
+/// - `target`: The character to find
+/// - `repeat`: Whether the motion is repeated by `;` or `,`, so `t` and `T`
+///   skip a target right next to the cursor
+///
+/// # Returns
+///
+/// - The new index of the cursor, if the character was found
+pub fn find_char(line: &str, x: usize, kind: char, target: char, repeat: bool) -> Option<usize> {
+    let graphemes: Vec<&str> = line.graphemes(true).collect();
+    let is_target = |grapheme: &&str| grapheme.chars().eq(std::iter::once(target));
+    let skip = usize::from(repeat && matches!(kind, 't' | 'T'));
+    match kind {
+        'f' | 't' => {
+            let start = x + 1 + skip;
+            let found = start + graphemes.get(start..)?.iter().position(is_target)?;
+            Some(if kind == 't' { found - 1 } else { found })
+        }
+        'F' | 'T' => {
+            let end = std::cmp::min(x.saturating_sub(skip), graphemes.len());
+            let found = graphemes[..end].iter().rposition(is_target)?;
+            Some(if kind == 'T' { found + 1 } else { found })
+        }
+        _ => None,
+    }
+}
+
+/// Find where a word motion moves the cursor, crossing lines when needed
+///
+/// # Args
+///
+/// - `document`: The document to move in
+/// - `from`: The position of the cursor
+/// - `motion`: The motion to make
+/// - `big`: Whether to move by WORDs, which are separated by whitespace only
+///
+/// # Returns
+///
+/// - The new position of the cursor
+pub fn word(document: &Document, from: &Position, motion: WordMotion, big: bool) -> Position {
+    let mut cursor = Cursor::new(document, from, big);
+    match motion {
+        WordMotion::Forward => {
+            let class = cursor.class();
+            if class == Class::Empty {
+                cursor.next();
+            } else {
+                while cursor.class() == class && cursor.next() {}
+            }
+            while cursor.class() == Class::Space && cursor.next() {}
+        }
+        WordMotion::ForwardEnd => {
+            cursor.next();
+            while matches!(cursor.class(), Class::Space | Class::Empty) && cursor.next() {}
+            let class = cursor.class();
+            while cursor.peek_next() == Some(class) {
+                cursor.next();
+            }
+        }
+        WordMotion::Backward => {
+            cursor.prev();
+            while cursor.class() == Class::Space && cursor.prev() {}
+            let class = cursor.class();
+            if class != Class::Empty {
+                while cursor.peek_prev() == Some(class) {
+                    cursor.prev();
+                }
+            }
+        }
+        WordMotion::BackwardEnd => {
+            let class = cursor.class();
+            if matches!(class, Class::Space | Class::Empty) {
+                cursor.prev();
+            } else {
+                while cursor.class() == class && cursor.prev() {}
+            }
+            while cursor.class() == Class::Space && cursor.prev() {}
+        }
+    }
+    cursor.position()
+}
+
+/// Walks the characters of a document, stopping once at the end of each line
+struct Cursor<'a> {
+    document: &'a Document,
+    big: bool,
+    x: usize,
+    y: usize,
+
+    /// The classes of the characters of line `y`
+    classes: Vec<Class>,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(document: &'a Document, from: &Position, big: bool) -> Self {
+        let mut cursor = Self {
+            document,
+            big,
+            x: from.x,
+            y: from.y,
+            classes: Vec::new(),
+        };
+        cursor.load_line();
+        cursor
+    }
+
+    fn load_line(&mut self) {
+        let big = self.big;
+        self.classes = self.document.row(self.y).map_or_else(Vec::new, |row| {
+            row.as_str()
+                .graphemes(true)
+                .map(|grapheme| classify(grapheme, big))
+                .collect()
+        });
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            x: self.x,
+            y: self.y,
+        }
+    }
+
+    fn class(&self) -> Class {
+        if self.classes.is_empty() {
+            Class::Empty
+        } else {
+            self.classes.get(self.x).copied().unwrap_or(Class::Space)
+        }
+    }
+
+    /// Move to the next character, returning whether there was one
+    fn next(&mut self) -> bool {
+        if self.x < self.classes.len() {
+            self.x += 1;
+        } else if self.y + 1 < self.document.len() {
+            self.y += 1;
+            self.x = 0;
+            self.load_line();
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Move to the previous character, returning whether there was one
+    fn prev(&mut self) -> bool {
+        if self.x > 0 {
+            self.x = std::cmp::min(self.x, self.classes.len()).saturating_sub(1);
+        } else if self.y > 0 {
+            self.y -= 1;
+            self.load_line();
+            self.x = self.classes.len();
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// The class of the next character on the same line, if there is one
+    fn peek_next(&self) -> Option<Class> {
+        self.classes.get(self.x + 1).copied()
+    }
+
+    /// The class of the previous character on the same line, if there is one
+    fn peek_prev(&self) -> Option<Class> {
+        self.classes.get(self.x.checked_sub(1)?).copied()
+    }
+}
+
+/// Find what a character is made of, every non-whitespace character being
+/// part of a word for WORDs
+pub fn classify(grapheme: &str, big: bool) -> Class {
+    if grapheme.chars().all(char::is_whitespace) {
+        Class::Space
+    } else if big || grapheme.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        Class::Word
+    } else {
+        Class::Punctuation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+
+    fn moved(text: &str, from: Position, motion: WordMotion, big: bool) -> (usize, usize) {
+        let to = word(&Document::from_text(text), &from, motion, big);
+        (to.x, to.y)
+    }
+
+    #[test]
+    fn find_char_forward() {
+        assert_eq!(find_char("a,b,c", 0, 'f', ',', false), Some(1));
+        assert_eq!(find_char("a,b,c", 0, 't', ',', false), Some(0));
+        assert_eq!(find_char("a,b,c", 0, 't', ',', true), Some(2));
+        assert_eq!(find_char("a,b,c", 0, 'f', 'z', false), None);
+    }
+
+    #[test]
+    fn find_char_backward() {
+        assert_eq!(find_char("a,b,c", 4, 'F', ',', false), Some(3));
+        assert_eq!(find_char("a,b,c", 4, 'T', ',', false), Some(4));
+        assert_eq!(find_char("a,b,c", 4, 'T', ',', true), Some(2));
+        assert_eq!(find_char("a,b,c", 0, 'F', ',', false), None);
+    }
+
+    #[test]
+    fn find_char_counts_graphemes() {
+        assert_eq!(find_char("e\u{301}x,", 0, 'f', ',', false), Some(2));
+    }
+
+    #[test]
+    fn words_and_big_words() {
+        let text = "foo.bar baz";
+        assert_eq!(moved(text, at(0, 0), WordMotion::Forward, false), (3, 0));
+        assert_eq!(moved(text, at(0, 0), WordMotion::Forward, true), (8, 0));
+        assert_eq!(moved(text, at(0, 0), WordMotion::ForwardEnd, false), (2, 0));
+        assert_eq!(moved(text, at(0, 0), WordMotion::ForwardEnd, true), (6, 0));
+        assert_eq!(moved(text, at(8, 0), WordMotion::Backward, false), (4, 0));
+        assert_eq!(moved(text, at(8, 0), WordMotion::Backward, true), (0, 0));
+        assert_eq!(
+            moved(text, at(8, 0), WordMotion::BackwardEnd, false),
+            (6, 0)
+        );
+    }
+
+    #[test]
+    fn empty_lines_are_words() {
+        let text = "one\n\ntwo";
+        assert_eq!(moved(text, at(0, 0), WordMotion::Forward, false), (0, 1));
+        assert_eq!(moved(text, at(0, 1), WordMotion::Forward, false), (0, 2));
+        assert_eq!(moved(text, at(0, 2), WordMotion::Backward, false), (0, 1));
+        assert_eq!(moved(text, at(0, 0), WordMotion::ForwardEnd, false), (2, 0));
+        assert_eq!(moved(text, at(0, 2), WordMotion::ForwardEnd, false), (2, 2));
+    }
+
+    #[test]
+    fn classify_graphemes() {
+        assert!(classify(" ", false) == Class::Space);
+        assert!(classify("_", false) == Class::Word);
+        assert!(classify("é", false) == Class::Word);
+        assert!(classify(".", false) == Class::Punctuation);
+        assert!(classify(".", true) == Class::Word);
+    }
+}
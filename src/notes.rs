@@ -0,0 +1,22 @@
+use crate::local_history;
+use crate::project;
+use std::fs;
+use std::path::PathBuf;
+
+/// Retrieve the file holding the notes of the current project, creating the
+/// directory it lives in. Projects are identified by the root of their
+/// repository, or by the working directory outside of one
+///
+/// # Returns
+///
+/// - The path of the notes file, or a description of the problem
+pub fn path() -> Result<PathBuf, String> {
+    let root = project::root()?.to_string_lossy().to_string();
+
+    let dir = local_history::state_dir()
+        .ok_or_else(|| "No state directory".to_string())?
+        .join("notes");
+    fs::create_dir_all(&dir)
+        .map_err(|err| format!("Could not create {}: {}", dir.display(), err))?;
+    Ok(dir.join(root.replace('/', "%")))
+}
@@ -0,0 +1,387 @@
+use std::ops::Range;
+
+/// The brackets `%` jumps between
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// The word that continues a chain of blocks after the closing brace of one
+const CHAIN: &str = "else";
+
+/// What `%` jumps between in a file type, besides brackets
+pub struct Pairs<'a> {
+    /// Groups of words written as in Vim's matchit, e.g. `if:elif:else:fi`.
+    /// The first word opens the group and the last one closes it, words
+    /// between them stop `%` on the way. Alternatives are separated by `|`,
+    /// as in `for|while|until:done`
+    pub groups: &'a [String],
+
+    /// Words starting a block between braces, e.g. `if`, which `%` jumps to
+    /// the end of, or to a following `else`
+    pub blocks: &'a [String],
+
+    /// Whether HTML or XML tags are matched with their closing tags
+    pub tags: bool,
+}
+
+/// A place in the lines of a document, as the line and the byte index in it
+type Location = (usize, usize);
+
+/// Find where `%` jumps to from a place in a document: from a word of a group
+/// to the next one, from a block word past its block, from a tag to its
+/// closing tag, or from the bracket at or after the cursor to its match
+///
+/// # Args
+///
+/// - `lines`: The lines of the document
+/// - `at`: The line and byte index of the cursor
+/// - `pairs`: What to match besides brackets
+///
+/// # Returns
+///
+/// - The line and byte index to jump to, if there is a match
+pub fn find(lines: &[&str], at: Location, pairs: &Pairs) -> Option<Location> {
+    let (y, x) = at;
+    let line = lines.get(y)?;
+    if let Some(word) = tokens(line).into_iter().find(|word| word.contains(&x)) {
+        let groups = pairs.groups.iter().map(|group| parse_group(group));
+        for group in groups {
+            if let Some(part) = part_of(&group, &line[word.clone()]) {
+                return match_group(lines, (y, word), &group, part);
+            }
+        }
+    }
+    let block = pairs.blocks.iter().find_map(|block| {
+        occurrences(line, block).find(|start| (*start..start + block.len()).contains(&x))
+    });
+    if let Some(start) = block {
+        if let Some(found) = match_block(lines, (y, start)) {
+            return Some(found);
+        }
+    }
+    if pairs.tags {
+        if let Some(found) = match_tag(lines, at) {
+            return Some(found);
+        }
+    }
+    let (offset, _) = line[x..].char_indices().find(|(_, c)| {
+        BRACKETS
+            .iter()
+            .any(|&(open, close)| *c == open || *c == close)
+    })?;
+    match_bracket(lines, (y, x + offset))
+}
+
+/// Split a group such as `if:elif:else:fi` into the alternatives of each part
+fn parse_group(group: &str) -> Vec<Vec<&str>> {
+    group
+        .split(':')
+        .map(|part| part.split('|').collect())
+        .collect()
+}
+
+/// Find which part of a group a word belongs to
+fn part_of(group: &[Vec<&str>], word: &str) -> Option<usize> {
+    group.iter().position(|part| part.contains(&word))
+}
+
+/// Split a line into words of letters, digits and underscores, which may
+/// start with `#` as in `#endif`
+fn tokens(line: &str) -> Vec<Range<usize>> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (index, c) in line.char_indices() {
+        match start {
+            None if is_word(c) || c == '#' => start = Some(index),
+            Some(first) if !is_word(c) => {
+                if index > first + 1 || !line[first..].starts_with('#') {
+                    tokens.push(first..index);
+                }
+                start = if c == '#' { Some(index) } else { None };
+            }
+            _ => (),
+        }
+    }
+    if let Some(first) = start {
+        tokens.push(first..line.len());
+    }
+    tokens
+}
+
+/// Find the occurrences of a word in a line that are not part of a longer
+/// word
+fn occurrences<'a>(line: &'a str, word: &'a str) -> impl Iterator<Item = usize> + 'a {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    line.match_indices(word)
+        .map(|(start, _)| start)
+        .filter(move |&start| {
+            let end = start + word.len();
+            // Only edges made of letters need a boundary
+            (!is_word(word.chars().next()) || !is_word(line[..start].chars().last()))
+                && (!is_word(word.chars().last()) || !is_word(line[end..].chars().next()))
+        })
+}
+
+/// Jump from a word of a group to the next word of the same group at the
+/// same depth, or from its closing word back to its opening word
+fn match_group(
+    lines: &[&str],
+    (y, word): (usize, Range<usize>),
+    group: &[Vec<&str>],
+    part: usize,
+) -> Option<Location> {
+    let last = group.len() - 1;
+    let mut depth = 0;
+    if part < last {
+        let after = lines.iter().enumerate().skip(y).flat_map(|(index, line)| {
+            tokens(line)
+                .into_iter()
+                .filter(move |token| index > y || token.start >= word.end)
+                .map(move |token| (index, token))
+        });
+        for (index, token) in after {
+            match part_of(group, &lines[index][token.clone()]) {
+                Some(0) => depth += 1,
+                Some(found) if found == last && depth > 0 => depth -= 1,
+                Some(_) if depth == 0 => return Some((index, token.start)),
+                _ => (),
+            }
+        }
+    } else {
+        let before = (0..=y).rev().flat_map(|index| {
+            tokens(lines[index])
+                .into_iter()
+                .rev()
+                .filter(move |token| index < y || token.end <= word.start)
+                .map(move |token| (index, token))
+        });
+        for (index, token) in before {
+            match part_of(group, &lines[index][token.clone()]) {
+                Some(found) if found == last => depth += 1,
+                Some(0) if depth == 0 => return Some((index, token.start)),
+                Some(0) => depth -= 1,
+                _ => (),
+            }
+        }
+    }
+    None
+}
+
+/// Jump from a word starting a block to the closing brace of the block, or to
+/// the `else` following it
+fn match_block(lines: &[&str], (y, start): Location) -> Option<Location> {
+    let mut depth = 0;
+    let mut open = None;
+    'lines: for (index, line) in lines.iter().enumerate().skip(y) {
+        let from = if index == y { start } else { 0 };
+        for (offset, c) in line[from..].char_indices() {
+            match c {
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth -= 1,
+                '{' if depth <= 0 => {
+                    open = Some((index, from + offset));
+                    break 'lines;
+                }
+                // A statement ended without a block
+                ';' if depth <= 0 => return None,
+                _ => (),
+            }
+        }
+    }
+    let (close_y, close_x) = match_bracket(lines, open?)?;
+    // `else` may follow on the same line or start the next one
+    for (index, from) in [(close_y, close_x + 1), (close_y + 1, 0)] {
+        let rest = match lines.get(index) {
+            Some(line) => &line[from..],
+            None => break,
+        };
+        let start = rest.len() - rest.trim_start().len();
+        if occurrences(rest, CHAIN).next() == Some(start) {
+            return Some((index, from + start));
+        }
+        if !rest.trim().is_empty() {
+            break;
+        }
+    }
+    Some((close_y, close_x))
+}
+
+/// Jump from a bracket to the bracket it pairs with
+///
+/// # Args
+///
+/// - `lines`: The lines of the document
+/// - `at`: The line and byte index of the bracket
+///
+/// # Returns
+///
+/// - The line and byte index of the other bracket, if `at` is on a bracket
+///   that has one
+pub fn match_bracket(lines: &[&str], (y, x): Location) -> Option<Location> {
+    let c = lines.get(y)?[x..].chars().next()?;
+    let (open, close, forward) = BRACKETS
+        .iter()
+        .find_map(|&(open, close)| (c == open || c == close).then_some((open, close, c == open)))?;
+    let mut depth = 0;
+    let mut step = |c: char| {
+        if c == open {
+            depth += if forward { 1 } else { -1 };
+        } else if c == close {
+            depth += if forward { -1 } else { 1 };
+        }
+        depth == 0
+    };
+    if forward {
+        for (index, line) in lines.iter().enumerate().skip(y) {
+            let from = if index == y { x } else { 0 };
+            for (offset, c) in line[from..].char_indices() {
+                if step(c) {
+                    return Some((index, from + offset));
+                }
+            }
+        }
+    } else {
+        for index in (0..=y).rev() {
+            let line = lines[index];
+            let to = if index == y {
+                x + c.len_utf8()
+            } else {
+                line.len()
+            };
+            for (offset, c) in line[..to].char_indices().rev() {
+                if step(c) {
+                    return Some((index, offset));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// A tag found in a line, e.g. `<div class="a">` or `</div>`
+struct Tag<'a> {
+    name: &'a str,
+    start: usize,
+    closing: bool,
+    self_closing: bool,
+}
+
+/// Find the tags starting in a line. Comments, doctypes and processing
+/// instructions are left out
+fn tags(line: &str) -> Vec<Tag<'_>> {
+    line.match_indices('<')
+        .filter_map(|(start, _)| {
+            let rest = &line[start + 1..];
+            let closing = rest.starts_with('/');
+            let rest = rest.strip_prefix('/').unwrap_or(rest);
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || "-_:.".contains(c)))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return None;
+            }
+            let self_closing = rest[end..]
+                .find('>')
+                .is_some_and(|close| rest[end..end + close].ends_with('/'));
+            Some(Tag {
+                name: &rest[..end],
+                start,
+                closing,
+                self_closing,
+            })
+        })
+        .collect()
+}
+
+/// Jump from an opening tag to its closing tag, or back
+fn match_tag(lines: &[&str], (y, x): Location) -> Option<Location> {
+    let line = lines.get(y)?;
+    let tag = tags(line)
+        .into_iter()
+        .rfind(|tag| tag.start <= x && !line[tag.start..x].contains('>') && !tag.self_closing)?;
+    let mut depth = 0;
+    if tag.closing {
+        for index in (0..=y).rev() {
+            let before = tags(lines[index])
+                .into_iter()
+                .rev()
+                .filter(|other| index < y || other.start < tag.start);
+            for other in before.filter(|other| other.name == tag.name && !other.self_closing) {
+                match (other.closing, depth) {
+                    (true, _) => depth += 1,
+                    (false, 0) => return Some((index, other.start)),
+                    (false, _) => depth -= 1,
+                }
+            }
+        }
+    } else {
+        for (index, line) in lines.iter().enumerate().skip(y) {
+            let after = tags(line)
+                .into_iter()
+                .filter(|other| index > y || other.start > tag.start);
+            for other in after.filter(|other| other.name == tag.name && !other.self_closing) {
+                match (other.closing, depth) {
+                    (false, _) => depth += 1,
+                    (true, 0) => return Some((index, other.start)),
+                    (true, _) => depth -= 1,
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs<'a>(groups: &'a [String], blocks: &'a [String], tags: bool) -> Pairs<'a> {
+        Pairs {
+            groups,
+            blocks,
+            tags,
+        }
+    }
+
+    #[test]
+    fn brackets_match_by_kind() {
+        let lines = ["f(a[1], {", "})"];
+        assert_eq!(find(&lines, (0, 0), &pairs(&[], &[], false)), Some((1, 1)));
+        assert_eq!(match_bracket(&lines, (1, 1)), Some((0, 1)));
+        assert_eq!(match_bracket(&lines, (0, 8)), Some((1, 0)));
+        assert_eq!(match_bracket(&lines, (0, 0)), None);
+    }
+
+    #[test]
+    fn groups_skip_nested_groups() {
+        let groups = ["if:elif:else:fi".to_string()];
+        let pairs = pairs(&groups, &[], false);
+        let lines = ["if a", "  if b", "  fi", "elif c", "fi"];
+        assert_eq!(find(&lines, (0, 0), &pairs), Some((3, 0)));
+        assert_eq!(find(&lines, (3, 1), &pairs), Some((4, 0)));
+        assert_eq!(find(&lines, (4, 0), &pairs), Some((0, 0)));
+    }
+
+    #[test]
+    fn blocks_jump_to_else() {
+        let blocks = ["if".to_string()];
+        let pairs = pairs(&[], &blocks, false);
+        let lines = ["if (x) {", "  y;", "} else {", "}"];
+        assert_eq!(find(&lines, (0, 0), &pairs), Some((2, 2)));
+        assert_eq!(find(&["if x {", "}", "y"], (0, 1), &pairs), Some((1, 0)));
+        assert_eq!(find(&["if x;"], (0, 0), &pairs), None);
+    }
+
+    #[test]
+    fn tags_skip_self_closing_and_nested_tags() {
+        let pairs = pairs(&[], &[], true);
+        let lines = ["<div>", "  <div/>", "  <div>a</div>", "</div>"];
+        assert_eq!(find(&lines, (0, 1), &pairs), Some((3, 0)));
+        assert_eq!(find(&lines, (3, 2), &pairs), Some((0, 0)));
+    }
+
+    #[test]
+    fn tokens_keep_hash_prefix() {
+        assert_eq!(tokens("#if x #endif"), vec![0..3, 4..5, 6..12]);
+        assert_eq!(tokens("a # b"), vec![0..1, 4..5]);
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// How long an operation runs before its progress is shown. Operations
+/// finishing sooner never show it
+const SHOW_AFTER: Duration = Duration::from_millis(100);
+
+/// Progress of a long-running operation done in the background. The thread
+/// doing the work updates it, and the editor shows it in the message bar
+/// between keys
+#[derive(Clone)]
+pub struct Progress {
+    label: String,
+    started: Instant,
+    done: Arc<AtomicUsize>,
+    /// The amount of work to do, 0 while unknown
+    total: Arc<AtomicUsize>,
+    /// Whether the user asked for the work to stop
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Progress {
+    /// Start reporting the progress of an operation
+    ///
+    /// # Args
+    ///
+    /// - `label`: What the operation does, e.g. `Writing`
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            started: Instant::now(),
+            done: Arc::new(AtomicUsize::new(0)),
+            total: Arc::new(AtomicUsize::new(0)),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Report how much of the operation is done
+    ///
+    /// # Args
+    ///
+    /// - `done`: The amount of work done
+    /// - `total`: The amount of work to do, if known
+    pub fn update(&self, done: usize, total: Option<usize>) {
+        self.done.store(done, Ordering::Relaxed);
+        self.total.store(total.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Ask for the operation to stop, it checks with `is_cancelled`
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Check whether the operation should stop early
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Describe the progress for the message bar
+    ///
+    /// # Args
+    ///
+    /// - `frame`: The number of times the progress was shown so far, turning
+    ///   the spinner
+    ///
+    /// # Returns
+    ///
+    /// - The text to show, unless the operation only just started
+    pub fn describe(&self, frame: usize) -> Option<String> {
+        if self.started.elapsed() < SHOW_AFTER {
+            return None;
+        }
+        let done = self.done.load(Ordering::Relaxed);
+        let mut text = format!("{} {}", SPINNER[frame % SPINNER.len()], self.label);
+        match self.total.load(Ordering::Relaxed) {
+            // Work that cannot tell how far it got only shows the spinner
+            0 if done == 0 => {}
+            0 => text.push_str(&format!(" {}", done)),
+            total => text.push_str(&format!(
+                " {}/{} ({}%)",
+                done,
+                total,
+                done.saturating_mul(100) / total
+            )),
+        }
+        Some(text)
+    }
+}
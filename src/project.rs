@@ -0,0 +1,121 @@
+use crate::config::Config;
+use crate::git;
+use crate::local_history;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The name of the config file a project may hold at its root
+const CONFIG_FILE: &str = ".donovimrc";
+
+/// Retrieve the root of the current project: the root of its repository, or
+/// the working directory outside of one
+///
+/// # Returns
+///
+/// - The path of the root, or a description of the problem
+pub fn root() -> Result<PathBuf, String> {
+    match git::top_level() {
+        Ok(root) => Ok(PathBuf::from(root)),
+        Err(_) => env::current_dir().map_err(|err| format!("Could not find the project: {}", err)),
+    }
+}
+
+/// Retrieve the config file of the current project, unless it has none or it
+/// is the user's own config file
+pub fn config_path() -> Option<PathBuf> {
+    let path = root().ok()?.join(CONFIG_FILE);
+    let is_user_config = |path: &Path| {
+        let user = Config::path().and_then(|user| fs::canonicalize(user).ok());
+        fs::canonicalize(path).ok() == user
+    };
+    (path.is_file() && !is_user_config(&path)).then_some(path)
+}
+
+/// Retrieve the file listing the trusted projects, one per line: the hash of
+/// the config file trusted, then the root of the project
+fn trusted_file() -> Option<PathBuf> {
+    local_history::state_dir().map(|dir| dir.join("trusted"))
+}
+
+/// Hash the contents of a config file, so trusting a project only covers
+/// the settings the user saw
+fn digest(contents: &str) -> String {
+    Sha256::digest(contents.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Read the trusted projects
+///
+/// # Returns
+///
+/// - The hash of the config file trusted and the root of each project
+fn trusted() -> Vec<(String, PathBuf)> {
+    let contents = trusted_file()
+        .and_then(|file| fs::read_to_string(file).ok())
+        .unwrap_or_default();
+    contents
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(hash, root)| (hash.to_string(), PathBuf::from(root)))
+        .collect()
+}
+
+/// Check whether the user trusts a project to change every setting with its
+/// config file. Trust is lost once the file changes
+///
+/// # Args
+///
+/// - `root`: The root of the project
+/// - `contents`: The contents of its config file
+pub fn is_trusted(root: &Path, contents: &str) -> bool {
+    let digest = digest(contents);
+    trusted()
+        .iter()
+        .any(|(hash, path)| path == root && *hash == digest)
+}
+
+/// Trust a project with the current contents of its config file, as done by
+/// `:Trust`, in place of what was trusted of it before
+///
+/// # Args
+///
+/// - `root`: The root of the project
+/// - `contents`: The contents of its config file
+///
+/// # Returns
+///
+/// - Unit or a description of the problem
+pub fn trust(root: &Path, contents: &str) -> Result<(), String> {
+    let file = trusted_file().ok_or_else(|| "No state directory".to_string())?;
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("Could not create {}: {}", dir.display(), err))?;
+    }
+    let mut lines: Vec<String> = trusted()
+        .into_iter()
+        .filter(|(_, path)| path != root)
+        .map(|(hash, path)| format!("{} {}", hash, path.display()))
+        .collect();
+    lines.push(format!("{} {}", digest(contents), root.display()));
+    fs::write(&file, lines.join("\n") + "\n")
+        .map_err(|err| format!("Could not write {}: {}", file.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_depends_on_contents() {
+        assert_eq!(digest("theme = \"dark\""), digest("theme = \"dark\""));
+        assert_ne!(digest("theme = \"dark\""), digest("theme = \"light\""));
+        assert_eq!(
+            digest(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The number of entries kept, older ones are forgotten
+const MAX_ENTRIES: usize = 100;
+
+/// What was typed at a prompt before, which `Up` and `Down` bring back.
+/// Entries are kept in a file, so they carry over to the next session
+#[derive(Default)]
+pub struct PromptHistory {
+    /// The file holding the entries, `None` for a history that is not kept
+    path: Option<PathBuf>,
+
+    /// The entries, oldest first
+    entries: Vec<String>,
+}
+
+impl PromptHistory {
+    /// Read a history kept in a file
+    ///
+    /// # Args
+    ///
+    /// - `path`: The file, or nothing when there is nowhere to keep it
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let entries = path.as_deref().map(read).unwrap_or_default();
+        Self { path, entries }
+    }
+
+    /// Remember an entry as the newest one, dropping an older copy of it. The
+    /// file is read again first, so entries added by other sessions are kept
+    ///
+    /// # Args
+    ///
+    /// - `entry`: What was typed
+    ///
+    /// # Returns
+    ///
+    /// - Unit or any Error encountered writing the file
+    pub fn add(&mut self, entry: &str) -> Result<(), io::Error> {
+        if let Some(path) = &self.path {
+            self.entries = read(path);
+        }
+        self.entries.retain(|other| other != entry);
+        self.entries.push(entry.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut contents = self.entries.join("\n");
+        contents.push('\n');
+        fs::write(path, contents)
+    }
+
+    /// Find the newest entry older than another that starts with what was
+    /// typed, as done by `Up`
+    ///
+    /// # Args
+    ///
+    /// - `before`: The index of the entry shown, or `usize::MAX` when none is
+    /// - `prefix`: What was typed before going through the history
+    ///
+    /// # Returns
+    ///
+    /// - The index and text of the entry, if there is one
+    pub fn older(&self, before: usize, prefix: &str) -> Option<(usize, &str)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .take(before)
+            .rev()
+            .find(|(_, entry)| entry.starts_with(prefix))
+            .map(|(index, entry)| (index, entry.as_str()))
+    }
+
+    /// Find the oldest entry newer than another that starts with what was
+    /// typed, as done by `Down`
+    ///
+    /// # Args
+    ///
+    /// - `after`: The index of the entry shown
+    /// - `prefix`: What was typed before going through the history
+    ///
+    /// # Returns
+    ///
+    /// - The index and text of the entry, if there is one
+    pub fn newer(&self, after: usize, prefix: &str) -> Option<(usize, &str)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .skip(after.saturating_add(1))
+            .find(|(_, entry)| entry.starts_with(prefix))
+            .map(|(index, entry)| (index, entry.as_str()))
+    }
+}
+
+/// Read the entries of a history, none when its file cannot be read
+fn read(path: &Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .map(|contents| {
+            contents
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,122 @@
+use crate::search::Pattern;
+use crate::Position;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The number of matches collected at most, so searching for something
+/// common stays fast
+pub const MAX_ENTRIES: usize = 10_000;
+
+/// A place found by `:grep` or `:vimgrep`
+pub struct Entry {
+    /// The file of the match
+    pub file_name: String,
+
+    /// Where the match starts
+    pub position: Position,
+
+    /// The line of the match
+    pub text: String,
+}
+
+impl Entry {
+    /// Describe the entry as a line of the quickfix window, e.g.
+    /// `src/main.rs:12:5: fn main() {`
+    pub fn describe(&self) -> String {
+        format!(
+            "{}:{}:{}: {}",
+            self.file_name,
+            self.position.y.saturating_add(1),
+            self.position.x.saturating_add(1),
+            self.text.trim()
+        )
+    }
+}
+
+/// The places found by the last `:grep` or `:vimgrep`, which `:cnext` and
+/// `:cprevious` go through
+#[derive(Default)]
+pub struct QuickfixList {
+    entries: Vec<Entry>,
+
+    /// The index of the entry last gone to
+    index: usize,
+}
+
+impl QuickfixList {
+    /// Create a list starting at its first entry
+    pub fn new(entries: Vec<Entry>) -> Self {
+        Self { entries, index: 0 }
+    }
+
+    /// Retrieve the entries, in the order they were found
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Retrieve the index of the entry last gone to
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Retrieve the entry last gone to, if the list is not empty
+    pub fn current(&self) -> Option<&Entry> {
+        self.entries.get(self.index)
+    }
+
+    /// Go to the next or the previous entry, as done by `:cnext` and
+    /// `:cprevious`
+    ///
+    /// # Args
+    ///
+    /// - `forward`: Whether to go to the next entry
+    ///
+    /// # Returns
+    ///
+    /// - The entry, or `None` past either end of the list
+    pub fn step(&mut self, forward: bool) -> Option<&Entry> {
+        let index = if forward {
+            self.index.checked_add(1)?
+        } else {
+            self.index.checked_sub(1)?
+        };
+        if index >= self.entries.len() {
+            return None;
+        }
+        self.index = index;
+        self.entries.get(index)
+    }
+}
+
+/// Collect the matches of a pattern in the lines of a file, one entry per
+/// match
+///
+/// # Args
+///
+/// - `file_name`: The file the lines come from
+/// - `lines`: The lines
+/// - `pattern`: The pattern searched for
+/// - `entries`: The entries found so far
+pub fn search(file_name: &str, lines: &[&str], pattern: &Pattern, entries: &mut Vec<Entry>) {
+    for (y, line) in lines.iter().enumerate() {
+        let mut start = 0;
+        while let Some(found) = pattern.find_at(line, start) {
+            if entries.len() >= MAX_ENTRIES {
+                return;
+            }
+            entries.push(Entry {
+                file_name: file_name.to_string(),
+                position: Position {
+                    x: line[..found.start].graphemes(true).count(),
+                    y,
+                },
+                text: line.to_string(),
+            });
+            // Empty matches must not find themselves again
+            start = match line[found.end..].chars().next() {
+                Some(c) if found.is_empty() => found.end + c.len_utf8(),
+                None if found.is_empty() => break,
+                _ => found.end,
+            };
+        }
+    }
+}
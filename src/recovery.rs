@@ -0,0 +1,174 @@
+use crate::local_history;
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Retrieve the directory holding the recovery copies of files
+fn dir() -> Option<PathBuf> {
+    local_history::state_dir().map(|dir| dir.join("recovery"))
+}
+
+/// Retrieve the directory holding the files that tell which editor has a
+/// file open
+fn owners_dir() -> Option<PathBuf> {
+    local_history::state_dir().map(|dir| dir.join("owners"))
+}
+
+/// Flatten the absolute path of a file into a single file name, so files not
+/// written yet have a place in the state directory too
+///
+/// # Args
+///
+/// - `file_name`: The path of the file
+fn flatten(file_name: &str) -> Option<String> {
+    let path = fs::canonicalize(file_name)
+        .or_else(|_| env::current_dir().map(|dir| dir.join(file_name)))
+        .ok()?;
+    Some(escape(&path.to_string_lossy()))
+}
+
+/// Write a path as a file name: `%` becomes `%25` and `/` becomes `%2F`, so
+/// no two paths share a name
+///
+/// # Args
+///
+/// - `path`: The path to write
+fn escape(path: &str) -> String {
+    path.replace('%', "%25").replace('/', "%2F")
+}
+
+/// Read back a path written by `escape`
+///
+/// # Args
+///
+/// - `name`: The file name
+fn unescape(name: &str) -> String {
+    name.replace("%2F", "/").replace("%25", "%")
+}
+
+/// Retrieve where the recovery copy of a file goes
+///
+/// # Args
+///
+/// - `file_name`: The path of the file
+pub fn path(file_name: &str) -> Option<PathBuf> {
+    Some(dir()?.join(flatten(file_name)?))
+}
+
+/// Check whether the recovery area holds changes of a file the file does
+/// not, left by an editor that crashed or was killed before saving them
+///
+/// # Args
+///
+/// - `file_name`: The path of the file
+pub fn has_changes(file_name: &str) -> bool {
+    match path(file_name).map(fs::read) {
+        Some(Ok(copy)) => fs::read(file_name).map_or(true, |contents| contents != copy),
+        _ => false,
+    }
+}
+
+/// Mark a file as open in this editor, unless another editor still running
+/// has it open
+///
+/// # Args
+///
+/// - `file_name`: The path of the file
+///
+/// # Returns
+///
+/// - The process id of the other editor, if there is one
+pub fn claim(file_name: &str) -> Option<u32> {
+    let path = owners_dir()?.join(flatten(file_name)?);
+    let owner = fs::read_to_string(&path)
+        .ok()
+        .and_then(|owner| owner.trim().parse::<u32>().ok());
+    if let Some(pid) = owner.filter(|pid| *pid != process::id() && is_running(*pid)) {
+        return Some(pid);
+    }
+    // Another editor finds out it shares the file when it is claimed, so a
+    // failure here only loses that warning
+    fs::create_dir_all(path.parent()?).ok();
+    fs::write(&path, process::id().to_string()).ok();
+    None
+}
+
+/// Mark a file as no longer open in this editor, once its buffer is closed
+///
+/// # Args
+///
+/// - `file_name`: The path of the file
+pub fn release(file_name: &str) {
+    let path = match owners_dir().zip(flatten(file_name)) {
+        Some((dir, name)) => dir.join(name),
+        None => return,
+    };
+    let owner = fs::read_to_string(&path).unwrap_or_default();
+    if owner.trim() == process::id().to_string() {
+        fs::remove_file(path).ok();
+    }
+}
+
+/// Check whether a process is still running
+fn is_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Make sure the recovery directory exists before a copy is written to it,
+/// readable by the user only
+pub fn create_dir() -> Result<(), io::Error> {
+    let dir = dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)?;
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+}
+
+/// Drop the recovery copy of a file, once the file itself holds its changes
+///
+/// # Args
+///
+/// - `file_name`: The path of the file
+pub fn remove(file_name: &str) {
+    if let Some(path) = path(file_name) {
+        fs::remove_file(path).ok();
+    }
+}
+
+/// Find the recovery copy written last
+///
+/// # Returns
+///
+/// - The path of the file it was written for and the path of the copy, if
+///   there is one
+pub fn latest() -> Option<(String, PathBuf)> {
+    let (_, copy) = fs::read_dir(dir()?)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max_by_key(|(modified, _)| *modified)?;
+    let file_name = unescape(&copy.file_name()?.to_string_lossy());
+    Some((file_name, copy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaped_paths_do_not_collide() {
+        assert_ne!(escape("/a/b%c"), escape("/a%b/c"));
+        assert_ne!(escape("/a%2Fb"), escape("/a/b"));
+    }
+
+    #[test]
+    fn escaped_paths_read_back() {
+        for path in ["/tmp/a%b.txt", "/a/b%c", "/a%2Fb/%25", "/plain/file"] {
+            assert_eq!(unescape(&escape(path)), path);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+/// The name of the register used when none is given
+pub const UNNAMED: char = '"';
+
+/// The name of the register holding the text last typed in Insert mode
+pub const LAST_INSERTED: char = '.';
+
+/// Text stored by a yank
+#[derive(Clone)]
+pub struct Register {
+    /// The lines of text
+    pub lines: Vec<String>,
+
+    /// Whether whole lines were yanked, rather than a range of characters
+    pub linewise: bool,
+}
+
+/// Holds the unnamed register and the named registers `a` to `z`
+#[derive(Default)]
+pub struct Registers {
+    registers: HashMap<char, Register>,
+}
+
+impl Registers {
+    /// Check whether a register exists with the given name
+    ///
+    /// # Args
+    ///
+    /// - `name`: `"` for the unnamed register, a letter for a named one
+    pub fn is_valid(name: char) -> bool {
+        name == UNNAMED || name.is_ascii_alphabetic()
+    }
+
+    /// Check whether a register can be pasted from, which besides the
+    /// registers yanks go to includes the read-only `.` register
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the register
+    pub fn is_readable(name: char) -> bool {
+        Self::is_valid(name) || name == LAST_INSERTED
+    }
+
+    /// Retrieve the contents of a register
+    ///
+    /// # Args
+    ///
+    /// - `name`: `"` for the unnamed register, a letter for a named one, `.`
+    ///   for the text last typed in Insert mode
+    pub fn get(&self, name: char) -> Option<&Register> {
+        self.registers.get(&name.to_ascii_lowercase())
+    }
+
+    /// Store a yank in a register. The unnamed register always receives the
+    /// latest yank, and uppercase names append to the named register
+    ///
+    /// # Args
+    ///
+    /// - `name`: `"` for the unnamed register, a letter for a named one
+    /// - `register`: The yanked text
+    pub fn set(&mut self, name: char, register: Register) {
+        let register = match self.registers.get(&name.to_ascii_lowercase()) {
+            Some(previous) if name.is_ascii_uppercase() => Self::append(previous, register),
+            _ => register,
+        };
+
+        if name != UNNAMED {
+            self.registers
+                .insert(name.to_ascii_lowercase(), register.clone());
+        }
+        self.registers.insert(UNNAMED, register);
+    }
+
+    /// Store the text typed during a visit to Insert mode in the `.`
+    /// register, leaving the unnamed register alone
+    ///
+    /// # Args
+    ///
+    /// - `text`: The typed text, lines separated by `\n`
+    pub fn set_last_inserted(&mut self, text: &str) {
+        let register = Register {
+            lines: text.split('\n').map(str::to_string).collect(),
+            linewise: false,
+        };
+        self.registers.insert(LAST_INSERTED, register);
+    }
+
+    /// Join two yanks, the result is linewise if either of them is
+    fn append(previous: &Register, next: Register) -> Register {
+        let mut lines = previous.lines.clone();
+        if previous.linewise || next.linewise {
+            lines.extend(next.lines);
+        } else if let Some((first, rest)) = next.lines.split_first() {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(first);
+            }
+            lines.extend_from_slice(rest);
+        }
+
+        Register {
+            lines,
+            linewise: previous.linewise || next.linewise,
+        }
+    }
+}
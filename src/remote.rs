@@ -0,0 +1,373 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// The variable naming the socket, for running more than one server
+const SOCKET_VAR: &str = "DONOVIM_SERVER";
+
+/// How long a client may take to send its request before it is turned away
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Something another program asked the running editor to do, sent as a JSON
+/// object on a line of its own, e.g.
+/// `{"command":"open","file":"/src/main.rs","line":42,"wait":true}` or
+/// `{"command":"ex","args":"set number"}`
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Request {
+    /// Open a file, at a line if given, as done by `--remote file:line`. With
+    /// `wait`, the answer only comes once the buffer is closed
+    Open {
+        file: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        line: Option<usize>,
+        #[serde(default, skip_serializing_if = "is_false")]
+        wait: bool,
+    },
+
+    /// Run a command of the command line, given without the `:`
+    #[serde(rename = "ex")]
+    Command { args: String },
+}
+
+/// The answer to a request, e.g. `{"ok":true}` or
+/// `{"ok":false,"error":"No such file"}`
+#[derive(Serialize, Deserialize)]
+struct Reply {
+    ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A request waiting for the editor, along with the connection the answer
+/// goes back on
+pub struct Call {
+    pub request: Request,
+    stream: UnixStream,
+}
+
+impl Call {
+    /// Answer the program that sent the request, which waits for it
+    ///
+    /// # Args
+    ///
+    /// - `result`: Unit, or a description of what went wrong
+    pub fn reply(mut self, result: Result<(), String>) {
+        self.stream.write_all(reply(result).as_bytes()).ok();
+    }
+}
+
+/// Listens on a Unix socket for requests from other programs. The socket is
+/// removed when the server is dropped
+pub struct Server {
+    path: PathBuf,
+    calls: Receiver<Call>,
+}
+
+impl Server {
+    /// Start listening, accepting connections on a thread of their own so the
+    /// editor only has to check for calls between keys
+    ///
+    /// # Args
+    ///
+    /// - `path`: The socket, or nothing for the default one
+    ///
+    /// # Returns
+    ///
+    /// - The server, or a description of the problem
+    pub fn listen(path: Option<PathBuf>) -> Result<Self, String> {
+        let path = path.unwrap_or_else(socket_path);
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(format!("Another editor listens on {}", path.display()));
+            }
+            // Left behind by an editor that did not quit cleanly
+            fs::remove_file(&path).ok();
+        }
+        let listener = UnixListener::bind(&path)
+            .map_err(|err| format!("Could not listen on {}: {}", path.display(), err))?;
+
+        let (sender, calls) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().filter_map(Result::ok) {
+                // A client slow to send its request must not hold up the others
+                let sender = sender.clone();
+                thread::spawn(move || read_call(stream, &sender));
+            }
+        });
+        Ok(Self { path, calls })
+    }
+
+    /// Take the next request waiting, without blocking
+    pub fn try_recv(&self) -> Option<Call> {
+        self.calls.try_recv().ok()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Read the request sent on a connection and hand it to the editor, or
+/// answer with the problem if there is no valid request
+///
+/// # Args
+///
+/// - `stream`: The connection of the client
+/// - `sender`: Where the editor receives calls
+fn read_call(mut stream: UnixStream, sender: &Sender<Call>) {
+    let mut line = String::new();
+    let read = stream
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .and_then(|_| stream.try_clone())
+        .and_then(|clone| BufReader::new(clone).read_line(&mut line));
+    let request = read
+        .map_err(|err| err.to_string())
+        .and_then(|_| parse_request(&line));
+    match request {
+        Ok(request) => {
+            sender.send(Call { request, stream }).ok();
+        }
+        Err(err) => {
+            stream.write_all(reply(Err(err)).as_bytes()).ok();
+        }
+    }
+}
+
+/// Retrieve the socket the editor listens on unless told otherwise: the one
+/// named by `DONOVIM_SERVER`, or one in the runtime directory of the user
+fn socket_path() -> PathBuf {
+    if let Some(path) = env::var_os(SOCKET_VAR) {
+        return PathBuf::from(path);
+    }
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => Path::new(&dir).join("donovim.sock"),
+        None => {
+            let user = env::var("USER").unwrap_or_default();
+            env::temp_dir().join(format!("donovim-{}.sock", user))
+        }
+    }
+}
+
+/// Send files to the running editor, as done by `donovim --remote`. Each one
+/// is opened in its own buffer, at its line when written as `file:line`.
+/// After `--wait`, the files are all sent before waiting for their buffers to
+/// be closed, as `git mergetool` and `crontab -e` expect of an editor
+///
+/// # Args
+///
+/// - `args`: The arguments after `--remote`
+///
+/// # Returns
+///
+/// - The status to exit with, non-zero when a file could not be opened or
+///   its buffer was closed with `:cq`
+pub fn open_remote(args: &[String]) -> i32 {
+    let (wait, files) = match args.split_first() {
+        Some((flag, files)) if flag == "--wait" => (true, files),
+        _ => (false, args),
+    };
+    if files.is_empty() {
+        eprintln!("Usage: donovim --remote [--wait] FILE[:LINE]...");
+        return 2;
+    }
+    let mut code = 0;
+    let mut report = |result: Result<(), String>| {
+        if let Err(err) = result {
+            eprintln!("donovim: {}", err);
+            code = 1;
+        }
+    };
+    let mut waiting = Vec::new();
+    for file in files {
+        let (file, line) = match file.rsplit_once(':') {
+            Some((file, line)) if !file.is_empty() && line.parse::<usize>().is_ok() => {
+                (file, line.parse().ok())
+            }
+            _ => (file.as_str(), None),
+        };
+        // The editor may run in another directory
+        let path = env::current_dir()
+            .map(|dir| dir.join(file))
+            .unwrap_or_else(|_| PathBuf::from(file));
+        let request = Request::Open {
+            file: path.to_string_lossy().to_string(),
+            line,
+            wait,
+        };
+        match send(&request) {
+            Ok(stream) if wait => waiting.push(stream),
+            Ok(stream) => report(answer(stream)),
+            Err(err) => report(Err(err)),
+        }
+    }
+    for stream in waiting {
+        report(answer(stream));
+    }
+    code
+}
+
+/// Send a request to the running editor
+///
+/// # Returns
+///
+/// - The connection its answer comes back on, or a description of the
+///   problem
+fn send(request: &Request) -> Result<UnixStream, String> {
+    let path = socket_path();
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|err| format!("No editor listens on {}: {}", path.display(), err))?;
+    let mut message = encode_request(request);
+    message.push('\n');
+    stream
+        .write_all(message.as_bytes())
+        .map_err(|err| err.to_string())?;
+    Ok(stream)
+}
+
+/// Wait for the answer to a request
+///
+/// # Args
+///
+/// - `stream`: The connection the request was sent on
+///
+/// # Returns
+///
+/// - Unit, or a description of what went wrong
+fn answer(stream: UnixStream) -> Result<(), String> {
+    let mut answer = String::new();
+    BufReader::new(stream)
+        .read_line(&mut answer)
+        .map_err(|err| err.to_string())?;
+    match serde_json::from_str(&answer) {
+        Ok(Reply { ok: true, .. }) => Ok(()),
+        Ok(Reply {
+            error: Some(err), ..
+        }) => Err(err),
+        _ => Err("Invalid answer".to_string()),
+    }
+}
+
+/// Write a request as a JSON object
+fn encode_request(request: &Request) -> String {
+    // Requests hold nothing but strings, numbers and booleans
+    serde_json::to_string(request).unwrap_or_default()
+}
+
+/// Read a request written as a JSON object
+///
+/// # Returns
+///
+/// - The request, or a description of what is wrong with it
+fn parse_request(text: &str) -> Result<Request, String> {
+    match serde_json::from_str(text) {
+        Ok(Request::Open { line: Some(0), .. }) => Err("Invalid line".to_string()),
+        Ok(request) => Ok(request),
+        Err(err) => Err(format!("Invalid request: {}", err)),
+    }
+}
+
+/// Write the answer to a request as a JSON object
+fn reply(result: Result<(), String>) -> String {
+    let reply = Reply {
+        ok: result.is_ok(),
+        error: result.err(),
+    };
+    let mut text = serde_json::to_string(&reply).unwrap_or_default();
+    text.push('\n');
+    text
+}
+
+/// Check whether a flag is off, leaving it out of the requests
+fn is_false(flag: &bool) -> bool {
+    !flag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_read_back() {
+        let requests = [
+            Request::Open {
+                file: "/src/main.rs".to_string(),
+                line: Some(42),
+                wait: true,
+            },
+            Request::Open {
+                file: "/tmp/\"quoted\"\\name\n".to_string(),
+                line: None,
+                wait: false,
+            },
+            Request::Command {
+                args: "s/\t/ /g \u{1f600}".to_string(),
+            },
+        ];
+        for request in requests {
+            assert_eq!(parse_request(&encode_request(&request)), Ok(request));
+        }
+    }
+
+    #[test]
+    fn requests_are_encoded_compactly() {
+        let request = Request::Open {
+            file: "/a".to_string(),
+            line: None,
+            wait: false,
+        };
+        assert_eq!(
+            encode_request(&request),
+            r#"{"command":"open","file":"/a"}"#
+        );
+    }
+
+    #[test]
+    fn surrogate_pairs_are_combined() {
+        let text = r#"{"command":"ex","args":"echo \ud83d\ude00"}"#;
+        assert_eq!(
+            parse_request(text),
+            Ok(Request::Command {
+                args: "echo \u{1f600}".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_requests_are_rejected() {
+        for text in [
+            r#"{"command":"open","file":"/a","line":0}"#,
+            r#"{"command":"open","file":"/a","line":-1}"#,
+            r#"{"command":"open"}"#,
+            r#"{"command":"delete","file":"/a"}"#,
+            r#"{"command":"ex","args":"\ud83d"}"#,
+            "open /a",
+        ] {
+            assert!(parse_request(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn replies_read_back() {
+        let reply = |result: Result<(), String>| -> Result<(), String> {
+            let answer: Reply = serde_json::from_str(&super::reply(result)).unwrap();
+            match answer {
+                Reply { ok: true, .. } => Ok(()),
+                Reply { error, .. } => Err(error.unwrap_or_default()),
+            }
+        };
+        assert_eq!(reply(Ok(())), Ok(()));
+        assert_eq!(
+            reply(Err("No \"such\" file \u{1f600}".to_string())),
+            Err("No \"such\" file \u{1f600}".to_string())
+        );
+    }
+}
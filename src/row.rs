@@ -1,13 +1,18 @@
-use crate::{filetype::HighlightingOptions, highlighting};
+use crate::{filetype::HighlightingOptions, highlighting, search::Pattern, Theme};
 use std::cmp;
+use std::ops::Range;
 use termion::color;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 #[derive(Default)]
 pub struct Row {
     string: String,
     highlighting: Vec<highlighting::Type>,
     pub is_highlighted: bool,
+    /// Whether search matches or references were highlighted last time,
+    /// which have to be taken off again when there are none anymore
+    shows_matches: bool,
     len: usize,
 }
 
@@ -20,6 +25,7 @@ impl From<&str> for Row {
             string: String::from(slice),
             highlighting: Vec::new(),
             is_highlighted: false,
+            shows_matches: false,
             len: slice.graphemes(true).count(),
         }
     }
@@ -27,36 +33,41 @@ impl From<&str> for Row {
 
 impl Row {
     /**
-     * Return a substring of Row.string
+     * Return the screen columns from start to end of Row.string, colored with
+     * the given theme and with tabs expanded to the next multiple of tab_size.
+     * Wide graphemes cut by either edge are shown as blanks, as are graphemes
+     * without a width of their own, so every column stays where it is counted
      */
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end: usize = cmp::min(end, self.string.len());
-        let start: usize = cmp::min(start, end);
+    pub fn render(&self, start: usize, end: usize, theme: &Theme, tab_size: usize) -> String {
         let mut result: String = String::new();
         let mut current_highlight: &highlighting::Type = &highlighting::Type::None;
+        let mut column: usize = 0;
 
-        for (index, grapheme) in self.string[..]
-            .graphemes(true)
-            .enumerate()
-            .skip(start)
-            .take(end - start)
-        {
-            if let Some(c) = grapheme.chars().next() {
-                let highlighting_type: &highlighting::Type = self
-                    .highlighting
-                    .get(index)
-                    .unwrap_or(&highlighting::Type::None);
-                if highlighting_type != current_highlight {
-                    current_highlight = highlighting_type;
-                    let start_highlight =
-                        format!("{}", termion::color::Fg(highlighting_type.to_color()));
-                    result.push_str(&start_highlight[..]);
-                }
-                if c == '\t' {
-                    result.push_str("    ");
-                } else {
-                    result.push(c);
-                }
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            if column >= end {
+                break;
+            }
+            let width: usize = grapheme_width(grapheme, column, tab_size);
+            let first: usize = column;
+            column += width;
+            let shown: usize = cmp::min(column, end).saturating_sub(cmp::max(first, start));
+            if shown == 0 {
+                continue;
+            }
+            let highlighting_type: &highlighting::Type = self
+                .highlighting
+                .get(index)
+                .unwrap_or(&highlighting::Type::None);
+            if highlighting_type != current_highlight {
+                current_highlight = highlighting_type;
+                let start_highlight =
+                    format!("{}", termion::color::Fg(theme.color(*highlighting_type)));
+                result.push_str(&start_highlight[..]);
+            }
+            if grapheme == "\t" || shown < width || grapheme.width() == 0 {
+                result.push_str(&" ".repeat(shown));
+            } else {
+                result.push_str(grapheme);
             }
         }
         let end_highlight: String = format!("{}", termion::color::Fg(color::Reset));
@@ -65,26 +76,50 @@ impl Row {
     }
 
     /**
-     * Insert the specified char at the specified location in the current row
+     * Return the number of screen columns the graphemes before the given
+     * index take, which is the column the grapheme at it is shown at. Tabs
+     * reach to the next multiple of tab_size and wide characters take two
+     * columns
      */
-    pub fn insert(&mut self, at: usize, c: char) {
-        if at >= self.len() {
-            self.string.push(c);
-            self.len += 1;
-            return;
-        }
-        let mut result: String = String::new();
-        let mut length: usize = 0;
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            length += 1;
-            if index == at {
-                length += 1;
-                result.push(c);
+    pub fn width_until(&self, at: usize, tab_size: usize) -> usize {
+        self.string
+            .graphemes(true)
+            .take(at)
+            .fold(0, |column, grapheme| {
+                column + grapheme_width(grapheme, column, tab_size)
+            })
+    }
+
+    /**
+     * Return the index of the grapheme shown at the given screen column, or
+     * the length of the row past its end
+     */
+    pub fn index_at(&self, column: usize, tab_size: usize) -> usize {
+        let mut end: usize = 0;
+        for (index, grapheme) in self.string.graphemes(true).enumerate() {
+            end += grapheme_width(grapheme, end, tab_size);
+            if end > column {
+                return index;
             }
-            result.push_str(grapheme);
         }
-        self.len = length;
-        self.string = result;
+        self.len
+    }
+
+    /**
+     * Return the number of screen columns the row takes
+     */
+    pub fn width(&self, tab_size: usize) -> usize {
+        self.width_until(self.len, tab_size)
+    }
+
+    /**
+     * Insert the specified char at the specified location in the current row
+     */
+    pub fn insert(&mut self, at: usize, c: char) {
+        let index: usize = self.byte_index(at);
+        self.string.insert(index, c);
+        // A combining character joins the grapheme before it
+        self.len = self.string.graphemes(true).count();
     }
 
     pub fn peek_white(&self, at: usize) -> usize {
@@ -136,22 +171,64 @@ impl Row {
         idx
     }
 
-    pub fn find(&self, query: &str, after: usize) -> Option<usize> {
-        if after > self.len || query.is_empty() {
+    /**
+     * Find the first match of the pattern starting at or after the given
+     * index, returning the range of graphemes it covers
+     */
+    pub fn find(&self, pattern: &Pattern, after: usize) -> Option<Range<usize>> {
+        if after > self.len {
             return None;
         }
-        let substring: String = self.string[..].graphemes(true).skip(after).collect();
-        let matching_byte_index: Option<usize> = self.string.find(query);
-        if let Some(matching_byte_index) = matching_byte_index {
-            for (grapheme_index, (byte_index, _)) in
-                substring[..].grapheme_indices(true).enumerate()
-            {
-                if matching_byte_index == byte_index {
-                    return Some(after + grapheme_index);
-                }
-            }
+        let found = pattern.find_at(&self.string, self.byte_index(after))?;
+        let start = self.string[..found.start].graphemes(true).count();
+        let len = self.string[found].graphemes(true).count();
+        Some(start..start + len)
+    }
+
+    /**
+     * Return the byte index of the grapheme at the given index, or the length
+     * of the row past its end
+     */
+    pub fn byte_index(&self, at: usize) -> usize {
+        self.string
+            .grapheme_indices(true)
+            .nth(at)
+            .map_or(self.string.len(), |(index, _)| index)
+    }
+
+    /**
+     * Return the byte range of the word (letters, digits and underscores)
+     * holding the grapheme at the given index
+     */
+    pub fn word_at(&self, at: usize) -> Option<Range<usize>> {
+        let graphemes: Vec<(usize, &str)> = self.string.grapheme_indices(true).collect();
+        let is_word = |grapheme: &str| grapheme.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if !is_word(graphemes.get(at)?.1) {
+            return None;
         }
-        None
+
+        let first = graphemes[..at]
+            .iter()
+            .rposition(|(_, grapheme)| !is_word(grapheme))
+            .map_or(0, |index| index + 1);
+        let end = graphemes[at..]
+            .iter()
+            .find(|(_, grapheme)| !is_word(grapheme))
+            .map_or(self.string.len(), |(index, _)| *index);
+        Some(graphemes[first].0..end)
+    }
+
+    /**
+     * Count the non-overlapping matches of the pattern in the row
+     */
+    pub fn count(&self, pattern: &Pattern) -> usize {
+        let mut count: usize = 0;
+        let mut index: usize = 0;
+        while let Some(search_match) = self.find(pattern, index) {
+            count += 1;
+            index = cmp::max(search_match.end, search_match.start + 1);
+        }
+        count
     }
 
     /**
@@ -161,63 +238,97 @@ impl Row {
         if at >= self.len() {
             return;
         }
-        let mut result: String = String::new();
-        let mut length: usize = 0;
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            if index != at {
-                length += 1;
-                result.push_str(grapheme);
-            }
-        }
-        self.len = length;
-        self.string = result;
+        let start: usize = self.byte_index(at);
+        let end: usize = self.byte_index(at + 1);
+        self.string.replace_range(start..end, "");
+        self.len = self.string.graphemes(true).count();
     }
 
     /**
      * Split a row at the given column and return the remainder
      */
     pub fn split(&mut self, at: usize) -> Self {
-        let mut row: String = String::new();
-        let mut length: usize = 0;
-        let mut splitted_row: String = String::new();
-        let mut splitted_length: usize = 0;
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            if index < at {
-                length += 1;
-                row.push_str(grapheme);
-            } else {
-                splitted_length += 1;
-                splitted_row.push_str(grapheme);
-            }
-        }
+        let splitted_row: String = self.string.split_off(self.byte_index(at));
+        let length: usize = cmp::min(at, self.len);
+        let splitted_length: usize = self.len - length;
 
-        self.string = row;
         self.len = length;
         self.is_highlighted = false;
         Self {
             string: splitted_row,
             highlighting: Vec::new(),
             is_highlighted: false,
+            shows_matches: false,
             len: splitted_length,
         }
     }
 
-    fn highlight_match(&mut self, word: &Option<String>) {
-        if let Some(word) = word {
-            if word.is_empty() {
-                return;
-            }
-            let mut index: usize = 0;
-            while let Some(search_match) = self.find(word, index) {
-                if let Some(next_index) = search_match.checked_add(word[..].graphemes(true).count())
-                {
-                    for i in index.saturating_add(search_match)..next_index {
-                        self.highlighting[i] = highlighting::Type::Match;
-                    }
-                    index = next_index;
+    /**
+     * Return whether the grapheme at the given index was highlighted as part
+     * of a string, a character or a comment
+     */
+    pub fn is_literal(&self, at: usize) -> bool {
+        matches!(
+            self.highlighting.get(at),
+            Some(
+                highlighting::Type::String
+                    | highlighting::Type::Character
+                    | highlighting::Type::Comment
+                    | highlighting::Type::MultilineComment
+            )
+        )
+    }
+
+    /**
+     * Return the text with the strings, characters and comments blanked out
+     * with spaces, so every other grapheme keeps its byte index
+     */
+    pub fn code(&self) -> String {
+        self.string
+            .graphemes(true)
+            .enumerate()
+            .map(|(index, grapheme)| {
+                if self.is_literal(index) {
+                    " ".repeat(grapheme.len())
                 } else {
-                    break;
+                    grapheme.to_string()
                 }
+            })
+            .collect()
+    }
+
+    /**
+     * Turn the highlighting found character by character into highlighting
+     * by grapheme, as the rest of the row is indexed, each grapheme taking
+     * the type of its first character
+     */
+    fn highlight_graphemes(&mut self) {
+        if self.highlighting.len() == self.len {
+            return;
+        }
+        let mut index: usize = 0;
+        let mut highlighting: Vec<highlighting::Type> = Vec::with_capacity(self.len);
+        for grapheme in self.string.graphemes(true) {
+            highlighting.push(
+                self.highlighting
+                    .get(index)
+                    .copied()
+                    .unwrap_or(highlighting::Type::None),
+            );
+            index += grapheme.chars().count();
+        }
+        self.highlighting = highlighting;
+    }
+
+    fn highlight_match(&mut self, pattern: Option<&Pattern>, hl_type: highlighting::Type) {
+        if let Some(pattern) = pattern {
+            let mut index: usize = 0;
+            while let Some(search_match) = self.find(pattern, index) {
+                for i in search_match.clone() {
+                    self.highlighting[i] = hl_type;
+                }
+                // Step over empty matches so the search moves on
+                index = cmp::max(search_match.end, search_match.start + 1);
             }
         }
     }
@@ -447,12 +558,12 @@ impl Row {
     pub fn highlight(
         &mut self,
         opts: &HighlightingOptions,
-        word: &Option<String>,
+        pattern: Option<&Pattern>,
+        reference: Option<&Pattern>,
+        brackets: &[usize],
         start_with_comment: bool,
     ) -> bool {
-        let chars: Vec<char> = self.string.chars().collect();
-
-        if self.is_highlighted && word.is_none() {
+        if self.is_highlighted && !self.shows_matches && pattern.is_none() && reference.is_none() {
             if let Some(hl_type) = self.highlighting.last() {
                 if *hl_type == highlighting::Type::MultilineComment
                     && self.string.len() > 1
@@ -464,6 +575,7 @@ impl Row {
             return false;
         }
 
+        let chars: Vec<char> = self.string.chars().collect();
         self.highlighting = Vec::new();
         let mut index: usize = 0;
 
@@ -500,8 +612,16 @@ impl Row {
             self.highlighting.push(highlighting::Type::None);
             index += 1;
         }
+        self.highlight_graphemes();
 
-        self.highlight_match(word);
+        self.highlight_match(reference, highlighting::Type::Reference);
+        self.highlight_match(pattern, highlighting::Type::Match);
+        for &at in brackets {
+            if let Some(hl_type) = self.highlighting.get_mut(at) {
+                *hl_type = highlighting::Type::MatchParen;
+            }
+        }
+        self.shows_matches = pattern.is_some() || reference.is_some();
 
         if in_ml_comment && &self.string[self.string.len().saturating_sub(2)..] != "*/" {
             return true;
@@ -515,14 +635,18 @@ impl Row {
      * Append a row to the current row
      */
     pub fn append(&mut self, new: &Self) {
-        self.string = format!("{}{}", self.string, new.string);
-        self.len += new.len;
+        self.string.push_str(&new.string);
+        self.len = self.string.graphemes(true).count();
     }
 
     pub fn as_bytes(&self) -> &[u8] {
         self.string.as_bytes()
     }
 
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+
     pub fn len(&self) -> usize {
         self.len
     }
@@ -535,3 +659,18 @@ impl Row {
 fn is_separator(c: char) -> bool {
     c.is_ascii_punctuation() || c.is_ascii_whitespace()
 }
+
+/**
+ * Return the number of screen columns a grapheme shown at the given column
+ * takes, a tab reaching to the next multiple of tab_size. Wide characters
+ * such as CJK and emoji take two columns, and graphemes without a width of
+ * their own one, so the cursor can rest on them
+ */
+fn grapheme_width(grapheme: &str, column: usize, tab_size: usize) -> usize {
+    if grapheme == "\t" {
+        let tab_size: usize = cmp::max(tab_size, 1);
+        tab_size - column % tab_size
+    } else {
+        cmp::max(grapheme.width(), 1)
+    }
+}
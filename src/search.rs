@@ -0,0 +1,144 @@
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+/// A search query, read as a regular expression or as plain text when it is
+/// not a valid one
+pub struct Pattern {
+    /// The compiled query, `None` when it is empty and matches nothing
+    regex: Option<Regex>,
+}
+
+impl Pattern {
+    /// Compile a search query
+    ///
+    /// # Args
+    ///
+    /// - `query`: The text typed by the user. `\c` in it makes letters match
+    ///   regardless of their case and `\C` makes them match exactly, over
+    ///   `ignore_case`
+    /// - `ignore_case`: Whether letters match regardless of their case
+    pub fn new(query: &str, ignore_case: bool) -> Self {
+        let (query, forced) = case_override(query);
+        let query = &query[..];
+        let ignore_case = forced.unwrap_or(ignore_case);
+        // An empty regular expression would match everywhere
+        if query.is_empty() {
+            return Self { regex: None };
+        }
+
+        let build = |pattern: &str| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .build()
+        };
+        Self {
+            regex: build(query).or_else(|_| build(&regex::escape(query))).ok(),
+        }
+    }
+
+    /// Find the first match in some text
+    ///
+    /// # Args
+    ///
+    /// - `text`: The text to search in
+    /// - `start`: The byte index to start searching from
+    ///
+    /// # Returns
+    ///
+    /// - The byte range of the match if there is one
+    pub fn find_at(&self, text: &str, start: usize) -> Option<Range<usize>> {
+        let found = self.regex.as_ref()?.find_at(text, start)?;
+        Some(found.range())
+    }
+}
+
+/// Take the `\c` and `\C` case overrides out of a query, the last one
+/// winning. An escaped backslash followed by `c` is left alone
+///
+/// # Args
+///
+/// - `query`: The text typed by the user
+///
+/// # Returns
+///
+/// - The query without the overrides, and whether letters match regardless
+///   of their case if an override says so
+fn case_override(query: &str) -> (String, Option<bool>) {
+    let mut stripped = String::with_capacity(query.len());
+    let mut forced = None;
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            stripped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('c') => forced = Some(true),
+            Some('C') => forced = Some(false),
+            Some(next) => {
+                stripped.push(c);
+                stripped.push(next);
+            }
+            None => stripped.push(c),
+        }
+    }
+    (stripped, forced)
+}
+
+/// Check whether a query holds an upper case letter, which `smart_case`
+/// takes as asking for the case to match. Letters after a backslash, such as
+/// the one of `\S`, are part of the syntax rather than the text searched for
+///
+/// # Args
+///
+/// - `query`: The text typed by the user
+pub fn has_upper_case(query: &str) -> bool {
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_override_last_one_wins() {
+        assert_eq!(case_override("foo"), ("foo".to_string(), None));
+        assert_eq!(case_override("\\cfoo"), ("foo".to_string(), Some(true)));
+        assert_eq!(case_override("f\\coo\\C"), ("foo".to_string(), Some(false)));
+    }
+
+    #[test]
+    fn case_override_keeps_other_escapes() {
+        assert_eq!(case_override("\\\\c"), ("\\\\c".to_string(), None));
+        assert_eq!(case_override("a\\d\\"), ("a\\d\\".to_string(), None));
+    }
+
+    #[test]
+    fn pattern_follows_override() {
+        assert_eq!(
+            Pattern::new("\\cFOO", false).find_at("a foo", 0),
+            Some(2..5)
+        );
+        assert_eq!(Pattern::new("\\CFOO", true).find_at("a foo", 0), None);
+        assert_eq!(Pattern::new("\\c", true).find_at("a foo", 0), None);
+    }
+
+    #[test]
+    fn invalid_regex_is_plain_text() {
+        assert_eq!(Pattern::new("a(b", false).find_at("xa(b", 0), Some(1..4));
+    }
+
+    #[test]
+    fn upper_case_after_backslash_is_syntax() {
+        assert!(has_upper_case("Foo"));
+        assert!(!has_upper_case("\\Sfoo"));
+    }
+}
@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// The file `:mksession` and `:source` use when none is given
+pub const DEFAULT_FILE: &str = "Session.toml";
+
+/// A buffer of a session, along with the view of it
+#[derive(Deserialize, Serialize)]
+pub struct Buffer {
+    /// The file of the buffer
+    pub file_name: String,
+
+    /// The line of the cursor, starting at 0
+    pub line: usize,
+
+    /// The column of the cursor, in graphemes from the start of the line
+    pub column: usize,
+
+    /// The line at the top of the screen
+    pub top: usize,
+}
+
+/// The state of the editor saved by `:mksession`, so the work can be picked
+/// up where it was left with `:source` or `--session`. Written in TOML
+#[derive(Deserialize, Serialize)]
+pub struct Session {
+    /// The working directory
+    pub dir: String,
+
+    /// The index of the buffer shown
+    pub current: usize,
+
+    /// Whether the quickfix window was open
+    pub quickfix_open: bool,
+
+    /// The buffers that have a file, in order
+    pub buffers: Vec<Buffer>,
+}
+
+impl Session {
+    /// Read a session file
+    ///
+    /// # Returns
+    ///
+    /// - The session, or a description of the problem
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        toml::from_str(&contents).map_err(|err| format!("Invalid {}: {}", path.display(), err))
+    }
+
+    /// Write the session to a file, replacing what it held
+    ///
+    /// # Returns
+    ///
+    /// - Unit, or a description of the problem
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents =
+            toml::to_string(self).map_err(|err| format!("Could not describe session: {}", err))?;
+        fs::write(path, contents)
+            .map_err(|err| format!("Could not write {}: {}", path.display(), err))
+    }
+}
@@ -0,0 +1,67 @@
+use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The number of graphemes of recent keys kept, older ones scroll off
+const MAX_LEN: usize = 40;
+
+/// The keys pressed last, shown in a corner of the screen with `showkeys`
+/// for screencasts and pairing. They are written in Vim's notation, e.g.
+/// `ciwfoo<Esc>`
+#[derive(Default)]
+pub struct ShownKeys {
+    text: String,
+}
+
+impl ShownKeys {
+    /// Add a key after the others, dropping the oldest ones past `MAX_LEN`
+    ///
+    /// # Args
+    ///
+    /// - `key`: The key pressed
+    pub fn push(&mut self, key: Key) {
+        self.text.push_str(&name(key));
+        let len = self.text.graphemes(true).count();
+        if len > MAX_LEN {
+            let start = self
+                .text
+                .grapheme_indices(true)
+                .nth(len - MAX_LEN)
+                .map_or(self.text.len(), |(index, _)| index);
+            self.text.drain(..start);
+        }
+    }
+
+    /// Retrieve the keys, oldest first
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// Write a key in Vim's notation: characters as they are, other keys in
+/// angle brackets such as `<CR>` or `<C-o>`
+fn name(key: Key) -> String {
+    match key {
+        Key::Char(' ') => "<Space>".to_string(),
+        Key::Char('\n') => "<CR>".to_string(),
+        Key::Char('\t') => "<Tab>".to_string(),
+        Key::Char('<') => "<lt>".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("<C-{}>", c),
+        Key::Alt(c) => format!("<M-{}>", c),
+        Key::F(number) => format!("<F{}>", number),
+        Key::Esc => "<Esc>".to_string(),
+        Key::Backspace => "<BS>".to_string(),
+        Key::Delete => "<Del>".to_string(),
+        Key::Insert => "<Insert>".to_string(),
+        Key::Up => "<Up>".to_string(),
+        Key::Down => "<Down>".to_string(),
+        Key::Left => "<Left>".to_string(),
+        Key::Right => "<Right>".to_string(),
+        Key::Home => "<Home>".to_string(),
+        Key::End => "<End>".to_string(),
+        Key::PageUp => "<PageUp>".to_string(),
+        Key::PageDown => "<PageDown>".to_string(),
+        Key::BackTab => "<S-Tab>".to_string(),
+        _ => String::new(),
+    }
+}
@@ -0,0 +1,309 @@
+use crate::theme::Color;
+use crate::{Document, Position};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use toml::Value;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// The text drawn between two segments on the same side
+const SEPARATOR: &str = " | ";
+
+/// A piece of information shown in the status bar
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Segment {
+    /// The current mode, e.g. `NORMAL`
+    Mode,
+
+    /// The name of the file, or `[No Name]`
+    FileName,
+
+    /// `[+]` when the document has unsaved changes
+    Modified,
+
+    /// Notices about the file: whether it is still being read, or was changed
+    /// on disk
+    State,
+
+    /// The file type, e.g. `Rust`
+    FileType,
+
+    /// The encoding of the file, and whether it starts with a byte order mark
+    Encoding,
+
+    /// How the lines of the file end, and whether the last one does not
+    FileFormat,
+
+    /// The number of lines
+    Lines,
+
+    /// The definitions around the cursor, e.g. `Editor > run`
+    Context,
+
+    /// The line and column of the cursor, e.g. `12:5`
+    Position,
+
+    /// How far through the document the cursor line is, e.g. `40%`
+    Percentage,
+}
+
+/// What the segments of a status bar describe
+pub struct Info<'a> {
+    pub document: &'a Document,
+    pub cursor: &'a Position,
+    pub mode: String,
+
+    /// Whether to spell out what the colors of the status bar show, for
+    /// screen readers
+    pub screen_reader: bool,
+}
+
+/// A piece of text in the status bar, with the color of the segment it shows
+/// if it has its own
+pub type Piece = (String, Option<Color>);
+
+impl Segment {
+    /// Build the text of the segment
+    ///
+    /// # Returns
+    ///
+    /// - The text, empty when there is nothing to show
+    fn text(self, info: &Info) -> String {
+        let document = info.document;
+        match self {
+            Self::Mode => info.mode.to_uppercase(),
+            Self::FileName => {
+                let mut name = document
+                    .file_name
+                    .clone()
+                    .unwrap_or_else(|| "[No Name]".to_string());
+                name.truncate(name.char_indices().nth(20).map_or(name.len(), |(i, _)| i));
+                name
+            }
+            Self::Modified if document.is_dirty() => "[+]".to_string(),
+            Self::Modified => String::new(),
+            Self::State => {
+                let mut notices = Vec::new();
+                if let Some(percent) = document.loading_progress() {
+                    notices.push(format!("[loading {}%]", percent));
+                }
+                if document.changed_on_disk() {
+                    notices.push("[changed on disk, :e! to reload]".to_string());
+                }
+                if info.screen_reader && !document.conflicts().is_empty() {
+                    notices.push("[conflicts]".to_string());
+                }
+                notices.join(" ")
+            }
+            Self::FileType => document.file_type(),
+            Self::Encoding if document.has_bom() => format!("{} [BOM]", document.encoding()),
+            Self::Encoding => document.encoding().to_string(),
+            Self::FileFormat if document.is_missing_final_newline() => {
+                format!("{} [noeol]", document.file_format())
+            }
+            Self::FileFormat => document.file_format().to_string(),
+            Self::Lines => format!("{} lines", document.len()),
+            Self::Context => document
+                .context(info.cursor.y)
+                .iter()
+                .map(|(_, definition)| definition.name)
+                .collect::<Vec<&str>>()
+                .join(" > "),
+            Self::Position => format!(
+                "{}:{}",
+                info.cursor.y.saturating_add(1),
+                info.cursor.x.saturating_add(1)
+            ),
+            Self::Percentage => {
+                let percent = info.cursor.y.saturating_add(1) * 100 / document.len().max(1);
+                format!("{}%", percent.min(100))
+            }
+        }
+    }
+}
+
+/// Parse a color written as `#rrggbb`
+fn parse_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let channel = |at: usize| u8::from_str_radix(hex.get(at..at + 2)?, 16).ok();
+    Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Parse the colors of the segments that have their own
+///
+/// # Args
+///
+/// - `colors`: The colors by segment name, written as `#rrggbb`
+///
+/// # Returns
+///
+/// - The colors by segment, or a description of the first invalid entry
+pub fn parse_colors(colors: &BTreeMap<String, String>) -> Result<BTreeMap<Segment, Color>, String> {
+    colors
+        .iter()
+        .map(|(name, color)| {
+            let segment = Value::String(name.clone())
+                .try_into()
+                .map_err(|_| format!("Invalid status segment {:?}", name))?;
+            let color = parse_color(color).ok_or_else(|| {
+                format!(
+                    "Invalid status color {:?}, colors must be written as #rrggbb",
+                    color
+                )
+            })?;
+            Ok((segment, color))
+        })
+        .collect()
+}
+
+/// Lay out a status bar: the segments of the left side from its start, the
+/// ones of the right side up to its end, and spaces between them. Empty
+/// segments are left out
+///
+/// # Args
+///
+/// - `left`: The segments of the left side, in order
+/// - `right`: The segments of the right side, in order
+/// - `colors`: The colors of the segments that have their own
+/// - `info`: What the segments describe
+/// - `width`: The width of the status bar
+///
+/// # Returns
+///
+/// - The pieces of the status bar, `width` columns long unless a wide
+///   character had to be cut off
+pub fn layout(
+    left: &[Segment],
+    right: &[Segment],
+    colors: &BTreeMap<Segment, Color>,
+    info: &Info,
+    width: usize,
+) -> Vec<Piece> {
+    let side = |segments: &[Segment]| {
+        let mut pieces: Vec<Piece> = Vec::new();
+        for segment in segments {
+            let text = segment.text(info);
+            if text.is_empty() {
+                continue;
+            }
+            if !pieces.is_empty() {
+                pieces.push((SEPARATOR.to_string(), None));
+            }
+            pieces.push((text, colors.get(segment).copied()));
+        }
+        pieces
+    };
+    let width_of =
+        |pieces: &[Piece]| -> usize { pieces.iter().map(|(text, _)| text.width()).sum() };
+
+    let mut pieces = vec![(" ".to_string(), None)];
+    pieces.extend(side(left));
+    let mut right = side(right);
+    right.push((" ".to_string(), None));
+    let used = width_of(&pieces) + width_of(&right);
+    pieces.push((" ".repeat(width.saturating_sub(used)), None));
+    pieces.extend(right);
+
+    // Whatever does not fit is cut off at the end
+    let mut left_over = width;
+    for (text, _) in &mut pieces {
+        left_over -= truncate(text, left_over);
+    }
+    pieces.retain(|(text, _)| !text.is_empty());
+    pieces
+}
+
+/// Cut text off at a number of columns, keeping whole graphemes
+///
+/// # Args
+///
+/// - `text`: The text to shorten
+/// - `width`: The number of columns the text may take
+///
+/// # Returns
+///
+/// - The number of columns the text takes once shortened
+pub fn truncate(text: &mut String, width: usize) -> usize {
+    let mut kept = 0;
+    let mut end = 0;
+    for grapheme in text.graphemes(true) {
+        if kept + grapheme.width() > width {
+            break;
+        }
+        kept += grapheme.width();
+        end += grapheme.len();
+    }
+    text.truncate(end);
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(pieces: &[Piece]) -> Vec<&str> {
+        pieces.iter().map(|(text, _)| text.as_str()).collect()
+    }
+
+    #[test]
+    fn layout_fills_width() {
+        let document = Document::from_text("a\nb\nc\nd");
+        let info = Info {
+            document: &document,
+            cursor: &Position { x: 1, y: 0 },
+            mode: "normal".to_string(),
+            screen_reader: false,
+        };
+        let colors = BTreeMap::from([(Segment::Mode, Color::Rgb(1, 2, 3))]);
+        let left = [Segment::Mode, Segment::Modified, Segment::FileName];
+        let right = [Segment::Position, Segment::Percentage];
+
+        let pieces = layout(&left, &right, &colors, &info, 40);
+        assert_eq!(
+            texts(&pieces),
+            vec![
+                " ",
+                "NORMAL",
+                " | ",
+                "[No Name]",
+                &" ".repeat(11),
+                "1:2",
+                " | ",
+                "25%",
+                " "
+            ]
+        );
+        assert_eq!(pieces[1].1, Some(Color::Rgb(1, 2, 3)));
+        assert_eq!(pieces[3].1, None);
+
+        let pieces = layout(&left, &right, &colors, &info, 10);
+        assert_eq!(texts(&pieces), vec![" ", "NORMAL", " | "]);
+    }
+
+    #[test]
+    fn truncate_keeps_whole_graphemes() {
+        let mut text = "a日b".to_string();
+        assert_eq!(truncate(&mut text, 2), 1);
+        assert_eq!(text, "a");
+        let mut text = "ab".to_string();
+        assert_eq!(truncate(&mut text, 5), 2);
+        assert_eq!(text, "ab");
+    }
+
+    #[test]
+    fn parse_segment_colors() {
+        let colors = BTreeMap::from([("mode".to_string(), "#0a0b0c".to_string())]);
+        let parsed = parse_colors(&colors).ok().unwrap();
+        assert_eq!(parsed.get(&Segment::Mode), Some(&Color::Rgb(10, 11, 12)));
+
+        let colors = BTreeMap::from([("nope".to_string(), "#000000".to_string())]);
+        assert_eq!(
+            parse_colors(&colors).err().unwrap(),
+            "Invalid status segment \"nope\""
+        );
+        let colors = BTreeMap::from([("mode".to_string(), "red".to_string())]);
+        assert!(parse_colors(&colors).is_err());
+        assert_eq!(parse_color("#12345"), None);
+    }
+}
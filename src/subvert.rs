@@ -0,0 +1,165 @@
+use crate::search::Pattern;
+
+/// A case-preserving substitution, as done by `:S/old/new/`: every case of
+/// `old` is replaced by `new` written in the same case
+pub struct Subvert<'a> {
+    old: &'a str,
+    new: &'a str,
+
+    /// Whether the plural of `old` is replaced by the plural of `new` too
+    plural: bool,
+}
+
+impl<'a> Subvert<'a> {
+    pub fn new(old: &'a str, new: &'a str, plural: bool) -> Self {
+        Self { old, new, plural }
+    }
+
+    /// Build the pattern matching `old` in any case as a whole word, so
+    /// `cat` leaves `concatenate` alone, and its plural first when plurals
+    /// are replaced too
+    pub fn pattern(&self) -> Pattern {
+        let old = whole_word(self.old);
+        let query = if self.plural {
+            format!("{}|{}", whole_word(&plural(self.old)), old)
+        } else {
+            old
+        };
+        Pattern::new(&query, true)
+    }
+
+    /// Find the text to put in place of a match
+    ///
+    /// # Args
+    ///
+    /// - `found`: The matched text
+    ///
+    /// # Returns
+    ///
+    /// - `new`, or its plural for a plural match, in the case of the match
+    pub fn replacement(&self, found: &str) -> String {
+        let new = if self.plural && found.to_lowercase() == plural(self.old).to_lowercase() {
+            plural(self.new)
+        } else {
+            self.new.to_string()
+        };
+
+        let is_upper = |text: &str| {
+            text.chars().any(char::is_alphabetic) && !text.chars().any(char::is_lowercase)
+        };
+        if is_upper(found) && found.chars().filter(|c| c.is_alphabetic()).count() > 1 {
+            new.to_uppercase()
+        } else if found.starts_with(char::is_uppercase) {
+            let mut chars = new.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        } else if found.starts_with(char::is_lowercase) {
+            let mut chars = new.chars();
+            chars
+                .next()
+                .map(|first| first.to_lowercase().chain(chars).collect())
+                .unwrap_or_default()
+        } else {
+            new
+        }
+    }
+}
+
+/// Build a regular expression matching some text only where it is not part
+/// of a longer word
+///
+/// # Args
+///
+/// - `text`: The text to match
+fn whole_word(text: &str) -> String {
+    // A word boundary next to punctuation would need a letter beside it
+    let boundary = |c: Option<char>| {
+        if c.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            r"\b"
+        } else {
+            ""
+        }
+    };
+    format!(
+        "{}{}{}",
+        boundary(text.chars().next()),
+        regex::escape(text),
+        boundary(text.chars().last())
+    )
+}
+
+/// Form the plural of an English word by the simple rules: `box` to `boxes`,
+/// `city` to `cities` and `cat` to `cats`
+fn plural(word: &str) -> String {
+    let lower = word.to_lowercase();
+    let before_y = lower.strip_suffix('y').and_then(|rest| rest.chars().last());
+    if ["s", "x", "z", "ch", "sh"]
+        .iter()
+        .any(|ending| lower.ends_with(ending))
+    {
+        format!("{}es", word)
+    } else if before_y.is_some_and(|c| !"aeiou".contains(c)) {
+        format!("{}ies", &word[..word.len() - 1])
+    } else {
+        format!("{}s", word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Replace every match of a substitution in a line
+    fn substitute(subvert: &Subvert, line: &str) -> String {
+        let pattern = subvert.pattern();
+        let mut result = String::new();
+        let mut start = 0;
+        while let Some(found) = pattern.find_at(line, start) {
+            result.push_str(&line[start..found.start]);
+            result.push_str(&subvert.replacement(&line[found.clone()]));
+            start = found.end;
+        }
+        result + &line[start..]
+    }
+
+    #[test]
+    fn keeps_the_case_of_each_match() {
+        let subvert = Subvert::new("cat", "dog", false);
+        assert_eq!(substitute(&subvert, "cat Cat CAT"), "dog Dog DOG");
+    }
+
+    #[test]
+    fn leaves_longer_words_alone() {
+        let subvert = Subvert::new("cat", "dog", false);
+        assert_eq!(
+            substitute(&subvert, "concatenate cat_food cat."),
+            "concatenate cat_food dog."
+        );
+    }
+
+    #[test]
+    fn matches_punctuation_at_the_edges() {
+        let subvert = Subvert::new("-x", "-y", false);
+        assert_eq!(substitute(&subvert, "a -x b"), "a -y b");
+    }
+
+    #[test]
+    fn replaces_plurals() {
+        let subvert = Subvert::new("city", "box", true);
+        assert_eq!(
+            substitute(&subvert, "Cities city CITIES"),
+            "Boxes box BOXES"
+        );
+    }
+
+    #[test]
+    fn forms_plurals() {
+        assert_eq!(plural("box"), "boxes");
+        assert_eq!(plural("church"), "churches");
+        assert_eq!(plural("city"), "cities");
+        assert_eq!(plural("day"), "days");
+        assert_eq!(plural("cat"), "cats");
+    }
+}
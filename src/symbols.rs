@@ -0,0 +1,113 @@
+use crate::git;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// A definition found in the project
+#[derive(Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub file_name: String,
+    pub line: usize,
+}
+
+/// Collect the symbols of every file in the project, read from the `tags`
+/// file at the root of the project or generated with ctags when there is none
+///
+/// # Returns
+///
+/// - The symbols if successful, or a description of the problem
+pub fn load() -> Result<Vec<Symbol>, String> {
+    let root = git::top_level().unwrap_or_else(|_| ".".to_string());
+    let tags_file = Path::new(&root).join("tags");
+
+    let tags = if tags_file.exists() {
+        fs::read_to_string(&tags_file)
+            .map_err(|err| format!("Could not read {}: {}", tags_file.display(), err))?
+    } else {
+        let output = Command::new("ctags")
+            .args(["-R", "-f", "-", "--excmd=number", &root])
+            .output()
+            .map_err(|err| format!("Could not run ctags: {}", err))?;
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    Ok(tags.lines().filter_map(|line| parse(line, &root)).collect())
+}
+
+/// Parse a line of a tags file such as `main\tsrc/main.rs\t12;"\tf`
+///
+/// # Args
+///
+/// - `line`: The line of the tags file
+/// - `root`: The directory relative paths in the tags file start from
+fn parse(line: &str, root: &str) -> Option<Symbol> {
+    if line.starts_with("!_TAG_") {
+        return None;
+    }
+
+    let mut fields = line.split('\t');
+    let name = fields.next()?;
+    let file_name = fields.next()?;
+    // Only line number addresses can be used, search patterns are skipped
+    let line: usize = fields.next()?.trim_end_matches(";\"").parse().ok()?;
+
+    let file_name = if Path::new(file_name).is_absolute() || root == "." {
+        file_name.to_string()
+    } else {
+        Path::new(root)
+            .join(file_name)
+            .to_string_lossy()
+            .to_string()
+    };
+    Some(Symbol {
+        name: name.to_string(),
+        file_name,
+        line: line.saturating_sub(1),
+    })
+}
+
+/// Score how well a query matches some text, where every character of the
+/// query must appear in the text in order
+///
+/// # Args
+///
+/// - `query`: The characters typed by the user
+/// - `text`: The text to match against
+///
+/// # Returns
+///
+/// - The score if the query matches, lower is better
+pub fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut position = 0;
+    let mut score = 0;
+
+    for c in query.chars().flat_map(char::to_lowercase) {
+        let found = text[position..].iter().position(|&t| t == c)?;
+        // Gaps between matched characters make the match worse
+        score += found;
+        position += found + 1;
+    }
+    Some(score + text.len() - position)
+}
+
+/// Filter symbols by a query, best matches first
+///
+/// # Args
+///
+/// - `symbols`: The symbols to filter
+/// - `query`: The characters typed by the user
+/// - `limit`: The maximum number of symbols returned
+pub fn filter<'a>(symbols: &'a [Symbol], query: &str, limit: usize) -> Vec<&'a Symbol> {
+    let mut matches: Vec<(usize, &Symbol)> = symbols
+        .iter()
+        .filter_map(|symbol| Some((fuzzy_score(query, &symbol.name)?, symbol)))
+        .collect();
+    matches.sort_by_key(|(score, _)| *score);
+    matches
+        .into_iter()
+        .take(limit)
+        .map(|(_, symbol)| symbol)
+        .collect()
+}
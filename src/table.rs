@@ -0,0 +1,249 @@
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// How the text of a column is lined up, set by the colons of the separator
+/// row
+#[derive(Clone, Copy)]
+enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// Find the Markdown table holding a line: the lines around it starting
+/// with `|`
+///
+/// # Args
+///
+/// - `lines`: The lines of the document
+/// - `y`: The line
+///
+/// # Returns
+///
+/// - The lines of the table, if the line is part of one
+pub fn bounds(lines: &[&str], y: usize) -> Option<Range<usize>> {
+    let is_row = |line: &&str| line.trim_start().starts_with('|');
+    if !lines.get(y).is_some_and(is_row) {
+        return None;
+    }
+    let start = lines[..y]
+        .iter()
+        .rposition(|line| !is_row(line))
+        .map_or(0, |index| index + 1);
+    let end = lines[y..]
+        .iter()
+        .position(|line| !is_row(line))
+        .map_or(lines.len(), |index| y + index);
+    Some(start..end)
+}
+
+/// Line up the cells of a table, padding every cell to the width of its
+/// column and redrawing the separator row
+///
+/// # Args
+///
+/// - `lines`: The rows of the table
+///
+/// # Returns
+///
+/// - The aligned rows, indented like the first one
+pub fn align(lines: &[&str]) -> Vec<String> {
+    let indent = lines
+        .first()
+        .map_or("", |line| &line[..line.len() - line.trim_start().len()]);
+    let rows: Vec<Vec<&str>> = lines.iter().map(|line| cells(line)).collect();
+    let is_separator =
+        |row: &Vec<&str>| !row.is_empty() && row.iter().all(|cell| is_separator_cell(cell));
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return lines.iter().map(|line| line.to_string()).collect();
+    }
+
+    let separator = rows.iter().find(|row| is_separator(row));
+    let alignments: Vec<Alignment> = (0..columns)
+        .map(|column| {
+            let cell = separator.and_then(|row| row.get(column)).unwrap_or(&"");
+            match (cell.starts_with(':'), cell.len() > 1 && cell.ends_with(':')) {
+                (true, true) => Alignment::Center,
+                (false, true) => Alignment::Right,
+                _ => Alignment::Left,
+            }
+        })
+        .collect();
+    // Separators need three characters, `:-:`
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .filter(|row| !is_separator(row))
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.width())
+                .fold(3, std::cmp::max)
+        })
+        .collect();
+
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = (0..columns)
+                .map(|column| {
+                    let width = widths[column];
+                    if is_separator(row) {
+                        separator_cell(alignments[column], width)
+                    } else {
+                        pad(row.get(column).unwrap_or(&""), width, alignments[column])
+                    }
+                })
+                .collect();
+            format!("{}| {} |", indent, cells.join(" | "))
+        })
+        .collect()
+}
+
+/// Find the cell holding a character of a row
+///
+/// # Args
+///
+/// - `line`: The row
+/// - `x`: The index of the character
+///
+/// # Returns
+///
+/// - The index of the cell, 0 being the indentation before the first `|`,
+///   and the index of the character within the text of the cell
+pub fn cell_at(line: &str, x: usize) -> (usize, usize) {
+    let at = line
+        .grapheme_indices(true)
+        .nth(x)
+        .map_or(line.len(), |(index, _)| index);
+    let pipes = pipes(line);
+    let cell = pipes.iter().filter(|&&pipe| pipe < at).count();
+    if cell == 0 {
+        return (0, x);
+    }
+
+    let before = &line[pipes[cell - 1] + 1..at];
+    let offset = before.trim_start().graphemes(true).count();
+    (cell, offset)
+}
+
+/// Find the character at an index within the text of a cell of an aligned
+/// row
+///
+/// # Args
+///
+/// - `line`: The aligned row
+/// - `cell`: The index of the cell, as returned by `cell_at`
+/// - `offset`: The index of the character within the text of the cell
+///
+/// # Returns
+///
+/// - The index of the character in the row
+pub fn position(line: &str, cell: usize, offset: usize) -> usize {
+    let pipes = pipes(line);
+    let start = match cell.checked_sub(1).and_then(|index| pipes.get(index)) {
+        // The text of aligned cells starts after a space
+        Some(&pipe) => std::cmp::min(pipe + 2, line.len()),
+        None => return offset,
+    };
+    let end = pipes.get(cell).copied().unwrap_or(line.len());
+    let text = line[start..end].trim_end().graphemes(true).count();
+    line[..start].graphemes(true).count() + std::cmp::min(offset, text)
+}
+
+/// Find the byte indexes of the `|` separating cells, skipping escaped ones
+fn pipes(line: &str) -> Vec<usize> {
+    let mut escaped = false;
+    let mut pipes = Vec::new();
+    for (index, c) in line.char_indices() {
+        if c == '|' && !escaped {
+            pipes.push(index);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    pipes
+}
+
+/// Split a row into the text of its cells
+fn cells(line: &str) -> Vec<&str> {
+    let pipes = pipes(line);
+    let mut cells: Vec<&str> = pipes
+        .windows(2)
+        .map(|pair| line[pair[0] + 1..pair[1]].trim())
+        .collect();
+    // A row missing its closing `|` still has a last cell
+    if let Some(&last) = pipes.last() {
+        let rest = line[last + 1..].trim();
+        if !rest.is_empty() {
+            cells.push(rest);
+        }
+    }
+    cells
+}
+
+fn is_separator_cell(cell: &str) -> bool {
+    let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+    !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+}
+
+fn separator_cell(alignment: Alignment, width: usize) -> String {
+    match alignment {
+        Alignment::Left => "-".repeat(width),
+        Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+        Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+    }
+}
+
+fn pad(text: &str, width: usize, alignment: Alignment) -> String {
+    let space = width.saturating_sub(text.width());
+    let (left, right) = match alignment {
+        Alignment::Left => (0, space),
+        Alignment::Center => (space / 2, space - space / 2),
+        Alignment::Right => (space, 0),
+    };
+    format!("{}{}{}", " ".repeat(left), text, " ".repeat(right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_of_table() {
+        let lines = ["text", "|a|", "|b|", "more"];
+        assert_eq!(bounds(&lines, 1), Some(1..3));
+        assert_eq!(bounds(&lines, 2), Some(1..3));
+        assert_eq!(bounds(&lines, 0), None);
+        assert_eq!(bounds(&lines, 4), None);
+    }
+
+    #[test]
+    fn align_pads_columns() {
+        assert_eq!(
+            align(&["|a|bb|", "|:-:|--:|", "|ccccc|d"]),
+            vec!["|   a   |  bb |", "| :---: | --: |", "| ccccc |   d |"]
+        );
+    }
+
+    #[test]
+    fn align_keeps_indent_and_escaped_pipes() {
+        assert_eq!(align(&["  |x|"]), vec!["  | x   |"]);
+        assert_eq!(align(&["|a \\| b|c|"]), vec!["| a \\| b | c   |"]);
+    }
+
+    #[test]
+    fn align_by_display_width() {
+        assert_eq!(align(&["|日本|", "|a|"]), vec!["| 日本 |", "| a    |"]);
+    }
+
+    #[test]
+    fn cell_and_position_round_trip() {
+        let line = "| ab | cd |";
+        assert_eq!(cell_at(line, 3), (1, 1));
+        assert_eq!(cell_at(line, 8), (2, 1));
+        assert_eq!(cell_at(line, 0), (0, 0));
+        assert_eq!(position(line, 1, 1), 3);
+        assert_eq!(position(line, 2, 1), 8);
+        assert_eq!(position(line, 2, 5), 9);
+        assert_eq!(position(line, 0, 3), 3);
+    }
+}
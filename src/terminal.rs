@@ -1,10 +1,27 @@
 use crate::Position;
-use std::io::{self, stdout, Write};
+use std::io::{self, stdout, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
 use termion::color;
 use termion::event::Key;
 use termion::input::TermRead;
 use termion::raw::{IntoRawMode, RawTerminal};
 
+/// Something that happened while waiting for input
+pub enum Event {
+    /// A key was pressed
+    Key(Key),
+
+    /// No key was pressed before the timeout, the moment for work done
+    /// while idle
+    Tick,
+
+    /// No key was pressed before the timeout, and the terminal has a new
+    /// size
+    Resize,
+}
+
 pub struct Size {
     pub width: u16,
     pub height: u16,
@@ -13,6 +30,7 @@ pub struct Size {
 pub struct Terminal {
     size: Size,
     _stdout: RawTerminal<std::io::Stdout>,
+    keys: Receiver<Result<Key, io::Error>>,
 }
 
 impl Terminal {
@@ -22,14 +40,32 @@ impl Terminal {
      * Defaults:
      *  Heigh and Width are retrieved automatically on invocation
      */
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Result<Self, std::io::Error> {
         let size: (u16, u16) = termion::terminal_size()?;
+        // Keys come from the terminal itself when stdin is a pipe, as in
+        // `ps aux | donovim -`
+        let input: Box<dyn Read + Send> = if termion::is_tty(&io::stdin()) {
+            Box::new(io::stdin())
+        } else {
+            Box::new(termion::get_tty()?)
+        };
+        // Keys are read on their own thread, so waiting for one can time out
+        let (sender, keys) = mpsc::channel();
+        thread::spawn(move || {
+            for key in input.keys() {
+                if sender.send(key).is_err() {
+                    break;
+                }
+            }
+        });
         Ok(Self {
             size: Size {
                 width: size.0,
                 height: size.1.saturating_sub(2),
             },
             _stdout: stdout().into_raw_mode()?,
+            keys,
         })
     }
 
@@ -40,6 +76,22 @@ impl Terminal {
         &self.size
     }
 
+    /**
+     * Reads the size of the terminal again, returning whether it changed
+     */
+    pub fn refresh_size(&mut self) -> Result<bool, std::io::Error> {
+        let size: (u16, u16) = termion::terminal_size()?;
+        let height = size.1.saturating_sub(2);
+        if size.0 == self.size.width && height == self.size.height {
+            return Ok(false);
+        }
+        self.size = Size {
+            width: size.0,
+            height,
+        };
+        Ok(true)
+    }
+
     /**
      * Clears the screen
      */
@@ -82,11 +134,11 @@ impl Terminal {
         print!("{}", termion::clear::CurrentLine);
     }
 
-    pub fn set_bg_color(color: color::Rgb) {
+    pub fn set_bg_color(color: impl color::Color) {
         print!("{}", color::Bg(color));
     }
 
-    pub fn set_fg_color(color: color::Rgb) {
+    pub fn set_fg_color(color: impl color::Color) {
         print!("{}", color::Fg(color));
     }
 
@@ -106,12 +158,16 @@ impl Terminal {
     }
 
     /**
-     * Loop over stdin and return input keys
+     * Wait for the next key, for no longer than the timeout. The size of the
+     * terminal is read again when none is pressed
      */
-    pub fn read_key() -> Result<Key, io::Error> {
-        loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
+    pub fn next_event(&mut self, timeout: Duration) -> Result<Event, io::Error> {
+        match self.keys.recv_timeout(timeout) {
+            Ok(key) => key.map(Event::Key),
+            Err(RecvTimeoutError::Timeout) if self.refresh_size()? => Ok(Event::Resize),
+            Err(RecvTimeoutError::Timeout) => Ok(Event::Tick),
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Input closed"))
             }
         }
     }
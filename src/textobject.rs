@@ -0,0 +1,341 @@
+use crate::motion::{self, Class};
+use crate::Position;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The text a text object such as `iw` or `a(` selects for an operator
+pub struct TextObject {
+    /// The position of the first character
+    pub start: Position,
+
+    /// The position just past the last character. For whole lines, the line
+    /// after the last one
+    pub end: Position,
+
+    /// Whether whole lines are selected, from `start.y` up to `end.y`
+    pub linewise: bool,
+}
+
+impl TextObject {
+    fn chars(y: usize, range: Range<usize>) -> Self {
+        Self {
+            start: Position { x: range.start, y },
+            end: Position { x: range.end, y },
+            linewise: false,
+        }
+    }
+
+    fn lines(lines: Range<usize>) -> Self {
+        Self {
+            start: Position {
+                x: 0,
+                y: lines.start,
+            },
+            end: Position { x: 0, y: lines.end },
+            linewise: true,
+        }
+    }
+}
+
+/// Find the text selected by a text object around the cursor: `w` and `W`
+/// for a word or WORD, `"`, `'` and `` ` `` for a quoted string, `(`, `[`,
+/// `{` and `<` (or their closing brackets, `b` and `B`) for a bracketed block
+/// and `p` for a paragraph
+///
+/// # Args
+///
+/// - `lines`: The lines of the document
+/// - `at`: The position of the cursor
+/// - `inner`: Whether to leave out the surroundings, as for `i`, rather than
+///   include them, as for `a`
+/// - `kind`: The key naming the text object
+///
+/// # Returns
+///
+/// - The selected text, if the cursor is on such an object
+pub fn find(lines: &[&str], at: &Position, inner: bool, kind: char) -> Option<TextObject> {
+    match kind {
+        'w' | 'W' => word(lines.get(at.y)?, at, inner, kind == 'W'),
+        '"' | '\'' | '`' => quoted(lines.get(at.y)?, at, inner, kind),
+        '(' | ')' | 'b' => block(lines, at, inner, ('(', ')')),
+        '[' | ']' => block(lines, at, inner, ('[', ']')),
+        '{' | '}' | 'B' => block(lines, at, inner, ('{', '}')),
+        '<' | '>' => block(lines, at, inner, ('<', '>')),
+        'p' => paragraph(lines, at.y, inner),
+        _ => None,
+    }
+}
+
+/// Select the run of word characters, punctuation or whitespace under the
+/// cursor, along with the whitespace after it (or before it, when there is
+/// none after it) unless `inner`
+fn word(line: &str, at: &Position, inner: bool, big: bool) -> Option<TextObject> {
+    let classes: Vec<Class> = line
+        .graphemes(true)
+        .map(|grapheme| motion::classify(grapheme, big))
+        .collect();
+    let class = *classes.get(at.x)?;
+    let run_end = |from: usize| {
+        let class = classes[from];
+        classes[from..]
+            .iter()
+            .position(|other| *other != class)
+            .map_or(classes.len(), |len| from + len)
+    };
+    let start = classes[..at.x]
+        .iter()
+        .rposition(|other| *other != class)
+        .map_or(0, |index| index + 1);
+    let end = run_end(at.x);
+    if inner {
+        return Some(TextObject::chars(at.y, start..end));
+    }
+
+    let range = if class == Class::Space {
+        // The word after the whitespace comes along
+        start..if end < classes.len() {
+            run_end(end)
+        } else {
+            end
+        }
+    } else if classes.get(end) == Some(&Class::Space) {
+        start..run_end(end)
+    } else {
+        let before = classes[..start]
+            .iter()
+            .rposition(|other| *other != Class::Space)
+            .map_or(0, |index| index + 1);
+        before..end
+    };
+    Some(TextObject::chars(at.y, range))
+}
+
+/// Select the quoted string holding the cursor, or the next one on the line.
+/// Quotes pair up from the start of the line, skipping escaped ones. Unless
+/// `inner`, the quotes and the whitespace after them are included
+fn quoted(line: &str, at: &Position, inner: bool, quote: char) -> Option<TextObject> {
+    let graphemes: Vec<&str> = line.graphemes(true).collect();
+    let mut quotes = Vec::new();
+    let mut escaped = false;
+    for (index, grapheme) in graphemes.iter().enumerate() {
+        if !escaped && grapheme.chars().eq(std::iter::once(quote)) {
+            quotes.push(index);
+        }
+        escaped = *grapheme == "\\" && !escaped;
+    }
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, close)| at.x <= close)?;
+    let range = if inner {
+        open + 1..close
+    } else {
+        let end = graphemes[close + 1..]
+            .iter()
+            .position(|grapheme| grapheme.trim().len() == grapheme.len())
+            .map_or(graphemes.len(), |len| close + 1 + len);
+        open..end
+    };
+    Some(TextObject::chars(at.y, range))
+}
+
+/// Select the block between the innermost pair of brackets around the
+/// cursor. Inside a block whose brackets end and start lines, the lines
+/// between them are selected whole
+fn block(lines: &[&str], at: &Position, inner: bool, pair: (char, char)) -> Option<TextObject> {
+    let (open, close) = pair;
+    let is = |grapheme: &str, c: char| grapheme.chars().eq(std::iter::once(c));
+    let graphemes = |y: usize| -> Vec<&str> { lines[y].graphemes(true).collect() };
+
+    // Walk back to the opening bracket no closing bracket pairs with
+    let mut depth = 0;
+    let mut start = None;
+    'back: for y in (0..=at.y).rev() {
+        let line = graphemes(y);
+        let end = if y == at.y {
+            std::cmp::min(at.x + 1, line.len())
+        } else {
+            line.len()
+        };
+        for x in (0..end).rev() {
+            if is(line[x], close) && (x, y) != (at.x, at.y) {
+                depth += 1;
+            } else if is(line[x], open) {
+                if depth == 0 {
+                    start = Some(Position { x, y });
+                    break 'back;
+                }
+                depth -= 1;
+            }
+        }
+    }
+    let start = start?;
+
+    let mut depth = 0;
+    let mut end = None;
+    'forward: for (y, line) in lines.iter().enumerate().skip(start.y) {
+        let from = if y == start.y { start.x + 1 } else { 0 };
+        for (x, grapheme) in line.graphemes(true).enumerate().skip(from) {
+            if is(grapheme, open) {
+                depth += 1;
+            } else if is(grapheme, close) {
+                if depth == 0 {
+                    end = Some(Position { x, y });
+                    break 'forward;
+                }
+                depth -= 1;
+            }
+        }
+    }
+    let end = end?;
+
+    if !inner {
+        return Some(TextObject {
+            start,
+            end: Position {
+                x: end.x + 1,
+                y: end.y,
+            },
+            linewise: false,
+        });
+    }
+    let opens_line = start.x + 1 == graphemes(start.y).len();
+    let closes_line = graphemes(end.y)[..end.x]
+        .iter()
+        .all(|grapheme| grapheme.trim().is_empty());
+    if opens_line && closes_line && end.y > start.y {
+        return Some(TextObject::lines(start.y + 1..end.y));
+    }
+    Some(TextObject {
+        start: Position {
+            x: start.x + 1,
+            y: start.y,
+        },
+        end,
+        linewise: false,
+    })
+}
+
+/// Select the lines around a line that are all blank or all not blank, along
+/// with the blank lines after them (or before them, when there are none
+/// after them) unless `inner`
+fn paragraph(lines: &[&str], y: usize, inner: bool) -> Option<TextObject> {
+    let is_blank = |y: usize| lines[y].trim().is_empty();
+    let run = |y: usize| {
+        let blank = is_blank(y);
+        let start = (0..y)
+            .rev()
+            .find(|&other| is_blank(other) != blank)
+            .map_or(0, |other| other + 1);
+        let end = (y..lines.len())
+            .find(|&other| is_blank(other) != blank)
+            .unwrap_or(lines.len());
+        start..end
+    };
+    if y >= lines.len() {
+        return None;
+    }
+    let paragraph = run(y);
+    if inner {
+        return Some(TextObject::lines(paragraph));
+    }
+    let range = if paragraph.end < lines.len() {
+        paragraph.start..run(paragraph.end).end
+    } else if paragraph.start > 0 && !is_blank(y) {
+        run(paragraph.start - 1).start..paragraph.end
+    } else {
+        paragraph
+    };
+    Some(TextObject::lines(range))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The start and end of the selected text, and whether it is linewise
+    fn selected(
+        lines: &[&str],
+        x: usize,
+        y: usize,
+        inner: bool,
+        kind: char,
+    ) -> Option<(usize, usize, usize, usize, bool)> {
+        let object = find(lines, &Position { x, y }, inner, kind)?;
+        Some((
+            object.start.x,
+            object.start.y,
+            object.end.x,
+            object.end.y,
+            object.linewise,
+        ))
+    }
+
+    #[test]
+    fn word_with_surrounding_space() {
+        let lines = ["foo bar baz"];
+        assert_eq!(selected(&lines, 4, 0, true, 'w'), Some((4, 0, 7, 0, false)));
+        assert_eq!(
+            selected(&lines, 4, 0, false, 'w'),
+            Some((4, 0, 8, 0, false))
+        );
+        assert_eq!(
+            selected(&lines, 8, 0, false, 'w'),
+            Some((7, 0, 11, 0, false))
+        );
+        assert_eq!(
+            selected(&lines, 3, 0, false, 'w'),
+            Some((3, 0, 7, 0, false))
+        );
+        assert_eq!(
+            selected(&["a.b c"], 0, 0, true, 'W'),
+            Some((0, 0, 3, 0, false))
+        );
+    }
+
+    #[test]
+    fn quoted_skips_escaped_quotes() {
+        let lines = [r#"say "hi \"x\"" ok"#];
+        assert_eq!(
+            selected(&lines, 0, 0, true, '"'),
+            Some((5, 0, 13, 0, false))
+        );
+        assert_eq!(
+            selected(&lines, 0, 0, false, '"'),
+            Some((4, 0, 15, 0, false))
+        );
+        assert_eq!(selected(&lines, 16, 0, true, '"'), None);
+    }
+
+    #[test]
+    fn innermost_block() {
+        let lines = ["f(a, (b))"];
+        assert_eq!(selected(&lines, 6, 0, true, '('), Some((6, 0, 7, 0, false)));
+        assert_eq!(
+            selected(&lines, 6, 0, false, 'b'),
+            Some((5, 0, 8, 0, false))
+        );
+        assert_eq!(selected(&lines, 8, 0, true, ')'), Some((2, 0, 8, 0, false)));
+        assert_eq!(selected(&lines, 0, 0, true, '['), None);
+    }
+
+    #[test]
+    fn block_between_lines_is_linewise() {
+        let lines = ["fn x() {", "  a;", "}"];
+        assert_eq!(selected(&lines, 1, 1, true, 'B'), Some((0, 1, 0, 2, true)));
+        assert_eq!(
+            selected(&lines, 1, 1, false, '{'),
+            Some((7, 0, 1, 2, false))
+        );
+    }
+
+    #[test]
+    fn paragraph_with_blank_lines() {
+        let lines = ["a", "b", "", "", "c"];
+        assert_eq!(selected(&lines, 0, 0, true, 'p'), Some((0, 0, 0, 2, true)));
+        assert_eq!(selected(&lines, 0, 0, false, 'p'), Some((0, 0, 0, 4, true)));
+        assert_eq!(selected(&lines, 0, 4, false, 'p'), Some((0, 2, 0, 5, true)));
+        assert_eq!(selected(&lines, 0, 2, true, 'p'), Some((0, 2, 0, 4, true)));
+        assert_eq!(selected(&lines, 0, 5, true, 'p'), None);
+    }
+}
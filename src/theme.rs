@@ -0,0 +1,217 @@
+use crate::highlighting;
+use std::fmt;
+use termion::color;
+
+/// A color of a theme: a true color, or one of the 16 colors of the
+/// terminal's own palette for terminals without true color
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    /// A true color, by its red, green and blue channels
+    Rgb(u8, u8, u8),
+
+    /// The index of the color in the palette: black, red, green, yellow,
+    /// blue, magenta, cyan and white, then their bright versions
+    Ansi(u8),
+}
+
+impl color::Color for Color {
+    fn write_fg(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Rgb(r, g, b) => color::Rgb(r, g, b).write_fg(f),
+            Self::Ansi(index) if index < 8 => write!(f, "\x1b[{}m", 30 + index),
+            Self::Ansi(index) => write!(f, "\x1b[{}m", 90 + index % 8),
+        }
+    }
+
+    fn write_bg(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Rgb(r, g, b) => color::Rgb(r, g, b).write_bg(f),
+            Self::Ansi(index) if index < 8 => write!(f, "\x1b[{}m", 40 + index),
+            Self::Ansi(index) => write!(f, "\x1b[{}m", 100 + index % 8),
+        }
+    }
+}
+
+/// A named color palette used to draw the editor
+#[derive(Clone)]
+pub struct Theme {
+    pub name: &'static str,
+    pub background: Color,
+    pub foreground: Color,
+    pub status_fg: Color,
+    pub status_bg: Color,
+    pub insert_status_bg: Color,
+    pub replace_status_bg: Color,
+    pub conflict_status_bg: Color,
+    pub inactive_background: Color,
+    pub inactive_status_fg: Color,
+    pub inactive_status_bg: Color,
+    pub gutter_fg: Color,
+    pub warning_bg: Color,
+    pub error_bg: Color,
+    pub number: Color,
+    pub search_match: Color,
+    pub reference: Color,
+    pub match_paren: Color,
+    pub string: Color,
+    pub character: Color,
+    pub comment: Color,
+    pub primary_keywords: Color,
+    pub secondary_keywords: Color,
+    pub diff_add_bg: Color,
+    pub diff_change_bg: Color,
+    pub diff_filler_bg: Color,
+    pub conflict_marker_bg: Color,
+    pub conflict_ours_bg: Color,
+    pub conflict_base_bg: Color,
+    pub conflict_theirs_bg: Color,
+}
+
+impl Theme {
+    /// Retrieve every built-in theme
+    pub fn all() -> Vec<Self> {
+        vec![Self::gruvbox(), Self::solarized(), Self::basic()]
+    }
+
+    /// Find a built-in theme by name
+    ///
+    /// # Args
+    ///
+    /// - `name`: The name of the theme
+    ///
+    /// # Returns
+    ///
+    /// - The theme if one exists with that name
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::all().into_iter().find(|theme| theme.name == name)
+    }
+
+    /// Retrieve the color used to draw a highlighting type
+    pub fn color(&self, hl_type: highlighting::Type) -> Color {
+        match hl_type {
+            highlighting::Type::Number => self.number,
+            highlighting::Type::Match => self.search_match,
+            highlighting::Type::Reference => self.reference,
+            highlighting::Type::MatchParen => self.match_paren,
+            highlighting::Type::String => self.string,
+            highlighting::Type::Character => self.character,
+            highlighting::Type::Comment | highlighting::Type::MultilineComment => self.comment,
+            highlighting::Type::PrimaryKeywords => self.primary_keywords,
+            highlighting::Type::SecondaryKeywords => self.secondary_keywords,
+            highlighting::Type::None => self.foreground,
+        }
+    }
+
+    fn gruvbox() -> Self {
+        Self {
+            name: "gruvbox",
+            background: Color::Rgb(29, 32, 33),
+            foreground: Color::Rgb(255, 255, 255),
+            status_fg: Color::Rgb(239, 239, 239),
+            status_bg: Color::Rgb(120, 120, 120),
+            insert_status_bg: Color::Rgb(121, 116, 14),
+            replace_status_bg: Color::Rgb(175, 58, 3),
+            conflict_status_bg: Color::Rgb(157, 0, 6),
+            inactive_background: Color::Rgb(18, 20, 21),
+            inactive_status_fg: Color::Rgb(168, 153, 132),
+            inactive_status_bg: Color::Rgb(60, 56, 54),
+            gutter_fg: Color::Rgb(124, 111, 100),
+            warning_bg: Color::Rgb(181, 118, 20),
+            error_bg: Color::Rgb(157, 0, 6),
+            number: Color::Rgb(177, 98, 134),
+            search_match: Color::Rgb(38, 139, 210),
+            reference: Color::Rgb(142, 192, 124),
+            match_paren: Color::Rgb(254, 128, 25),
+            string: Color::Rgb(152, 151, 26),
+            character: Color::Rgb(177, 98, 134),
+            comment: Color::Rgb(146, 131, 116),
+            primary_keywords: Color::Rgb(251, 73, 52),
+            secondary_keywords: Color::Rgb(215, 153, 33),
+            diff_add_bg: Color::Rgb(50, 72, 38),
+            diff_change_bg: Color::Rgb(38, 56, 80),
+            diff_filler_bg: Color::Rgb(60, 56, 54),
+            conflict_marker_bg: Color::Rgb(80, 73, 69),
+            conflict_ours_bg: Color::Rgb(38, 56, 80),
+            conflict_base_bg: Color::Rgb(60, 48, 72),
+            conflict_theirs_bg: Color::Rgb(50, 72, 38),
+        }
+    }
+
+    fn solarized() -> Self {
+        Self {
+            name: "solarized",
+            background: Color::Rgb(0, 43, 54),
+            foreground: Color::Rgb(147, 161, 161),
+            status_fg: Color::Rgb(238, 232, 213),
+            status_bg: Color::Rgb(7, 54, 66),
+            insert_status_bg: Color::Rgb(133, 153, 0),
+            replace_status_bg: Color::Rgb(203, 75, 22),
+            conflict_status_bg: Color::Rgb(220, 50, 47),
+            inactive_background: Color::Rgb(0, 30, 38),
+            inactive_status_fg: Color::Rgb(88, 110, 117),
+            inactive_status_bg: Color::Rgb(0, 36, 46),
+            gutter_fg: Color::Rgb(88, 110, 117),
+            warning_bg: Color::Rgb(181, 137, 0),
+            error_bg: Color::Rgb(220, 50, 47),
+            number: Color::Rgb(211, 54, 130),
+            search_match: Color::Rgb(181, 137, 0),
+            reference: Color::Rgb(108, 113, 196),
+            match_paren: Color::Rgb(203, 75, 22),
+            string: Color::Rgb(42, 161, 152),
+            character: Color::Rgb(211, 54, 130),
+            comment: Color::Rgb(88, 110, 117),
+            primary_keywords: Color::Rgb(133, 153, 0),
+            secondary_keywords: Color::Rgb(38, 139, 210),
+            diff_add_bg: Color::Rgb(0, 60, 40),
+            diff_change_bg: Color::Rgb(0, 50, 80),
+            diff_filler_bg: Color::Rgb(0, 36, 46),
+            conflict_marker_bg: Color::Rgb(7, 54, 66),
+            conflict_ours_bg: Color::Rgb(0, 50, 80),
+            conflict_base_bg: Color::Rgb(40, 40, 80),
+            conflict_theirs_bg: Color::Rgb(0, 60, 40),
+        }
+    }
+
+    /// A theme of the 16 colors of the terminal's palette, for terminals
+    /// without true color
+    fn basic() -> Self {
+        Self {
+            name: "basic",
+            background: Color::Ansi(0),
+            foreground: Color::Ansi(7),
+            status_fg: Color::Ansi(0),
+            status_bg: Color::Ansi(7),
+            insert_status_bg: Color::Ansi(2),
+            replace_status_bg: Color::Ansi(3),
+            conflict_status_bg: Color::Ansi(1),
+            inactive_background: Color::Ansi(0),
+            inactive_status_fg: Color::Ansi(7),
+            inactive_status_bg: Color::Ansi(8),
+            gutter_fg: Color::Ansi(8),
+            warning_bg: Color::Ansi(3),
+            error_bg: Color::Ansi(1),
+            number: Color::Ansi(5),
+            search_match: Color::Ansi(4),
+            reference: Color::Ansi(6),
+            match_paren: Color::Ansi(11),
+            string: Color::Ansi(2),
+            character: Color::Ansi(5),
+            comment: Color::Ansi(8),
+            primary_keywords: Color::Ansi(1),
+            secondary_keywords: Color::Ansi(3),
+            diff_add_bg: Color::Ansi(2),
+            diff_change_bg: Color::Ansi(4),
+            diff_filler_bg: Color::Ansi(8),
+            conflict_marker_bg: Color::Ansi(8),
+            conflict_ours_bg: Color::Ansi(4),
+            conflict_base_bg: Color::Ansi(5),
+            conflict_theirs_bg: Color::Ansi(2),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::gruvbox()
+    }
+}
@@ -0,0 +1,50 @@
+/// The integrations a build of the editor may have, and whether this one
+/// has them. Config files check for them with `[has.<feature>]` tables.
+/// Every build has the integrations running external programs, the others
+/// depend on the platform it is built for
+const FEATURES: [(&str, bool); 5] = [
+    ("ctags", true),
+    ("git", true),
+    ("man", true),
+    ("remote", cfg!(unix)),
+    ("unix", cfg!(unix)),
+];
+
+/// Check whether this build has a feature
+///
+/// # Args
+///
+/// - `feature`: The name of the feature, e.g. `git`
+///
+/// # Returns
+///
+/// - Whether the build has it, false for names it does not know
+pub fn has(feature: &str) -> bool {
+    FEATURES
+        .iter()
+        .any(|(name, enabled)| *name == feature && *enabled)
+}
+
+/// Describe the build, as shown by `:version`: the version, the kind of
+/// build and the platform, then every feature marked with `+` when the
+/// build has it and `-` when it does not, e.g.
+/// `donovim 0.1.0 (release, linux x86_64) +git +unix`
+pub fn describe() -> String {
+    let profile = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    };
+    let features: Vec<String> = FEATURES
+        .iter()
+        .map(|(name, enabled)| format!("{}{}", if *enabled { '+' } else { '-' }, name))
+        .collect();
+    format!(
+        "donovim {} ({}, {} {}) {}",
+        env!("CARGO_PKG_VERSION"),
+        profile,
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        features.join(" ")
+    )
+}